@@ -6,41 +6,42 @@ mod phf;
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
 fn compare(c: &mut Criterion) {
-    use std::fs::File;
-    use std::io::{BufRead, BufReader, ErrorKind};
-    use std::path::Path;
+    use std::fs;
+    use std::io::ErrorKind;
 
-    use intern_str::builder::{Builder, IgnoreCase, Utf8Graph};
+    use intern_str::builder::{
+        Builder, DuplicatePolicy, IgnoreCase, LineOptions, Splitting, Utf8Graph,
+    };
     use intern_str::CaseInsensitive;
 
     // Read in lines from /usr/share/dict/words
-    let file = BufReader::new(match File::open(Path::new("/usr/share/dict/words")) {
-        Ok(file) => file,
+    let words = match fs::read_to_string("/usr/share/dict/words") {
+        Ok(words) => words,
         Err(e) if e.kind() == ErrorKind::NotFound => {
             // If the file is not found, we skip the test.
             return;
         }
         Err(e) => panic!("{}", e),
-    });
-
-    let mut builder = Builder::<_, IgnoreCase<Utf8Graph>>::new();
-
-    for line in file.lines() {
-        // Intern lines into the builder.
-        let mut line = line.unwrap();
-        if line.ends_with('\n') {
-            line.pop();
-        }
-
-        if !line.is_ascii() {
-            continue;
-        }
+    };
 
+    let load = |splitting: Splitting, max_chunk_len: usize| {
         // Eat duplicates.
-        builder.add(line, ()).ok();
-    }
+        let mut builder = Builder::<_, IgnoreCase<Utf8Graph>>::new();
+        builder.set_duplicate_policy(DuplicatePolicy::KeepFirst);
+        builder.set_splitting(splitting);
+        builder.set_max_chunk_len(max_chunk_len);
+        builder
+            .add_lines(
+                words.as_bytes(),
+                LineOptions::new().ascii_only(true),
+                |_, _| (),
+            )
+            .unwrap();
+        builder
+    };
 
     // Build the graph.
+    let mut builder = load(Splitting::LongestPrefix, std::usize::MAX);
     let mut buffer = vec![];
     let graph = builder.build(&mut buffer);
 
@@ -66,6 +67,23 @@ fn compare(c: &mut Criterion) {
         })
     });
 
+    // Compare the step count against the node fanout with other chunking settings.
+    for &(name, splitting, max_chunk_len) in &[
+        ("units", Splitting::Units, 1),
+        ("max chunk 4", Splitting::LongestPrefix, 4),
+    ] {
+        let mut builder = load(splitting, max_chunk_len);
+        let mut buffer = vec![];
+        let graph = builder.build(&mut buffer);
+
+        c.bench_function(&format!("intern_str::Graph::process ({})", name), |b| {
+            b.iter(|| {
+                let word = &test_words[rng.usize(..test_words_len)];
+                black_box(graph.process(black_box(CaseInsensitive(word))))
+            })
+        });
+    }
+
     c.bench_function("phf::Map::get", |b| {
         b.iter(|| {
             // Get a random word.
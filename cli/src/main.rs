@@ -0,0 +1,294 @@
+//! Generate `intern-str` graphs from the command line.
+//!
+//! This does the same work as `intern_str_codegen::build_from_file`, for build systems
+//! other than Cargo and for trying out a set of keys without writing a build script.
+//! Run `intern-str --help` for the options.
+
+#![forbid(
+    unsafe_code,
+    missing_docs,
+    missing_debug_implementations,
+    missing_copy_implementations,
+    future_incompatible,
+    rust_2018_idioms
+)]
+
+use intern_str::builder::{
+    AsciiGraph, Builder, Column, GraphType, IgnoreCase, LineOptions, Utf8Graph,
+};
+use intern_str::serialize::{self, ByteKey};
+use intern_str::{CaseInsensitive, Graph};
+use intern_str_codegen::{Key, Options};
+
+use std::fmt::Debug;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::{env, process};
+
+const USAGE: &str = "\
+Usage: intern-str [OPTIONS] <INPUT>
+
+Build a graph from INPUT and write it out. INPUT is a list of keys, one per line,
+where each key's value is its index in the list and lines that start with `#` are
+comments. Files that end in `.csv` are read as CSV files with a header row, where
+the first column is the key and the second column is the value. Use `-` to read
+from standard input.
+
+Options:
+    -o, --output <PATH>       Write to PATH instead of standard output
+    -f, --format <FORMAT>     Write `rust` code (the default), a `dot` file, or a
+                              `binary` graph for `intern_str::serialize::read`
+    -i, --case-insensitive    Match keys case-insensitively
+        --ascii               Only accept ASCII keys, and match them as bytes
+        --csv                 Read INPUT as a CSV file
+        --value-type <TYPE>   The type of the values in Rust code [default: usize]
+        --compact             Write Rust code without indentation
+    -h, --help                Print this message
+";
+
+/// The error for drawing a graph with byte keys, which don't have labels.
+const NO_DRAW: &str = "`--format dot` can't be used with `--ascii`";
+
+fn main() {
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("intern-str: {}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(err) = run(&args) {
+        eprintln!("intern-str: {}", err);
+        process::exit(1);
+    }
+}
+
+/// The command-line arguments.
+#[derive(Debug)]
+struct Args {
+    /// The file to read the keys from, or `-` for standard input.
+    input: PathBuf,
+
+    /// The file to write to, or standard output if there isn't one.
+    output: Option<PathBuf>,
+
+    /// The format to write.
+    format: Format,
+
+    /// Whether keys are matched case-insensitively.
+    case_insensitive: bool,
+
+    /// Whether keys must be ASCII, and are matched as bytes.
+    ascii: bool,
+
+    /// Whether the input is a CSV file.
+    csv: bool,
+
+    /// The type of the values in Rust code.
+    value_type: String,
+
+    /// Whether Rust code is written without indentation.
+    compact: bool,
+}
+
+/// The format that a graph is written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// Rust code for an expression of type `Graph`.
+    Rust,
+
+    /// A Graphviz file.
+    Dot,
+
+    /// The binary format of `intern_str::serialize`.
+    Binary,
+}
+
+impl Args {
+    /// Parse the arguments, or return `None` if the help message was asked for.
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut input = None;
+        let mut output = None;
+        let mut format = Format::Rust;
+        let mut case_insensitive = false;
+        let mut ascii = false;
+        let mut csv = false;
+        let mut value_type = "usize".to_string();
+        let mut compact = false;
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("`{}` needs a value", name))
+            };
+
+            match &*arg {
+                "-h" | "--help" => return Ok(None),
+                "-o" | "--output" => output = Some(PathBuf::from(value(&arg)?)),
+                "-f" | "--format" => {
+                    format = match &*value(&arg)? {
+                        "rust" => Format::Rust,
+                        "dot" => Format::Dot,
+                        "binary" => Format::Binary,
+                        other => return Err(format!("unknown format `{}`", other)),
+                    }
+                }
+                "-i" | "--case-insensitive" => case_insensitive = true,
+                "--ascii" => ascii = true,
+                "--csv" => csv = true,
+                "--value-type" => value_type = value(&arg)?,
+                "--compact" => compact = true,
+                _ if arg.starts_with('-') && arg != "-" => {
+                    return Err(format!("unknown option `{}`", arg));
+                }
+                _ if input.is_some() => return Err(format!("unexpected argument `{}`", arg)),
+                _ => input = Some(PathBuf::from(arg)),
+            }
+        }
+
+        let input = input.ok_or_else(|| "no input file".to_string())?;
+        let csv = csv || input.extension().map_or(false, |ext| ext == "csv");
+
+        Ok(Some(Args {
+            input,
+            output,
+            format,
+            case_insensitive,
+            ascii,
+            csv,
+            value_type,
+            compact,
+        }))
+    }
+}
+
+/// Build the graph and write it out.
+fn run(args: &Args) -> Result<(), String> {
+    let out = match (args.case_insensitive, args.ascii) {
+        (false, false) => generate::<Utf8Graph>(args, "&'static str")?,
+        (false, true) => generate::<AsciiGraph>(args, "&'static [u8]")?,
+        (true, false) => {
+            generate::<IgnoreCase<Utf8Graph>>(args, "intern_str::CaseInsensitive<&'static str>")?
+        }
+        (true, true) => {
+            generate::<IgnoreCase<AsciiGraph>>(args, "intern_str::CaseInsensitive<&'static [u8]>")?
+        }
+    };
+
+    match &args.output {
+        Some(path) => fs::write(path, out).map_err(|err| format!("{}: {}", path.display(), err)),
+        None => io::stdout().write_all(&out).map_err(|err| err.to_string()),
+    }
+}
+
+/// Build the graph for one type of key, and write it in the format that was asked for.
+fn generate<Type>(args: &Args, input_type: &str) -> Result<Vec<u8>, String>
+where
+    Type: for<'a> GraphType<'a>,
+    for<'a> <Type as GraphType<'a>>::InputKey: CliKey<'a>,
+{
+    let mut builder =
+        load::<Type>(args).map_err(|err| format!("{}: {}", args.input.display(), err))?;
+    let mut buffer = Vec::new();
+    let graph = builder.build(&mut buffer);
+    let mut out = Vec::new();
+
+    match args.format {
+        Format::Rust => {
+            let output_type = format!("Option<{}>", args.value_type);
+            let code = Options::new().compact(args.compact).generate(
+                &graph,
+                input_type,
+                &output_type,
+                |f, value| match value {
+                    Some(value) => write!(f, "Some({})", value),
+                    None => write!(f, "None"),
+                },
+            );
+            out.extend_from_slice(code.as_bytes());
+        }
+        Format::Dot => CliKey::draw(&graph, &mut out)?,
+        Format::Binary => serialize::write(&graph, &mut out, |value, out| {
+            // Misses are written as empty outputs.
+            if let Some(value) = value {
+                out.extend_from_slice(value.as_bytes());
+            }
+        }),
+    }
+
+    Ok(out)
+}
+
+/// Load the keys and values from the input into a builder.
+///
+/// The values are the code for each value, which is also what the binary format holds.
+fn load<Type>(args: &Args) -> Result<Builder<String, Type>, Box<dyn std::error::Error>>
+where
+    Type: for<'a> GraphType<'a>,
+{
+    let reader: Box<dyn BufRead> = if args.input == Path::new("-") {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        Box::new(BufReader::new(File::open(&args.input)?))
+    };
+    let mut builder = Builder::new();
+
+    if args.csv {
+        builder.add_csv(reader, Column::Index(0), Column::Index(1), |value| {
+            Ok::<_, String>(value.to_string())
+        })?;
+    } else {
+        let mut index = 0;
+        builder.add_lines(reader, LineOptions::new().comment("#"), |_, _| {
+            index += 1;
+            (index - 1).to_string()
+        })?;
+    }
+
+    Ok(builder)
+}
+
+/// A key that the command-line tool can write in every format.
+trait CliKey<'a>: Key + ByteKey<'a> + Sized {
+    /// Write the graph as a Graphviz file.
+    fn draw<Output: Debug>(
+        graph: &Graph<'_, '_, Self, Output>,
+        out: &mut Vec<u8>,
+    ) -> Result<(), String>;
+}
+
+impl<'a> CliKey<'a> for &'a str {
+    fn draw<Output: Debug>(
+        graph: &Graph<'_, '_, Self, Output>,
+        out: &mut Vec<u8>,
+    ) -> Result<(), String> {
+        intern_str_visualize::as_graphviz(graph, out, "G").map_err(|err| err.to_string())
+    }
+}
+
+impl<'a> CliKey<'a> for CaseInsensitive<&'a str> {
+    fn draw<Output: Debug>(
+        graph: &Graph<'_, '_, Self, Output>,
+        out: &mut Vec<u8>,
+    ) -> Result<(), String> {
+        intern_str_visualize::as_graphviz(graph, out, "G").map_err(|err| err.to_string())
+    }
+}
+
+impl<'a> CliKey<'a> for &'a [u8] {
+    fn draw<Output: Debug>(_: &Graph<'_, '_, Self, Output>, _: &mut Vec<u8>) -> Result<(), String> {
+        Err(NO_DRAW.to_string())
+    }
+}
+
+impl<'a> CliKey<'a> for CaseInsensitive<&'a [u8]> {
+    fn draw<Output: Debug>(_: &Graph<'_, '_, Self, Output>, _: &mut Vec<u8>) -> Result<(), String> {
+        Err(NO_DRAW.to_string())
+    }
+}
@@ -0,0 +1,512 @@
+//! Generating a graph from a file in a build script.
+
+use crate::{read_input_hash, write_input_hash, Expr, InputHasher, Key, Options};
+
+use intern_str::builder::{Builder, Column, GraphType, IgnoreCase, LineOptions, Stats, Utf8Graph};
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+#[cfg(feature = "dot")]
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::hash::Hasher;
+use core::{write, writeln};
+
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::{env, fs, println};
+
+/// Build a graph from a file and write its code into `OUT_DIR`.
+///
+/// This is everything that a build script needs to do to generate a graph. Files
+/// that end in `.csv` are read as CSV files with a header row, where the first column
+/// is the key and the second column is the value, written into the code as it is.
+/// Any other file is read as a list of keys, one per line, and each key's value is
+/// its index in the list. Lines that start with `#` are comments.
+///
+/// The code is an expression of type `Graph<'static, 'static, Input, Option<V>>`,
+/// where `V` is [`BuildOptions::value_type`] and `Input` is `&'static str`, or
+/// `CaseInsensitive<&'static str>` if the keys are case-insensitive. It is written to
+/// [`BuildOptions::file_name`] in `OUT_DIR`, and the path that it was written to is
+/// returned. Cargo is told to run the build script again if the file changes.
+///
+/// The code starts with a header that records a hash of the file and the options, as
+/// written by [`write_input_hash`]. If the code in `OUT_DIR` already has the same
+/// hash, it isn't written again, so a large graph isn't regenerated and the crate that
+/// includes it isn't recompiled when nothing has changed.
+///
+/// [`BuildOptions::manifest`] also writes a JSON manifest with the same name and a
+/// `.json` extension, with the size of the graph and the options that it was built
+/// with, for build tooling that tracks it without parsing the code.
+///
+/// With the `dot` feature, [`BuildOptions::dot`] also writes a Graphviz file with the
+/// same name and a `.dot` extension, so changes to the graph can be reviewed visually.
+///
+/// ```no_run
+/// // build.rs
+/// use intern_str_codegen::{build_from_file, BuildOptions};
+///
+/// build_from_file("keywords.txt", &BuildOptions::new()).unwrap();
+/// ```
+///
+/// ```ignore
+/// // src/lib.rs
+/// const KEYWORDS: intern_str::Graph<'static, 'static, &'static str, Option<usize>> =
+///     include!(concat!(env!("OUT_DIR"), "/keywords.rs"));
+/// ```
+pub fn build_from_file(
+    path: impl AsRef<Path>,
+    options: &BuildOptions,
+) -> Result<PathBuf, BuildError> {
+    let path = path.as_ref();
+    println!("cargo:rerun-if-changed={}", path.display());
+
+    let out_dir = env::var_os("OUT_DIR").ok_or(BuildError::NoOutDir)?;
+    let file_name = match &options.file_name {
+        Some(name) => PathBuf::from(name),
+        None => {
+            Path::new(path.file_stem().unwrap_or_else(|| OsStr::new("graph"))).with_extension("rs")
+        }
+    };
+
+    let out = Path::new(&out_dir).join(file_name);
+    let data = fs::read(path).map_err(BuildError::Io)?;
+    let hash = input_hash(&data, options);
+    let current =
+        is_up_to_date(&out, hash) && (!options.manifest || out.with_extension("json").exists());
+    #[cfg(feature = "dot")]
+    let current = current && (!options.dot || out.with_extension("dot").exists());
+    if current {
+        return Ok(out);
+    }
+
+    if options.case_insensitive {
+        let builder = load::<IgnoreCase<Utf8Graph>>(path, &data, options)?;
+        generate(
+            builder,
+            "intern_str::CaseInsensitive<&'static str>",
+            path,
+            &out,
+            hash,
+            options,
+        )?;
+    } else {
+        let builder = load::<Utf8Graph>(path, &data, options)?;
+        generate(builder, "&'static str", path, &out, hash, options)?;
+    }
+
+    Ok(out)
+}
+
+/// Tell if the code at `path` starts with the header for `hash`.
+///
+/// This only reads the first line of the file, so it is cheap to call even for a large
+/// graph. Returns `false` if the file doesn't exist or can't be read.
+///
+/// ```no_run
+/// use core::hash::Hasher;
+/// use intern_str_codegen::{is_up_to_date, InputHasher};
+///
+/// let mut hasher = InputHasher::new();
+/// hasher.write(&std::fs::read("keywords.txt").unwrap());
+///
+/// if !is_up_to_date("keywords.rs", hasher.finish()) {
+///     // Generate the code, starting with `write_input_hash`.
+/// }
+/// ```
+pub fn is_up_to_date(path: impl AsRef<Path>, hash: u64) -> bool {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return false,
+    };
+
+    let mut line = String::new();
+    if BufReader::new(file).read_line(&mut line).is_err() {
+        return false;
+    }
+
+    read_input_hash(&line) == Some(hash)
+}
+
+/// Hash the data in a file, and the options that it is generated with.
+fn input_hash(data: &[u8], options: &BuildOptions) -> u64 {
+    let mut hasher = InputHasher::new();
+    hasher.write(data);
+    hasher.write(format!("{:?}", options).as_bytes());
+
+    // A new version may generate different code from the same input.
+    hasher.write(env!("CARGO_PKG_VERSION").as_bytes());
+    hasher.finish()
+}
+
+/// Load the keys and values from the data in a file into a builder.
+fn load<Type>(
+    path: &Path,
+    data: &[u8],
+    options: &BuildOptions,
+) -> Result<Builder<Expr, Type>, BuildError>
+where
+    Type: for<'a> GraphType<'a>,
+{
+    let mut builder = Builder::new();
+
+    if path.extension() == Some(OsStr::new("csv")) {
+        builder
+            .add_csv(data, Column::Index(0), Column::Index(1), |value| {
+                Ok::<_, String>(Expr::new(value))
+            })
+            .map_err(|err| BuildError::Load(Box::new(err)))?;
+    } else {
+        let mut index = 0;
+        builder
+            .add_lines(data, options.lines, |_, _| {
+                index += 1;
+                Expr::new((index - 1).to_string())
+            })
+            .map_err(|err| BuildError::Load(Box::new(err)))?;
+    }
+
+    Ok(builder)
+}
+
+/// Generate the code for the graph in a builder, and write it to `out` along with the
+/// manifest.
+fn generate<Type>(
+    builder: Builder<Expr, Type>,
+    input_type: &str,
+    path: &Path,
+    out: &Path,
+    hash: u64,
+    options: &BuildOptions,
+) -> Result<(), BuildError>
+where
+    Type: for<'a> GraphType<'a>,
+    for<'a> <Type as GraphType<'a>>::InputKey: BuildKey,
+{
+    // The stats have to be taken before the builder is used up.
+    let stats = if options.manifest {
+        Some(builder.stats())
+    } else {
+        None
+    };
+
+    let code = generate_code(builder, input_type, out, hash, options)?;
+    fs::write(out, &code).map_err(BuildError::Io)?;
+
+    if let Some(stats) = stats {
+        let manifest = manifest(path, out, hash, &stats, code.len(), options);
+        fs::write(out.with_extension("json"), manifest).map_err(BuildError::Io)?;
+    }
+
+    Ok(())
+}
+
+/// Generate the code for the graph in a builder, and the Graphviz file next to `out`.
+#[cfg_attr(not(feature = "dot"), allow(unused_variables))]
+fn generate_code<Type>(
+    mut builder: Builder<Expr, Type>,
+    input_type: &str,
+    out: &Path,
+    hash: u64,
+    options: &BuildOptions,
+) -> Result<String, BuildError>
+where
+    Type: for<'a> GraphType<'a>,
+    for<'a> <Type as GraphType<'a>>::InputKey: BuildKey,
+{
+    let output_type = format!("Option<{}>", options.value_type);
+    let write_output = |f: &mut dyn Write, value: Option<&Expr>| match value {
+        Some(value) => write!(f, "Some({})", value),
+        None => write!(f, "None"),
+    };
+    let mut code = String::new();
+    write_input_hash(&mut code, hash).ok();
+
+    // The graph needs to be kept around to draw it, so it can't be streamed.
+    #[cfg(feature = "dot")]
+    {
+        if options.dot {
+            let mut buffer = Vec::new();
+            let graph = builder.build(&mut buffer);
+            options
+                .codegen
+                .generate_into(&mut code, &graph, input_type, &output_type, |f, value| {
+                    write_output(f, value.as_ref())
+                })
+                .ok();
+
+            let mut dot = Vec::new();
+            intern_str_visualize::as_graphviz(&graph, &mut dot, "G").map_err(BuildError::Io)?;
+            fs::write(out.with_extension("dot"), dot).map_err(BuildError::Io)?;
+            return Ok(code);
+        }
+    }
+
+    options
+        .codegen
+        .generate_streaming(
+            &mut builder,
+            &mut code,
+            input_type,
+            &output_type,
+            |f, value| write_output(f, *value),
+        )
+        .ok();
+    Ok(code)
+}
+
+/// Write the JSON manifest for the code generated from `path` into `out`.
+fn manifest(
+    path: &Path,
+    out: &Path,
+    hash: u64,
+    stats: &Stats,
+    code_size: usize,
+    options: &BuildOptions,
+) -> String {
+    let codegen = &options.codegen;
+    let mut manifest = String::new();
+
+    // The hash is written as a string, since JSON numbers can't hold every `u64`.
+    writeln!(manifest, "{{").ok();
+    writeln!(
+        manifest,
+        "  \"source\": {},",
+        JsonStr(&path.to_string_lossy())
+    )
+    .ok();
+    writeln!(manifest, "  \"code\": {},", JsonStr(&out.to_string_lossy())).ok();
+    writeln!(manifest, "  \"input_hash\": \"0x{:016x}\",", hash).ok();
+    writeln!(manifest, "  \"keys\": {},", stats.keys()).ok();
+    writeln!(manifest, "  \"nodes\": {},", stats.nodes()).ok();
+    writeln!(manifest, "  \"max_depth\": {},", stats.max_depth()).ok();
+    writeln!(manifest, "  \"data_size\": {},", stats.static_size()).ok();
+    writeln!(manifest, "  \"code_size\": {},", code_size).ok();
+    writeln!(manifest, "  \"options\": {{").ok();
+    writeln!(
+        manifest,
+        "    \"case_insensitive\": {},",
+        options.case_insensitive
+    )
+    .ok();
+    writeln!(
+        manifest,
+        "    \"value_type\": {},",
+        JsonStr(&options.value_type)
+    )
+    .ok();
+    writeln!(manifest, "    \"use_static\": {},", codegen.use_static).ok();
+    writeln!(manifest, "    \"compact\": {},", codegen.compact).ok();
+    match codegen.split_nodes {
+        Some(len) => writeln!(manifest, "    \"split_nodes\": {}", len).ok(),
+        None => writeln!(manifest, "    \"split_nodes\": null").ok(),
+    };
+    writeln!(manifest, "  }}").ok();
+    writeln!(manifest, "}}").ok();
+
+    manifest
+}
+
+/// Writes a string as a JSON string literal.
+struct JsonStr<'a>(&'a str);
+
+impl fmt::Display for JsonStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"")?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+/// The keys of a graph that can be generated.
+#[cfg(not(feature = "dot"))]
+trait BuildKey: Key {}
+
+#[cfg(not(feature = "dot"))]
+impl<T: Key> BuildKey for T {}
+
+/// The keys of a graph that can be generated, and drawn with their keys as labels.
+#[cfg(feature = "dot")]
+trait BuildKey: Key + fmt::Display {}
+
+#[cfg(feature = "dot")]
+impl<T: Key + fmt::Display> BuildKey for T {}
+
+/// Options for [`build_from_file`].
+#[derive(Debug, Clone)]
+pub struct BuildOptions {
+    /// Whether keys are matched case-insensitively.
+    case_insensitive: bool,
+
+    /// The type of the values.
+    value_type: String,
+
+    /// The name of the file that the code is written to.
+    file_name: Option<String>,
+
+    /// How the lines of a list of keys are read.
+    lines: LineOptions,
+
+    /// The options for the generated code.
+    codegen: Options,
+
+    /// Whether a JSON manifest is written next to the code.
+    manifest: bool,
+
+    /// Whether a Graphviz file is written next to the code.
+    #[cfg(feature = "dot")]
+    dot: bool,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BuildOptions {
+    /// Create the default options.
+    pub fn new() -> Self {
+        BuildOptions {
+            case_insensitive: false,
+            value_type: "usize".to_string(),
+            file_name: None,
+            lines: LineOptions::new().comment("#"),
+            codegen: Options::new(),
+            manifest: false,
+            #[cfg(feature = "dot")]
+            dot: false,
+        }
+    }
+
+    /// Match keys case-insensitively.
+    ///
+    /// By default, this is `false`.
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Set the type of the values.
+    ///
+    /// The values in a CSV file must be expressions of this type. By default, this is
+    /// `usize`, which is the type of the values in a list of keys.
+    pub fn value_type(mut self, value_type: impl Into<String>) -> Self {
+        self.value_type = value_type.into();
+        self
+    }
+
+    /// Set the name of the file in `OUT_DIR` that the code is written to.
+    ///
+    /// By default, this is the name of the input file with an `.rs` extension.
+    pub fn file_name(mut self, file_name: impl Into<String>) -> Self {
+        self.file_name = Some(file_name.into());
+        self
+    }
+
+    /// Set how the lines of a list of keys are read.
+    ///
+    /// By default, blank lines and lines that start with `#` are skipped.
+    pub fn lines(mut self, lines: LineOptions) -> Self {
+        self.lines = lines;
+        self
+    }
+
+    /// Set the options for the generated code.
+    pub fn codegen(mut self, codegen: Options) -> Self {
+        self.codegen = codegen;
+        self
+    }
+
+    /// Write a JSON manifest next to the code, with the same name and a `.json`
+    /// extension.
+    ///
+    /// The manifest describes the generated graph for build tooling, such as a check in
+    /// CI that its size stays within a budget:
+    ///
+    /// ```json
+    /// {
+    ///   "source": "keywords.txt",
+    ///   "code": "target/debug/build/.../out/keywords.rs",
+    ///   "input_hash": "0x1f2e3d4c5b6a7988",
+    ///   "keys": 3,
+    ///   "nodes": 6,
+    ///   "max_depth": 2,
+    ///   "data_size": 312,
+    ///   "code_size": 1024,
+    ///   "options": {
+    ///     "case_insensitive": false,
+    ///     "value_type": "usize",
+    ///     "use_static": false,
+    ///     "compact": false,
+    ///     "split_nodes": null
+    ///   }
+    /// }
+    /// ```
+    ///
+    /// `data_size` is the estimated size of the graph's static data in bytes, as given
+    /// by `Builder::stats`, and `code_size` is the size of the code in bytes.
+    ///
+    /// By default, this is `false`.
+    pub fn manifest(mut self, manifest: bool) -> Self {
+        self.manifest = manifest;
+        self
+    }
+
+    /// Write a Graphviz file next to the code, with the same name and a `.dot`
+    /// extension. Requires the `dot` feature.
+    ///
+    /// By default, this is `false`.
+    #[cfg(feature = "dot")]
+    pub fn dot(mut self, dot: bool) -> Self {
+        self.dot = dot;
+        self
+    }
+}
+
+/// An error that occurs in [`build_from_file`].
+#[derive(Debug)]
+pub enum BuildError {
+    /// `OUT_DIR` isn't set, which means that this isn't running in a build script.
+    NoOutDir,
+
+    /// The file couldn't be read, or the code couldn't be written.
+    Io(io::Error),
+
+    /// The keys and values couldn't be loaded from the file.
+    Load(Box<dyn Error + Send + Sync>),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::NoOutDir => write!(f, "OUT_DIR is not set"),
+            BuildError::Io(err) => write!(f, "{}", err),
+            BuildError::Load(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for BuildError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BuildError::NoOutDir => None,
+            BuildError::Io(err) => Some(err),
+            BuildError::Load(err) => Some(&**err),
+        }
+    }
+}
@@ -0,0 +1,267 @@
+//! Generating a graph as C code.
+
+use intern_str::{ByteKey, CaseInsensitive, Graph};
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::{write, writeln};
+
+/// Generate a C header with the tables for a graph and a function that looks keys up
+/// in it.
+///
+/// This lets code written in C share the exact same table as the Rust side, such as
+/// firmware components that are built with a C toolchain. The header defines, with
+/// every name starting with `name`:
+///
+/// ```c
+/// static OUTPUT name_lookup(const unsigned char *input, size_t len);
+/// ```
+///
+/// which gives the same output for the same bytes as `Graph::process`. `output_type`
+/// is the C type of the outputs, and `write_output` writes the C expression for each
+/// output. Everything in the header is `static`, so it should be included by a single
+/// source file, which can wrap the lookup in a function of its own to share it. The
+/// code needs C99.
+///
+/// ```
+/// use intern_str::builder::{Builder, Utf8Graph};
+/// use intern_str_codegen::generate_c;
+///
+/// let mut builder = Builder::<_, Utf8Graph>::new();
+/// builder.add("get", 1).unwrap();
+/// builder.add("set", 2).unwrap();
+///
+/// let mut buffer = Vec::new();
+/// let graph = builder.build(&mut buffer);
+/// let code = generate_c(&graph, "command", "int", |f, out| match out {
+///     Some(out) => write!(f, "{}", out),
+///     None => write!(f, "-1"),
+/// });
+///
+/// assert!(code.contains("static int command_lookup(const unsigned char *input, size_t len)"));
+/// ```
+pub fn generate_c<'a, Input: CKey<'a>, Output>(
+    graph: &Graph<'_, '_, Input, Output>,
+    name: &str,
+    output_type: &str,
+    mut write_output: impl FnMut(&mut dyn Write, &Output) -> fmt::Result,
+) -> String {
+    let mut out = String::new();
+    let guard = name.to_ascii_uppercase();
+
+    writeln!(out, "/* Generated by intern-str-codegen. */").ok();
+    writeln!(out, "#ifndef {}_H", guard).ok();
+    writeln!(out, "#define {}_H", guard).ok();
+    writeln!(out).ok();
+    writeln!(out, "#include <stddef.h>").ok();
+    writeln!(out).ok();
+
+    // Write the types.
+    writeln!(out, "typedef struct {{").ok();
+    writeln!(out, "    size_t key;").ok();
+    writeln!(out, "    size_t key_len;").ok();
+    writeln!(out, "    size_t next;").ok();
+    writeln!(out, "}} {}_edge_t;", name).ok();
+    writeln!(out).ok();
+    writeln!(out, "typedef struct {{").ok();
+    writeln!(out, "    size_t first_edge;").ok();
+    writeln!(out, "    size_t edges;").ok();
+    writeln!(out, "    {} output;", output_type).ok();
+    writeln!(out, "    size_t default_node;").ok();
+    writeln!(out, "    size_t amount;").ok();
+    writeln!(out, "}} {}_node_t;", name).ok();
+    writeln!(out).ok();
+
+    // Lay out the keys of every edge in one array.
+    let mut keys = Vec::new();
+    let mut edges = Vec::new();
+    for node in graph.nodes() {
+        for (input, next) in node.inputs() {
+            let key = input.to_bytes();
+            edges.push((keys.len(), key.len(), *next));
+            keys.extend_from_slice(key);
+        }
+    }
+
+    // C doesn't allow empty arrays, so there is always at least one item.
+    writeln!(out, "static const unsigned char {}_keys[] = {{", name).ok();
+    if keys.is_empty() {
+        keys.push(0);
+    }
+    for row in keys.chunks(16) {
+        write!(out, "   ").ok();
+        for byte in row {
+            write!(out, " 0x{:02x},", byte).ok();
+        }
+        writeln!(out).ok();
+    }
+    writeln!(out, "}};").ok();
+    writeln!(out).ok();
+
+    writeln!(out, "static const {}_edge_t {}_edges[] = {{", name, name).ok();
+    if edges.is_empty() {
+        edges.push((0, 0, 0));
+    }
+    for (key, key_len, next) in edges {
+        writeln!(out, "    {{ {}, {}, {} }},", key, key_len, next).ok();
+    }
+    writeln!(out, "}};").ok();
+    writeln!(out).ok();
+
+    writeln!(out, "static const {}_node_t {}_nodes[] = {{", name, name).ok();
+    let mut first_edge = 0;
+    for node in graph.nodes() {
+        write!(out, "    {{ {}, {}, ", first_edge, node.inputs().len()).ok();
+        write_output(&mut out, node.output()).ok();
+        writeln!(out, ", {}, {} }},", node.default(), Size(node.amount())).ok();
+        first_edge += node.inputs().len();
+    }
+    writeln!(out, "}};").ok();
+    writeln!(out).ok();
+
+    // Write the comparison, which has to order keys the same way as `Ord`.
+    writeln!(
+        out,
+        "static int {}_compare(const unsigned char *key, size_t key_len, const unsigned char *chunk, size_t chunk_len) {{",
+        name
+    )
+    .ok();
+    writeln!(
+        out,
+        "    size_t common = key_len < chunk_len ? key_len : chunk_len;"
+    )
+    .ok();
+    writeln!(out, "    size_t i;").ok();
+    writeln!(out, "    for (i = 0; i < common; i++) {{").ok();
+    writeln!(out, "        unsigned char a = key[i];").ok();
+    writeln!(out, "        unsigned char b = chunk[i];").ok();
+    if Input::CASE_INSENSITIVE {
+        writeln!(out, "        if (a >= 'A' && a <= 'Z') a += 'a' - 'A';").ok();
+        writeln!(out, "        if (b >= 'A' && b <= 'Z') b += 'a' - 'A';").ok();
+    }
+    writeln!(out, "        if (a != b) return a < b ? -1 : 1;").ok();
+    writeln!(out, "    }}").ok();
+    writeln!(
+        out,
+        "    return key_len == chunk_len ? 0 : key_len < chunk_len ? -1 : 1;"
+    )
+    .ok();
+    writeln!(out, "}}").ok();
+    writeln!(out).ok();
+
+    // Write the lookup, which walks the graph like `Graph::process`.
+    writeln!(
+        out,
+        "static {} {}_lookup(const unsigned char *input, size_t len) {{",
+        output_type, name
+    )
+    .ok();
+    writeln!(
+        out,
+        "    const {}_node_t *node = &{}_nodes[{}];",
+        name,
+        name,
+        graph.start()
+    )
+    .ok();
+    writeln!(
+        out,
+        "    if (len < {} || len > {}) return {}_nodes[node->default_node].output;",
+        graph.min_key_len(),
+        Size(graph.max_key_len()),
+        name
+    )
+    .ok();
+    writeln!(out, "    for (;;) {{").ok();
+    writeln!(out, "        size_t at = node->amount;").ok();
+    writeln!(out, "        size_t next = node->default_node;").ok();
+    writeln!(out, "        size_t lo = node->first_edge;").ok();
+    writeln!(out, "        size_t hi = lo + node->edges;").ok();
+    writeln!(out, "        if (at > len) {{").ok();
+    writeln!(
+        out,
+        "            if (len == 0 || node->edges == 0) return node->output;"
+    )
+    .ok();
+    writeln!(
+        out,
+        "            return {}_nodes[node->default_node].output;",
+        name
+    )
+    .ok();
+    writeln!(out, "        }}").ok();
+    if Input::UTF8 {
+        writeln!(
+            out,
+            "        while (at < len && (input[at] & 0xc0) == 0x80) at++;"
+        )
+        .ok();
+    }
+    writeln!(out, "        while (lo < hi) {{").ok();
+    writeln!(out, "            size_t mid = lo + (hi - lo) / 2;").ok();
+    writeln!(
+        out,
+        "            const {}_edge_t *edge = &{}_edges[mid];",
+        name, name
+    )
+    .ok();
+    writeln!(
+        out,
+        "            int order = {}_compare(&{}_keys[edge->key], edge->key_len, input, at);",
+        name, name
+    )
+    .ok();
+    writeln!(out, "            if (order == 0) {{").ok();
+    writeln!(out, "                next = edge->next;").ok();
+    writeln!(out, "                break;").ok();
+    writeln!(out, "            }}").ok();
+    writeln!(out, "            if (order < 0) lo = mid + 1;").ok();
+    writeln!(out, "            else hi = mid;").ok();
+    writeln!(out, "        }}").ok();
+    writeln!(out, "        node = &{}_nodes[next];", name).ok();
+    writeln!(out, "        input += at;").ok();
+    writeln!(out, "        len -= at;").ok();
+    writeln!(out, "    }}").ok();
+    writeln!(out, "}}").ok();
+    writeln!(out).ok();
+    writeln!(out, "#endif").ok();
+
+    out
+}
+
+/// A key that the C code from [`generate_c`] can match.
+///
+/// The C code works on bytes, so this describes how the key's type compares and
+/// splits them.
+pub trait CKey<'a>: ByteKey<'a> {
+    /// Whether ASCII letters are compared without regard to case.
+    const CASE_INSENSITIVE: bool = false;
+
+    /// Whether the key is never split inside of a UTF-8 character.
+    const UTF8: bool = false;
+}
+
+impl<'a> CKey<'a> for &'a str {
+    const UTF8: bool = true;
+}
+
+impl<'a> CKey<'a> for &'a [u8] {}
+
+impl<'a, T: CKey<'a> + AsRef<[u8]>> CKey<'a> for CaseInsensitive<T> {
+    const CASE_INSENSITIVE: bool = true;
+    const UTF8: bool = T::UTF8;
+}
+
+/// Writes a `size_t`, which may be `SIZE_MAX`.
+struct Size(usize);
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 == core::usize::MAX {
+            f.write_str("(size_t)-1")
+        } else {
+            fmt::Display::fmt(&self.0, f)
+        }
+    }
+}
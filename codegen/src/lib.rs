@@ -25,18 +25,59 @@
 //! let code = generate(
 //!     &graph,
 //!     "&'static str",
-//!     "usize",
-//!     |f, out| write!(f, "{}", out),
+//!     "Option<usize>",
+//!     |f, out| write!(f, "{:?}", out),
 //! );
 //!
 //! let mut out = BufWriter::new(File::create("graph.rs").unwrap());
 //! writeln!(
 //!     out,
-//!     "const GRAPH: intern_str::Graph<'static, 'static, &'static str, usize> = {}",
+//!     "const GRAPH: intern_str::Graph<'static, 'static, &'static str, Option<usize>> = {}",
 //!     code,
 //! )?;
 //! # Ok(()) }
 //! ```
+//!
+//! With the `build` feature, `build_from_file` does all of this in one call, for keys
+//! that are listed in a text or CSV file. For firmware and other code written in C,
+//! [`generate_c`] writes the same graph as a C header.
+//!
+//! ## Outputs
+//!
+//! `write_output` writes the code for each output, so outputs don't need to be
+//! literals. Any constant expression of the output type will do:
+//!
+//! - Function pointers, such as `Some(commands::get)`, for a command-dispatch table
+//!   with the output type `Option<fn(&[&str])>`. Function items are coerced to the
+//!   pointer type of the graph.
+//! - References to `static` or `const` items, such as `Some(&PAYLOADS[3])`, so that
+//!   large outputs aren't copied into every node.
+//! - Calls to `const fn`s, enum variants and struct literals.
+//!
+//! The values in the builder don't have to be the outputs themselves. A function
+//! pointer can't be turned back into the path to the function, so build the graph with
+//! an [`Expr`] holding that path instead:
+//!
+//! ```
+//! use intern_str::builder::{Builder, Utf8Graph};
+//! use intern_str_codegen::{generate, Expr};
+//!
+//! let mut builder = Builder::<_, Utf8Graph>::new();
+//! for command in &["add", "commit", "push"] {
+//!     builder.add(*command, Expr::new(format!("commands::{}", command))).unwrap();
+//! }
+//!
+//! let mut buffer = Vec::new();
+//! let graph = builder.build(&mut buffer);
+//! let code = generate(
+//!     &graph,
+//!     "&'static str",
+//!     "Option<fn(&[&str]) -> Result<(), Error>>",
+//!     |f, out| write!(f, "{:?}", out),
+//! );
+//!
+//! assert!(code.contains("Some(commands::commit),"));
+//! ```
 
 #![no_std]
 #![forbid(
@@ -53,123 +94,2562 @@
 )]
 
 extern crate alloc;
+#[cfg(feature = "build")]
+extern crate std;
 
+use alloc::collections::BTreeMap;
 use alloc::string::String;
+use alloc::vec::Vec;
+use core::cmp;
 use core::fmt::{self, Write};
+use core::hash::Hasher;
 use core::{write, writeln};
 
-use intern_str::{CaseInsensitive, Graph, Segmentable};
+use intern_str::{
+    ByteFold, ByteKey, CaseInsensitive, Chars, Flat, Fnv, Folded, Graph, Map, Node, Packed,
+    Segmentable, SeparatorInsensitive, Separators,
+};
+
+#[cfg(feature = "builder")]
+use intern_str::builder::{AddError, Builder, GraphType};
+
+pub mod output;
+
+mod c;
+pub use c::{generate_c, CKey};
+
+#[cfg(feature = "build")]
+mod build;
+#[cfg(feature = "build")]
+pub use build::{build_from_file, is_up_to_date, BuildError, BuildOptions};
+
+#[cfg(feature = "tokens")]
+use alloc::string::ToString;
+#[cfg(feature = "tokens")]
+use proc_macro2::{Literal, TokenStream};
+#[cfg(feature = "tokens")]
+use quote::{quote, ToTokens};
 
 /// The whole point.
 ///
 /// See the crate documentation for more information.
+///
+/// Large outputs don't need to be copied into every node. Instead, the output type
+/// can be a reference such as `Option<&'static Payload>`, with `write_output`
+/// writing out a reference to a `static` (e.g. `Some(&PAYLOADS[3])`).
 pub fn generate<Input: Key, Output>(
     graph: &Graph<'_, '_, Input, Output>,
     input_type: &str,
     output_type: &str,
-    mut write_output: impl FnMut(&mut dyn Write, &Output) -> fmt::Result,
+    write_output: impl FnMut(&mut dyn Write, &Output) -> fmt::Result,
+) -> String {
+    Options::new().generate(graph, input_type, output_type, write_output)
+}
+
+/// Write the same code as [`generate`] into `out`, instead of returning it.
+///
+/// The code for a large graph can run to hundreds of megabytes, so writing it straight
+/// into a file keeps it from being held in memory alongside the graph. `out` can be a
+/// `String` or an adapter around a file.
+pub fn generate_into<Input: Key, Output>(
+    out: &mut dyn Write,
+    graph: &Graph<'_, '_, Input, Output>,
+    input_type: &str,
+    output_type: &str,
+    write_output: impl FnMut(&mut dyn Write, &Output) -> fmt::Result,
+) -> fmt::Result {
+    Options::new().generate_into(out, graph, input_type, output_type, write_output)
+}
+
+/// Generate the same code as [`generate`], with `write_input` writing each key.
+///
+/// This is for input types that don't implement [`Key`], such as a newtype around a
+/// string from another crate, which can't implement it because of the orphan rules.
+/// `write_input` writes the expression for a key, mirroring `write_output`. Types
+/// from your own crate can implement [`Key`] instead, which works with every function
+/// in this crate.
+///
+/// ```
+/// use intern_str::{Graph, Node};
+/// use intern_str_codegen::generate_with_input;
+///
+/// const NODES: &[Node<'static, &'static str, Option<u8>>] = &[
+///     Node::new(&[], None, 0, core::usize::MAX),
+///     Node::new(&[], Some(1), 0, 2),
+///     Node::new(&[("hi", 1)], None, 0, 2),
+/// ];
+/// let graph = Graph::new(NODES, 2);
+///
+/// let code = generate_with_input(
+///     &graph,
+///     "Token<'static>",
+///     "Option<u8>",
+///     |f, key| write!(f, "Token({:?})", key),
+///     |f, out| write!(f, "{:?}", out),
+/// );
+///
+/// assert!(code.contains("(Token(\"hi\"), 1),"));
+/// ```
+pub fn generate_with_input<Input: Segmentable, Output>(
+    graph: &Graph<'_, '_, Input, Output>,
+    input_type: &str,
+    output_type: &str,
+    write_input: impl FnMut(&mut dyn Write, &Input) -> fmt::Result,
+    write_output: impl FnMut(&mut dyn Write, &Output) -> fmt::Result,
+) -> String {
+    Options::new().generate_with_input(graph, input_type, output_type, write_input, write_output)
+}
+
+/// Generate the code for a [`Map`].
+///
+/// Each output is written once into an `OUTPUTS` table, and the nodes refer to
+/// their outputs by index. This avoids duplicating large outputs into the nodes.
+/// If the map was built after calling `Builder::dedup_outputs`, equal outputs are
+/// only written once as well.
+pub fn generate_map<Input: Key, Output>(
+    map: &Map<'_, '_, '_, Input, Output>,
+    input_type: &str,
+    output_type: &str,
+    write_output: impl FnMut(&mut dyn Write, &Output) -> fmt::Result,
 ) -> String {
-    let mut out = String::new();
+    Options::new().generate_map(map, input_type, output_type, write_output)
+}
+
+/// Write the same code as [`generate_map`] into `out`, instead of returning it.
+///
+/// See [`generate_into`].
+pub fn generate_map_into<Input: Key, Output>(
+    out: &mut dyn Write,
+    map: &Map<'_, '_, '_, Input, Output>,
+    input_type: &str,
+    output_type: &str,
+    write_output: impl FnMut(&mut dyn Write, &Output) -> fmt::Result,
+) -> fmt::Result {
+    Options::new().generate_map_into(out, map, input_type, output_type, write_output)
+}
+
+/// Generate the code for a [`Map`] as a [`Packed`] graph.
+///
+/// The nodes are packed into a fixed-size byte array, and the outputs are written
+/// once into an `OUTPUTS` table, as in [`generate_map`]. A byte array is much quicker
+/// for `rustc` to compile than a table of nodes, and doesn't need to be relocated when
+/// the program is loaded. The code is an expression of type `Packed<'static, Input,
+/// Output>`, where `Input` is the same type as `input_type`.
+///
+/// # Panics
+///
+/// Panics if the packed nodes would be larger than `u32::MAX` bytes.
+pub fn generate_packed<'a, Input: ByteKey<'a>, Output: 'a>(
+    map: &Map<'_, '_, '_, Input, Output>,
+    input_type: &str,
+    output_type: &str,
+    write_output: impl FnMut(&mut dyn Write, &Output) -> fmt::Result,
+) -> String {
+    Options::new().generate_packed(map, input_type, output_type, write_output)
+}
 
-    writeln!(out, "{{").ok();
+/// Generate the code for a graph as a [`Flat`] graph.
+///
+/// The nodes and transitions are written as flat tables of `u32`s, and the keys as a
+/// single table of bytes, instead of the nested `Node` literals and key slices that
+/// [`generate`] writes. Each distinct output is written once into an `OUTPUTS` table.
+/// This makes the graph much smaller in the binary, which matters most for `wasm`
+/// targets, at the cost of decoding the tables as the graph is walked. The code is an
+/// expression of type `Flat<'static, Input, Output>`, where `Input` is the same type as
+/// `input_type`.
+///
+/// Outputs are the same if `write_output` writes the same code for them.
+///
+/// ```
+/// use intern_str::builder::{Builder, Utf8Graph};
+/// use intern_str_codegen::generate_flat;
+///
+/// let mut builder = Builder::<_, Utf8Graph>::new();
+/// builder.add("hello", 1).unwrap();
+/// builder.add("world", 2).unwrap();
+///
+/// let mut buffer = Vec::new();
+/// let graph = builder.build(&mut buffer);
+/// let code = generate_flat(&graph, "&'static str", "Option<usize>", |f, out| {
+///     write!(f, "{:?}", out)
+/// });
+///
+/// assert!(code.contains("intern_str::Flat::new(&NODES, &EDGES, &KEYS, OUTPUTS)"));
+/// ```
+///
+/// # Panics
+///
+/// Panics if any of the tables would have more than `u32::MAX` entries.
+pub fn generate_flat<'a, Input: ByteKey<'a>, Output: 'a>(
+    graph: &Graph<'_, '_, Input, Output>,
+    input_type: &str,
+    output_type: &str,
+    write_output: impl FnMut(&mut dyn Write, &Output) -> fmt::Result,
+) -> String {
+    Options::new().generate_flat(graph, input_type, output_type, write_output)
+}
 
-    // Write the nodes.
-    writeln!(
-        out,
-        "{}const NODES: &[intern_str::Node<'static, {}, {}>] = &[",
-        Indent(4),
+/// Serialize a graph into `blob`, and generate a function that loads it.
+///
+/// The graph is written in the format of `intern_str::serialize`, to be saved to a
+/// file at `blob_path`. The code is a function named `name` that includes the file
+/// with `include_bytes!` and reads the graph from it:
+///
+/// ```ignore
+/// pub fn name<'nodes>(nodes: &'nodes mut Vec<intern_str::Node<'static, Input, Output>>)
+///     -> intern_str::Graph<'static, 'nodes, Input, Output>;
+/// ```
+///
+/// For a large graph, this is much quicker to compile than the table of nodes that
+/// [`generate`] writes, at the cost of building the nodes when the program runs.
+/// `write_output` appends the bytes for an output, and `read_output` is the code for
+/// a function or closure that turns those bytes back into the output, with the type
+/// `FnMut(&'static [u8]) -> Option<Output>`. `Vec` must be in scope where the code is
+/// included.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_blob<'a, Input: ByteKey<'a>, Output>(
+    graph: &Graph<'_, '_, Input, Output>,
+    blob: &mut Vec<u8>,
+    blob_path: &str,
+    name: &str,
+    input_type: &str,
+    output_type: &str,
+    write_output: impl FnMut(&Output, &mut Vec<u8>),
+    read_output: &str,
+) -> String {
+    Options::new().generate_blob(
+        graph,
+        blob,
+        blob_path,
+        name,
         input_type,
-        output_type
+        output_type,
+        write_output,
+        read_output,
     )
-    .ok();
+}
 
-    for node in graph.nodes().iter() {
-        writeln!(out, "{}intern_str::Node::new(", Indent(8)).ok();
+/// Generate the code for several graphs that share one set of nodes.
+///
+/// This is meant for graphs built with `intern_str::builder::build_shared`. Unlike
+/// [`generate`], this emits items rather than an expression: a private `NODES` constant,
+/// followed by a `pub const` for each graph with the given name. Include the code in
+/// its own module to avoid name collisions.
+///
+/// # Panics
+///
+/// Panics if the graphs don't all refer to the same nodes.
+pub fn generate_shared<Input: Key, Output>(
+    graphs: &[(&str, Graph<'_, '_, Input, Output>)],
+    input_type: &str,
+    output_type: &str,
+    write_output: impl FnMut(&mut dyn Write, &Output) -> fmt::Result,
+) -> String {
+    Options::new().generate_shared(graphs, input_type, output_type, write_output)
+}
 
-        writeln!(out, "{}&[", Indent(12)).ok();
+/// Generate an item for each variant of a graph, each enabled by a feature.
+///
+/// A crate with a lot of data can offer trade-offs between size and behavior, such as
+/// a smaller set of keys or case-insensitive matching, by generating several variants
+/// of a graph into the same file. Each variant is a `pub const` named `name`, enabled
+/// by its [`Variant::feature`] of the crate that includes the code. If several of the
+/// features are enabled, the first of their variants is used. The variant without a
+/// feature is used when none of them are enabled.
+///
+/// # Panics
+///
+/// Panics if more than one variant doesn't have a feature.
+pub fn generate_variants(name: &str, variants: &[Variant<'_>]) -> String {
+    Options::new().generate_variants(name, variants)
+}
 
-        for (input, next) in node.inputs() {
-            writeln!(
-                out,
-                "{}({}, {}),",
-                Indent(16),
-                WriteKey(input),
-                next
-            )
-            .ok();
-        }
+/// Generate the code for a graph while it is being built.
+///
+/// This writes the same code as [`generate`] into `out`, but each node is written as
+/// soon as the builder adds it, using [`Builder::build_streaming`]. Neither the nodes
+/// nor the code are ever held in memory all at once, which keeps the memory use of a
+/// build script that generates a very large graph down. `out` can be a `String` or an
+/// adapter around a file.
+///
+/// The nodes refer to the values in the builder, so `write_output` is given an
+/// `Option<&T>`. Values that were added with `Builder::add_with_meta` have their
+/// metadata written as a comment above them.
+#[cfg(feature = "builder")]
+pub fn generate_streaming<'a, T, Type>(
+    builder: &'a mut Builder<T, Type>,
+    out: &mut dyn Write,
+    input_type: &str,
+    output_type: &str,
+    write_output: impl FnMut(&mut dyn Write, &Option<&'a T>) -> fmt::Result,
+) -> fmt::Result
+where
+    Type: GraphType<'a>,
+    Type::InputKey: Key,
+{
+    Options::new().generate_streaming(builder, out, input_type, output_type, write_output)
+}
+
+/// Generate an enum with a variant for each key, and a graph that maps the keys to it.
+///
+/// This is for sets of strings that stand for symbolic constants, such as the methods
+/// of a protocol. The code is an enum named `name`, with a variant for each of `keys`
+/// named by `naming`, along with:
+///
+/// - A `GRAPH` constant of type `Graph<'static, 'static, Input, Option<Name>>` that
+///   maps each key to its variant, where `Input` is `input_type`.
+/// - An `as_str` method that gives the key for a variant, as it was passed in.
+///
+/// The keys are matched as `Type` matches them, so they can be case-insensitive, but
+/// the graph keeps the casing that they were passed in with. Returns an error if a key is empty, or the same as another key.
+///
+/// ```
+/// use intern_str::builder::Utf8Graph;
+/// use intern_str_codegen::{generate_enum, Naming};
+///
+/// let code = generate_enum::<Utf8Graph, _>("Method", "&'static str", &["GET", "POST"], Naming::UpperCamel)
+///     .unwrap();
+///
+/// assert!(code.contains("    Get,\n    Post,\n"));
+/// ```
+#[cfg(feature = "builder")]
+pub fn generate_enum<Type, K: AsRef<str>>(
+    name: &str,
+    input_type: &str,
+    keys: impl IntoIterator<Item = K>,
+    naming: Naming,
+) -> Result<String, AddError<usize>>
+where
+    Type: for<'k> GraphType<'k>,
+    for<'k> <Type as GraphType<'k>>::InputKey: Key,
+{
+    Options::new().generate_enum::<Type, K>(name, input_type, keys, naming)
+}
 
-        writeln!(out, "{}],", Indent(12)).ok();
+/// Generate a plain function that does the same lookup as the graph, without it.
+///
+/// The function is named `name`, and compares its input against each key in turn,
+/// returning the output of the first one that is equal. This is useful for comparing
+/// the performance of the graph against the naive approach on a real set of keys, and
+/// for small sets of keys where the graph isn't worth it. The keys are compared as the
+/// graph's input type, so case-insensitive keys are still compared case-insensitively.
+/// They are written with the casing kept by [`Builder::preserve_case`], if any.
+///
+/// Inputs that aren't keys give `write_output(&None)`. Wildcards aren't included, so
+/// the function only matches the same inputs as the graph if there aren't any. This
+/// has to be called before the builder is built, since building it moves the values
+/// out of it.
+#[cfg(feature = "builder")]
+pub fn generate_fallback<T, Type>(
+    out: &mut dyn Write,
+    builder: &Builder<T, Type>,
+    name: &str,
+    input_type: &str,
+    output_type: &str,
+    write_output: impl FnMut(&mut dyn Write, &Option<&T>) -> fmt::Result,
+) -> fmt::Result
+where
+    Type: for<'k> GraphType<'k>,
+    for<'k> <Type as GraphType<'k>>::InputKey: Key,
+{
+    Options::new().generate_fallback(out, builder, name, input_type, output_type, write_output)
+}
 
-        write!(out, "{}", Indent(12)).ok();
-        write_output(&mut out, node.output()).ok();
-        writeln!(out, ",").ok();
+/// Generate a function that picks a graph by the length of its input.
+///
+/// The keys are grouped by their length, and each group gets a graph of its own. The
+/// function is named `name`, and matches on the length of its input before walking the
+/// graph for that length, so an input whose length isn't the length of any key is a
+/// miss without walking a graph at all. For key sets with only a few distinct lengths,
+/// this removes most of the work for misses. The function has the signature:
+///
+/// ```ignore
+/// pub fn name(input: Input) -> &'static Output;
+/// ```
+///
+/// Lengths are counted in the units that the input is split by, and inputs that aren't
+/// keys give `write_output(&None)`. As in [`generate_fallback`], wildcards aren't
+/// included, and this has to be called before the builder is built.
+#[cfg(feature = "builder")]
+pub fn generate_by_length<T, Type>(
+    out: &mut dyn Write,
+    builder: &Builder<T, Type>,
+    name: &str,
+    input_type: &str,
+    output_type: &str,
+    write_output: impl FnMut(&mut dyn Write, &Option<&T>) -> fmt::Result,
+) -> fmt::Result
+where
+    Type: for<'k> GraphType<'k>,
+    for<'k> <Type as GraphType<'k>>::InputKey: Key,
+{
+    Options::new().generate_by_length(out, builder, name, input_type, output_type, write_output)
+}
 
-        writeln!(out, "{}{},", Indent(12), node.default(),).ok();
+/// Generate a table that maps values back to their keys.
+///
+/// Serialization code often needs to turn a value back into the key it was parsed
+/// from. Generating that table from the same builder as the graph keeps the two from
+/// drifting apart. The code is an expression of type `&'static [(T, &'static str)]`,
+/// with an entry for each value in the builder and its canonical key, in the sorted
+/// order of the keys. Values added with aliases appear once, under the key they were
+/// added with. Wildcards and denied keys aren't included. With
+/// [`Builder::preserve_case`], the keys have the casing that they were added with.
+///
+/// This has to be called before the builder is built, since building it moves the
+/// values out of it.
+#[cfg(feature = "builder")]
+pub fn generate_reverse<'a, T, Type: GraphType<'a>>(
+    out: &mut dyn Write,
+    builder: &Builder<T, Type>,
+    output_type: &str,
+    write_output: impl FnMut(&mut dyn Write, &T) -> fmt::Result,
+) -> fmt::Result {
+    Options::new().generate_reverse(out, builder, output_type, write_output)
+}
 
-        writeln!(out, "{}{},", Indent(12), Index(node.amount()),).ok();
+/// Generate a test module that checks a generated graph against the keys it was
+/// generated from.
+///
+/// The module asserts that each of `keys` gives its output from the generated graph
+/// at `path`, and that a handful of inputs made by cutting a unit off of either end
+/// of a key, which aren't keys themselves, give `miss`. This catches mistakes in the
+/// generated code, or in the data it was generated from, when the code is
+/// regenerated.
+///
+/// The expected outputs come from `keys` rather than from a graph, so `keys` should
+/// be every key that the graph was built from. Graphs with wildcards or fallbacks
+/// match inputs that aren't keys, so their tests can expect a miss for an input
+/// that the graph matches.
+///
+/// The code is a `#[cfg(test)] mod tests` item that imports everything from its
+/// parent module, so it should be included next to the graph. `path` is how the graph
+/// is named there, such as `GRAPH`. The output type must implement `PartialEq` and
+/// `Debug`.
+pub fn generate_tests<Input: Key + Clone, Output>(
+    out: &mut dyn Write,
+    path: &str,
+    keys: impl IntoIterator<Item = (Input, Output)>,
+    miss: &Output,
+    write_output: impl FnMut(&mut dyn Write, &Output) -> fmt::Result,
+) -> fmt::Result {
+    Options::new().generate_tests(out, path, keys, miss, write_output)
+}
 
-        writeln!(out, "{}),", Indent(8)).ok();
-    }
+/// Generate the same code as [`generate`], as tokens rather than text.
+///
+/// This is meant for procedural macros and other code that works with tokens, which
+/// can pass the input and output types as tokens as well. `write_output` turns each
+/// output into the expression for it. Requires the `tokens` feature.
+#[cfg(feature = "tokens")]
+pub fn generate_tokens<Input: Key, Output>(
+    graph: &Graph<'_, '_, Input, Output>,
+    input_type: &TokenStream,
+    output_type: &TokenStream,
+    write_output: impl FnMut(&Output) -> TokenStream,
+) -> TokenStream {
+    Options::new().generate_tokens(graph, input_type, output_type, write_output)
+}
 
-    writeln!(out, "{}];", Indent(4)).ok();
+/// The start of the header that records the hash of the input.
+const INPUT_HASH_HEADER: &str = "// intern-str-codegen input hash: ";
 
-    // Write the graph.
-    writeln!(
-        out,
-        "{}const GRAPH: intern_str::Graph<'static, 'static, {}, {}> = intern_str::Graph::new(NODES, {});",
-        Indent(4),
-        input_type,
-        output_type,
-        graph.start(),
-    ).ok();
+/// Write a header that records the hash of the data and options that code was
+/// generated from.
+///
+/// The header is a line comment, so it can be written before any generated code. When
+/// the code is generated again, [`read_input_hash`] reads the hash back, and if it is
+/// the same, the code doesn't need to be written again. Leaving the file alone keeps
+/// its modification time, so `rustc` doesn't recompile the crate that includes it.
+/// The hash can come from an [`InputHasher`].
+///
+/// ```
+/// use intern_str_codegen::{read_input_hash, write_input_hash};
+///
+/// let mut code = String::new();
+/// write_input_hash(&mut code, 0x1234).unwrap();
+/// code.push_str("{ /* the graph */ }");
+///
+/// assert_eq!(read_input_hash(&code), Some(0x1234));
+/// ```
+pub fn write_input_hash(out: &mut dyn Write, hash: u64) -> fmt::Result {
+    writeln!(out, "{}{:#018x}", INPUT_HASH_HEADER, hash)
+}
 
-    writeln!(out, "{}GRAPH", Indent(4)).ok();
+/// Read the hash written by [`write_input_hash`] from the first line of `code`.
+///
+/// Returns `None` if the code doesn't start with the header.
+pub fn read_input_hash(code: &str) -> Option<u64> {
+    let line = code.lines().next()?;
+    if !line.starts_with(INPUT_HASH_HEADER) {
+        return None;
+    }
 
-    writeln!(out, "}}").ok();
+    let hash = line[INPUT_HASH_HEADER.len()..].trim_end();
+    if !hash.starts_with("0x") {
+        return None;
+    }
 
-    out
+    u64::from_str_radix(&hash[2..], 16).ok()
 }
 
-/// An item that can be used as a key.
-pub trait Key: Segmentable {
-    /// Format the key as a Rust expression.
-    fn format(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+/// A hasher for the data and options that code is generated from.
+///
+/// This is a 64-bit FNV-1a hash that writes integers in little-endian order. Unlike
+/// the hasher in `std`, it is the same on every platform and with every version of
+/// Rust, so a hash written by [`write_input_hash`] stays valid.
+///
+/// ```
+/// use core::hash::Hasher;
+/// use intern_str_codegen::InputHasher;
+///
+/// let mut hasher = InputHasher::new();
+/// hasher.write(b"GET\nPOST\n");
+/// hasher.write(b"case-insensitive");
+/// let hash = hasher.finish();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputHasher(Fnv);
+
+impl Default for InputHasher {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl<'a> Key for &'a str {
-    fn format(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "\"{}\"", self)
+impl InputHasher {
+    /// Create a hasher that hasn't hashed anything yet.
+    pub fn new() -> Self {
+        InputHasher(Fnv::default())
     }
 }
 
-impl<'a, T: fmt::Debug + Ord> Key for &'a [T] {
-    fn format(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "&[")?;
+// The integer methods are forwarded too, since the defaults use the native byte order.
+impl Hasher for InputHasher {
+    fn finish(&self) -> u64 {
+        self.0.finish()
+    }
 
-        for (i, item) in self.iter().enumerate() {
-            if i != 0 {
-                write!(f, ", ")?;
-            }
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.write(bytes);
+    }
 
-            write!(f, "{:?}", item)?;
-        }
+    fn write_u16(&mut self, i: u16) {
+        self.0.write_u16(i);
+    }
 
-        write!(f, "]")
+    fn write_u32(&mut self, i: u32) {
+        self.0.write_u32(i);
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.0.write_u64(i);
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.0.write_u128(i);
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.0.write_usize(i);
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.0.write_i16(i);
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.0.write_i32(i);
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.0.write_i64(i);
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.0.write_i128(i);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.0.write_isize(i);
     }
 }
 
-impl<T: AsRef<[u8]> + Key> Key for CaseInsensitive<T> {
-    fn format(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "intern_str::CaseInsensitive({})", WriteKey(&self.0))
+/// Options that change the shape of the generated code.
+///
+/// The free functions in this crate use the default options. Set options with the
+/// builder methods, then call the method with the same name as the function.
+///
+/// ```
+/// use intern_str::builder::{Builder, Utf8Graph};
+/// use intern_str_codegen::Options;
+///
+/// let mut builder = Builder::<_, Utf8Graph>::new();
+/// builder.add("hello", 1).unwrap();
+///
+/// let mut buffer = Vec::new();
+/// let graph = builder.build(&mut buffer);
+///
+/// let code = Options::new()
+///     .use_static(true)
+///     .generate(&graph, "&'static str", "Option<usize>", |f, out| write!(f, "{:?}", out));
+///
+/// assert!(code.contains("static GRAPH"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// Whether the nodes and the graph are written as `static` items.
+    use_static: bool,
+
+    /// Whether each node is written on a single line without indentation.
+    compact: bool,
+
+    /// The summary written as a doc comment on the graph.
+    summary: Option<Summary>,
+
+    /// The attributes written on the generated items.
+    attributes: Vec<String>,
+
+    /// The number of nodes in each part of the nodes, if they are split into parts.
+    split_nodes: Option<usize>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-struct WriteKey<'a, T>(&'a T);
+impl Options {
+    /// Create the default options.
+    pub fn new() -> Self {
+        Options {
+            use_static: false,
+            compact: false,
+            summary: None,
+            attributes: Vec::new(),
+            split_nodes: None,
+        }
+    }
 
-impl<'a, T: Key> fmt::Display for WriteKey<'a, T> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.0.format(f)
+    /// Write the nodes and the graph as `static` items instead of `const` items.
+    ///
+    /// A `const` is copied into every place that uses it, so a large graph that is
+    /// used in several places can end up in the binary several times. A `static` is
+    /// only ever stored once. The input and output types must be `Sync` to be stored
+    /// in a `static`.
+    ///
+    /// By default, this is `false`.
+    pub fn use_static(mut self, use_static: bool) -> Self {
+        self.use_static = use_static;
+        self
+    }
+
+    /// Write each node on a single line, without indentation.
+    ///
+    /// The code for a very large graph can be hundreds of megabytes, most of which is
+    /// whitespace. Compact code is much smaller and faster for `rustc` to parse, at the
+    /// cost of being harder to read. Metadata comments are still written, on their own
+    /// lines above their node.
+    ///
+    /// By default, this is `false`.
+    pub fn compact(mut self, compact: bool) -> Self {
+        self.compact = compact;
+        self
+    }
+
+    /// Write a doc comment with a summary of the graph on the items that hold it.
+    ///
+    /// Code that uses the generated graph otherwise has no way of telling what is in
+    /// it without reading the code that generated it.
+    pub fn summary(mut self, summary: Summary) -> Self {
+        self.summary = Some(summary);
+        self
+    }
+
+    /// Add an attribute to the generated items, such as `allow(clippy::all)`,
+    /// `rustfmt::skip` or `doc(hidden)`.
+    ///
+    /// The attribute is written without the `#[...]` around it. When the code is an
+    /// expression, each item in its block gets the attribute. When the code is a
+    /// function or an enum, that function or enum gets it instead. Attributes are
+    /// written in the order that they were added.
+    ///
+    /// # Panics
+    ///
+    /// [`Options::generate_tokens`] panics if the attribute isn't valid Rust tokens.
+    pub fn attribute(mut self, attribute: impl Into<String>) -> Self {
+        self.attributes.push(attribute.into());
+        self
+    }
+
+    /// Split the nodes into parts of `len` nodes each.
+    ///
+    /// `rustc` needs a lot of memory to compile a single array with hundreds of
+    /// thousands of nodes, and has to compile all of it again whenever any of it
+    /// changes. Split nodes are written as several `const` arrays, `NODES_0`, `NODES_1`
+    /// and so on, which are joined back into one slice with `as_flattened`. The last
+    /// part is padded out with nodes that can't be reached. This doesn't apply to
+    /// [`Options::generate_tokens`].
+    ///
+    /// By default, the nodes aren't split.
+    ///
+    /// # Compatibility
+    ///
+    /// Code generated with this option needs Rust 1.80 or later to compile, which is
+    /// when `as_flattened` became usable in constants, while the rest of the generated
+    /// code compiles with the same versions as `intern-str`. A [`Graph`] reads its
+    /// nodes from one contiguous slice, so there's no way to join the parts on older
+    /// compilers without copying them, which needs the outputs to be `Copy`. Crates
+    /// that support older compilers should leave the nodes in one array.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` is zero.
+    pub fn split_nodes(mut self, len: usize) -> Self {
+        assert!(len != 0, "parts must have at least one node");
+        self.split_nodes = Some(len);
+        self
+    }
+
+    /// Generate the code for a graph, like [`generate`].
+    pub fn generate<Input: Key, Output>(
+        &self,
+        graph: &Graph<'_, '_, Input, Output>,
+        input_type: &str,
+        output_type: &str,
+        write_output: impl FnMut(&mut dyn Write, &Output) -> fmt::Result,
+    ) -> String {
+        let mut out = String::new();
+        self.generate_into(&mut out, graph, input_type, output_type, write_output)
+            .ok();
+        out
+    }
+
+    /// Write the code for a graph into `out`, like [`generate_into`].
+    pub fn generate_into<Input: Key, Output>(
+        &self,
+        out: &mut dyn Write,
+        graph: &Graph<'_, '_, Input, Output>,
+        input_type: &str,
+        output_type: &str,
+        write_output: impl FnMut(&mut dyn Write, &Output) -> fmt::Result,
+    ) -> fmt::Result {
+        self.generate_into_with_input(out, graph, input_type, output_type, write_key, write_output)
+    }
+
+    /// Generate the code for a graph, writing the keys with `write_input`, like
+    /// [`generate_with_input`].
+    pub fn generate_with_input<Input: Segmentable, Output>(
+        &self,
+        graph: &Graph<'_, '_, Input, Output>,
+        input_type: &str,
+        output_type: &str,
+        write_input: impl FnMut(&mut dyn Write, &Input) -> fmt::Result,
+        write_output: impl FnMut(&mut dyn Write, &Output) -> fmt::Result,
+    ) -> String {
+        let mut out = String::new();
+        self.generate_into_with_input(
+            &mut out,
+            graph,
+            input_type,
+            output_type,
+            write_input,
+            write_output,
+        )
+        .ok();
+        out
+    }
+
+    /// Write the code for a graph into `out`, writing the keys with `write_input`, like
+    /// [`generate_with_input`].
+    pub fn generate_into_with_input<Input: Segmentable, Output>(
+        &self,
+        out: &mut dyn Write,
+        graph: &Graph<'_, '_, Input, Output>,
+        input_type: &str,
+        output_type: &str,
+        write_input: impl FnMut(&mut dyn Write, &Input) -> fmt::Result,
+        write_output: impl FnMut(&mut dyn Write, &Output) -> fmt::Result,
+    ) -> fmt::Result {
+        writeln!(out, "{{")?;
+
+        // Write the nodes.
+        self.write_nodes(
+            out,
+            graph,
+            input_type,
+            output_type,
+            write_input,
+            write_output,
+        )?;
+
+        // Write the graph.
+        self.write_summary(out)?;
+        self.write_attributes(out, 4)?;
+        writeln!(
+            out,
+            "{}{} GRAPH: intern_str::Graph<'static, 'static, {}, {}> = {};",
+            self.indent(4),
+            self.item(),
+            input_type,
+            output_type,
+            WriteGraph::of(graph),
+        )?;
+
+        writeln!(out, "{}GRAPH", self.indent(4))?;
+
+        writeln!(out, "}}")
+    }
+
+    /// Generate the code for a [`Map`], like [`generate_map`].
+    pub fn generate_map<Input: Key, Output>(
+        &self,
+        map: &Map<'_, '_, '_, Input, Output>,
+        input_type: &str,
+        output_type: &str,
+        write_output: impl FnMut(&mut dyn Write, &Output) -> fmt::Result,
+    ) -> String {
+        let mut out = String::new();
+        self.generate_map_into(&mut out, map, input_type, output_type, write_output)
+            .ok();
+        out
+    }
+
+    /// Write the code for a [`Map`] into `out`, like [`generate_map_into`].
+    pub fn generate_map_into<Input: Key, Output>(
+        &self,
+        out: &mut dyn Write,
+        map: &Map<'_, '_, '_, Input, Output>,
+        input_type: &str,
+        output_type: &str,
+        mut write_output: impl FnMut(&mut dyn Write, &Output) -> fmt::Result,
+    ) -> fmt::Result {
+        let graph = map.graph();
+
+        writeln!(out, "{{")?;
+
+        // Write the nodes.
+        self.write_nodes(out, &graph, input_type, "usize", write_key, |f, index| {
+            write!(f, "{}", Index(*index))
+        })?;
+
+        // Write the outputs.
+        self.write_attributes(out, 4)?;
+        writeln!(
+            out,
+            "{}{} OUTPUTS: &[{}] = &[",
+            self.indent(4),
+            self.item(),
+            output_type
+        )?;
+
+        for output in map.outputs() {
+            write!(out, "{}", self.indent(8))?;
+            write_output(out, output)?;
+            writeln!(out, ",")?;
+        }
+
+        writeln!(out, "{}];", self.indent(4))?;
+
+        // Write the map.
+        self.write_summary(out)?;
+        self.write_attributes(out, 4)?;
+        writeln!(
+            out,
+            "{}{} MAP: intern_str::Map<'static, 'static, 'static, {}, {}> = intern_str::Map::new({}, OUTPUTS);",
+            self.indent(4),
+            self.item(),
+            input_type,
+            output_type,
+            WriteGraph::of(&graph),
+        )?;
+
+        writeln!(out, "{}MAP", self.indent(4))?;
+
+        writeln!(out, "}}")
+    }
+
+    /// Generate the code for a [`Map`] as a [`Packed`] graph, like [`generate_packed`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the packed nodes would be larger than `u32::MAX` bytes.
+    pub fn generate_packed<'a, Input: ByteKey<'a>, Output: 'a>(
+        &self,
+        map: &Map<'_, '_, '_, Input, Output>,
+        input_type: &str,
+        output_type: &str,
+        mut write_output: impl FnMut(&mut dyn Write, &Output) -> fmt::Result,
+    ) -> String {
+        let mut blob = Vec::new();
+        Packed::<'a, Input, Output>::pack(map, &mut blob);
+
+        let mut out = String::new();
+        writeln!(out, "{{").ok();
+
+        // Write the packed nodes, a row of bytes at a time.
+        self.write_attributes(&mut out, 4).ok();
+        writeln!(
+            out,
+            "{}{} BLOB: [u8; {}] = [",
+            self.indent(4),
+            self.item(),
+            blob.len()
+        )
+        .ok();
+
+        for row in blob.chunks(if self.compact { blob.len() } else { 16 }) {
+            write!(out, "{}", self.indent(8)).ok();
+            for (i, byte) in row.iter().enumerate() {
+                let sep = if i == 0 || self.compact { "" } else { " " };
+                write!(out, "{}{},", sep, byte).ok();
+            }
+            writeln!(out).ok();
+        }
+
+        writeln!(out, "{}];", self.indent(4)).ok();
+
+        // Write the outputs.
+        self.write_attributes(&mut out, 4).ok();
+        writeln!(
+            out,
+            "{}{} OUTPUTS: &[{}] = &[",
+            self.indent(4),
+            self.item(),
+            output_type
+        )
+        .ok();
+
+        for output in map.outputs() {
+            write!(out, "{}", self.indent(8)).ok();
+            write_output(&mut out, output).ok();
+            writeln!(out, ",").ok();
+        }
+
+        writeln!(out, "{}];", self.indent(4)).ok();
+
+        // Write the packed graph.
+        self.write_summary(&mut out).ok();
+        self.write_attributes(&mut out, 4).ok();
+        writeln!(
+            out,
+            "{}{} PACKED: intern_str::Packed<'static, {}, {}> = intern_str::Packed::new(&BLOB, OUTPUTS);",
+            self.indent(4),
+            self.item(),
+            input_type,
+            output_type,
+        )
+        .ok();
+
+        writeln!(out, "{}PACKED", self.indent(4)).ok();
+
+        writeln!(out, "}}").ok();
+        out
+    }
+
+    /// Generate the code for a graph as a [`Flat`] graph, like [`generate_flat`].
+    pub fn generate_flat<'a, Input: ByteKey<'a>, Output: 'a>(
+        &self,
+        graph: &Graph<'_, '_, Input, Output>,
+        input_type: &str,
+        output_type: &str,
+        mut write_output: impl FnMut(&mut dyn Write, &Output) -> fmt::Result,
+    ) -> String {
+        // Write each output once, and have the nodes refer to it by index.
+        let mut outputs = Vec::new();
+        let mut indices = BTreeMap::new();
+        let (mut nodes, mut edges, mut keys) = (Vec::new(), Vec::new(), Vec::new());
+        Flat::<'a, Input, Output>::flatten(graph, &mut nodes, &mut edges, &mut keys, |output| {
+            let mut code = String::new();
+            write_output(&mut code, output).ok();
+            let next = outputs.len();
+            *indices.entry(code.clone()).or_insert_with(|| {
+                outputs.push(code);
+                next
+            })
+        });
+
+        let mut out = String::new();
+        writeln!(out, "{{").ok();
+
+        // The nodes and transitions are written a row per entry.
+        self.write_table(&mut out, "NODES", "u32", &nodes[..3], &nodes[3..], 5);
+        self.write_table(&mut out, "EDGES", "u32", &[], &edges, 3);
+        self.write_table(&mut out, "KEYS", "u8", &[], &keys, 16);
+
+        // Write the outputs.
+        self.write_attributes(&mut out, 4).ok();
+        writeln!(
+            out,
+            "{}{} OUTPUTS: &[{}] = &[",
+            self.indent(4),
+            self.item(),
+            output_type
+        )
+        .ok();
+
+        for output in &outputs {
+            writeln!(out, "{}{},", self.indent(8), output).ok();
+        }
+
+        writeln!(out, "{}];", self.indent(4)).ok();
+
+        // Write the flat graph.
+        self.write_summary(&mut out).ok();
+        self.write_attributes(&mut out, 4).ok();
+        writeln!(
+            out,
+            "{}{} FLAT: intern_str::Flat<'static, {}, {}> = intern_str::Flat::new(&NODES, &EDGES, &KEYS, OUTPUTS);",
+            self.indent(4),
+            self.item(),
+            input_type,
+            output_type,
+        )
+        .ok();
+
+        writeln!(out, "{}FLAT", self.indent(4)).ok();
+
+        writeln!(out, "}}").ok();
+        out
+    }
+
+    /// Write a table of integers named `name`, with `head` on its own row and `row`
+    /// items on each row after it.
+    fn write_table<T: fmt::Display>(
+        &self,
+        out: &mut String,
+        name: &str,
+        ty: &str,
+        head: &[T],
+        items: &[T],
+        row: usize,
+    ) {
+        self.write_attributes(out, 4).ok();
+        writeln!(
+            out,
+            "{}{} {}: [{}; {}] = [",
+            self.indent(4),
+            self.item(),
+            name,
+            ty,
+            head.len() + items.len()
+        )
+        .ok();
+
+        let rows = core::iter::once(head)
+            .filter(|head| !head.is_empty())
+            .chain(items.chunks(row));
+        if self.compact {
+            for item in head.iter().chain(items) {
+                write!(out, "{},", item).ok();
+            }
+            writeln!(out).ok();
+        } else {
+            for row in rows {
+                write!(out, "{}", self.indent(8)).ok();
+                for (i, item) in row.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { " " };
+                    write!(out, "{}{},", sep, item).ok();
+                }
+                writeln!(out).ok();
+            }
+        }
+
+        writeln!(out, "{}];", self.indent(4)).ok();
+    }
+
+    /// Serialize a graph into `blob`, and generate a function that loads it, like
+    /// [`generate_blob`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn generate_blob<'a, Input: ByteKey<'a>, Output>(
+        &self,
+        graph: &Graph<'_, '_, Input, Output>,
+        blob: &mut Vec<u8>,
+        blob_path: &str,
+        name: &str,
+        input_type: &str,
+        output_type: &str,
+        write_output: impl FnMut(&Output, &mut Vec<u8>),
+        read_output: &str,
+    ) -> String {
+        blob.clear();
+        intern_str::serialize::write(graph, blob, write_output);
+
+        let mut out = String::new();
+        if let Some(summary) = &self.summary {
+            summary.write_lines(&mut out, "///").ok();
+        }
+
+        self.write_attributes(&mut out, 0).ok();
+        writeln!(
+            out,
+            "pub fn {}<'nodes>(nodes: &'nodes mut Vec<intern_str::Node<'static, {}, {}>>) -> intern_str::Graph<'static, 'nodes, {}, {}> {{",
+            name, input_type, output_type, input_type, output_type,
+        )
+        .ok();
+        writeln!(
+            out,
+            "{}{} BLOB: &[u8] = include_bytes!({:?});",
+            self.indent(4),
+            self.item(),
+            blob_path
+        )
+        .ok();
+        writeln!(
+            out,
+            "{}intern_str::serialize::read(BLOB, nodes, {}).expect(\"the serialized graph is invalid\")",
+            self.indent(4),
+            read_output
+        )
+        .ok();
+        writeln!(out, "}}").ok();
+        out
+    }
+
+    /// Generate the code for several graphs that share one set of nodes, like
+    /// [`generate_shared`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the graphs don't all refer to the same nodes.
+    pub fn generate_shared<Input: Key, Output>(
+        &self,
+        graphs: &[(&str, Graph<'_, '_, Input, Output>)],
+        input_type: &str,
+        output_type: &str,
+        write_output: impl FnMut(&mut dyn Write, &Output) -> fmt::Result,
+    ) -> String {
+        let mut out = String::new();
+
+        let nodes = match graphs.first() {
+            Some((_, graph)) => graph,
+            None => return out,
+        };
+        assert!(
+            graphs
+                .iter()
+                .all(|(_, graph)| core::ptr::eq(graph.nodes(), nodes.nodes())),
+            "graphs do not share the same nodes"
+        );
+
+        // Write the nodes.
+        self.write_nodes(
+            &mut out,
+            nodes,
+            input_type,
+            output_type,
+            write_key,
+            write_output,
+        )
+        .ok();
+
+        // Write each of the graphs.
+        for (name, graph) in graphs {
+            self.write_summary(&mut out).ok();
+            self.write_attributes(&mut out, 4).ok();
+            writeln!(
+                out,
+                "{}pub {} {}: intern_str::Graph<'static, 'static, {}, {}> = {};",
+                self.indent(4),
+                self.item(),
+                name,
+                input_type,
+                output_type,
+                WriteGraph::of(graph),
+            )
+            .ok();
+        }
+
+        out
+    }
+
+    /// Generate an item for each variant of a graph, like [`generate_variants`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if more than one variant doesn't have a feature.
+    pub fn generate_variants(&self, name: &str, variants: &[Variant<'_>]) -> String {
+        assert!(
+            variants.iter().filter(|v| v.feature.is_none()).count() <= 1,
+            "only one variant can be the default"
+        );
+
+        let features = variants
+            .iter()
+            .filter_map(|v| v.feature)
+            .collect::<Vec<_>>();
+        let mut earlier = 0;
+        let mut out = String::new();
+
+        for variant in variants {
+            // Variants with a feature give way to the variants before them.
+            match variant.feature {
+                Some(feature) if earlier == 0 => {
+                    writeln!(out, "#[cfg(feature = {:?})]", feature).ok();
+                }
+                Some(feature) => {
+                    write!(out, "#[cfg(all(feature = {:?}, not(any(", feature).ok();
+                    write_features(&mut out, &features[..earlier]).ok();
+                    writeln!(out, "))))]").ok();
+                }
+                None if !features.is_empty() => {
+                    write!(out, "#[cfg(not(any(").ok();
+                    write_features(&mut out, &features).ok();
+                    writeln!(out, ")))]").ok();
+                }
+                None => {}
+            }
+
+            if variant.feature.is_some() {
+                earlier += 1;
+            }
+
+            if let Some(summary) = &self.summary {
+                summary.write_lines(&mut out, "///").ok();
+            }
+
+            self.write_attributes(&mut out, 0).ok();
+            writeln!(
+                out,
+                "pub {} {}: {} = {};",
+                self.item(),
+                name,
+                variant.ty,
+                variant.code.trim_end()
+            )
+            .ok();
+        }
+
+        out
+    }
+
+    /// Generate the code for a graph while it is being built, like
+    /// [`generate_streaming`].
+    #[cfg(feature = "builder")]
+    pub fn generate_streaming<'a, T, Type>(
+        &self,
+        builder: &'a mut Builder<T, Type>,
+        out: &mut dyn Write,
+        input_type: &str,
+        output_type: &str,
+        mut write_output: impl FnMut(&mut dyn Write, &Option<&'a T>) -> fmt::Result,
+    ) -> fmt::Result
+    where
+        Type: GraphType<'a>,
+        Type::InputKey: Key,
+    {
+        writeln!(out, "{{")?;
+
+        // Write the nodes as they are built.
+        self.write_nodes_start(out, input_type, output_type)?;
+
+        let mut result = Ok(());
+        let mut index = 0;
+        let graph = builder.build_streaming_with_meta(|node, meta| {
+            if result.is_ok() {
+                result = self
+                    .write_part_start(out, index, input_type, output_type)
+                    .and_then(|()| {
+                        self.write_node(out, &node, meta, &mut write_key, &mut write_output)
+                    });
+            }
+            index += 1;
+        });
+        result?;
+
+        self.write_nodes_end(out, graph.len(), input_type, output_type, |f| {
+            write_output(f, &None)
+        })?;
+
+        // Write the graph.
+        self.write_summary(out)?;
+        self.write_attributes(out, 4)?;
+        writeln!(
+            out,
+            "{}{} GRAPH: intern_str::Graph<'static, 'static, {}, {}> = {};",
+            self.indent(4),
+            self.item(),
+            input_type,
+            output_type,
+            WriteGraph {
+                start: graph.start(),
+                min_key_len: graph.min_key_len(),
+                max_key_len: graph.max_key_len(),
+            },
+        )?;
+
+        writeln!(out, "{}GRAPH", self.indent(4))?;
+
+        writeln!(out, "}}")
+    }
+
+    /// Generate an enum with a variant for each key, like [`generate_enum`].
+    #[cfg(feature = "builder")]
+    pub fn generate_enum<Type, K: AsRef<str>>(
+        &self,
+        name: &str,
+        input_type: &str,
+        keys: impl IntoIterator<Item = K>,
+        naming: Naming,
+    ) -> Result<String, AddError<usize>>
+    where
+        Type: for<'k> GraphType<'k>,
+        for<'k> <Type as GraphType<'k>>::InputKey: Key,
+    {
+        let keys = keys
+            .into_iter()
+            .map(|key| String::from(key.as_ref()))
+            .collect::<Vec<_>>();
+
+        // Name the variants, making sure that no two are the same.
+        let mut variants = Vec::<String>::with_capacity(keys.len());
+        for (i, key) in keys.iter().enumerate() {
+            let base = naming.variant(key, i);
+            let mut variant = base.clone();
+            let mut suffix = 2;
+            while variants.contains(&variant) {
+                variant = String::new();
+                write!(variant, "{}{}", base, suffix).ok();
+                suffix += 1;
+            }
+            variants.push(variant);
+        }
+
+        // The graph spells the keys the same way as `as_str` does.
+        let mut builder = Builder::<usize, Type>::new();
+        builder.preserve_case();
+        for (i, key) in keys.iter().enumerate() {
+            builder.add(key.as_str(), i)?;
+        }
+
+        let mut buffer = Vec::new();
+        let graph = builder.build(&mut buffer);
+        let mut output_type = String::new();
+        write!(output_type, "Option<{}>", name).ok();
+        let code =
+            self.inner()
+                .generate(&graph, input_type, &output_type, |f, index| match index {
+                    Some(index) => write!(f, "Some({}::{})", name, variants[*index]),
+                    None => write!(f, "None"),
+                });
+
+        let mut out = String::new();
+        if let Some(summary) = &self.summary {
+            summary.write_lines(&mut out, "///").ok();
+        }
+
+        // Write the enum.
+        writeln!(
+            out,
+            "#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]"
+        )
+        .ok();
+        match naming {
+            Naming::UpperCamel => {}
+            Naming::ScreamingSnake | Naming::Custom(_) => {
+                writeln!(out, "#[allow(non_camel_case_types)]").ok();
+            }
+        }
+        self.write_attributes(&mut out, 0).ok();
+        writeln!(out, "pub enum {} {{", name).ok();
+
+        for variant in &variants {
+            writeln!(out, "{}{},", self.indent(4), variant).ok();
+        }
+
+        writeln!(out, "}}").ok();
+        writeln!(out).ok();
+
+        // Write the graph and the conversion back to the key.
+        self.write_attributes(&mut out, 0).ok();
+        writeln!(out, "impl {} {{", name).ok();
+        writeln!(
+            out,
+            "{}/// The graph that maps each key to its variant.",
+            self.indent(4)
+        )
+        .ok();
+        write!(
+            out,
+            "{}pub const GRAPH: intern_str::Graph<'static, 'static, {}, {}> = ",
+            self.indent(4),
+            input_type,
+            output_type
+        )
+        .ok();
+
+        self.write_block(&mut out, &code, 4).ok();
+        writeln!(out, ";").ok();
+        writeln!(out).ok();
+        writeln!(out, "{}/// Get the key for this variant.", self.indent(4)).ok();
+        writeln!(
+            out,
+            "{}pub fn as_str(self) -> &'static str {{",
+            self.indent(4)
+        )
+        .ok();
+        writeln!(out, "{}match self {{", self.indent(8)).ok();
+
+        for (key, variant) in keys.iter().zip(&variants) {
+            writeln!(
+                out,
+                "{}{}::{} => {},",
+                self.indent(12),
+                name,
+                variant,
+                WriteKey(&key.as_str())
+            )
+            .ok();
+        }
+
+        writeln!(out, "{}}}", self.indent(8)).ok();
+        writeln!(out, "{}}}", self.indent(4)).ok();
+        writeln!(out, "}}").ok();
+
+        Ok(out)
+    }
+
+    /// Generate a plain function that does the same lookup as the graph, like
+    /// [`generate_fallback`].
+    #[cfg(feature = "builder")]
+    pub fn generate_fallback<T, Type>(
+        &self,
+        out: &mut dyn Write,
+        builder: &Builder<T, Type>,
+        name: &str,
+        input_type: &str,
+        output_type: &str,
+        mut write_output: impl FnMut(&mut dyn Write, &Option<&T>) -> fmt::Result,
+    ) -> fmt::Result
+    where
+        Type: for<'k> GraphType<'k>,
+        for<'k> <Type as GraphType<'k>>::InputKey: Key,
+    {
+        if let Some(summary) = &self.summary {
+            summary.write_lines(out, "///")?;
+        }
+
+        self.write_attributes(out, 0)?;
+        writeln!(
+            out,
+            "pub fn {}(input: {}) -> {} {{",
+            name, input_type, output_type
+        )?;
+
+        for (key, value) in builder.iter_cased() {
+            let key = <Type as GraphType<'_>>::key(&key);
+            writeln!(out, "{}if input == {} {{", self.indent(4), WriteKey(&key))?;
+            write!(out, "{}return ", self.indent(8))?;
+            write_output(out, &Some(value))?;
+            writeln!(out, ";")?;
+            writeln!(out, "{}}}", self.indent(4))?;
+        }
+
+        write!(out, "{}", self.indent(4))?;
+        write_output(out, &None)?;
+        writeln!(out)?;
+
+        writeln!(out, "}}")
+    }
+
+    /// Generate a function that picks a graph by the length of its input, like
+    /// [`generate_by_length`].
+    #[cfg(feature = "builder")]
+    pub fn generate_by_length<T, Type>(
+        &self,
+        out: &mut dyn Write,
+        builder: &Builder<T, Type>,
+        name: &str,
+        input_type: &str,
+        output_type: &str,
+        mut write_output: impl FnMut(&mut dyn Write, &Option<&T>) -> fmt::Result,
+    ) -> fmt::Result
+    where
+        Type: for<'k> GraphType<'k>,
+        for<'k> <Type as GraphType<'k>>::InputKey: Key,
+    {
+        let mut keys = builder
+            .iter_cased()
+            .map(|(key, value)| (Type::key_len(key.as_bytes()), key, value))
+            .collect::<Vec<_>>();
+        keys.sort_by_key(|&(len, _, _)| len);
+
+        if let Some(summary) = &self.summary {
+            summary.write_lines(out, "///")?;
+        }
+
+        let inner = self.inner();
+        self.write_attributes(out, 0)?;
+        writeln!(
+            out,
+            "pub fn {}(input: {}) -> &'static {} {{",
+            name, input_type, output_type
+        )?;
+        writeln!(
+            out,
+            "{}match intern_str::Segmentable::len(&input) {{",
+            self.indent(4)
+        )?;
+
+        let mut rest = &keys[..];
+        while let Some(&(len, _, _)) = rest.first() {
+            let end = rest
+                .iter()
+                .position(|&(other, _, _)| other != len)
+                .unwrap_or(rest.len());
+            let (group, next) = rest.split_at(end);
+            rest = next;
+
+            let mut lengths = Builder::<&T, Type>::new();
+            lengths.preserve_case();
+            for (_, key, value) in group {
+                lengths
+                    .add(key.as_str(), *value)
+                    .unwrap_or_else(|_| unreachable!("keys are already unique"));
+            }
+
+            let mut buffer = Vec::new();
+            let graph = lengths.build(&mut buffer);
+            let code = inner.generate(&graph, input_type, output_type, |f, value| {
+                write_output(f, value)
+            });
+
+            writeln!(out, "{}{} => {{", self.indent(8), len)?;
+            write!(
+                out,
+                "{}static GRAPH: intern_str::Graph<'static, 'static, {}, {}> = ",
+                self.indent(12),
+                input_type,
+                output_type
+            )?;
+            self.write_block(out, &code, 12)?;
+            writeln!(out, ";")?;
+            writeln!(out, "{}GRAPH.process(input)", self.indent(12))?;
+            writeln!(out, "{}}}", self.indent(8))?;
+        }
+
+        writeln!(out, "{}_ => {{", self.indent(8))?;
+        write!(out, "{}static MISS: {} = ", self.indent(12), output_type)?;
+        write_output(out, &None)?;
+        writeln!(out, ";")?;
+        writeln!(out, "{}&MISS", self.indent(12))?;
+        writeln!(out, "{}}}", self.indent(8))?;
+        writeln!(out, "{}}}", self.indent(4))?;
+        writeln!(out, "}}")
+    }
+
+    /// Generate a table that maps values back to their keys, like
+    /// [`generate_reverse`].
+    #[cfg(feature = "builder")]
+    pub fn generate_reverse<'a, T, Type: GraphType<'a>>(
+        &self,
+        out: &mut dyn Write,
+        builder: &Builder<T, Type>,
+        output_type: &str,
+        mut write_output: impl FnMut(&mut dyn Write, &T) -> fmt::Result,
+    ) -> fmt::Result {
+        // Aliases visit the same value again, under a key with the same canonical key.
+        let mut entries = builder
+            .iter()
+            .map(|(key, value)| (builder.canonical(&key).unwrap_or(key), value))
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries.dedup_by(|a, b| a.0 == b.0);
+        for (key, _) in &mut entries {
+            if let Some(cased) = builder.cased(key) {
+                *key = cased;
+            }
+        }
+
+        writeln!(out, "{{")?;
+
+        self.write_attributes(out, 4)?;
+        writeln!(
+            out,
+            "{}{} KEYS: &[({}, &str)] = &[",
+            self.indent(4),
+            self.item(),
+            output_type
+        )?;
+
+        for (key, value) in &entries {
+            write!(out, "{}(", self.indent(8))?;
+            write_output(out, value)?;
+            writeln!(out, ", {}),", WriteKey(&key.as_str()))?;
+        }
+
+        writeln!(out, "{}];", self.indent(4))?;
+
+        writeln!(out, "{}KEYS", self.indent(4))?;
+
+        writeln!(out, "}}")
+    }
+
+    /// Generate a test module for a generated graph, like [`generate_tests`].
+    pub fn generate_tests<Input: Key + Clone, Output>(
+        &self,
+        out: &mut dyn Write,
+        path: &str,
+        keys: impl IntoIterator<Item = (Input, Output)>,
+        miss: &Output,
+        mut write_output: impl FnMut(&mut dyn Write, &Output) -> fmt::Result,
+    ) -> fmt::Result {
+        /// The largest number of mutated keys that are tested.
+        const MUTATIONS: usize = 16;
+
+        let mut keys = keys.into_iter().collect::<Vec<_>>();
+        keys.sort_by(|a, b| a.0.cmp(&b.0));
+        keys.dedup_by(|a, b| a.0 == b.0);
+
+        // Cut a unit off of either end of each key, skipping the cuts that are keys.
+        let mut mutations = Vec::new();
+        for (key, _) in &keys {
+            let len = key.len();
+            let cuts = key
+                .clone()
+                .split(len.saturating_sub(1))
+                .map(|(start, _)| start)
+                .into_iter()
+                .chain(key.clone().split(1).map(|(_, end)| end));
+
+            for cut in cuts {
+                if cut.len() < len && keys.binary_search_by(|(key, _)| key.cmp(&cut)).is_err() {
+                    mutations.push((cut, miss));
+                }
+            }
+        }
+        mutations.sort_by(|a, b| a.0.cmp(&b.0));
+        mutations.dedup_by(|a, b| a.0 == b.0);
+        mutations.truncate(MUTATIONS);
+
+        let keys = keys.iter().map(|(key, output)| (key.clone(), output));
+        let tests = [
+            ("keys", keys.collect::<Vec<_>>()),
+            ("mutated_keys", mutations),
+        ];
+
+        writeln!(out, "#[cfg(test)]")?;
+        writeln!(out, "mod tests {{")?;
+        writeln!(out, "{}use super::*;", self.indent(4))?;
+
+        for (name, keys) in tests.iter() {
+            writeln!(out)?;
+            writeln!(out, "{}#[test]", self.indent(4))?;
+            writeln!(out, "{}fn {}() {{", self.indent(4), name)?;
+            writeln!(out, "{}let graph = &{};", self.indent(8), path)?;
+
+            for (key, output) in keys {
+                write!(
+                    out,
+                    "{}assert_eq!(*graph.process({}), ",
+                    self.indent(8),
+                    WriteKey(key)
+                )?;
+                write_output(out, output)?;
+                writeln!(out, ");")?;
+            }
+
+            writeln!(out, "{}}}", self.indent(4))?;
+        }
+
+        writeln!(out, "}}")
+    }
+
+    /// Generate the code for a graph as tokens, like [`generate_tokens`].
+    #[cfg(feature = "tokens")]
+    pub fn generate_tokens<Input: Key, Output>(
+        &self,
+        graph: &Graph<'_, '_, Input, Output>,
+        input_type: &TokenStream,
+        output_type: &TokenStream,
+        mut write_output: impl FnMut(&Output) -> TokenStream,
+    ) -> TokenStream {
+        let nodes = graph.nodes().iter().map(|node| {
+            let inputs = node.inputs().iter().map(|(input, next)| {
+                let input = input.tokens();
+                let next = Literal::usize_unsuffixed(*next);
+                quote!((#input, #next))
+            });
+            let output = write_output(node.output());
+            let default = Literal::usize_unsuffixed(node.default());
+            let amount = index_tokens(node.amount());
+
+            quote! {
+                intern_str::Node::new(&[#(#inputs,)*], #output, #default, #amount)
+            }
+        });
+
+        let start = Literal::usize_unsuffixed(graph.start());
+        let min_key_len = Literal::usize_unsuffixed(graph.min_key_len());
+        let max_key_len = index_tokens(graph.max_key_len());
+
+        let item = if self.use_static {
+            quote!(static)
+        } else {
+            quote!(const)
+        };
+
+        let mut summary = String::new();
+        if let Some(s) = &self.summary {
+            s.write_lines(&mut summary, "").ok();
+        }
+        let docs = summary.lines().map(|line| quote!(#[doc = #line]));
+        let attributes = self
+            .attributes
+            .iter()
+            .map(|attribute| {
+                attribute
+                    .parse::<TokenStream>()
+                    .unwrap_or_else(|_| panic!("invalid attribute: {}", attribute))
+            })
+            .collect::<Vec<_>>();
+
+        quote! {
+            {
+                #(#[#attributes])*
+                #item NODES: &[intern_str::Node<'static, #input_type, #output_type>] = &[#(#nodes,)*];
+                #(#docs)*
+                #(#[#attributes])*
+                #item GRAPH: intern_str::Graph<'static, 'static, #input_type, #output_type> =
+                    intern_str::Graph::with_key_lengths(NODES, #start, #min_key_len, #max_key_len);
+                GRAPH
+            }
+        }
+    }
+
+    /// Write the summary of the graph as a doc comment, if there is one.
+    fn write_summary(&self, out: &mut dyn Write) -> fmt::Result {
+        match &self.summary {
+            Some(summary) => {
+                let mut prefix = String::new();
+                write!(prefix, "{}///", self.indent(4))?;
+                summary.write_lines(out, &prefix)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Write the attributes for an item at `level`.
+    fn write_attributes(&self, out: &mut dyn Write, level: usize) -> fmt::Result {
+        for attribute in &self.attributes {
+            writeln!(out, "{}#[{}]", self.indent(level), attribute)?;
+        }
+
+        Ok(())
+    }
+
+    /// The options for code that is nested in an item that already has the summary and
+    /// the attributes.
+    #[cfg(feature = "builder")]
+    fn inner(&self) -> Options {
+        Options {
+            summary: None,
+            attributes: Vec::new(),
+            ..self.clone()
+        }
+    }
+
+    /// The keyword for the items that hold the nodes and the graph.
+    fn item(&self) -> &'static str {
+        if self.use_static {
+            "static"
+        } else {
+            "const"
+        }
+    }
+
+    /// Write out the `NODES` item for a graph.
+    fn write_nodes<Input: Segmentable, Output>(
+        &self,
+        out: &mut dyn Write,
+        graph: &Graph<'_, '_, Input, Output>,
+        input_type: &str,
+        output_type: &str,
+        mut write_input: impl FnMut(&mut dyn Write, &Input) -> fmt::Result,
+        mut write_output: impl FnMut(&mut dyn Write, &Output) -> fmt::Result,
+    ) -> fmt::Result {
+        self.write_nodes_start(out, input_type, output_type)?;
+
+        for (i, node) in graph.nodes().iter().enumerate() {
+            self.write_part_start(out, i, input_type, output_type)?;
+            self.write_node(out, node, None, &mut write_input, &mut write_output)?;
+        }
+
+        // Padding takes the output of the first node, which is as good as any.
+        let nodes = graph.nodes();
+        self.write_nodes_end(out, nodes.len(), input_type, output_type, |f| {
+            write_output(f, nodes[0].output())
+        })
+    }
+
+    /// Write the start of the `NODES` item, unless the nodes are split into parts.
+    fn write_nodes_start(
+        &self,
+        out: &mut dyn Write,
+        input_type: &str,
+        output_type: &str,
+    ) -> fmt::Result {
+        if self.split_nodes.is_some() {
+            return Ok(());
+        }
+
+        self.write_attributes(out, 4)?;
+        writeln!(
+            out,
+            "{}{} NODES: &[intern_str::Node<'static, {}, {}>] = &[",
+            self.indent(4),
+            self.item(),
+            input_type,
+            output_type
+        )
+    }
+
+    /// Start a new part before the node at `index`, if the nodes are split into parts
+    /// and the last one is full.
+    fn write_part_start(
+        &self,
+        out: &mut dyn Write,
+        index: usize,
+        input_type: &str,
+        output_type: &str,
+    ) -> fmt::Result {
+        let len = match self.split_nodes {
+            Some(len) if index % len == 0 => len,
+            _ => return Ok(()),
+        };
+
+        if index != 0 {
+            writeln!(out, "{}];", self.indent(4))?;
+        }
+
+        // Parts are always `const`, since a `static` can't be copied into `PARTS`.
+        self.write_attributes(out, 4)?;
+        writeln!(
+            out,
+            "{}const NODES_{}: [intern_str::Node<'static, {}, {}>; {}] = [",
+            self.indent(4),
+            index / len,
+            input_type,
+            output_type,
+            len
+        )
+    }
+
+    /// Finish the `NODES` item after `count` nodes have been written.
+    ///
+    /// If the nodes are split into parts, the last part is padded out to its full length
+    /// with nodes that can't be reached, whose outputs are written by `write_padding`.
+    fn write_nodes_end(
+        &self,
+        out: &mut dyn Write,
+        count: usize,
+        input_type: &str,
+        output_type: &str,
+        mut write_padding: impl FnMut(&mut dyn Write) -> fmt::Result,
+    ) -> fmt::Result {
+        let len = match self.split_nodes {
+            Some(len) => len,
+            None => return writeln!(out, "{}];", self.indent(4)),
+        };
+
+        let parts = count / len + if count % len == 0 { 0 } else { 1 };
+        if count != 0 {
+            for index in count..parts * len {
+                self.write_padding(out, index, &mut write_padding)?;
+            }
+
+            writeln!(out, "{}];", self.indent(4))?;
+        }
+
+        // Join the parts back together into one slice.
+        self.write_attributes(out, 4)?;
+        write!(
+            out,
+            "{}const PARTS: &[[intern_str::Node<'static, {}, {}>; {}]] = &[",
+            self.indent(4),
+            input_type,
+            output_type,
+            len
+        )?;
+
+        for part in 0..parts {
+            if part != 0 {
+                write!(out, ", ")?;
+            }
+
+            write!(out, "NODES_{}", part)?;
+        }
+
+        writeln!(out, "];")?;
+
+        // This is what needs Rust 1.80, as documented on `Options::split_nodes`.
+        self.write_attributes(out, 4)?;
+        writeln!(
+            out,
+            "{}{} NODES: &[intern_str::Node<'static, {}, {}>] = PARTS.as_flattened();",
+            self.indent(4),
+            self.item(),
+            input_type,
+            output_type
+        )
+    }
+
+    /// Write a node at `index` that pads out the last part of the nodes.
+    ///
+    /// The node has no inputs and is its own default, so it isn't an accepting node,
+    /// and no other node ever goes to it.
+    fn write_padding(
+        &self,
+        out: &mut dyn Write,
+        index: usize,
+        write_output: &mut impl FnMut(&mut dyn Write) -> fmt::Result,
+    ) -> fmt::Result {
+        if self.compact {
+            write!(out, "intern_str::Node::new(&[],")?;
+            write_output(out)?;
+            return writeln!(out, ",{},{}),", index, Index(core::usize::MAX));
+        }
+
+        writeln!(out, "{}intern_str::Node::new(", Indent(8))?;
+        writeln!(out, "{}&[", Indent(12))?;
+        writeln!(out, "{}],", Indent(12))?;
+
+        write!(out, "{}", Indent(12))?;
+        write_output(out)?;
+        writeln!(out, ",")?;
+
+        writeln!(out, "{}{},", Indent(12), index)?;
+        writeln!(out, "{}{},", Indent(12), Index(core::usize::MAX))?;
+        writeln!(out, "{}),", Indent(8))
+    }
+
+    /// Write out a single node in the `NODES` item, with `meta` as a comment.
+    fn write_node<Input: Segmentable, Output>(
+        &self,
+        out: &mut dyn Write,
+        node: &Node<'_, Input, Output>,
+        meta: Option<&str>,
+        write_input: &mut impl FnMut(&mut dyn Write, &Input) -> fmt::Result,
+        write_output: &mut impl FnMut(&mut dyn Write, &Output) -> fmt::Result,
+    ) -> fmt::Result {
+        if self.compact {
+            for line in meta.into_iter().flat_map(str::lines) {
+                writeln!(out, "// {}", line)?;
+            }
+
+            write!(out, "intern_str::Node::new(&[")?;
+
+            for (i, (input, next)) in node.inputs().iter().enumerate() {
+                if i != 0 {
+                    write!(out, ",")?;
+                }
+
+                write!(out, "(")?;
+                write_input(out, input)?;
+                write!(out, ",{})", next)?;
+            }
+
+            write!(out, "],")?;
+            write_output(out, node.output())?;
+            return writeln!(out, ",{},{}),", node.default(), Index(node.amount()));
+        }
+
+        writeln!(out, "{}intern_str::Node::new(", Indent(8))?;
+
+        writeln!(out, "{}&[", Indent(12))?;
+
+        for (input, next) in node.inputs() {
+            write!(out, "{}(", Indent(16))?;
+            write_input(out, input)?;
+            writeln!(out, ", {}),", next)?;
+        }
+
+        writeln!(out, "{}],", Indent(12))?;
+
+        for line in meta.into_iter().flat_map(str::lines) {
+            writeln!(out, "{}// {}", Indent(12), line)?;
+        }
+
+        write!(out, "{}", Indent(12))?;
+        write_output(out, node.output())?;
+        writeln!(out, ",")?;
+
+        writeln!(out, "{}{},", Indent(12), node.default(),)?;
+
+        writeln!(out, "{}{},", Indent(12), Index(node.amount()),)?;
+
+        writeln!(out, "{}),", Indent(8))
+    }
+
+    /// Write generated code that is nested `level` deep, without its final newline.
+    ///
+    /// The first line isn't indented, since it follows whatever was written before it.
+    #[cfg(feature = "builder")]
+    fn write_block(&self, out: &mut dyn Write, code: &str, level: usize) -> fmt::Result {
+        for (i, line) in code.trim_end().lines().enumerate() {
+            if i != 0 {
+                write!(out, "\n{}", self.indent(level))?;
+            }
+            write!(out, "{}", line)?;
+        }
+
+        Ok(())
+    }
+
+    /// The indentation for a line at `level`, which is nothing if the code is compact.
+    fn indent(&self, level: usize) -> Indent {
+        if self.compact {
+            Indent(0)
+        } else {
+            Indent(level)
+        }
+    }
+}
+
+/// Write a node index or key length as tokens, which may be `usize::MAX`.
+#[cfg(feature = "tokens")]
+fn index_tokens(index: usize) -> TokenStream {
+    if index == core::usize::MAX {
+        quote!(core::usize::MAX)
+    } else {
+        Literal::usize_unsuffixed(index).into_token_stream()
+    }
+}
+
+/// How the variants of an enum from [`generate_enum`] are named.
+#[derive(Debug, Clone, Copy)]
+pub enum Naming {
+    /// Each run of ASCII letters and digits is a word, and the words are capitalized
+    /// and joined, so `text/plain` becomes `TextPlain`.
+    UpperCamel,
+
+    /// Each run of ASCII letters and digits is a word, and the words are uppercased and
+    /// joined with underscores, so `text/plain` becomes `TEXT_PLAIN`.
+    ScreamingSnake,
+
+    /// The variants are named by a function of the key. Characters that can't be in
+    /// an identifier are replaced with underscores.
+    Custom(fn(&str) -> String),
+}
+
+impl Default for Naming {
+    fn default() -> Self {
+        Naming::UpperCamel
+    }
+}
+
+impl Naming {
+    /// Name the variant for the key at `index`.
+    #[cfg(feature = "builder")]
+    fn variant(self, key: &str, index: usize) -> String {
+        let words = key
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty());
+        let mut name = String::new();
+
+        match self {
+            Naming::UpperCamel => {
+                for word in words {
+                    let mut chars = word.chars();
+                    name.extend(chars.next().map(|c| c.to_ascii_uppercase()));
+                    name.extend(chars.map(|c| c.to_ascii_lowercase()));
+                }
+            }
+            Naming::ScreamingSnake => {
+                for (i, word) in words.enumerate() {
+                    if i != 0 {
+                        name.push('_');
+                    }
+                    name.push_str(&word.to_ascii_uppercase());
+                }
+            }
+            Naming::Custom(custom) => {
+                name = custom(key)
+                    .chars()
+                    .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                    .collect();
+            }
+        }
+
+        // Make sure the name is an identifier that can be used for a variant.
+        if name.is_empty() || name == "_" {
+            name = String::new();
+            write!(name, "Key{}", index).ok();
+        } else if name.starts_with(|c: char| c.is_ascii_digit()) {
+            name.insert(0, '_');
+        } else if name == "Self" {
+            name.push('_');
+        }
+
+        name
+    }
+}
+
+/// Write a list of features for a `cfg` attribute.
+fn write_features(out: &mut dyn Write, features: &[&str]) -> fmt::Result {
+    for (i, feature) in features.iter().enumerate() {
+        if i != 0 {
+            write!(out, ", ")?;
+        }
+
+        write!(out, "feature = {:?}", feature)?;
+    }
+
+    Ok(())
+}
+
+/// A variant of a graph, for [`generate_variants`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Variant<'a> {
+    /// The feature that enables the variant, or `None` for the default.
+    feature: Option<&'a str>,
+
+    /// The type of the variant.
+    ty: &'a str,
+
+    /// The code for the variant.
+    code: &'a str,
+}
+
+impl<'a> Variant<'a> {
+    /// Create a variant from its type, such as `intern_str::Graph<'static, 'static,
+    /// &'static str, Option<u32>>`, and its code, such as the code from [`generate`].
+    ///
+    /// The variant is used when none of the other variants' features are enabled,
+    /// unless it is given a feature.
+    pub fn new(ty: &'a str, code: &'a str) -> Self {
+        Variant {
+            feature: None,
+            ty,
+            code,
+        }
+    }
+
+    /// Enable this variant with a feature of the crate that includes the code.
+    pub fn feature(mut self, feature: &'a str) -> Self {
+        self.feature = Some(feature);
+        self
+    }
+}
+
+/// A summary of what is in a graph, written as a doc comment by [`Options::summary`].
+///
+/// ```
+/// use intern_str::builder::{Builder, Utf8Graph};
+/// use intern_str_codegen::Summary;
+///
+/// let mut builder = Builder::<_, Utf8Graph>::new();
+/// builder.add("hello", 1).unwrap();
+/// builder.add("world", 2).unwrap();
+///
+/// let summary = Summary::new()
+///     .option("case-sensitive")
+///     .source_hash(0x1234)
+///     .sample(builder.keys());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Summary {
+    /// The number of keys, if it has been set or counted.
+    keys: Option<usize>,
+
+    /// The options that the graph was built with.
+    options: Vec<String>,
+
+    /// The hash of the data that the graph was built from.
+    source_hash: Option<u64>,
+
+    /// The first few keys.
+    sample: Vec<String>,
+}
+
+impl Default for Summary {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Summary {
+    /// The number of keys that are written out by [`Summary::sample`].
+    pub const SAMPLE_LEN: usize = 8;
+
+    /// Create an empty summary.
+    pub fn new() -> Self {
+        Summary {
+            keys: None,
+            options: Vec::new(),
+            source_hash: None,
+            sample: Vec::new(),
+        }
+    }
+
+    /// Set the number of keys in the graph.
+    pub fn keys(mut self, keys: usize) -> Self {
+        self.keys = Some(keys);
+        self
+    }
+
+    /// Add an option that the graph was built with, such as `case-insensitive`.
+    pub fn option(mut self, option: impl Into<String>) -> Self {
+        self.options.push(option.into());
+        self
+    }
+
+    /// Set the hash of the data that the graph was built from.
+    ///
+    /// This makes it easy to tell whether the generated code is out of date.
+    pub fn source_hash(mut self, hash: u64) -> Self {
+        self.source_hash = Some(hash);
+        self
+    }
+
+    /// Take a sample of the keys in the graph.
+    ///
+    /// Only the first [`Summary::SAMPLE_LEN`] keys are written out. The rest are
+    /// counted, which sets the number of keys if it hasn't been set already.
+    pub fn sample<K: Into<String>>(mut self, keys: impl IntoIterator<Item = K>) -> Self {
+        let mut count = 0;
+        self.sample.clear();
+
+        for key in keys {
+            if count < Self::SAMPLE_LEN {
+                self.sample.push(key.into());
+            }
+            count += 1;
+        }
+
+        if self.keys.is_none() {
+            self.keys = Some(count);
+        }
+        self
+    }
+
+    /// Write the summary, with each line starting with `prefix`.
+    fn write_lines(&self, out: &mut dyn Write, prefix: &str) -> fmt::Result {
+        match self.keys {
+            Some(1) => writeln!(out, "{} A graph of 1 key.", prefix)?,
+            Some(keys) => writeln!(out, "{} A graph of {} keys.", prefix, keys)?,
+            None => writeln!(out, "{} A graph generated by `intern-str-codegen`.", prefix)?,
+        }
+
+        if self.options.is_empty() && self.source_hash.is_none() && self.sample.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(out, "{}", prefix)?;
+
+        if !self.options.is_empty() {
+            writeln!(out, "{} - Options: {}", prefix, self.options.join(", "))?;
+        }
+
+        if let Some(hash) = self.source_hash {
+            writeln!(out, "{} - Source hash: `{:#018x}`", prefix, hash)?;
+        }
+
+        if !self.sample.is_empty() {
+            write!(out, "{} - Keys: ", prefix)?;
+
+            for (i, key) in self.sample.iter().enumerate() {
+                if i != 0 {
+                    write!(out, ", ")?;
+                }
+
+                write!(out, "`{:?}`", key)?;
+            }
+
+            let rest = self.keys.unwrap_or(0).saturating_sub(self.sample.len());
+            if rest != 0 {
+                write!(out, " and {} more", rest)?;
+            }
+
+            writeln!(out)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The code for an expression, such as the path to a function or a `static`.
+///
+/// Both `Debug` and `Display` write the code itself, rather than a string literal. This
+/// makes it easy to use outputs that can't be written out from their values, such as
+/// function pointers: build the graph with the code for each output, and write it with
+/// `write!(f, "{:?}", out)` like any other output.
+///
+/// ```
+/// use intern_str::builder::{Builder, Utf8Graph};
+/// use intern_str_codegen::{generate, Expr};
+///
+/// let mut builder = Builder::<_, Utf8Graph>::new();
+/// builder.add("get", Expr::new("commands::get")).unwrap();
+/// builder.add("set", Expr::new("commands::set")).unwrap();
+///
+/// let mut buffer = Vec::new();
+/// let graph = builder.build(&mut buffer);
+/// let code = generate(&graph, "&'static str", "Option<fn(&[&str])>", |f, out| {
+///     write!(f, "{:?}", out)
+/// });
+///
+/// assert!(code.contains("Some(commands::get),"));
+/// ```
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Expr(String);
+
+impl Expr {
+    /// Create an expression from its code.
+    pub fn new(code: impl Into<String>) -> Self {
+        Expr(code.into())
+    }
+
+    /// Get the code for the expression.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Expr {
+    fn from(code: String) -> Self {
+        Expr(code)
+    }
+}
+
+impl From<&str> for Expr {
+    fn from(code: &str) -> Self {
+        Expr(code.into())
+    }
+}
+
+impl fmt::Debug for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// An item that can be used as a key.
+///
+/// Implement this for your own input types, such as a newtype that folds case in its
+/// own way, to generate graphs over them. [`Key::format`] writes the expression that
+/// creates the key, and can write the inner key with its own implementation:
+///
+/// ```
+/// use core::fmt;
+/// use intern_str::Segmentable;
+/// use intern_str_codegen::Key;
+///
+/// #[derive(PartialEq, Eq, PartialOrd, Ord)]
+/// struct Token<'a>(&'a str);
+///
+/// impl Segmentable for Token<'_> {
+///     fn split(self, at: usize) -> Option<(Self, Self)> {
+///         Segmentable::split(self.0, at).map(|(a, b)| (Token(a), Token(b)))
+///     }
+///
+///     fn len(&self) -> usize {
+///         Segmentable::len(&self.0)
+///     }
+/// }
+///
+/// impl Key for Token<'_> {
+///     fn format(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         write!(f, "my_crate::Token(")?;
+///         self.0.format(f)?;
+///         write!(f, ")")
+///     }
+/// }
+/// ```
+///
+/// For types from other crates, pass a `write_input` closure to
+/// [`generate_with_input`] instead.
+pub trait Key: Segmentable {
+    /// Format the key as a Rust expression.
+    fn format(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+
+    /// Write the key as the tokens of a Rust expression.
+    ///
+    /// By default, this parses the output of [`Key::format`].
+    #[cfg(feature = "tokens")]
+    fn tokens(&self) -> TokenStream {
+        WriteKey(self)
+            .to_string()
+            .parse()
+            .expect("key is not a valid expression")
+    }
+}
+
+impl Key for &str {
+    fn format(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_str_literal(f, self)
+    }
+
+    #[cfg(feature = "tokens")]
+    fn tokens(&self) -> TokenStream {
+        quote!(#self)
+    }
+}
+
+impl<T: KeyUnit> Key for &[T] {
+    fn format(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        T::format_slice(self, f)
+    }
+}
+
+/// An item of a slice that can be used as a key.
+pub trait KeyUnit: fmt::Debug + Ord + Sized {
+    /// Format a slice of items as a Rust expression.
+    ///
+    /// By default, this writes an array of the items' `Debug` representations.
+    fn format_slice(slice: &[Self], f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "&[")?;
+
+        for (i, item) in slice.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+
+            write!(f, "{:?}", item)?;
+        }
+
+        write!(f, "]")
+    }
+}
+
+impl KeyUnit for u8 {
+    /// Bytes are written as a byte string literal, which is much shorter than an
+    /// array for mostly ASCII keys.
+    fn format_slice(slice: &[Self], f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "b\"")?;
+
+        for &byte in slice {
+            for c in core::ascii::escape_default(byte) {
+                f.write_char(char::from(c))?;
+            }
+        }
+
+        write!(f, "\"")
+    }
+}
+
+macro_rules! key_units {
+    ($($ty:ty),*) => {
+        $(
+            impl KeyUnit for $ty {}
+        )*
+    };
+}
+
+key_units!(u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, char, bool);
+
+impl<T: AsRef<[u8]> + Key> Key for CaseInsensitive<T> {
+    fn format(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "intern_str::CaseInsensitive({})", WriteKey(&self.0))
+    }
+
+    #[cfg(feature = "tokens")]
+    fn tokens(&self) -> TokenStream {
+        let inner = self.0.tokens();
+        quote!(intern_str::CaseInsensitive(#inner))
+    }
+}
+
+impl<T: AsRef<[u8]> + Key, S: Separators> Key for SeparatorInsensitive<T, S> {
+    fn format(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "intern_str::SeparatorInsensitive::new({})",
+            WriteKey(&self.0)
+        )
+    }
+}
+
+impl<T: AsRef<[u8]> + Key, F: ByteFold> Key for Folded<T, F> {
+    fn format(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "intern_str::Folded::new({})", WriteKey(&self.0))
+    }
+}
+
+impl Key for Chars<'_> {
+    fn format(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "intern_str::Chars({})", WriteKey(&self.0))
+    }
+}
+
+/// Write a string literal that evaluates to `s`.
+///
+/// Keys with quotes or backslashes, but nothing else that needs escaping, are written
+/// as raw strings so they stay readable. Everything else is escaped.
+fn write_str_literal(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    let needs_raw = s.contains(|c| c == '"' || c == '\\');
+    let escapes_otherwise = s
+        .chars()
+        .any(|c| c != '"' && c != '\\' && c.escape_debug().nth(1).is_some());
+
+    if !needs_raw || escapes_otherwise {
+        return write!(f, "{:?}", s);
+    }
+
+    // Use one more `#` than the longest run of them after a quote.
+    let mut hashes = 0;
+    for (i, _) in s.match_indices('"') {
+        let run = s[i + 1..].chars().take_while(|&c| c == '#').count();
+        hashes = cmp::max(hashes, run + 1);
+    }
+
+    write!(f, "r")?;
+    for _ in 0..hashes {
+        write!(f, "#")?;
+    }
+    write!(f, "\"{}\"", s)?;
+    for _ in 0..hashes {
+        write!(f, "#")?;
+    }
+    Ok(())
+}
+
+struct WriteKey<'a, T>(&'a T);
+
+/// Write a key with its implementation of [`Key`].
+fn write_key<T: Key>(out: &mut dyn Write, key: &T) -> fmt::Result {
+    write!(out, "{}", WriteKey(key))
+}
+
+impl<'a, T: Key> fmt::Display for WriteKey<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.format(f)
+    }
+}
+
+/// Writes the expression that creates a graph over `NODES`.
+struct WriteGraph {
+    start: usize,
+    min_key_len: usize,
+    max_key_len: usize,
+}
+
+impl WriteGraph {
+    fn of<Input: Segmentable, Output>(graph: &Graph<'_, '_, Input, Output>) -> Self {
+        WriteGraph {
+            start: graph.start(),
+            min_key_len: graph.min_key_len(),
+            max_key_len: graph.max_key_len(),
+        }
+    }
+}
+
+impl fmt::Display for WriteGraph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "intern_str::Graph::with_key_lengths(NODES, {}, {}, {})",
+            self.start,
+            self.min_key_len,
+            Index(self.max_key_len),
+        )
     }
 }
 
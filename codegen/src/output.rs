@@ -0,0 +1,94 @@
+//! Ready-made `write_output` functions for the common kinds of outputs.
+//!
+//! Each of these can be passed straight to [`generate`](crate::generate) and the other
+//! generators in place of a closure. Outputs of graphs from the builder are `Option`s,
+//! so wrap the writer for the values in [`some`]:
+//!
+//! ```
+//! use intern_str::builder::{Builder, Utf8Graph};
+//! use intern_str_codegen::{generate, output};
+//!
+//! #[derive(Debug)]
+//! enum Method {
+//!     Get,
+//!     Post,
+//! }
+//!
+//! let mut builder = Builder::<_, Utf8Graph>::new();
+//! builder.add("GET", Method::Get).unwrap();
+//! builder.add("POST", Method::Post).unwrap();
+//!
+//! let mut buffer = Vec::new();
+//! let graph = builder.build(&mut buffer);
+//! let code = generate(
+//!     &graph,
+//!     "&'static str",
+//!     "Option<Method>",
+//!     output::some(output::enum_path("Method")),
+//! );
+//!
+//! assert!(code.contains("Some(Method::Post),"));
+//! ```
+
+use crate::WriteKey;
+
+use core::fmt::{self, Write};
+use core::write;
+
+/// Write an output with its `Debug` implementation.
+///
+/// This is right for outputs whose `Debug` output is also the expression that creates
+/// them, such as integers, `bool`s, `char`s, and tuples and arrays of them.
+pub fn debug<T: fmt::Debug + ?Sized>(f: &mut dyn Write, value: &T) -> fmt::Result {
+    write!(f, "{:?}", value)
+}
+
+/// Write an integer output in decimal.
+///
+/// Unlike [`debug`], this never adds a type suffix or any other formatting, so the
+/// integer takes on the output type of the graph.
+pub fn integer<T: fmt::Display>(f: &mut dyn Write, value: &T) -> fmt::Result {
+    write!(f, "{}", value)
+}
+
+/// Write a string output as a string literal.
+///
+/// The string is escaped as needed, and strings with quotes or backslashes are written
+/// as raw strings so they stay readable.
+pub fn string<T: AsRef<str> + ?Sized>(f: &mut dyn Write, value: &T) -> fmt::Result {
+    write!(f, "{}", WriteKey(&value.as_ref()))
+}
+
+/// Write an enum output as the path to its variant, in the enum named `path`.
+///
+/// The variant is written with its `Debug` implementation, which is its name for unit
+/// variants. `path` can be any path to the enum, such as `crate::Method`.
+pub fn enum_path<'a, T: fmt::Debug + ?Sized>(
+    path: &'a str,
+) -> impl FnMut(&mut dyn Write, &T) -> fmt::Result + 'a {
+    move |f, value| write!(f, "{}::{:?}", path, value)
+}
+
+/// Write an `Option` output, with `write_value` writing the value inside of `Some`.
+///
+/// Inputs that aren't keys give `None`, which is written as it is. Generators that
+/// work from a builder, such as `generate_fallback`, pass outputs that borrow from
+/// it, so call the writer from a closure for those:
+///
+/// ```ignore
+/// generate_fallback(out, &builder, name, input_type, output_type, |f, out| {
+///     output::some(output::string)(f, out)
+/// })
+/// ```
+pub fn some<T>(
+    mut write_value: impl FnMut(&mut dyn Write, &T) -> fmt::Result,
+) -> impl FnMut(&mut dyn Write, &Option<T>) -> fmt::Result {
+    move |f, value| match value {
+        Some(value) => {
+            write!(f, "Some(")?;
+            write_value(f, value)?;
+            write!(f, ")")
+        }
+        None => write!(f, "None"),
+    }
+}
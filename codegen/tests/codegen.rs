@@ -0,0 +1,1525 @@
+#![cfg(feature = "builder")]
+
+use intern_str::builder::{Builder, Utf8Graph};
+use intern_str::Graph;
+
+#[test]
+fn build_streaming() {
+    extern crate alloc;
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use core::fmt::Write;
+    use intern_str_codegen::{generate, generate_streaming};
+
+    let words = ["a", "ab", "abc", "b", "bc", "hello", "help"];
+    let new_builder = || {
+        let mut builder = Builder::<_, Utf8Graph>::new();
+        for (i, word) in words.iter().enumerate() {
+            builder.add(*word, i).unwrap();
+        }
+        builder
+    };
+
+    // The streamed nodes are the same as the built ones.
+    let mut builder = new_builder();
+    let mut nodes = Vec::new();
+    let streamed = builder.build_streaming(|node| nodes.push(node));
+    assert_eq!(streamed.len(), nodes.len());
+
+    let graph = Graph::with_key_lengths(
+        &nodes,
+        streamed.start(),
+        streamed.min_key_len(),
+        streamed.max_key_len(),
+    );
+    for (i, word) in words.iter().enumerate() {
+        assert_eq!(*graph.process(word), Some(&i));
+    }
+    assert_eq!(*graph.process("hel"), None);
+
+    // So is the generated code.
+    let write_output = |f: &mut dyn Write, out: &Option<&usize>| write!(f, "{:?}", out);
+    let mut builder = new_builder();
+    let mut buffer = Vec::new();
+    let expected = generate(
+        &builder.build_ref(&mut buffer),
+        "&'static str",
+        "Option<&'static usize>",
+        write_output,
+    );
+
+    let mut builder = new_builder();
+    let mut code = String::new();
+    generate_streaming(
+        &mut builder,
+        &mut code,
+        "&'static str",
+        "Option<&'static usize>",
+        write_output,
+    )
+    .unwrap();
+    assert_eq!(code, expected);
+}
+
+#[test]
+fn metadata() {
+    extern crate alloc;
+    use alloc::string::String;
+    use core::fmt::Write;
+    use intern_str::builder::DuplicatePolicy;
+    use intern_str_codegen::generate_streaming;
+
+    let mut builder = Builder::<u32, Utf8Graph>::new();
+    builder
+        .add_with_meta("text/plain", 1, "mime.types:12 plain text")
+        .unwrap();
+    builder
+        .add_with_meta("text/html", 2, "mime.types:40\nHTML documents")
+        .unwrap();
+    builder.add("text/css", 3).unwrap();
+    assert!(builder.add_with_meta("text/css", 4, "dup").is_err());
+
+    assert_eq!(builder.meta("text/plain"), Some("mime.types:12 plain text"));
+    assert_eq!(builder.meta("text/css"), None);
+    assert_eq!(builder.meta("text/xml"), None);
+
+    // Metadata is only kept along with its value.
+    let mut policies = Builder::<u32, Utf8Graph>::new();
+    policies.add_with_meta("a", 1, "first").unwrap();
+    policies.set_duplicate_policy(DuplicatePolicy::KeepFirst);
+    policies.add_with_meta("a", 2, "kept first").unwrap();
+    assert_eq!(policies.meta("a"), Some("first"));
+    policies.set_duplicate_policy(DuplicatePolicy::Replace);
+    policies.add_with_meta("a", 3, "replaced").unwrap();
+    assert_eq!(policies.meta("a"), Some("replaced"));
+    assert_eq!(policies.get("a"), Some(&3));
+
+    let mut code = String::new();
+    generate_streaming(
+        &mut builder,
+        &mut code,
+        "&'static str",
+        "Option<&'static u32>",
+        |f: &mut dyn Write, out: &Option<&u32>| write!(f, "{:?}", out),
+    )
+    .unwrap();
+
+    let lines = code.lines().map(str::trim).collect::<alloc::vec::Vec<_>>();
+    let plain = lines.iter().position(|line| *line == "Some(1),").unwrap();
+    assert_eq!(lines[plain - 1], "// mime.types:12 plain text");
+    let html = lines.iter().position(|line| *line == "Some(2),").unwrap();
+    assert_eq!(
+        lines[html - 2..html],
+        ["// mime.types:40", "// HTML documents"]
+    );
+    let css = lines.iter().position(|line| *line == "Some(3),").unwrap();
+    assert!(!lines[css - 1].starts_with("//"));
+}
+
+#[test]
+fn generate_into() {
+    extern crate alloc;
+    use alloc::string::String;
+    use core::fmt::{self, Write};
+    use intern_str_codegen::{generate, generate_into, generate_map, generate_map_into};
+
+    // Fails once more than `limit` bytes are written.
+    struct Limited {
+        out: String,
+        limit: usize,
+    }
+
+    impl Write for Limited {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            if self.out.len() + s.len() > self.limit {
+                return Err(fmt::Error);
+            }
+            self.out.push_str(s);
+            Ok(())
+        }
+    }
+
+    let mut builder = Builder::<u32, Utf8Graph>::new();
+    builder.add("hello", 1).unwrap();
+    builder.add("help", 2).unwrap();
+    builder.add("world", 3).unwrap();
+
+    let mut buffer = alloc::vec![];
+    let mut outputs = alloc::vec![];
+    let map = builder.build_map(&mut buffer, &mut outputs);
+    let write_output = |f: &mut dyn Write, out: &usize| write!(f, "{}", out);
+    let graph = map.graph();
+
+    let expected = generate(&graph, "&'static str", "usize", write_output);
+    let mut out = Limited {
+        out: String::new(),
+        limit: usize::MAX,
+    };
+    generate_into(&mut out, &graph, "&'static str", "usize", write_output).unwrap();
+    assert_eq!(out.out, expected);
+
+    let write_value = |f: &mut dyn Write, out: &u32| write!(f, "{}", out);
+    let expected = generate_map(&map, "&'static str", "u32", write_value);
+    let mut out = Limited {
+        out: String::new(),
+        limit: usize::MAX,
+    };
+    generate_map_into(&mut out, &map, "&'static str", "u32", write_value).unwrap();
+    assert_eq!(out.out, expected);
+
+    // Errors from the writer are passed on.
+    let mut out = Limited {
+        out: String::new(),
+        limit: 100,
+    };
+    assert!(generate_map_into(&mut out, &map, "&'static str", "u32", write_value).is_err());
+}
+
+#[cfg(feature = "tokens")]
+#[test]
+fn generate_tokens() {
+    extern crate alloc;
+    use intern_str::builder::IgnoreCase;
+    use intern_str_codegen::{generate, generate_tokens};
+    use proc_macro2::TokenStream;
+
+    let mut builder = Builder::<u32, IgnoreCase<Utf8Graph>>::new();
+    builder.add("hello", 1).unwrap();
+    builder.add("help", 2).unwrap();
+    builder.add("world", 3).unwrap();
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+
+    let input_type = "intern_str::CaseInsensitive<&'static str>";
+    let text = generate(&graph, input_type, "Option<u32>", |f, out| {
+        write!(f, "{:?}", out)
+    });
+    let tokens = generate_tokens(
+        &graph,
+        &input_type.parse().unwrap(),
+        &"Option<u32>".parse().unwrap(),
+        |out| match out {
+            Some(value) => quote_option(Some(*value)),
+            None => quote_option(None),
+        },
+    );
+
+    fn quote_option(value: Option<u32>) -> TokenStream {
+        alloc::format!("{:?}", value).parse().unwrap()
+    }
+
+    // Both produce the same tokens, apart from spacing and trailing commas.
+    let normalize = |code: alloc::string::String| {
+        code.split_whitespace()
+            .collect::<alloc::string::String>()
+            .replace(",)", ")")
+    };
+    let text = text.parse::<TokenStream>().unwrap().to_string();
+    assert_eq!(normalize(tokens.to_string()), normalize(text));
+}
+
+#[test]
+fn escaped_keys() {
+    extern crate alloc;
+    use alloc::string::ToString;
+    use core::fmt;
+    use intern_str_codegen::{generate, Key};
+    use proc_macro2::TokenStream;
+
+    struct Literal<'a>(&'a str);
+
+    impl fmt::Display for Literal<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.0.format(f)
+        }
+    }
+
+    let literal = |key: &str| Literal(key).to_string();
+    assert_eq!(literal("plain"), "\"plain\"");
+    assert_eq!(literal("naïve"), "\"naïve\"");
+    assert_eq!(literal("say \"hi\""), "r#\"say \"hi\"\"#");
+    assert_eq!(literal("C:\\Windows"), "r\"C:\\Windows\"");
+    assert_eq!(literal("\"#"), "r##\"\"#\"##");
+    assert_eq!(literal("tab\there"), "\"tab\\there\"");
+    assert_eq!(literal("\"\n"), "\"\\\"\\n\"");
+    assert_eq!(literal("\0\u{7f}"), "\"\\0\\u{7f}\"");
+    assert_eq!(literal("e\u{301}"), "\"e\\u{301}\"");
+
+    // The generated code for a graph of nasty keys can be tokenized.
+    let keys = [
+        "say \"hi\"",
+        "C:\\Windows",
+        "\"#",
+        "tab\there",
+        "new\nline",
+        "\r\n",
+        "e\u{301}",
+        "日本",
+    ];
+    let mut builder = Builder::<usize, Utf8Graph>::new();
+    for (i, key) in keys.iter().enumerate() {
+        builder.add(*key, i).unwrap();
+    }
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    for (i, key) in keys.iter().enumerate() {
+        assert_eq!(*graph.process(key), Some(i));
+    }
+
+    let code = generate(&graph, "&'static str", "Option<usize>", |f, out| {
+        write!(f, "{:?}", out)
+    });
+    assert!(code.parse::<TokenStream>().is_ok());
+}
+
+#[test]
+fn byte_string_keys() {
+    extern crate alloc;
+    use alloc::string::ToString;
+    use core::fmt;
+    use intern_str::builder::ByteGraph;
+    use intern_str_codegen::{generate, Key};
+    use proc_macro2::TokenStream;
+
+    struct Literal<'a, T>(&'a [T]);
+
+    impl<T: intern_str_codegen::KeyUnit> fmt::Display for Literal<'_, T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.0.format(f)
+        }
+    }
+
+    assert_eq!(Literal(b"hello").to_string(), "b\"hello\"");
+    assert_eq!(
+        Literal(b"\"\\\n\x00\xff'").to_string(),
+        "b\"\\\"\\\\\\n\\x00\\xff\\'\""
+    );
+    assert_eq!(Literal(&[1u16, 2]).to_string(), "&[1, 2]");
+
+    let mut builder = Builder::<u8, ByteGraph>::new();
+    builder.add_bytes(b"hello".to_vec(), 1).unwrap();
+    builder.add_bytes(b"help\xff".to_vec(), 2).unwrap();
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    let code = generate(&graph, "&'static [u8]", "Option<u8>", |f, out| {
+        write!(f, "{:?}", out)
+    });
+    assert!(code.contains("(b\"hel\", "));
+    assert!(code.contains("(b\"p\\xff\", "));
+    assert!(code.parse::<TokenStream>().is_ok());
+}
+
+#[test]
+fn use_static() {
+    extern crate alloc;
+    use core::fmt::Write;
+    use intern_str_codegen::{generate, Options};
+    use proc_macro2::TokenStream;
+
+    let mut builder = Builder::<u32, Utf8Graph>::new();
+    builder.add("hello", 1).unwrap();
+    builder.add("help", 2).unwrap();
+
+    let mut buffer = alloc::vec![];
+    let mut outputs = alloc::vec![];
+    let map = builder.build_map(&mut buffer, &mut outputs);
+    let graph = map.graph();
+    let write_output = |f: &mut dyn Write, out: &usize| write!(f, "{}", out);
+
+    // The default options generate the same code as the free functions.
+    assert_eq!(
+        Options::new().generate(&graph, "&'static str", "usize", write_output),
+        generate(&graph, "&'static str", "usize", write_output)
+    );
+
+    let options = Options::new().use_static(true);
+    let code = options.generate(&graph, "&'static str", "usize", write_output);
+    assert!(code.contains("static NODES: "));
+    assert!(code.contains("static GRAPH: "));
+    assert!(!code.contains("const "));
+    assert!(code.parse::<TokenStream>().is_ok());
+
+    let code = options.generate_map(&map, "&'static str", "u32", |f, out| write!(f, "{}", out));
+    assert!(code.contains("static OUTPUTS: "));
+    assert!(code.contains("static MAP: "));
+    assert!(!code.contains("const "));
+
+    let code = options.generate_shared(&[("FIRST", graph)], "&'static str", "usize", write_output);
+    assert!(code.contains("pub static FIRST: "));
+}
+
+#[test]
+fn compact() {
+    extern crate alloc;
+    use alloc::string::{String, ToString};
+    use core::fmt::Write;
+    use intern_str_codegen::{generate, generate_streaming, Options};
+    use proc_macro2::TokenStream;
+
+    fn builder() -> Builder<u32, Utf8Graph> {
+        let mut builder = Builder::new();
+        builder.add("hello", 1).unwrap();
+        builder.add_with_meta("help", 2, "from line 2").unwrap();
+        builder.add("world", 3).unwrap();
+        builder
+    }
+
+    // Parse the code and drop the trailing commas that only the pretty code has.
+    fn tokens(code: &str) -> String {
+        code.parse::<TokenStream>()
+            .unwrap()
+            .to_string()
+            .replace(" ,)", ")")
+            .replace(" ,]", "]")
+    }
+
+    let mut builder1 = builder();
+    let mut buffer = alloc::vec![];
+    let graph = builder1.build(&mut buffer);
+    let write_output = |f: &mut dyn Write, out: &Option<u32>| write!(f, "{:?}", out);
+
+    let pretty = generate(&graph, "&'static str", "Option<u32>", write_output);
+    let compact =
+        Options::new()
+            .compact(true)
+            .generate(&graph, "&'static str", "Option<u32>", write_output);
+
+    // The same code, with less whitespace.
+    assert!(compact.len() < pretty.len() / 2);
+    assert!(!compact.contains("  "));
+    assert_eq!(tokens(&compact), tokens(&pretty));
+
+    // Each node is on its own line, with comments above it.
+    let write_output = |f: &mut dyn Write, out: &Option<&u32>| write!(f, "{:?}", out);
+    let (mut builder1, mut builder2) = (builder(), builder());
+    let mut compact = String::new();
+    Options::new()
+        .compact(true)
+        .generate_streaming(
+            &mut builder1,
+            &mut compact,
+            "&'static str",
+            "Option<u32>",
+            write_output,
+        )
+        .unwrap();
+    let mut pretty = String::new();
+    generate_streaming(
+        &mut builder2,
+        &mut pretty,
+        "&'static str",
+        "Option<u32>",
+        write_output,
+    )
+    .unwrap();
+
+    assert!(compact.contains("\n// from line 2\nintern_str::Node::new(&[],Some(2),"));
+    assert_eq!(tokens(&compact), tokens(&pretty));
+}
+
+#[cfg(feature = "tokens")]
+#[test]
+fn attributes() {
+    extern crate alloc;
+    use alloc::string::{String, ToString};
+    use core::fmt::Write;
+    use intern_str_codegen::Options;
+    use proc_macro2::TokenStream;
+
+    let mut builder = Builder::<u32, Utf8Graph>::new();
+    builder.add("hello", 1).unwrap();
+    builder.add("world", 2).unwrap();
+
+    let options = Options::new()
+        .attribute("allow(clippy::all)")
+        .attribute("rustfmt::skip");
+    let write_output = |f: &mut dyn Write, out: &Option<u32>| write!(f, "{:?}", out);
+
+    // Functions get the attributes, but not the items inside of them.
+    let mut code = String::new();
+    options
+        .generate_fallback(
+            &mut code,
+            &builder,
+            "lookup",
+            "&str",
+            "Option<u32>",
+            |f, out| write!(f, "{:?}", out),
+        )
+        .unwrap();
+    assert!(code.starts_with("#[allow(clippy::all)]\n#[rustfmt::skip]\npub fn lookup("));
+
+    let mut code = String::new();
+    options
+        .generate_by_length(
+            &mut code,
+            &builder,
+            "lookup",
+            "&'static str",
+            "Option<u32>",
+            |f, out| write!(f, "{:?}", out),
+        )
+        .unwrap();
+    assert!(code.starts_with("#[allow(clippy::all)]\n#[rustfmt::skip]\npub fn lookup("));
+    assert_eq!(code.matches("#[rustfmt::skip]").count(), 1);
+
+    // Each item in the block of an expression gets the attributes.
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    let code = options.generate(&graph, "&'static str", "Option<u32>", write_output);
+    assert!(code.contains("    #[allow(clippy::all)]\n    #[rustfmt::skip]\n    const NODES: "));
+    assert!(code.contains("    #[allow(clippy::all)]\n    #[rustfmt::skip]\n    const GRAPH: "));
+    assert!(code.parse::<TokenStream>().is_ok());
+
+    let tokens = options
+        .generate_tokens(
+            &graph,
+            &"&'static str".parse().unwrap(),
+            &"Option<u32>".parse().unwrap(),
+            |out| {
+                out.map_or("None".parse().unwrap(), |v| {
+                    alloc::format!("Some({})", v).parse().unwrap()
+                })
+            },
+        )
+        .to_string();
+    assert_eq!(tokens.matches("# [allow (clippy :: all)]").count(), 2);
+    assert_eq!(tokens.matches("# [rustfmt :: skip]").count(), 2);
+
+    // Without any attributes, nothing changes.
+    let code = intern_str_codegen::generate(&graph, "&'static str", "Option<u32>", write_output);
+    assert!(!code.contains("#["));
+}
+
+#[test]
+fn split_nodes() {
+    extern crate alloc;
+    use core::fmt::Write;
+    use intern_str_codegen::Options;
+    use proc_macro2::TokenStream;
+
+    let mut builder = Builder::<u32, Utf8Graph>::new();
+    builder.add("hello", 1).unwrap();
+    builder.add("help", 2).unwrap();
+    builder.add("world", 3).unwrap();
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    let nodes = graph.nodes().len();
+    let write_output = |f: &mut dyn Write, out: &Option<u32>| write!(f, "{:?}", out);
+
+    let code = Options::new().split_nodes(2).compact(true).generate(
+        &graph,
+        "&'static str",
+        "Option<u32>",
+        write_output,
+    );
+    let parts = (nodes + 1) / 2;
+    for part in 0..parts {
+        assert!(code.contains(&alloc::format!(
+            "const NODES_{}: [intern_str::Node<'static, &'static str, Option<u32>>; 2] = [\n",
+            part
+        )));
+    }
+    assert!(!code.contains(&alloc::format!("NODES_{}:", parts)));
+    assert!(code.contains(
+        "const NODES: &[intern_str::Node<'static, &'static str, Option<u32>>] = PARTS.as_flattened();\n"
+    ));
+    assert_eq!(code.matches("intern_str::Node::new(").count(), parts * 2);
+    if nodes % 2 != 0 {
+        assert!(code.contains(&alloc::format!(
+            "intern_str::Node::new(&[],None,{},core::usize::MAX),\n];",
+            nodes
+        )));
+    }
+    assert!(code.parse::<TokenStream>().is_ok());
+
+    // Statics still get split into `const` parts.
+    let code = Options::new().split_nodes(100).use_static(true).generate(
+        &graph,
+        "&'static str",
+        "Option<u32>",
+        write_output,
+    );
+    assert!(code.contains("    const NODES_0: "));
+    assert!(code.contains("    const PARTS: "));
+    assert!(code.contains("= &[NODES_0];\n"));
+    assert!(code.contains("    static NODES: "));
+    assert_eq!(code.matches("intern_str::Node::new(").count(), 100);
+    assert!(code.parse::<TokenStream>().is_ok());
+}
+
+#[test]
+fn expr_outputs() {
+    extern crate alloc;
+    use alloc::string::ToString;
+    use intern_str_codegen::{generate_map, Expr};
+    use proc_macro2::TokenStream;
+
+    let mut builder = Builder::<Expr, Utf8Graph>::new();
+    builder.add("add", Expr::new("commands::add")).unwrap();
+    builder.add("rm", "commands::remove".into()).unwrap();
+    builder
+        .add("del", "commands::remove".to_string().into())
+        .unwrap();
+    builder.dedup_outputs();
+
+    let mut nodes = alloc::vec![];
+    let mut outputs = alloc::vec![];
+    let map = builder.build_map(&mut nodes, &mut outputs);
+    let code = generate_map(&map, "&'static str", "fn(&[&str]) -> usize", |f, out| {
+        write!(f, "{:?}", out)
+    });
+
+    // Each output is written as its code, and equal code is only written once.
+    assert!(code.contains("        commands::add,\n"));
+    assert_eq!(code.matches("commands::remove").count(), 1);
+    assert!(code.parse::<TokenStream>().is_ok());
+
+    let expr = Expr::new("&PAYLOADS[3]");
+    assert_eq!(expr.as_str(), "&PAYLOADS[3]");
+    assert_eq!(
+        alloc::format!("{:?} {}", expr, expr),
+        "&PAYLOADS[3] &PAYLOADS[3]"
+    );
+}
+
+#[test]
+fn generate_with_input() {
+    extern crate alloc;
+    use core::fmt;
+    use intern_str::builder::IgnoreCase;
+    use intern_str::{CaseInsensitive, Node, Segmentable};
+    use intern_str_codegen::{generate, generate_with_input, Key};
+    use proc_macro2::TokenStream;
+
+    let mut builder = Builder::<u32, IgnoreCase<Utf8Graph>>::new();
+    builder.add("Hello", 1).unwrap();
+    builder.add("help", 2).unwrap();
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+
+    let code = generate_with_input(
+        &graph,
+        "my_crate::Folded<&'static str>",
+        "Option<u32>",
+        |f, key| write!(f, "my_crate::Folded({:?})", key.0),
+        |f, out| write!(f, "{:?}", out),
+    );
+    assert!(code.contains("(my_crate::Folded(\"hel\"), "));
+    assert!(!code.contains("CaseInsensitive"));
+    assert!(code.parse::<TokenStream>().is_ok());
+
+    // A newtype can implement `Key` itself.
+    #[derive(PartialEq, Eq, PartialOrd, Ord)]
+    struct Token<'a>(&'a str);
+
+    impl Segmentable for Token<'_> {
+        fn split(self, at: usize) -> Option<(Self, Self)> {
+            Segmentable::split(self.0, at).map(|(a, b)| (Token(a), Token(b)))
+        }
+
+        fn len(&self) -> usize {
+            Segmentable::len(&self.0)
+        }
+    }
+
+    impl Key for Token<'_> {
+        fn format(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Token(")?;
+            CaseInsensitive(self.0).format(f)?;
+            write!(f, ")")
+        }
+    }
+
+    let nodes = [
+        Node::new(&[], None, 0, core::usize::MAX),
+        Node::new(&[], Some(1), 0, 2),
+        Node::new(&[(Token("hi"), 1)], None, 0, 2),
+    ];
+    let graph = Graph::new(&nodes, 2);
+    let code = generate(&graph, "Token<'static>", "Option<u8>", |f, out| {
+        write!(f, "{:?}", out)
+    });
+    assert!(code.contains("(Token(intern_str::CaseInsensitive(\"hi\")), 1),"));
+    assert!(code.parse::<TokenStream>().is_ok());
+}
+
+#[test]
+fn output_writers() {
+    extern crate alloc;
+    use alloc::string::String;
+    use intern_str_codegen::{generate, generate_fallback, output};
+    use proc_macro2::TokenStream;
+
+    let write = |write_output: &mut dyn FnMut(&mut dyn core::fmt::Write) -> core::fmt::Result| {
+        let mut out = String::new();
+        write_output(&mut out).unwrap();
+        out
+    };
+
+    assert_eq!(write(&mut |f| output::debug(f, &(1u8, 'a'))), "(1, 'a')");
+    assert_eq!(write(&mut |f| output::integer(f, &-42i64)), "-42");
+    assert_eq!(write(&mut |f| output::string(f, "a\nb")), "\"a\\nb\"");
+    assert_eq!(
+        write(&mut |f| output::string(f, "say \"hi\"")),
+        "r#\"say \"hi\"\"#"
+    );
+
+    #[derive(Debug)]
+    enum Method {
+        Get,
+    }
+    assert_eq!(
+        write(&mut |f| output::enum_path("crate::Method")(f, &Method::Get)),
+        "crate::Method::Get"
+    );
+    assert_eq!(
+        write(&mut |f| output::some(output::integer)(f, &Some(3))),
+        "Some(3)"
+    );
+    assert_eq!(
+        write(&mut |f| output::some(output::integer::<u8>)(f, &None)),
+        "None"
+    );
+
+    // The writers can be passed straight to the generators.
+    let mut builder = Builder::<&str, Utf8Graph>::new();
+    builder.add("hello", "world").unwrap();
+    builder.add("quote", "\"").unwrap();
+
+    let mut code = String::new();
+    generate_fallback(
+        &mut code,
+        &builder,
+        "lookup",
+        "&str",
+        "Option<&'static str>",
+        |f, out| output::some(output::string)(f, out),
+    )
+    .unwrap();
+    assert!(code.contains("return Some(\"world\");"));
+    assert!(code.contains("return Some(r#\"\"\"#);"));
+    assert!(code.parse::<TokenStream>().is_ok());
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    let code = generate(
+        &graph,
+        "&'static str",
+        "Option<&'static str>",
+        output::some(output::string),
+    );
+    assert!(code.contains("Some(\"world\"),"));
+    assert!(code.parse::<TokenStream>().is_ok());
+}
+
+#[test]
+fn generate_c() {
+    use intern_str::builder::{AsciiGraph, IgnoreCase};
+    use intern_str_codegen::generate_c;
+
+    let write = |f: &mut dyn core::fmt::Write, out: &Option<i32>| match out {
+        Some(out) => write!(f, "{}", out),
+        None => write!(f, "-1"),
+    };
+
+    let mut builder = Builder::<_, Utf8Graph>::new();
+    builder.add("get", 1).unwrap();
+    builder.add("getter", 2).unwrap();
+    builder.add("héllo", 3).unwrap();
+    let mut buffer = Vec::new();
+    let graph = builder.build(&mut buffer);
+    let code = generate_c(&graph, "method", "int", write);
+
+    assert!(code.contains("#ifndef METHOD_H"));
+    assert!(code.contains("} method_node_t;"));
+    assert!(code.contains("static const unsigned char method_keys[] = {"));
+    assert!(code.contains("static int method_lookup(const unsigned char *input, size_t len) {"));
+    assert!(code.trim_end().ends_with("#endif"));
+
+    // Every edge and node is in the tables.
+    let edges: usize = graph.nodes().iter().map(|node| node.inputs().len()).sum();
+    let table = |name: &str| {
+        let start = code.find(name).unwrap();
+        let end = start + code[start..].find("};").unwrap();
+        code[start..end].lines().skip(1).count()
+    };
+    assert_eq!(table("method_edges[]"), edges);
+    assert_eq!(table("method_nodes[]"), graph.nodes().len());
+
+    // Strings are never split inside of a character.
+    assert!(code.contains("(input[at] & 0xc0) == 0x80"));
+    assert!(!code.contains("'A' && a <= 'Z'"));
+
+    let mut builder = Builder::<_, IgnoreCase<AsciiGraph>>::new();
+    builder.add("get", 1).unwrap();
+    let mut buffer = Vec::new();
+    let graph = builder.build(&mut buffer);
+    let code = generate_c(&graph, "method", "int", write);
+
+    assert!(!code.contains("(input[at] & 0xc0) == 0x80"));
+    assert!(code.contains("if (a >= 'A' && a <= 'Z') a += 'a' - 'A';"));
+}
+
+#[test]
+fn generate_flat() {
+    use intern_str_codegen::{generate_flat, Options};
+    use proc_macro2::TokenStream;
+
+    let mut builder = Builder::<_, Utf8Graph>::new();
+    builder.add("get", 1).unwrap();
+    builder.add("getter", 2).unwrap();
+    builder.add("héllo", 1).unwrap();
+    let mut buffer = Vec::new();
+    let graph = builder.build(&mut buffer);
+
+    let write = |f: &mut dyn core::fmt::Write, out: &Option<i32>| write!(f, "{:?}", out);
+    let code = generate_flat(&graph, "&'static str", "Option<i32>", write);
+    assert!(code.parse::<TokenStream>().is_ok());
+
+    // Each node is five entries, after the start node and key lengths.
+    let nodes = 3 + graph.nodes().len() * 5;
+    assert!(code.contains(&format!("const NODES: [u32; {}] = [", nodes)));
+    assert!(code.contains(&format!(
+        "\n        {}, {}, {},\n",
+        graph.start(),
+        graph.min_key_len(),
+        graph.max_key_len()
+    )));
+    let edges: usize = graph.nodes().iter().map(|node| node.inputs().len()).sum();
+    assert!(code.contains(&format!("const EDGES: [u32; {}] = [", edges * 3)));
+    assert!(code.contains("const KEYS: [u8; "));
+
+    // Outputs are only written once.
+    let outputs = code
+        .split("const OUTPUTS: &[Option<i32>] = &[\n")
+        .nth(1)
+        .unwrap();
+    let outputs = &outputs[..outputs.find("    ];").unwrap()];
+    assert_eq!(outputs.lines().count(), 3);
+    for output in &["None,", "Some(1),", "Some(2),"] {
+        assert_eq!(outputs.matches(output).count(), 1);
+    }
+    assert!(code.contains(
+        "const FLAT: intern_str::Flat<'static, &'static str, Option<i32>> = intern_str::Flat::new(&NODES, &EDGES, &KEYS, OUTPUTS);"
+    ));
+    assert!(code.ends_with("    FLAT\n}\n"));
+
+    let code = Options::new().compact(true).use_static(true).generate_flat(
+        &graph,
+        "&'static str",
+        "Option<i32>",
+        write,
+    );
+    assert!(code.contains(&format!(
+        "static NODES: [u32; {}] = [\n{},",
+        nodes,
+        graph.start()
+    )));
+    assert!(code.parse::<TokenStream>().is_ok());
+}
+
+#[cfg(feature = "tokens")]
+#[test]
+fn summary() {
+    extern crate alloc;
+    use alloc::string::ToString;
+    use core::fmt::Write;
+    use intern_str_codegen::{Options, Summary};
+    use proc_macro2::TokenStream;
+
+    let mut builder = Builder::<u32, Utf8Graph>::new();
+    for i in 0..10 {
+        builder.add(alloc::format!("key{}", i), i).unwrap();
+    }
+
+    let summary = Summary::new()
+        .option("case-sensitive")
+        .option("trimmed")
+        .source_hash(0xabcd)
+        .sample(builder.keys());
+    let options = Options::new().summary(summary);
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    let write_output = |f: &mut dyn Write, out: &Option<u32>| write!(f, "{:?}", out);
+
+    let code = options.generate(&graph, "&'static str", "Option<u32>", write_output);
+    assert!(code.contains(
+        "    /// A graph of 10 keys.\n\
+         \x20   ///\n\
+         \x20   /// - Options: case-sensitive, trimmed\n\
+         \x20   /// - Source hash: `0x000000000000abcd`\n\
+         \x20   /// - Keys: `\"key0\"`, `\"key1\"`, `\"key2\"`, `\"key3\"`, `\"key4\"`, `\"key5\"`, \
+         `\"key6\"`, `\"key7\"` and 2 more\n\
+         \x20   const GRAPH: "
+    ));
+    assert!(code.parse::<TokenStream>().is_ok());
+
+    let tokens = options
+        .generate_tokens(
+            &graph,
+            &"&'static str".parse().unwrap(),
+            &"Option<u32>".parse().unwrap(),
+            |out| {
+                out.map_or("None".parse().unwrap(), |v| {
+                    alloc::format!("Some({})", v).parse().unwrap()
+                })
+            },
+        )
+        .to_string();
+    assert!(tokens.contains("# [doc = \" A graph of 10 keys.\"]"));
+
+    // Only the number of keys is known.
+    let options = Options::new().summary(Summary::new().keys(1));
+    let code = options.generate(&graph, "&'static str", "Option<u32>", write_output);
+    assert!(code.contains("    /// A graph of 1 key.\n    const GRAPH: "));
+}
+
+#[test]
+fn generate_tests() {
+    extern crate alloc;
+    use alloc::string::String;
+    use intern_str_codegen::generate_tests;
+    use proc_macro2::TokenStream;
+
+    let mut builder = Builder::<u32, Utf8Graph>::new();
+    builder.add("hello", 1).unwrap();
+    builder.add("help", 2).unwrap();
+    builder.add("hell", 3).unwrap();
+    builder.add("world", 4).unwrap();
+    let keys = builder
+        .iter()
+        .map(|(key, value)| (key, Some(*value)))
+        .collect::<alloc::vec::Vec<_>>();
+
+    let mut code = String::new();
+    generate_tests(
+        &mut code,
+        "GRAPH",
+        keys.iter().map(|(key, value)| (key.as_str(), *value)),
+        &None,
+        |f, out| write!(f, "{:?}", out),
+    )
+    .unwrap();
+
+    assert!(code.starts_with("#[cfg(test)]\nmod tests {\n    use super::*;\n"));
+    assert!(code.contains("        let graph = &GRAPH;\n"));
+
+    // Every key is checked against its value.
+    assert!(code.contains("assert_eq!(*graph.process(\"hello\"), Some(1));"));
+    assert!(code.contains("assert_eq!(*graph.process(\"hell\"), Some(3));"));
+    assert!(code.contains("assert_eq!(*graph.process(\"world\"), Some(4));"));
+
+    // Cutting a key short gives an input that isn't a key, which should miss, but cuts
+    // that are keys aren't checked twice.
+    let mutated = &code[code.find("fn mutated_keys").unwrap()..];
+    assert!(mutated.contains("assert_eq!(*graph.process(\"ello\"), None);"));
+    assert!(mutated.contains("assert_eq!(*graph.process(\"worl\"), None);"));
+    assert!(!mutated.contains("(\"hell\")"));
+    assert!(code.parse::<TokenStream>().is_ok());
+
+    // The expected outputs come from the keys, so a graph that doesn't match them fails.
+    let mut code = String::new();
+    generate_tests(
+        &mut code,
+        "GRAPH",
+        alloc::vec![("hello", 5)],
+        &0,
+        |f, out| write!(f, "{:?}", out),
+    )
+    .unwrap();
+    assert!(code.contains("assert_eq!(*graph.process(\"hello\"), 5);"));
+    assert!(code.contains("assert_eq!(*graph.process(\"hell\"), 0);"));
+}
+
+#[test]
+fn generate_reverse() {
+    extern crate alloc;
+    use alloc::string::String;
+    use intern_str::builder::IgnoreCase;
+    use intern_str_codegen::generate_reverse;
+    use proc_macro2::TokenStream;
+
+    let mut builder = Builder::<u32, IgnoreCase<Utf8Graph>>::new();
+    builder.add("Text/Plain", 1).unwrap();
+    builder
+        .add_with_aliases("application/json", alloc::vec!["text/json"], 2)
+        .unwrap();
+    builder.add_wildcard("image/*", 3).unwrap();
+
+    let mut code = String::new();
+    generate_reverse(&mut code, &builder, "u32", |f, out| write!(f, "{}", out)).unwrap();
+
+    assert_eq!(
+        code,
+        "{\n    \
+         const KEYS: &[(u32, &str)] = &[\n        \
+         (2, \"application/json\"),\n        \
+         (1, \"text/plain\"),\n    \
+         ];\n    \
+         KEYS\n\
+         }\n"
+    );
+    assert!(code.parse::<TokenStream>().is_ok());
+}
+
+#[cfg(feature = "build")]
+#[test]
+fn build_from_file() {
+    use intern_str_codegen::{build_from_file, is_up_to_date, BuildError, BuildOptions};
+    use proc_macro2::TokenStream;
+    use std::fs;
+
+    let dir = std::env::temp_dir().join(format!("intern-str-build-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("keywords.txt"), "# Keywords\nfn\nlet\n\nmatch\n").unwrap();
+    fs::write(
+        dir.join("methods.csv"),
+        "method,value\nGET,Method::Get\nPOST,Method::Post\n",
+    )
+    .unwrap();
+    fs::write(dir.join("bad.txt"), "fn\nfn\n").unwrap();
+    std::env::set_var("OUT_DIR", &dir);
+
+    // A list of keys, where the values are their indices.
+    let out = build_from_file(dir.join("keywords.txt"), &BuildOptions::new()).unwrap();
+    assert_eq!(out, dir.join("keywords.rs"));
+    let code = fs::read_to_string(&out).unwrap();
+    assert!(code.contains("NODES: &[intern_str::Node<'static, &'static str, Option<usize>>]"));
+    assert!(code.contains("Some(0),"));
+    assert!(code.contains("Some(2),"));
+    assert!(!code.contains("Some(3),"));
+    assert!(code.starts_with("// intern-str-codegen input hash: 0x"));
+    assert!(code.parse::<TokenStream>().is_ok());
+
+    // Nothing is written if the file and options haven't changed.
+    fs::write(&out, format!("{}// Untouched.\n", code)).unwrap();
+    build_from_file(dir.join("keywords.txt"), &BuildOptions::new()).unwrap();
+    assert!(fs::read_to_string(&out)
+        .unwrap()
+        .ends_with("// Untouched.\n"));
+
+    let options = BuildOptions::new().value_type("u8");
+    build_from_file(dir.join("keywords.txt"), &options).unwrap();
+    let code = fs::read_to_string(&out).unwrap();
+    assert!(!code.ends_with("// Untouched.\n"));
+    assert!(code.contains("Option<u8>"));
+
+    fs::write(dir.join("keywords.txt"), "fn\nlet\nmatch\nloop\n").unwrap();
+    assert!(is_up_to_date(
+        &out,
+        intern_str_codegen::read_input_hash(&code).unwrap()
+    ));
+    build_from_file(dir.join("keywords.txt"), &options).unwrap();
+    assert!(fs::read_to_string(&out).unwrap().contains("Some(3),"));
+
+    // A CSV file, where the values are expressions.
+    let options = BuildOptions::new()
+        .case_insensitive(true)
+        .value_type("Method")
+        .file_name("methods_graph.rs");
+    let out = build_from_file(dir.join("methods.csv"), &options).unwrap();
+    assert_eq!(out, dir.join("methods_graph.rs"));
+    let code = fs::read_to_string(&out).unwrap();
+    assert!(code.contains("intern_str::CaseInsensitive<&'static str>, Option<Method>>"));
+    assert!(code.contains("Some(Method::Post),"));
+    assert!(code.parse::<TokenStream>().is_ok());
+
+    // Errors are reported.
+    assert!(matches!(
+        build_from_file(dir.join("bad.txt"), &BuildOptions::new()),
+        Err(BuildError::Load(_))
+    ));
+    assert!(matches!(
+        build_from_file(dir.join("missing.txt"), &BuildOptions::new()),
+        Err(BuildError::Io(_))
+    ));
+
+    // A Graphviz file can be written next to the code, with the same code.
+    let options = options.dot(true);
+    let out = build_from_file(dir.join("methods.csv"), &options).unwrap();
+    let without_header = |code: &str| code.splitn(2, '\n').nth(1).unwrap().to_string();
+    assert_eq!(
+        without_header(&fs::read_to_string(&out).unwrap()),
+        without_header(&code)
+    );
+    let dot = fs::read_to_string(dir.join("methods_graph.dot")).unwrap();
+    assert!(dot.starts_with("digraph G {\n"));
+    assert!(dot.contains("[label=\"Some(Method::Get)\"]"));
+    assert!(dot.contains(" [label=\"get\"];"));
+
+    // A manifest can be written next to the code.
+    let options = BuildOptions::new().manifest(true);
+    let out = build_from_file(dir.join("keywords.txt"), &options).unwrap();
+    let code = fs::read_to_string(&out).unwrap();
+    let manifest = fs::read_to_string(dir.join("keywords.json")).unwrap();
+    let hash = intern_str_codegen::read_input_hash(&code).unwrap();
+    assert!(manifest.contains(&format!("\"input_hash\": \"0x{:016x}\",", hash)));
+    assert!(manifest.contains("\"source\": \""));
+    assert!(manifest.contains("keywords.txt\",\n"));
+    assert!(manifest.contains("\"keys\": 4,\n"));
+    assert!(manifest.contains(&format!("\"code_size\": {},\n", code.len())));
+    assert!(manifest.contains("\"value_type\": \"usize\",\n"));
+    assert!(manifest.contains("\"split_nodes\": null\n"));
+    assert!(manifest.contains("\"data_size\": "));
+    assert!(manifest.ends_with("  }\n}\n"));
+
+    // The manifest is written again if it's missing.
+    fs::remove_file(dir.join("keywords.json")).unwrap();
+    build_from_file(dir.join("keywords.txt"), &options).unwrap();
+    assert!(dir.join("keywords.json").exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn input_hash() {
+    use core::hash::Hasher;
+    use intern_str_codegen::{read_input_hash, write_input_hash, InputHasher};
+
+    let mut hasher = InputHasher::new();
+    hasher.write(b"hello");
+    let hash = hasher.finish();
+    assert_eq!(hash, 0xa430_d846_80aa_bd0b);
+
+    // Integers hash the same on every platform.
+    let mut a = InputHasher::new();
+    a.write_usize(1);
+    let mut b = InputHasher::new();
+    b.write(&[1, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(a.finish(), b.finish());
+
+    let mut code = String::new();
+    write_input_hash(&mut code, hash).unwrap();
+    assert_eq!(
+        code,
+        "// intern-str-codegen input hash: 0xa430d84680aabd0b\n"
+    );
+    code.push_str("{}\n");
+    assert_eq!(read_input_hash(&code), Some(hash));
+
+    assert_eq!(read_input_hash("{}\n"), None);
+    assert_eq!(read_input_hash(""), None);
+    assert_eq!(
+        read_input_hash("// intern-str-codegen input hash: nope\n"),
+        None
+    );
+}
+
+#[test]
+fn generate_packed() {
+    extern crate alloc;
+    use alloc::vec::Vec;
+    use intern_str::Packed;
+    use intern_str_codegen::{generate_packed, Options};
+    use proc_macro2::TokenStream;
+
+    let mut builder = Builder::<u32, Utf8Graph>::new();
+    builder.add("hello", 1).unwrap();
+    builder.add("help", 2).unwrap();
+    builder.add("world", 3).unwrap();
+
+    let mut buffer = alloc::vec![];
+    let mut outputs = alloc::vec![];
+    let map = builder.build_map(&mut buffer, &mut outputs);
+    let mut blob = Vec::new();
+    Packed::pack(&map, &mut blob);
+
+    let code = generate_packed(&map, "&'static str", "u32", |f, out| write!(f, "{}", out));
+    assert!(code.contains(&alloc::format!(
+        "    const BLOB: [u8; {}] = [\n",
+        blob.len()
+    )));
+    assert!(code
+        .contains("    const OUTPUTS: &[u32] = &[\n        1,\n        2,\n        3,\n    ];\n"));
+    assert!(code.contains(
+        "    const PACKED: intern_str::Packed<'static, &'static str, u32> = \
+         intern_str::Packed::new(&BLOB, OUTPUTS);\n"
+    ));
+    assert!(code.parse::<TokenStream>().is_ok());
+
+    // The bytes in the code are the packed graph.
+    let start = code.find("= [").unwrap() + 3;
+    let end = code.find("];").unwrap();
+    let bytes = code[start..end]
+        .split(',')
+        .map(str::trim)
+        .filter(|byte| !byte.is_empty())
+        .map(|byte| byte.parse::<u8>().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(bytes, blob);
+
+    let packed = Packed::<&str, u32>::new(&bytes, map.outputs());
+    assert_eq!(packed.get("help"), Some(&2));
+    assert_eq!(packed.get("hell"), None);
+
+    let compact = Options::new()
+        .compact(true)
+        .use_static(true)
+        .generate_packed(&map, "&'static str", "u32", |f, out| write!(f, "{}", out));
+    assert!(compact.contains(&alloc::format!(
+        "static BLOB: [u8; {}] = [\n{},",
+        blob.len(),
+        blob[0]
+    )));
+    assert_eq!(compact.matches('\n').count(), 12);
+}
+
+#[test]
+fn generate_blob() {
+    extern crate alloc;
+    use intern_str::serialize;
+    use intern_str_codegen::generate_blob;
+    use proc_macro2::TokenStream;
+
+    let mut builder = Builder::<u32, Utf8Graph>::new();
+    builder.add("hello", 1).unwrap();
+    builder.add("world", 2).unwrap();
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+
+    let mut blob = alloc::vec![];
+    let code = generate_blob(
+        &graph,
+        &mut blob,
+        "/out/graph.bin",
+        "load_graph",
+        "&'static str",
+        "Option<u32>",
+        |output, out| {
+            if let Some(output) = output {
+                out.extend_from_slice(&output.to_le_bytes());
+            }
+        },
+        "read_output",
+    );
+
+    assert_eq!(
+        code,
+        "pub fn load_graph<'nodes>(nodes: &'nodes mut Vec<intern_str::Node<'static, &'static str, Option<u32>>>) \
+         -> intern_str::Graph<'static, 'nodes, &'static str, Option<u32>> {\n    \
+         const BLOB: &[u8] = include_bytes!(\"/out/graph.bin\");\n    \
+         intern_str::serialize::read(BLOB, nodes, read_output).expect(\"the serialized graph is invalid\")\n\
+         }\n"
+    );
+    assert!(code.parse::<TokenStream>().is_ok());
+
+    // The blob is the serialized graph.
+    let mut nodes = alloc::vec![];
+    let loaded =
+        serialize::read::<&str, Option<u32>>(&blob, &mut nodes, |bytes| match bytes.len() {
+            0 => Some(None),
+            4 => Some(Some(u32::from_le_bytes([
+                bytes[0], bytes[1], bytes[2], bytes[3],
+            ]))),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(loaded.fingerprint(), graph.fingerprint());
+}
+
+#[test]
+fn generate_variants() {
+    extern crate alloc;
+    use intern_str::builder::IgnoreCase;
+    use intern_str_codegen::{generate, generate_variants, Variant};
+    use proc_macro2::TokenStream;
+
+    let mut full = Builder::<u32, Utf8Graph>::new();
+    full.add("hello", 1).unwrap();
+    full.add("world", 2).unwrap();
+    let mut buffer = alloc::vec![];
+    let full = generate(
+        &full.build(&mut buffer),
+        "&'static str",
+        "Option<u32>",
+        |f, out| write!(f, "{:?}", out),
+    );
+
+    let mut minimal = Builder::<u32, Utf8Graph>::new();
+    minimal.add("hello", 1).unwrap();
+    let mut buffer = alloc::vec![];
+    let minimal = generate(
+        &minimal.build(&mut buffer),
+        "&'static str",
+        "Option<u32>",
+        |f, out| write!(f, "{:?}", out),
+    );
+
+    let mut folded = Builder::<u32, IgnoreCase<Utf8Graph>>::new();
+    folded.add("hello", 1).unwrap();
+    let mut buffer = alloc::vec![];
+    let folded = generate(
+        &folded.build(&mut buffer),
+        "intern_str::CaseInsensitive<&'static str>",
+        "Option<u32>",
+        |f, out| write!(f, "{:?}", out),
+    );
+
+    let ty = "intern_str::Graph<'static, 'static, &'static str, Option<u32>>";
+    let folded_ty =
+        "intern_str::Graph<'static, 'static, intern_str::CaseInsensitive<&'static str>, Option<u32>>";
+    let code = generate_variants(
+        "GRAPH",
+        &[
+            Variant::new(folded_ty, &folded).feature("case-insensitive"),
+            Variant::new(ty, &minimal).feature("minimal"),
+            Variant::new(ty, &full),
+        ],
+    );
+
+    let cfgs = code
+        .lines()
+        .filter(|line| line.starts_with("#[cfg"))
+        .collect::<alloc::vec::Vec<_>>();
+    assert_eq!(
+        cfgs,
+        [
+            "#[cfg(feature = \"case-insensitive\")]",
+            "#[cfg(all(feature = \"minimal\", not(any(feature = \"case-insensitive\"))))]",
+            "#[cfg(not(any(feature = \"case-insensitive\", feature = \"minimal\")))]",
+        ]
+    );
+    assert!(code.contains(&alloc::format!(
+        "#[cfg(not(any(feature = \"case-insensitive\", feature = \"minimal\")))]\n\
+         pub const GRAPH: {} = {};\n",
+        ty,
+        full.trim_end()
+    )));
+    assert!(code.parse::<TokenStream>().is_ok());
+
+    // A single variant doesn't need a `cfg`.
+    let code = generate_variants("GRAPH", &[Variant::new(ty, &full)]);
+    assert!(code.starts_with("pub const GRAPH: "));
+}
+
+#[test]
+fn generate_fallback() {
+    extern crate alloc;
+    use alloc::string::String;
+    use intern_str::builder::IgnoreCase;
+    use intern_str_codegen::generate_fallback;
+    use proc_macro2::TokenStream;
+
+    let mut builder = Builder::<u32, IgnoreCase<Utf8Graph>>::new();
+    builder.add("Hello", 1).unwrap();
+    builder.add("world", 2).unwrap();
+
+    let mut code = String::new();
+    generate_fallback(
+        &mut code,
+        &builder,
+        "lookup",
+        "intern_str::CaseInsensitive<&str>",
+        "Option<u32>",
+        |f, out| write!(f, "{:?}", out),
+    )
+    .unwrap();
+
+    assert_eq!(
+        code,
+        "pub fn lookup(input: intern_str::CaseInsensitive<&str>) -> Option<u32> {\n    \
+         if input == intern_str::CaseInsensitive(\"hello\") {\n        \
+         return Some(1);\n    \
+         }\n    \
+         if input == intern_str::CaseInsensitive(\"world\") {\n        \
+         return Some(2);\n    \
+         }\n    \
+         None\n\
+         }\n"
+    );
+    assert!(code.parse::<TokenStream>().is_ok());
+}
+
+#[test]
+fn generate_preserve_case() {
+    extern crate alloc;
+    use alloc::string::String;
+    use intern_str::builder::IgnoreCase;
+    use intern_str_codegen::{
+        generate_by_length, generate_enum, generate_fallback, generate_reverse, Naming,
+    };
+    use proc_macro2::TokenStream;
+
+    let mut builder = Builder::<u32, IgnoreCase<Utf8Graph>>::new();
+    builder.preserve_case();
+    builder.add("Content-Type", 1).unwrap();
+    builder
+        .add_with_aliases("X-Request-ID", alloc::vec!["Request-Id"], 2)
+        .unwrap();
+
+    let mut code = String::new();
+    generate_fallback(
+        &mut code,
+        &builder,
+        "lookup",
+        "intern_str::CaseInsensitive<&str>",
+        "Option<u32>",
+        |f, out| write!(f, "{:?}", out),
+    )
+    .unwrap();
+    assert!(code.contains("if input == intern_str::CaseInsensitive(\"Content-Type\") {"));
+    assert!(code.contains("if input == intern_str::CaseInsensitive(\"Request-Id\") {"));
+    assert!(code.contains("if input == intern_str::CaseInsensitive(\"X-Request-ID\") {"));
+
+    let mut code = String::new();
+    generate_reverse(&mut code, &builder, "u32", |f, out| write!(f, "{}", out)).unwrap();
+    assert!(code.contains("(1, \"Content-Type\"),\n        (2, \"X-Request-ID\"),\n"));
+
+    let mut code = String::new();
+    generate_by_length(
+        &mut code,
+        &builder,
+        "lookup",
+        "intern_str::CaseInsensitive<&'static str>",
+        "Option<u32>",
+        |f, out| write!(f, "{:?}", out),
+    )
+    .unwrap();
+    assert!(code.contains("(intern_str::CaseInsensitive(\"Request-Id\"), 1),"));
+    assert!(code.contains("(intern_str::CaseInsensitive(\"X-Request-ID\"), 2),"));
+    assert!(code.parse::<TokenStream>().is_ok());
+
+    // Keys that are passed to `generate_enum` keep their casing in the graph too.
+    let code = generate_enum::<IgnoreCase<Utf8Graph>, _>(
+        "Header",
+        "intern_str::CaseInsensitive<&'static str>",
+        &["Content-Type", "ETag"],
+        Naming::UpperCamel,
+    )
+    .unwrap();
+    assert!(code.contains("(intern_str::CaseInsensitive(\"ETag\"), 3),"));
+    assert!(code.contains("(intern_str::CaseInsensitive(\"ent-Type\"), 1),"));
+    assert!(code.contains("            Header::Etag => \"ETag\",\n"));
+}
+
+#[test]
+fn generate_by_length() {
+    extern crate alloc;
+    use alloc::string::String;
+    use intern_str_codegen::{generate_by_length, Options};
+    use proc_macro2::TokenStream;
+
+    let mut builder = Builder::<u32, Utf8Graph>::new();
+    builder.add("get", 1).unwrap();
+    builder.add("post", 2).unwrap();
+    builder.add("put", 3).unwrap();
+
+    let mut code = String::new();
+    generate_by_length(
+        &mut code,
+        &builder,
+        "lookup",
+        "&'static str",
+        "Option<u32>",
+        |f, out| write!(f, "{:?}", out),
+    )
+    .unwrap();
+
+    assert!(code.starts_with(
+        "pub fn lookup(input: &'static str) -> &'static Option<u32> {\n    \
+         match intern_str::Segmentable::len(&input) {\n        \
+         3 => {\n            \
+         static GRAPH: intern_str::Graph<'static, 'static, &'static str, Option<u32>> = {\n"
+    ));
+    assert!(code.contains("(\"get\", 1),"));
+    assert!(code.contains(
+        "            GRAPH.process(input)\n        \
+         }\n        \
+         4 => {\n"
+    ));
+    assert!(code.contains("(\"post\", 1),"));
+    assert!(code.ends_with(
+        "        _ => {\n            \
+         static MISS: Option<u32> = None;\n            \
+         &MISS\n        \
+         }\n    \
+         }\n\
+         }\n"
+    ));
+    assert!(code.parse::<TokenStream>().is_ok());
+
+    // The graphs for each length don't repeat the summary.
+    let mut code = String::new();
+    Options::new()
+        .summary(intern_str_codegen::Summary::new().keys(3))
+        .generate_by_length(
+            &mut code,
+            &builder,
+            "lookup",
+            "&'static str",
+            "Option<u32>",
+            |f, out| write!(f, "{:?}", out),
+        )
+        .unwrap();
+    assert!(code.starts_with("/// A graph of 3 keys.\npub fn lookup("));
+    assert_eq!(code.matches("A graph of").count(), 1);
+}
+
+#[test]
+fn generate_enum() {
+    extern crate alloc;
+    use alloc::string::String;
+    use intern_str::builder::IgnoreCase;
+    use intern_str_codegen::{generate_enum, Naming};
+    use proc_macro2::TokenStream;
+
+    let keys = ["text/plain", "text-plain", "404", "+", "self"];
+    let code =
+        generate_enum::<Utf8Graph, _>("Kind", "&'static str", &keys, Naming::UpperCamel).unwrap();
+
+    assert!(code.starts_with(
+        "#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]\n\
+         pub enum Kind {\n    \
+         TextPlain,\n    \
+         TextPlain2,\n    \
+         _404,\n    \
+         Key3,\n    \
+         Self_,\n\
+         }\n"
+    ));
+    assert!(code.contains(
+        "    pub const GRAPH: intern_str::Graph<'static, 'static, &'static str, Option<Kind>> = {\n        \
+         const NODES"
+    ));
+    assert!(code.contains("Some(Kind::TextPlain2),"));
+    assert!(code.contains("            Kind::TextPlain2 => \"text-plain\",\n"));
+    assert!(code.contains("            Kind::Key3 => \"+\",\n"));
+    assert!(code.parse::<TokenStream>().is_ok());
+
+    let code = generate_enum::<IgnoreCase<Utf8Graph>, _>(
+        "Method",
+        "intern_str::CaseInsensitive<&'static str>",
+        &["GET", "post-form"],
+        Naming::ScreamingSnake,
+    )
+    .unwrap();
+    assert!(code.contains(
+        "#[allow(non_camel_case_types)]\npub enum Method {\n    GET,\n    POST_FORM,\n}"
+    ));
+    assert!(code.contains("            Method::GET => \"GET\",\n"));
+
+    fn shout(key: &str) -> String {
+        alloc::format!("{}!", key)
+    }
+    let code =
+        generate_enum::<Utf8Graph, _>("Shout", "&'static str", &["hi"], Naming::Custom(shout))
+            .unwrap();
+    assert!(code.contains("pub enum Shout {\n    hi_,\n}"));
+
+    // Keys have to be distinct.
+    assert!(generate_enum::<IgnoreCase<Utf8Graph>, _>(
+        "Dup",
+        "&'static str",
+        &["a", "A"],
+        Naming::UpperCamel
+    )
+    .is_err());
+}
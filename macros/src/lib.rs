@@ -0,0 +1,197 @@
+//! Declare `intern-str` graphs inline.
+//!
+//! The [`intern_map!`] macro builds a graph from a list of keys and values when the
+//! crate that uses it is compiled, and expands to the nodes of the graph. This works
+//! like generating the graph with `intern-str-codegen` in a build script, without the
+//! build script.
+//!
+//! ## Example
+//!
+//! ```
+//! use intern_str::{CaseInsensitive, Graph};
+//! use intern_str_macros::intern_map;
+//!
+//! #[derive(Debug, Clone, Copy, PartialEq)]
+//! enum Method {
+//!     Get,
+//!     Post,
+//! }
+//!
+//! const METHODS: Graph<'static, 'static, CaseInsensitive<&'static str>, Option<Method>> = intern_map! {
+//!     case_insensitive,
+//!     "GET" => Method::Get,
+//!     "POST" => Method::Post,
+//! };
+//!
+//! assert_eq!(*METHODS.process(CaseInsensitive("get")), Some(Method::Get));
+//! assert_eq!(*METHODS.process(CaseInsensitive("PUT")), None);
+//! ```
+
+#![forbid(
+    unsafe_code,
+    missing_docs,
+    missing_debug_implementations,
+    missing_copy_implementations,
+    future_incompatible,
+    rust_2018_idioms
+)]
+
+use intern_str::builder::{Builder, GraphType, IgnoreCase, Utf8Graph};
+use intern_str::CaseInsensitive;
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{parse_macro_input, Expr, Ident, LitStr, Token};
+
+/// Build a graph from a list of keys and values.
+///
+/// The keys are string literals, and the values are constant expressions of any type
+/// `T`. The macro expands to an expression of type `Graph<'static, 'static, Input,
+/// Option<T>>`, where inputs that aren't keys produce `None`. The nodes are laid out
+/// in an inline `const` block, so the expression can be used in a `const` or `static`
+/// as well as in a function, and needs Rust 1.79 or later.
+///
+/// By default, keys are matched exactly and `Input` is `&'static str`. If the list
+/// starts with `case_insensitive`, keys are matched case-insensitively and `Input` is
+/// `CaseInsensitive<&'static str>`.
+///
+/// Keys that are empty or appear twice are reported as compile errors.
+#[proc_macro]
+pub fn intern_map(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let map = parse_macro_input!(input as InternMap);
+
+    let result = match &map.mode {
+        Some(mode) if mode == "case_insensitive" => expand::<IgnoreCase<Utf8Graph>>(&map),
+        Some(mode) => Err(syn::Error::new(
+            mode.span(),
+            "expected `case_insensitive` or a key",
+        )),
+        None => expand::<Utf8Graph>(&map),
+    };
+
+    result.unwrap_or_else(|err| err.to_compile_error()).into()
+}
+
+/// The input to [`intern_map!`].
+struct InternMap {
+    /// The way that keys are matched, if it isn't exact.
+    mode: Option<Ident>,
+
+    /// The keys and values.
+    entries: Punctuated<MapEntry, Token![,]>,
+}
+
+/// A key and its value.
+struct MapEntry {
+    /// The key.
+    key: LitStr,
+
+    /// The expression for the value.
+    value: Expr,
+}
+
+impl Parse for InternMap {
+    fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
+        let mut mode = None;
+        if input.peek(Ident) {
+            mode = Some(input.parse()?);
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+
+        Ok(InternMap {
+            mode,
+            entries: Punctuated::parse_terminated(input)?,
+        })
+    }
+}
+
+impl Parse for MapEntry {
+    fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
+        let key = input.parse()?;
+        input.parse::<Token![=>]>()?;
+        let value = input.parse()?;
+        Ok(MapEntry { key, value })
+    }
+}
+
+/// Build the graph for a map and expand it into the expression that creates it.
+fn expand<Type>(map: &InternMap) -> syn::Result<TokenStream>
+where
+    Type: for<'a> GraphType<'a>,
+    for<'a> <Type as GraphType<'a>>::InputKey: KeyTokens,
+{
+    let mut builder = Builder::<usize, Type>::new();
+    for (i, entry) in map.entries.iter().enumerate() {
+        if let Err(err) = builder.add(entry.key.value(), i) {
+            return Err(syn::Error::new(entry.key.span(), err));
+        }
+    }
+
+    let mut buffer = Vec::new();
+    let graph = builder.build(&mut buffer);
+
+    let nodes = graph.nodes().iter().map(|node| {
+        let inputs = node.inputs().iter().map(|(input, next)| {
+            let input = input.tokens();
+            quote!((#input, #next))
+        });
+        let output = match node.output() {
+            Some(index) => {
+                let value = &map.entries[*index].value;
+                quote!(::core::option::Option::Some(#value))
+            }
+            None => quote!(::core::option::Option::None),
+        };
+        let default = node.default();
+        let amount = index_tokens(node.amount());
+
+        quote! {
+            ::intern_str::Node::new(&[#(#inputs),*], #output, #default, #amount)
+        }
+    });
+
+    let start = graph.start();
+    let min_key_len = graph.min_key_len();
+    let max_key_len = index_tokens(graph.max_key_len());
+
+    Ok(quote! {
+        ::intern_str::Graph::with_key_lengths(
+            const { &[#(#nodes),*] },
+            #start,
+            #min_key_len,
+            #max_key_len,
+        )
+    })
+}
+
+/// Write a node index or length, which may be `usize::MAX`.
+fn index_tokens(index: usize) -> TokenStream {
+    if index == core::usize::MAX {
+        quote!(::core::usize::MAX)
+    } else {
+        quote!(#index)
+    }
+}
+
+/// An input key that can be written as an expression.
+trait KeyTokens {
+    /// Write the key as an expression.
+    fn tokens(&self) -> TokenStream;
+}
+
+impl KeyTokens for &str {
+    fn tokens(&self) -> TokenStream {
+        quote!(#self)
+    }
+}
+
+impl<T: KeyTokens> KeyTokens for CaseInsensitive<T> {
+    fn tokens(&self) -> TokenStream {
+        let inner = self.0.tokens();
+        quote!(::intern_str::CaseInsensitive(#inner))
+    }
+}
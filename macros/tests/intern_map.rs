@@ -0,0 +1,40 @@
+use intern_str::Graph;
+
+#[test]
+fn intern_map() {
+    use intern_str::CaseInsensitive;
+    use intern_str_macros::intern_map;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Method {
+        Get,
+        Post,
+        Put,
+    }
+
+    const METHODS: Graph<'static, 'static, CaseInsensitive<&'static str>, Option<Method>> = intern_map! {
+        case_insensitive,
+        "GET" => Method::Get,
+        "POST" => Method::Post,
+        "PUT" => Method::Put,
+    };
+
+    assert_eq!(*METHODS.process(CaseInsensitive("get")), Some(Method::Get));
+    assert_eq!(
+        *METHODS.process(CaseInsensitive("Post")),
+        Some(Method::Post)
+    );
+    assert_eq!(*METHODS.process(CaseInsensitive("PUT")), Some(Method::Put));
+    assert_eq!(*METHODS.process(CaseInsensitive("PU")), None);
+    assert_eq!(*METHODS.process(CaseInsensitive("DELETE")), None);
+
+    static NUMBERS: Graph<'static, 'static, &'static str, Option<u32>> = intern_map! {
+        "one" => 1,
+        "two" => 1 + 1,
+        "three" => 3,
+    };
+
+    assert_eq!(*NUMBERS.process("two"), Some(2));
+    assert_eq!(*NUMBERS.process("Two"), None);
+    assert_eq!(*NUMBERS.process("thre"), None);
+}
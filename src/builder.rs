@@ -1,341 +1,4014 @@
 //! A builder for graphs.
 //!
 //! This builder is not meant to be used in library code. Therefore, it is not thread-safe,
-//! and uses an allocator.
+//! and uses an allocator. It doesn't need the standard library, though, so graphs can
+//! be built on `no_std` targets with only `alloc`; the `std` feature adds the parts
+//! that read files.
+//!
+//! With the `rayon` feature enabled, the subtrees under each root node are sorted and
+//! laid out in parallel, which speeds up building graphs with a large number of keys.
 
 use super::Segmentable;
 
+use alloc::borrow::Cow;
+use alloc::collections::BTreeMap;
+use alloc::format;
 use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
 
+use core::iter::Peekable;
 use core::marker::PhantomData;
-use core::{fmt, mem};
+use core::ops::Bound;
+use core::{cmp, fmt, mem};
+
+#[cfg(feature = "arena")]
+mod arena;
+#[cfg(feature = "arena")]
+pub use arena::ArenaBuilder;
+#[cfg(feature = "std")]
+mod external;
+#[cfg(feature = "std")]
+pub use external::{ExternalBuilder, ExternalError};
+#[cfg(any(feature = "load-csv", feature = "load-json"))]
+mod load;
+#[cfg(feature = "load-csv")]
+pub use load::Column;
+#[cfg(any(feature = "load-csv", feature = "load-json"))]
+pub use load::LoadError;
+mod trie;
+pub use trie::{Trie, TrieIter};
 
 /// A builder for graphs.
+///
+/// The graph that is built only depends on the set of keys and values that were
+/// added, not on the order they were added in. Nodes are numbered by walking the keys
+/// in sorted order, so the same keys always produce the same nodes, and generated code
+/// only changes when the keys do.
 #[derive(Debug, Default)]
 pub struct Builder<T, Type> {
     /// The nodes in the graph.
-    nodes: Vec<Node<T>>,
+    nodes: Vec<Node>,
+
+    /// The nodes moved out of the builder by [`Builder::build`].
+    built: Vec<Node>,
+
+    /// The values that the nodes refer to.
+    ///
+    /// Values that have been removed are left as `None`, so the indices of the
+    /// other values don't change.
+    values: Vec<Option<Entry<T>>>,
+
+    /// The number of values that haven't been removed.
+    len: usize,
+
+    /// What to do when [`Builder::add`] is called with a key that already exists.
+    policy: DuplicatePolicy<T>,
+
+    /// Compares values to find equal ones, if subtrees or outputs are shared.
+    compare: Option<fn(&T, &T) -> cmp::Ordering>,
+
+    /// Whether identical subtrees are shared.
+    share: bool,
+
+    /// Whether equal outputs are only stored once.
+    dedup: bool,
+
+    /// The number of nodes to reserve in the node buffer when building.
+    node_hint: usize,
+
+    /// Cleans up keys before they are normalized.
+    key_options: KeyOptions,
+
+    /// Validates and normalizes keys before the graph type canonicalizes them.
+    normalize: Option<fn(&mut String) -> bool>,
+
+    /// The priority of the keys that are added.
+    priority: i32,
+
+    /// How keys are split into the chunks that each transition matches.
+    splitting: Splitting,
+
+    /// The longest chunk that a single transition can match.
+    max_chunk_len: usize,
+
+    /// The layout of each root node's subtree from the last build, keyed by the root
+    /// node's key, if subtrees are cached.
+    layouts: Option<BTreeMap<Vec<u8>, SubtreeLayout>>,
+
+    /// The casing that each canonicalized key was added with, if case is preserved.
+    cases: Option<BTreeMap<Vec<u8>, Vec<u8>>>,
 
     /// Whether or not the graph supports UTF-8.
     ty: PhantomData<Type>,
 }
 
+/// A value stored in the builder.
+#[derive(Debug)]
+struct Entry<T> {
+    /// The value itself.
+    value: T,
+
+    /// Every key for this value if it has aliases, starting with the canonical key.
+    aliases: Vec<Vec<u8>>,
+
+    /// Clones the value for each alias when the value is moved into a graph.
+    clone: Option<fn(&T) -> T>,
+
+    /// The priority of the value's keys, which decides which value wins when keys overlap.
+    priority: i32,
+
+    /// Where the value came from, for the comments in generated code.
+    meta: Option<String>,
+}
+
 impl<'a, T, Type: GraphType<'a>> Builder<T, Type> {
     /// Create a new builder.
     pub fn new() -> Self {
         Self {
             nodes: Vec::new(),
+            built: Vec::new(),
+            values: Vec::new(),
+            len: 0,
+            policy: DuplicatePolicy::Error,
+            compare: None,
+            share: false,
+            dedup: false,
+            node_hint: 0,
+            key_options: KeyOptions::new(),
+            normalize: None,
+            priority: 0,
+            splitting: Splitting::LongestPrefix,
+            max_chunk_len: core::usize::MAX,
+            layouts: None,
+            cases: None,
             ty: PhantomData,
         }
     }
 
-    /// Add a key/value pair to the map.
-    pub fn add(&mut self, mut key: String, value: T) -> Result<(), AddError<T>> {
-        if key.is_empty() {
-            return Err(AddError::Empty(value));
-        }
+    /// Create a new builder with room for the given number of keys.
+    ///
+    /// `approx_total_bytes` is the approximate length of all of the keys together, which
+    /// is used to estimate how many nodes the node buffer needs when the graph is built.
+    pub fn with_capacity(keys: usize, approx_total_bytes: usize) -> Self {
+        let mut builder = Self::new();
+        builder.reserve(keys, approx_total_bytes);
+        builder
+    }
+
+    /// Reserve room for at least `keys` more keys, with a combined length of about
+    /// `approx_total_bytes`.
+    ///
+    /// See [`Builder::with_capacity`] for more information.
+    pub fn reserve(&mut self, keys: usize, approx_total_bytes: usize) {
+        self.values.reserve(keys);
+
+        // Every key adds at most a leaf and a split node, but there can't be more
+        // nodes than there are bytes in the keys.
+        let nodes = cmp::min(keys.saturating_mul(2), approx_total_bytes);
+        self.node_hint = self.node_hint.saturating_add(nodes);
+    }
+
+    /// Set what [`Builder::add`] does when it is called with a key that already exists.
+    ///
+    /// By default, this is [`DuplicatePolicy::Error`].
+    pub fn set_duplicate_policy(&mut self, policy: DuplicatePolicy<T>) {
+        self.policy = policy;
+    }
+
+    /// Set the priority of the keys that are added from now on.
+    ///
+    /// Priorities decide which value wins when keys overlap. If a key is added that is
+    /// already in the builder, the one with the higher priority keeps the key, no matter
+    /// the [`DuplicatePolicy`]. This also applies to each key that a pattern expands to,
+    /// so only the keys that are taken over move to the new value. A wildcard with a
+    /// higher priority than an exact key or a longer wildcard under it takes precedence
+    /// over them.
+    ///
+    /// Keys with the same priority are ambiguous, so the builder's [`DuplicatePolicy`]
+    /// decides what happens to them, and [`DuplicatePolicy::Error`] reports them with
+    /// [`AddError::Conflict`]. Wildcards with the same priority are resolved by taking
+    /// the longest match. By default, every key has priority zero.
+    pub fn set_priority(&mut self, priority: i32) {
+        self.priority = priority;
+    }
+
+    /// Set a function that validates and normalizes every key, before the graph type
+    /// canonicalizes it.
+    ///
+    /// This covers rules that are specific to one set of keys, such as trimming
+    /// whitespace or rejecting control characters. If the function returns `false`, or
+    /// leaves the key empty, the key is rejected with [`AddError::Invalid`]. The function
+    /// also applies to keys that are looked up with [`Builder::remove`] and the like.
+    /// Binary keys are only passed to it if they are valid UTF-8.
+    pub fn set_normalizer(&mut self, normalize: fn(&mut String) -> bool) {
+        self.normalize = Some(normalize);
+    }
+
+    /// Set how whitespace and affixes are cleaned up from every key, before the
+    /// normalizer and the graph type see it.
+    ///
+    /// Like the normalizer, this also applies to keys that are looked up with
+    /// [`Builder::remove`] and the like. Queries against the graph should be cleaned up
+    /// the same way with [`KeyOptions::apply`]. A key that is left empty is rejected
+    /// with [`AddError::Invalid`].
+    pub fn set_key_options(&mut self, options: KeyOptions) {
+        self.key_options = options;
+    }
+
+    /// Set how keys are split into the chunks that each transition matches.
+    ///
+    /// By default, this is [`Splitting::LongestPrefix`].
+    pub fn set_splitting(&mut self, splitting: Splitting) {
+        self.splitting = splitting;
+        self.clear_layouts();
+    }
+
+    /// Limit the length of the chunk that a single transition can match, as measured by
+    /// [`GraphType::key_len`].
+    ///
+    /// Longer chunks mean fewer steps to match a key, but each step compares more of
+    /// the input, and splitting siblings to the same length adds more nodes. This can't
+    /// raise the limit of the graph type, and a limit of zero is treated as one.
+    pub fn set_max_chunk_len(&mut self, len: usize) {
+        self.max_chunk_len = len;
+        self.clear_layouts();
+    }
+
+    /// Keep the layout of each subtree between builds, so that rebuilding the graph
+    /// after a few keys are added or removed only lays out the subtrees that changed.
+    ///
+    /// The subtrees are those under each of the graph's first transitions. Unchanged
+    /// subtrees are copied over from the last build, rather than being sorted and laid
+    /// out again. This applies to [`Builder::build_ref`], [`Builder::build_map`] and
+    /// [`Builder::build_streaming`], which leave the keys in the builder, and uses
+    /// about as much memory as another copy of the graph. Subtrees can't be cached if
+    /// they are shared with [`Builder::share_subtrees`].
+    pub fn cache_subtrees(&mut self) {
+        if self.layouts.is_none() {
+            self.layouts = Some(BTreeMap::new());
+        }
+    }
+
+    /// Keep the casing that keys are added with in the keys of the built graph.
+    ///
+    /// Graph types like [`IgnoreCase`] canonicalize keys by lowercasing them, so a
+    /// graph with the key `Content-Type` has transitions for `content-type`, and that
+    /// is what generated code and drawings of the graph show. With this, the
+    /// transitions take the casing of the keys as they were added instead. Inputs
+    /// match the same keys either way, since they are still compared without regard
+    /// to case.
+    ///
+    /// The first casing that a key is added with is kept. Keys that share a prefix
+    /// share its transitions, which take the casing of the first of those keys in
+    /// sorted order. This only applies to keys that are added as strings, and only to
+    /// graph types whose keys compare without regard to ASCII case. The keys themselves
+    /// come out of [`Builder::iter_cased`] and [`Builder::cased`] with their casing.
+    pub fn preserve_case(&mut self) {
+        if self.cases.is_none() {
+            self.cases = Some(BTreeMap::new());
+        }
+    }
+
+    /// Share identical subtrees between keys when the graph is built.
+    ///
+    /// Subtrees are identical if they have the same transitions and equal values.
+    /// Sharing them means that common suffixes, such as "-ation" or "-ness" in a list
+    /// of English words, are only added to the graph once.
+    pub fn share_subtrees(&mut self)
+    where
+        T: Ord,
+    {
+        self.compare = Some(T::cmp);
+        self.share = true;
+    }
+
+    /// Only store equal outputs once when the graph is built as a [`Map`](super::Map).
+    ///
+    /// Keys with equal values refer to the same slot in the output table, so graphs with
+    /// `()` or enum values don't carry a copy of the value for every key. This carries
+    /// over to the `OUTPUTS` table that `intern-str-codegen` emits for a map.
+    pub fn dedup_outputs(&mut self)
+    where
+        T: Ord,
+    {
+        self.compare = Some(T::cmp);
+        self.dedup = true;
+    }
+
+    /// Add a key/value pair to the map.
+    ///
+    /// The key can be anything that converts into a `String`, such as a `&str` from a
+    /// static word list. If the key already exists, the builder's [`DuplicatePolicy`] decides what
+    /// happens to the value.
+    pub fn add(&mut self, key: impl Into<String>, value: T) -> Result<(), AddError<T>> {
+        let policy = self.policy;
+        self.add_with_policy(key.into(), value, policy, None)
+            .map(|_| ())
+    }
+
+    /// Add a key/value pair to the map, replacing the value if the key already exists.
+    ///
+    /// Returns the old value, if there was one. If the key is an alias, the value is
+    /// replaced for all of its aliases.
+    pub fn add_or_replace(
+        &mut self,
+        key: impl Into<String>,
+        value: T,
+    ) -> Result<Option<T>, AddError<T>> {
+        self.add_with_policy(key.into(), value, DuplicatePolicy::Replace, None)
+    }
+
+    /// Add a key/value pair to the map, unless the key already exists.
+    ///
+    /// Returns the value back if the key already exists.
+    pub fn add_if_absent(
+        &mut self,
+        key: impl Into<String>,
+        value: T,
+    ) -> Result<Option<T>, AddError<T>> {
+        self.add_with_policy(key.into(), value, DuplicatePolicy::KeepFirst, None)
+    }
+
+    /// Add a key/value pair to the map, handling duplicates with the given policy.
+    ///
+    /// Returns the value that was replaced or rejected, if any. The metadata is only
+    /// kept if the value is.
+    fn add_with_policy(
+        &mut self,
+        mut key: String,
+        value: T,
+        policy: DuplicatePolicy<T>,
+        meta: Option<String>,
+    ) -> Result<Option<T>, AddError<T>> {
+        if key.is_empty() {
+            return Err(AddError::Empty(value));
+        }
+
+        if !self.canonicalize_added(&mut key) {
+            return Err(AddError::Invalid(key, value));
+        }
+
+        self.add_canonical(key.into_bytes(), value, policy, meta)
+    }
+
+    /// Add a batch of key/value pairs to the map, all at once.
+    ///
+    /// The whole batch is checked before any of it is added. If any of the keys are
+    /// empty or invalid, or are duplicates while the builder's [`DuplicatePolicy`] is
+    /// [`DuplicatePolicy::Error`], then nothing is added and every problem is returned,
+    /// in the order that the keys appear in the batch. The values of the keys that
+    /// could have been added are dropped.
+    pub fn try_add_all<K: Into<String>>(
+        &mut self,
+        iter: impl IntoIterator<Item = (K, T)>,
+    ) -> Result<(), Vec<AddError<T>>> {
+        // Canonicalize every key.
+        let mut batch = Vec::new();
+        let mut errors = Vec::new();
+        for (key, value) in iter {
+            let mut key = key.into();
+            if key.is_empty() {
+                errors.push((batch.len(), AddError::Empty(value)));
+            } else if let Some(cased) = self.canonicalize_cased(&mut key) {
+                // The casing is only recorded once the whole batch is known to be added.
+                batch.push(Some((key.into_bytes(), value, cased)));
+                continue;
+            } else {
+                errors.push((batch.len(), AddError::Invalid(key, value)));
+            }
+
+            batch.push(None);
+        }
+
+        if let DuplicatePolicy::Error = self.policy {
+            let key = |i: usize| batch[i].as_ref().map(|(key, _, _)| key);
+
+            // Sort the keys to find the ones that are repeated within the batch.
+            let mut order = (0..batch.len())
+                .filter(|&i| key(i).is_some())
+                .collect::<Vec<_>>();
+            order.sort_by_key(|&i| key(i));
+
+            let mut repeated = order
+                .windows(2)
+                .filter(|pair| key(pair[0]) == key(pair[1]))
+                .map(|pair| pair[1])
+                .collect::<Vec<_>>();
+            repeated.sort_unstable();
+
+            // Keys that are already in the builder with the same priority conflict with
+            // the existing entry.
+            let existing = (0..batch.len())
+                .map(|i| {
+                    key(i)
+                        .and_then(|key| self.find(key))
+                        .filter(|&index| self.value_entry(index).priority == self.priority)
+                })
+                .collect::<Vec<_>>();
+
+            for (i, existing) in existing.into_iter().enumerate() {
+                if existing.is_none() && repeated.binary_search(&i).is_err() {
+                    continue;
+                }
+
+                let (key, value, _) = batch[i].take().unwrap();
+                let error = match existing {
+                    Some(index) => self.conflict(key, value, index),
+                    None => AddError::Duplicate(key_string(key), value),
+                };
+                errors.push((i, error));
+            }
+        }
+
+        if !errors.is_empty() {
+            errors.sort_by_key(|&(i, _)| i);
+            return Err(errors.into_iter().map(|(_, error)| error).collect());
+        }
+
+        // Nothing can fail, so add the whole batch.
+        let policy = self.policy;
+        for (key, value, cased) in batch.into_iter().flatten() {
+            self.record_case(&key, cased);
+            self.add_canonical(key, value, policy, None)
+                .unwrap_or_else(|_| unreachable!("batch was checked for duplicates"));
+        }
+
+        Ok(())
+    }
+
+    /// Add each line of a reader as a key.
+    ///
+    /// `value` is called with the line number, starting from one, and the line itself
+    /// to get the line's value. Lines that `options` says to skip aren't added, but are
+    /// still counted towards the line numbers. If a key already exists, the builder's
+    /// [`DuplicatePolicy`] decides what happens to the value.
+    ///
+    /// Returns the number of lines that weren't skipped, or stops at the first error.
+    #[cfg(feature = "std")]
+    pub fn add_lines<R: std::io::BufRead>(
+        &mut self,
+        reader: R,
+        options: LineOptions,
+        mut value: impl FnMut(usize, &str) -> T,
+    ) -> Result<usize, LinesError<T>> {
+        let mut added = 0;
+
+        for (i, line) in reader.lines().enumerate() {
+            let line_number = i + 1;
+            let mut line = line.map_err(LinesError::Io)?;
+            if line.ends_with('\r') {
+                line.pop();
+            }
+
+            if options.skip(&line) {
+                continue;
+            }
+
+            let value = value(line_number, &line);
+            self.add(line, value).map_err(|error| LinesError::Add {
+                line: line_number,
+                error,
+            })?;
+            added += 1;
+        }
+
+        Ok(added)
+    }
+
+    /// Add a binary key/value pair to the map.
+    ///
+    /// Keys don't need to be valid UTF-8 for graph types that match bytes, such as
+    /// [`ByteGraph`]. Other graph types reject keys that aren't valid UTF-8. If the
+    /// key already exists, the builder's [`DuplicatePolicy`] decides what happens to
+    /// the value.
+    pub fn add_bytes(&mut self, mut key: Vec<u8>, value: T) -> Result<(), AddError<T>> {
+        if key.is_empty() {
+            return Err(AddError::Empty(value));
+        }
+
+        if !self.canonicalize_bytes(&mut key) {
+            return Err(AddError::Invalid(key_string(key), value));
+        }
+
+        let policy = self.policy;
+        self.add_canonical(key, value, policy, None).map(|_| ())
+    }
+
+    /// Create a builder from an iterator of keys and values, sorted by key.
+    ///
+    /// Since each key comes after the ones before it, it can only share a prefix with
+    /// the last key that was added, so it is appended to the trie without looking
+    /// through the other keys. Keys that are out of order (for instance, because the
+    /// graph type canonicalizes them) are still added, just without this shortcut.
+    ///
+    /// Returns the first error, if any key can't be added.
+    pub fn from_sorted_iter<K, I>(iter: I) -> Result<Self, AddError<T>>
+    where
+        K: Into<String>,
+        I: IntoIterator<Item = (K, T)>,
+    {
+        let mut builder = Self::new();
+        let iter = iter.into_iter();
+        builder.reserve(iter.size_hint().0, 0);
+
+        let mut last: Option<Vec<u8>> = None;
+        let mut sorted = true;
+        for (key, value) in iter {
+            let mut key = key.into();
+            if key.is_empty() {
+                return Err(AddError::Empty(value));
+            }
+
+            if !builder.canonicalize(&mut key) {
+                return Err(AddError::Invalid(key, value));
+            }
+
+            let key = key.into_bytes();
+            sorted = sorted && last.as_ref().map_or(true, |last| key > *last);
+            if !sorted {
+                let policy = builder.policy;
+                builder.add_canonical(key, value, policy, None)?;
+                continue;
+            }
+
+            let index = builder.push_entry(Entry {
+                value,
+                aliases: Vec::new(),
+                clone: None,
+                priority: builder.priority,
+                meta: None,
+            });
+            builder.append(key.clone(), index);
+            last = Some(key);
+        }
+
+        Ok(builder)
+    }
+
+    /// Create a builder that maps the key of each value in a [`KeySet`] to the value.
+    ///
+    /// For an enum, this makes a graph that parses the enum from its variants' names.
+    pub fn from_key_set() -> Result<Self, AddError<T>>
+    where
+        T: KeySet,
+    {
+        let mut builder = Self::new();
+        for (key, value) in T::keys() {
+            builder.add(key, value)?;
+        }
+
+        Ok(builder)
+    }
+
+    /// Insert a key that comes after every other key in the trie.
+    ///
+    /// The key can only share a prefix with the last node in each set of siblings,
+    /// so only those nodes are checked.
+    fn append(&mut self, key: Vec<u8>, index: usize) {
+        self.touch(&key);
+        let mut node = Node::new(key);
+        node.output = Some(index);
+        let utf8 = self.chunking().utf8;
+        let mut siblings = &mut self.nodes;
+
+        loop {
+            let prefix_len = match siblings.last() {
+                Some(last) => prefix(&node.value, &last.value, utf8).len(),
+                None => 0,
+            };
+
+            if prefix_len == 0 {
+                // No shared prefix, so the node is the last sibling.
+                siblings.push(node);
+                return;
+            }
+
+            let last = siblings.len() - 1;
+            debug_assert!(prefix_len < node.value.len(), "key is out of order");
+
+            if prefix_len == siblings[last].value.len() {
+                // The last sibling is a prefix of the key, so move on to its children.
+                node.value = node.value[prefix_len..].to_vec();
+                siblings = &mut siblings[last].children;
+                continue;
+            }
+
+            // Split the last sibling at the shared prefix.
+            let mut sibling = siblings.pop().unwrap();
+            let prefix = sibling.value[..prefix_len].to_vec();
+            sibling.value = sibling.value[prefix_len..].to_vec();
+            node.value = node.value[prefix_len..].to_vec();
+
+            let mut prefix = Node::new(prefix);
+            prefix.children = vec![sibling, node];
+            siblings.push(prefix);
+            return;
+        }
+    }
+
+    /// Add a canonicalized key/value pair to the map, handling duplicates with the
+    /// given policy.
+    ///
+    /// If the key exists with a different priority, the higher priority wins instead.
+    fn add_canonical(
+        &mut self,
+        key: Vec<u8>,
+        value: T,
+        policy: DuplicatePolicy<T>,
+        meta: Option<String>,
+    ) -> Result<Option<T>, AddError<T>> {
+        let index = match self.find(&key) {
+            Some(index) => index,
+            None => {
+                self.insert_new(key, value, meta);
+                return Ok(None);
+            }
+        };
+
+        let priority = self.value_entry(index).priority;
+        if priority > self.priority {
+            // The existing key wins.
+            return Ok(Some(value));
+        } else if priority < self.priority {
+            // The new key wins, so it is taken away from the existing value.
+            let replaced = self.detach(&key, index);
+            self.insert_new(key, value, meta);
+            return Ok(replaced);
+        }
+
+        let entry = self.value_entry_mut(index);
+        let result = match policy {
+            DuplicatePolicy::Error => return Err(self.conflict(key, value, index)),
+            DuplicatePolicy::Replace => Some(mem::replace(&mut entry.value, value)),
+            DuplicatePolicy::KeepFirst => return Ok(Some(value)),
+            DuplicatePolicy::Merge(merge) => {
+                merge(&mut entry.value, value);
+                None
+            }
+        };
+
+        if meta.is_some() {
+            entry.meta = meta;
+        }
+        Ok(result)
+    }
+
+    /// Add a key ending in a `*` wildcard, which matches any input that starts with
+    /// the rest of the key.
+    ///
+    /// For instance, `image/*` matches `image/png` and `image/webp`. Exact keys and
+    /// longer wildcards take precedence, so `image/png` can still be given its own
+    /// value, unless they have a lower priority (see [`Builder::set_priority`]).
+    /// [`Builder::build`] clones the value for every node that can output it.
+    pub fn add_wildcard(&mut self, key: impl Into<String>, value: T) -> Result<(), AddError<T>>
+    where
+        T: Clone,
+    {
+        let mut key = key.into();
+        if !key.ends_with('*') {
+            return Err(AddError::Invalid(key, value));
+        }
+        key.pop();
+
+        self.add_fallback(key, value).map_err(|err| match err {
+            AddError::Conflict {
+                mut key,
+                mut existing,
+                value,
+                index,
+            } => {
+                key.push('*');
+                existing.push('*');
+                AddError::Conflict {
+                    key,
+                    existing,
+                    value,
+                    index,
+                }
+            }
+            AddError::Invalid(mut key, value) => {
+                key.push('*');
+                AddError::Invalid(key, value)
+            }
+            err => err,
+        })
+    }
+
+    /// Add a fallback value for inputs that start with `prefix`, but don't match any
+    /// longer key under it.
+    ///
+    /// This gives an intermediate node of the graph its own default, so that
+    /// `application/` can map unknown subtypes to a generic value while
+    /// `application/json` still matches exactly. Inputs that end partway through a key
+    /// under the prefix also get the fallback, and inputs that don't start with the
+    /// prefix still get the graph's usual miss output. It is the same as adding
+    /// `prefix` followed by a `*` with [`Builder::add_wildcard`], without the `*`.
+    pub fn add_fallback(&mut self, prefix: impl Into<String>, value: T) -> Result<(), AddError<T>>
+    where
+        T: Clone,
+    {
+        let mut key = prefix.into();
+        if key.is_empty() {
+            return Err(AddError::Empty(value));
+        }
+
+        if !self.canonicalize_added(&mut key) {
+            return Err(AddError::Invalid(key, value));
+        }
+
+        let new = self.push_entry(Entry {
+            value,
+            aliases: Vec::new(),
+            clone: Some(T::clone),
+            priority: self.priority,
+            meta: None,
+        });
+
+        let index = match self.insert(key.clone().into_bytes(), new, true) {
+            Ok(()) => return Ok(()),
+            Err(index) => index,
+        };
+
+        // Wildcards never have aliases, so the higher priority takes the whole entry.
+        let entry = self.take_entry(new).unwrap();
+        self.values.pop();
+        if self.value_entry(index).priority < entry.priority {
+            self.touch(key.as_bytes());
+            *self.value_entry_mut(index) = entry;
+        } else if self.value_entry(index).priority == entry.priority {
+            return Err(AddError::Conflict {
+                existing: key.clone(),
+                key,
+                value: entry.value,
+                index,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Deny a key, so that it never matches.
+    ///
+    /// Inputs equal to the key produce the same output as inputs that aren't in the
+    /// graph, even if a wildcard, an exact key, or the graph type's case folding would
+    /// otherwise match them. For instance, denying `image/svg+xml` after adding the
+    /// `image/*` wildcard matches every image type except for SVG. Longer inputs that
+    /// start with the key still fall back to the wildcards above it.
+    ///
+    /// Returns `false` if the key is empty or not valid for the graph type.
+    pub fn deny(&mut self, key: impl Into<String>) -> bool {
+        let mut key = key.into();
+        if key.is_empty() || !self.canonicalize(&mut key) {
+            return false;
+        }
+
+        let mut node = Node::new(key.into_bytes());
+        node.deny = true;
+        self.insert_node(node)
+            .unwrap_or_else(|_| unreachable!("denied keys have no value"));
+        true
+    }
+
+    /// Add a key to the map, along with several aliases that map to the same value.
+    ///
+    /// The value is only stored once, and is shared between the key and all of its
+    /// aliases. Either all of the keys are added, or none of them are. The exception is
+    /// keys that already exist with a different priority: the ones with a lower
+    /// priority move over to this value, and the ones with a higher priority are left
+    /// alone.
+    ///
+    /// [`Builder::build`] clones the value for each of the aliases.
+    pub fn add_with_aliases<A: Into<String>>(
+        &mut self,
+        key: impl Into<String>,
+        aliases: impl IntoIterator<Item = A>,
+        value: T,
+    ) -> Result<(), AddError<T>>
+    where
+        T: Clone,
+    {
+        // Validate every key before inserting any of them.
+        let mut keys: Vec<Vec<u8>> = Vec::new();
+        let mut taken = Vec::new();
+        for mut key in Some(key.into())
+            .into_iter()
+            .chain(aliases.into_iter().map(Into::into))
+        {
+            if key.is_empty() {
+                return Err(AddError::Empty(value));
+            }
+
+            if !self.canonicalize_added(&mut key) {
+                return Err(AddError::Invalid(key, value));
+            }
+
+            if keys.iter().any(|k| k == key.as_bytes()) {
+                return Err(AddError::Duplicate(key, value));
+            }
+
+            if let Some(index) = self.find(key.as_bytes()) {
+                let priority = self.value_entry(index).priority;
+                if priority == self.priority {
+                    return Err(self.conflict(key.into_bytes(), value, index));
+                } else if priority > self.priority {
+                    // The key stays with the existing value.
+                    continue;
+                }
+
+                taken.push((key.as_bytes().to_vec(), index));
+            }
+
+            keys.push(key.into_bytes());
+        }
+
+        if keys.is_empty() {
+            // Every key belongs to a value with a higher priority.
+            return Ok(());
+        }
+
+        for (key, index) in taken {
+            self.detach(&key, index);
+        }
+
+        let index = self.values.len();
+        for key in &keys {
+            self.insert(key.clone(), index, false)
+                .unwrap_or_else(|_| unreachable!("keys were checked for duplicates"));
+        }
+
+        self.push_entry(Entry {
+            value,
+            aliases: if keys.len() > 1 { keys } else { Vec::new() },
+            clone: Some(T::clone),
+            priority: self.priority,
+            meta: None,
+        });
+
+        Ok(())
+    }
+
+    /// Add a pattern that matches several keys, which all map to the same value.
+    ///
+    /// Parentheses group alternatives that are separated by `|`, such as `gr(a|e)y`. A
+    /// group with only one alternative is optional, so `colo(u)r` matches both `colour`
+    /// and `color`. Groups can be nested, and `\` escapes the character after it.
+    ///
+    /// The pattern is expanded into every key that it matches, which are added like
+    /// [`Builder::add_with_aliases`], with the first alternatives making up the canonical
+    /// key. Combine this with [`Builder::share_subtrees`] to merge the parts of the graph
+    /// that the keys have in common.
+    ///
+    /// Since every key is added on its own, the time and memory that this takes grow
+    /// with the number of keys, which is the product of the number of alternatives in
+    /// each group: `(a|b)(c|d)(e|f)` expands into eight keys, and each group added to
+    /// it doubles that. Patterns that expand into more than 1024 keys are rejected
+    /// with [`AddError::Invalid`], like malformed patterns.
+    pub fn add_pattern(&mut self, pattern: &str, value: T) -> Result<(), AddError<T>>
+    where
+        T: Clone,
+    {
+        let keys = match expand_pattern(pattern) {
+            Some(keys) => keys,
+            None => return Err(AddError::Invalid(pattern.to_string(), value)),
+        };
+
+        // Different alternatives can expand to the same key.
+        let mut unique: Vec<String> = Vec::with_capacity(keys.len());
+        for key in keys {
+            if !unique.contains(&key) {
+                unique.push(key);
+            }
+        }
+
+        let mut keys = unique.into_iter();
+        let key = keys.next().unwrap_or_default();
+        self.add_with_aliases(key, keys, value)
+    }
+
+    /// Remove a key from the map, returning its value.
+    ///
+    /// If the key has aliases, then all of its aliases are removed as well. Nodes
+    /// that are no longer needed after the key is removed are merged back together.
+    pub fn remove(&mut self, key: &str) -> Option<T> {
+        let mut key = key.to_string();
+        if !self.canonicalize(&mut key) {
+            return None;
+        }
+
+        let index = remove_key(&mut self.nodes, key.as_bytes())?;
+        let entry = self.take_entry(index).unwrap();
+        self.touch(key.as_bytes());
+
+        for alias in &entry.aliases {
+            if *alias != key.as_bytes() {
+                self.touch(alias);
+                remove_key(&mut self.nodes, alias);
+            }
+        }
+
+        if let Some(cases) = &mut self.cases {
+            cases.remove(key.as_bytes());
+            for alias in &entry.aliases {
+                cases.remove(alias);
+            }
+        }
+
+        Some(entry.value)
+    }
+
+    /// Get the canonical key for a key.
+    ///
+    /// If the key is an alias added through [`Builder::add_with_aliases`], this is the
+    /// key that the aliases were registered with. Otherwise, this is the key itself,
+    /// after being canonicalized by the graph type. Returns `None` if the key is not
+    /// in the map.
+    pub fn canonical(&self, key: &str) -> Option<String> {
+        let mut key = key.to_string();
+        if !self.canonicalize(&mut key) {
+            return None;
+        }
+
+        let index = self.find(key.as_bytes())?;
+        match self.value_entry(index).aliases.first() {
+            Some(canonical) => Some(key_string(canonical.clone())),
+            None => Some(key),
+        }
+    }
+
+    /// Get a key with the casing that it was added with.
+    ///
+    /// With [`Builder::preserve_case`], this is the first casing that the key was added
+    /// with. Otherwise, or if the casing wasn't kept, this is the key after being
+    /// canonicalized by the graph type. Unlike [`Builder::canonical`], aliases are not
+    /// followed. Returns `None` if the key is not in the map.
+    pub fn cased(&self, key: &str) -> Option<String> {
+        let mut key = key.to_string();
+        if !self.canonicalize(&mut key) {
+            return None;
+        }
+
+        self.find(key.as_bytes())?;
+        match self
+            .cases
+            .as_ref()
+            .and_then(|cases| cases.get(key.as_bytes()))
+        {
+            Some(cased) => Some(key_string(cased.clone())),
+            None => Some(key),
+        }
+    }
+
+    /// Check whether a key is in the builder.
+    ///
+    /// The key is canonicalized first, so with [`IgnoreCase`], `"TEXT/PLAIN"` is found
+    /// if `"text/plain"` was added. Aliases count as keys, but wildcards and denied keys
+    /// don't.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.lookup(key).is_some()
+    }
+
+    /// Get the value for a key, if it is in the builder.
+    ///
+    /// See [`Builder::contains_key`] for how the key is found.
+    pub fn get(&self, key: &str) -> Option<&T> {
+        self.lookup(key).map(|index| &self.value_entry(index).value)
+    }
+
+    /// Add a key/value pair, along with metadata that describes where it came from.
+    ///
+    /// The metadata is free-form text, such as the file and line that the key was read
+    /// from or a description of the value. Code generators that build the graph with
+    /// [`Builder::build_streaming_with_meta`] write it as a comment next to the value,
+    /// which makes large generated files possible to review. If the value replaces or
+    /// is merged into an existing value, the metadata replaces that value's metadata,
+    /// but if the existing value is kept instead, the metadata is dropped with the new
+    /// value.
+    pub fn add_with_meta(
+        &mut self,
+        key: impl Into<String>,
+        value: T,
+        meta: impl Into<String>,
+    ) -> Result<(), AddError<T>> {
+        let policy = self.policy;
+        self.add_with_policy(key.into(), value, policy, Some(meta.into()))
+            .map(|_| ())
+    }
+
+    /// Get the metadata of the value for a key, if it has any.
+    ///
+    /// See [`Builder::add_with_meta`].
+    pub fn meta(&self, key: &str) -> Option<&str> {
+        let index = self.lookup(key)?;
+        self.value_entry(index).meta.as_ref().map(|meta| &**meta)
+    }
+
+    /// Find the index of the value for a key that hasn't been canonicalized yet.
+    fn lookup(&self, key: &str) -> Option<usize> {
+        let mut key = key.to_string();
+        if !self.canonicalize(&mut key) {
+            return None;
+        }
+
+        let node = self.find_node(key.as_bytes())?;
+        if node.deny {
+            return None;
+        }
+        node.output
+    }
+
+    /// Get statistics about the graph that would be built, without building it.
+    ///
+    /// This does the same work as [`Builder::build`] on a copy of the keys, so the
+    /// node count takes shared subtrees and coalesced chains into account.
+    pub fn stats(&self) -> Stats {
+        let mut nodes = self.nodes.clone();
+        let mut node_buffer = Vec::new();
+        let graph = build_graph(
+            &mut nodes,
+            &mut node_buffer,
+            self.chunking(),
+            self.subtree_classes(),
+            &self.priorities(),
+            None,
+            None,
+            self.node_hint,
+            |key| key,
+            |output| output,
+        );
+
+        let mut stats = Stats {
+            keys: 0,
+            nodes: graph.nodes().len(),
+            max_depth: 0,
+            static_size: graph.nodes().len()
+                * mem::size_of::<super::Node<'a, Type::InputKey, Option<T>>>(),
+        };
+
+        // Count the transitions and the key data they refer to.
+        for node in graph.nodes() {
+            let inputs: &[_] = &node.inputs;
+            for (key, _) in inputs {
+                stats.static_size += mem::size_of::<(Type::InputKey, usize)>() + key.len();
+            }
+        }
+
+        for node in nodes.iter() {
+            node.stats(1, &mut stats);
+        }
+
+        stats
+    }
+
+    /// Get the entry for a key, to inspect, modify or insert its value.
+    ///
+    /// If the key is an alias, the entry refers to the value that it shares with the
+    /// rest of its aliases.
+    pub fn entry(&mut self, key: impl Into<String>) -> KeyEntry<'_, T, Type> {
+        let mut key = key.into();
+        if key.is_empty() || !self.canonicalize(&mut key) {
+            return KeyEntry::Vacant(VacantEntry {
+                builder: self,
+                key: Err(key),
+            });
+        }
+
+        let key = key.into_bytes();
+        match self.find(&key) {
+            Some(index) => KeyEntry::Occupied(OccupiedEntry {
+                builder: self,
+                index,
+            }),
+            None => KeyEntry::Vacant(VacantEntry {
+                builder: self,
+                key: Ok(key),
+            }),
+        }
+    }
+
+    /// Normalize and canonicalize a key, returning `false` if it is invalid.
+    fn canonicalize(&self, key: &mut String) -> bool {
+        self.clean(key) && Type::canonicalize(key)
+    }
+
+    /// Canonicalize a key that is being added, and remember its casing if case is
+    /// preserved.
+    fn canonicalize_added(&mut self, key: &mut String) -> bool {
+        match self.canonicalize_cased(key) {
+            Some(cased) => {
+                self.record_case(key.as_bytes(), cased);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Canonicalize a key that is being added, without remembering its casing.
+    ///
+    /// Returns `None` if the key isn't valid. Otherwise, returns the casing to pass to
+    /// [`Builder::record_case`], if case is preserved.
+    fn canonicalize_cased(&self, key: &mut String) -> Option<Option<String>> {
+        if !self.clean(key) {
+            return None;
+        }
+
+        let cased = self.cases.as_ref().map(|_| key.clone());
+        if !Type::canonicalize(key) {
+            return None;
+        }
+
+        // Keys that the graph type changed in other ways than case are left as they are.
+        Some(cased.filter(|cased| cased.eq_ignore_ascii_case(key)))
+    }
+
+    /// Remember the casing of a canonicalized key, unless it already has one.
+    fn record_case(&mut self, key: &[u8], cased: Option<String>) {
+        if let (Some(cases), Some(cased)) = (&mut self.cases, cased) {
+            cases
+                .entry(key.to_vec())
+                .or_insert_with(|| cased.into_bytes());
+        }
+    }
+
+    /// Clean up and normalize a key, before the graph type canonicalizes it.
+    fn clean(&self, key: &mut String) -> bool {
+        let cleaned = match self.key_options.apply(key) {
+            Cow::Borrowed(cleaned) if cleaned.len() == key.len() => None,
+            cleaned => Some(cleaned.into_owned()),
+        };
+        if let Some(cleaned) = cleaned {
+            if cleaned.is_empty() {
+                return false;
+            }
+
+            *key = cleaned;
+        }
+
+        if let Some(normalize) = self.normalize {
+            if !normalize(key) || key.is_empty() {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Normalize and canonicalize a binary key, returning `false` if it is invalid.
+    fn canonicalize_bytes(&self, key: &mut Vec<u8>) -> bool {
+        if let Ok(string) = core::str::from_utf8(key) {
+            let cleaned = match self.key_options.apply(string) {
+                Cow::Borrowed(cleaned) if cleaned.len() == string.len() => None,
+                cleaned => Some(cleaned.into_owned()),
+            };
+            if let Some(cleaned) = cleaned {
+                if cleaned.is_empty() {
+                    return false;
+                }
+
+                *key = cleaned.into_bytes();
+            }
+        }
+
+        if let (Some(normalize), Ok(string)) = (self.normalize, core::str::from_utf8(key)) {
+            let mut string = string.to_string();
+            if !normalize(&mut string) || string.is_empty() {
+                return false;
+            }
+
+            *key = string.into_bytes();
+        }
+
+        Type::canonicalize_bytes(key)
+    }
+
+    /// Find the index of the value for a canonicalized key.
+    fn find(&self, key: &[u8]) -> Option<usize> {
+        self.find_node(key)?.output
+    }
+
+    /// Find the node for a canonicalized key.
+    fn find_node(&self, key: &[u8]) -> Option<&Node> {
+        find_node(&self.nodes, key)
+    }
+
+    /// Add a value for a canonicalized key that isn't in the trie yet.
+    fn insert_new(&mut self, key: Vec<u8>, value: T, meta: Option<String>) {
+        let index = self.push_entry(Entry {
+            value,
+            aliases: Vec::new(),
+            clone: None,
+            priority: self.priority,
+            meta,
+        });
+
+        self.insert(key, index, false)
+            .unwrap_or_else(|_| unreachable!("key was checked for duplicates"));
+    }
+
+    /// Take a canonicalized key away from the value at `index`, leaving any other
+    /// aliases of the value in place.
+    ///
+    /// Returns the value if this was its last key.
+    fn detach(&mut self, key: &[u8], index: usize) -> Option<T> {
+        self.touch(key);
+        remove_key(&mut self.nodes, key);
+
+        let entry = self.value_entry_mut(index);
+        entry.aliases.retain(|alias| alias != key);
+        if entry.aliases.is_empty() {
+            self.take_entry(index).map(|entry| entry.value)
+        } else {
+            None
+        }
+    }
+
+    /// Insert a canonicalized key that refers to the value at `index`.
+    ///
+    /// If `wildcard` is set, the key is a prefix that matches any input starting with
+    /// it. Returns the index of the existing value if the key is a duplicate.
+    fn insert(&mut self, key: Vec<u8>, index: usize, wildcard: bool) -> Result<(), usize> {
+        let mut node = Node::new(key);
+        if wildcard {
+            node.wildcard = Some(index);
+        } else {
+            node.output = Some(index);
+        }
+
+        self.insert_node(node)
+    }
+
+    /// Insert a node with no children into the trie, merging it with an existing node
+    /// for the same key.
+    ///
+    /// Returns the index of the existing value if both nodes have an output, or both
+    /// have a wildcard.
+    fn insert_node(&mut self, node: Node) -> Result<(), usize> {
+        self.touch(&node.value);
+        let utf8 = self.chunking().utf8;
+        insert_node(&mut self.nodes, node, utf8)
+    }
+
+    /// Build the graph.
+    ///
+    /// The keys and values are moved into the graph rather than cloned, so the values
+    /// don't need to implement `Clone`. This empties the builder: once the graph is
+    /// dropped, [`Builder::len`] is zero and building again gives a graph without any
+    /// keys. The builder keeps its settings, so new keys can be added to it for another
+    /// graph. To keep the keys and values, use [`Builder::build_cloned`] or
+    /// [`Builder::build_ref`] instead.
+    pub fn build<'nodes>(
+        &'a mut self,
+        node_buffer: &'nodes mut Vec<super::Node<'a, Type::InputKey, Option<T>>>,
+    ) -> super::Graph<'a, 'nodes, Type::InputKey, Option<T>> {
+        self.build_moved(node_buffer, |value| value)
+    }
+
+    /// Build the graph, with `miss` as the output for inputs that aren't keys.
+    ///
+    /// Like [`Builder::build`], the values are moved into the graph, but each node
+    /// outputs its value directly instead of in an `Option`. This lets misses map to a
+    /// value from the same domain as the keys, such as `Mime::OctetStream`, without
+    /// checking for `None` after every lookup.
+    pub fn build_with_miss<'nodes>(
+        &'a mut self,
+        node_buffer: &'nodes mut Vec<super::Node<'a, Type::InputKey, T>>,
+        miss: T,
+    ) -> super::Graph<'a, 'nodes, Type::InputKey, T>
+    where
+        T: Clone,
+    {
+        self.build_moved(node_buffer, move |value| {
+            value.unwrap_or_else(|| miss.clone())
+        })
+    }
+
+    /// Build the graph, moving the values into it and converting each node's value
+    /// with `output`.
+    fn build_moved<'nodes, O>(
+        &'a mut self,
+        node_buffer: &'nodes mut Vec<super::Node<'a, Type::InputKey, O>>,
+        mut output: impl FnMut(Option<T>) -> O,
+    ) -> super::Graph<'a, 'nodes, Type::InputKey, O> {
+        // Move the keys and values out of the builder.
+        let classes = self.subtree_classes();
+        let priorities = self.priorities();
+        let chunking = self.chunking();
+        self.clear_layouts();
+        self.built = mem::replace(&mut self.nodes, Vec::new());
+        let mut values = move_values(mem::replace(&mut self.values, Vec::new()));
+        self.len = 0;
+
+        build_graph(
+            &mut self.built,
+            node_buffer,
+            chunking,
+            classes,
+            &priorities,
+            None,
+            self.cases.as_ref(),
+            self.node_hint,
+            Type::key_bytes,
+            move |index| output(values(index)),
+        )
+    }
+
+    /// Build a graph that owns its keys and values.
+    ///
+    /// Unlike [`Builder::build`], this doesn't need a node buffer and doesn't borrow
+    /// the builder, so the graph can be returned from a function or stored in a struct.
+    pub fn build_owned(mut self) -> OwnedGraph<T, Type> {
+        let mut nodes = Vec::new();
+        let classes = self.subtree_classes();
+        let priorities = self.priorities();
+        let chunking = self.chunking();
+        let graph = build_graph(
+            &mut self.nodes,
+            &mut nodes,
+            chunking,
+            classes,
+            &priorities,
+            None,
+            self.cases.as_ref(),
+            self.node_hint,
+            |key| key.to_vec(),
+            move_values(self.values),
+        );
+
+        OwnedGraph {
+            start: graph.start,
+            min_key_len: graph.min_key_len,
+            max_key_len: graph.max_key_len,
+            nodes,
+            ty: PhantomData,
+        }
+    }
+
+    /// Build the graph, cloning the values into it.
+    ///
+    /// Unlike [`Builder::build`], this leaves the keys and values in the builder, so it
+    /// can be built again.
+    pub fn build_cloned<'nodes>(
+        &'a mut self,
+        node_buffer: &'nodes mut Vec<super::Node<'a, Type::InputKey, Option<T>>>,
+    ) -> super::Graph<'a, 'nodes, Type::InputKey, Option<T>>
+    where
+        T: Clone,
+    {
+        let classes = self.subtree_classes();
+        let priorities = self.priorities();
+        let chunking = self.chunking();
+        let values = &self.values;
+        build_graph(
+            &mut self.nodes,
+            node_buffer,
+            chunking,
+            classes,
+            &priorities,
+            self.layouts.as_mut(),
+            self.cases.as_ref(),
+            self.node_hint,
+            Type::key_bytes,
+            |output| output.map(|index| values[index].as_ref().unwrap().value.clone()),
+        )
+    }
+
+    /// Build the graph, with each node referring to its value in the builder.
+    ///
+    /// Unlike [`Builder::build`], this leaves the values in the builder.
+    pub fn build_ref<'nodes>(
+        &'a mut self,
+        node_buffer: &'nodes mut Vec<super::Node<'a, Type::InputKey, Option<&'a T>>>,
+    ) -> super::Graph<'a, 'nodes, Type::InputKey, Option<&'a T>> {
+        let classes = self.subtree_classes();
+        let priorities = self.priorities();
+        let chunking = self.chunking();
+        let values = &self.values;
+        build_graph(
+            &mut self.nodes,
+            node_buffer,
+            chunking,
+            classes,
+            &priorities,
+            self.layouts.as_mut(),
+            self.cases.as_ref(),
+            self.node_hint,
+            Type::key_bytes,
+            |output| output.map(|index| &values[index].as_ref().unwrap().value),
+        )
+    }
+
+    /// Build the graph, passing each node to `emit` as soon as it is added instead of
+    /// collecting the nodes into a buffer.
+    ///
+    /// Each node only refers to nodes that were emitted before it, so the nodes can be
+    /// written out as they come, without holding the whole graph in memory. Like
+    /// [`Builder::build_ref`], the nodes refer to the values in the builder.
+    pub fn build_streaming(
+        &'a mut self,
+        mut emit: impl FnMut(super::Node<'a, Type::InputKey, Option<&'a T>>),
+    ) -> StreamedGraph {
+        self.build_streaming_with_meta(|node, _| emit(node))
+    }
+
+    /// Build the graph like [`Builder::build_streaming`], also passing the metadata of
+    /// each node's value to `emit`.
+    ///
+    /// See [`Builder::add_with_meta`].
+    pub fn build_streaming_with_meta(
+        &'a mut self,
+        mut emit: impl FnMut(super::Node<'a, Type::InputKey, Option<&'a T>>, Option<&'a str>),
+    ) -> StreamedGraph {
+        let classes = self.subtree_classes();
+        let priorities = self.priorities();
+        let chunking = self.chunking();
+        let values = &self.values;
+        let mut sink = Emit {
+            emit: |node: super::Node<'a, _, (Option<&'a T>, Option<&'a str>)>| {
+                let (output, meta) = node.output;
+                let node = super::Node {
+                    inputs: node.inputs,
+                    output,
+                    default: node.default,
+                    amount: node.amount,
+                };
+                emit(node, meta)
+            },
+            len: 0,
+        };
+        let (start, min_key_len, max_key_len) = build_into(
+            &mut self.nodes,
+            &mut sink,
+            chunking,
+            classes,
+            &priorities,
+            self.layouts.as_mut(),
+            self.cases.as_ref(),
+            &mut Type::key_bytes,
+            &mut |output| match output {
+                Some(index) => {
+                    let entry = values[index].as_ref().unwrap();
+                    (Some(&entry.value), entry.meta.as_ref().map(|meta| &**meta))
+                }
+                None => (None, None),
+            },
+        );
+
+        StreamedGraph {
+            len: sink.len,
+            start,
+            min_key_len,
+            max_key_len,
+        }
+    }
+
+    /// Build the graph as a [`Map`](super::Map).
+    ///
+    /// Unlike [`Builder::build`], the outputs are not wrapped in an `Option`. Instead,
+    /// they are written into `output_buffer`, and each node stores the index of its
+    /// output. Each value is only written once, even if it has aliases.
+    ///
+    /// The outputs are written in the order that the nodes refer to them, which only
+    /// depends on the keys, so they don't change with the order the keys are added in.
+    pub fn build_map<'nodes>(
+        &'a mut self,
+        node_buffer: &'nodes mut Vec<super::Node<'a, Type::InputKey, usize>>,
+        output_buffer: &'nodes mut Vec<T>,
+    ) -> super::Map<'a, 'nodes, 'nodes, Type::InputKey, T>
+    where
+        T: Clone,
+    {
+        output_buffer.clear();
+
+        // Equal values share the slot of the first one.
+        let classes = if self.dedup {
+            self.value_classes()
+        } else {
+            None
+        };
+
+        // Values are copied over as the nodes refer to them, so the order of the
+        // outputs only depends on the keys and not on the order they were added in.
+        let mut slots = vec![None; self.values.len()];
+        let values = &self.values;
+        let subtree_classes = self.subtree_classes();
+        let priorities = self.priorities();
+        let chunking = self.chunking();
+        let graph = build_graph(
+            &mut self.nodes,
+            node_buffer,
+            chunking,
+            subtree_classes,
+            &priorities,
+            self.layouts.as_mut(),
+            self.cases.as_ref(),
+            self.node_hint,
+            Type::key_bytes,
+            |output| {
+                let index = match output {
+                    Some(index) => classes.as_ref().map_or(index, |classes| classes[index]),
+                    None => return core::usize::MAX,
+                };
+
+                *slots[index].get_or_insert_with(|| {
+                    let entry = values[index]
+                        .as_ref()
+                        .expect("node refers to a removed value");
+                    output_buffer.push(entry.value.clone());
+                    output_buffer.len() - 1
+                })
+            },
+        );
+
+        super::Map::new(graph, &*output_buffer)
+    }
+
+    /// Forget the cached layout of the subtree that a canonicalized key is in.
+    fn touch(&mut self, key: &[u8]) {
+        let (layouts, first) = match (self.layouts.as_mut(), key.first()) {
+            (Some(layouts), Some(&first)) => (layouts, first),
+            _ => return,
+        };
+
+        // Root nodes never share a character, but they can share a byte.
+        let mut rest = layouts.split_off(&[first][..]);
+        if let Some(next) = first.checked_add(1) {
+            layouts.append(&mut rest.split_off(&[next][..]));
+        }
+    }
+
+    /// Forget every cached layout, if subtrees are cached.
+    fn clear_layouts(&mut self) {
+        if let Some(layouts) = &mut self.layouts {
+            layouts.clear();
+        }
+    }
+
+    /// Get the chunking for this builder's graph type and settings.
+    fn chunking(&self) -> Chunking {
+        let mut chunking = Chunking::of::<Type>();
+        let limit = match self.splitting {
+            Splitting::LongestPrefix => self.max_chunk_len,
+            Splitting::Units => 1,
+        };
+
+        chunking.max_len = cmp::min(chunking.max_len, cmp::max(limit, 1));
+        chunking
+    }
+
+    /// Get the priority of each value, or nothing if every value has priority zero.
+    fn priorities(&self) -> Vec<i32> {
+        let priority = |entry: &Option<Entry<T>>| entry.as_ref().map_or(0, |entry| entry.priority);
+        if self.values.iter().all(|entry| priority(entry) == 0) {
+            return Vec::new();
+        }
+
+        self.values.iter().map(priority).collect()
+    }
+
+    /// Get the value classes to share subtrees with, if subtrees should be shared.
+    fn subtree_classes(&self) -> Option<Vec<usize>> {
+        if self.share {
+            self.value_classes()
+        } else {
+            None
+        }
+    }
+
+    /// Get the canonical index of each value, where equal values share an index.
+    ///
+    /// Returns `None` if values can't be compared.
+    fn value_classes(&self) -> Option<Vec<usize>> {
+        self.compare
+            .map(|compare| value_classes(&self.values, compare))
+    }
+}
+
+impl<'a, T, Type: GraphType<'a>> Builder<Vec<T>, Type> {
+    /// Create a builder for a multi-map, where each key maps to a list of values.
+    ///
+    /// Adding a key that already exists appends the new values to the old ones, rather
+    /// than returning an error, so tags or other keys that naturally repeat can be
+    /// added as they come. Values are kept in the order they were added in.
+    pub fn multi_map() -> Self {
+        let mut builder = Self::new();
+        builder.set_duplicate_policy(DuplicatePolicy::Merge(extend_values));
+        builder
+    }
+
+    /// Add a value to the list of values for a key.
+    ///
+    /// This always appends to the key's existing values, whatever the builder's
+    /// [`DuplicatePolicy`] is.
+    pub fn push(&mut self, key: impl Into<String>, value: T) -> Result<(), AddError<Vec<T>>> {
+        self.add_with_policy(
+            key.into(),
+            vec![value],
+            DuplicatePolicy::Merge(extend_values),
+            None,
+        )
+        .map(|_| ())
+    }
+}
+
+/// Append a list of values to another, for [`Builder::multi_map`].
+fn extend_values<T>(values: &mut Vec<T>, more: Vec<T>) {
+    values.extend(more);
+}
+
+impl<T, Type> Builder<T, Type> {
+    /// Create the error for a key that is already in the builder, with the value at
+    /// `index`.
+    fn conflict(&self, key: Vec<u8>, value: T, index: usize) -> AddError<T> {
+        let existing = match self.value_entry(index).aliases.first() {
+            Some(canonical) => key_string(canonical.clone()),
+            None => key_string(key.clone()),
+        };
+
+        AddError::Conflict {
+            key: key_string(key),
+            value,
+            existing,
+            index,
+        }
+    }
+
+    /// The number of values in the builder.
+    ///
+    /// Each call to [`Builder::add`] or [`Builder::add_wildcard`] that adds a new key
+    /// adds a value, while a key that is merged into or replaces an existing value
+    /// doesn't. A value with aliases is only counted once. Once the builder has been
+    /// built with [`Builder::build`] or another method that moves the values out, it is
+    /// empty.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Tell whether the builder has no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Add an entry to the list of values, returning its index.
+    fn push_entry(&mut self, entry: Entry<T>) -> usize {
+        self.values.push(Some(entry));
+        self.len += 1;
+        self.values.len() - 1
+    }
+
+    /// Remove the entry at the given index, if it hasn't been removed already.
+    fn take_entry(&mut self, index: usize) -> Option<Entry<T>> {
+        let entry = self.values[index].take();
+        if entry.is_some() {
+            self.len -= 1;
+        }
+        entry
+    }
+
+    /// Get the entry at the given index, which must not have been removed.
+    fn value_entry(&self, index: usize) -> &Entry<T> {
+        self.values[index]
+            .as_ref()
+            .expect("node refers to a removed value")
+    }
+
+    /// Get the entry at the given index mutably, which must not have been removed.
+    fn value_entry_mut(&mut self, index: usize) -> &mut Entry<T> {
+        self.values[index]
+            .as_mut()
+            .expect("node refers to a removed value")
+    }
+
+    /// Iterate over the keys in the builder and their values, in sorted order.
+    ///
+    /// The keys are canonicalized, the same as the graph matches them. Each alias of a
+    /// value is its own key, so the value is visited once for each. Wildcards and
+    /// denied keys aren't included. Once the builder has been built, it is empty.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            walk: Walk::new(&self.nodes),
+            values: &self.values,
+        }
+    }
+
+    /// Iterate over the keys in the builder and their values, with the casing that the
+    /// keys were added with.
+    ///
+    /// This visits the same keys in the same order as [`Builder::iter`], but each key
+    /// is spelled the way [`Builder::cased`] spells it. Code that writes the keys back
+    /// out, such as generated code, should use this so that it keeps their casing.
+    pub fn iter_cased(&self) -> IterCased<'_, T> {
+        IterCased {
+            iter: self.iter(),
+            cases: self.cases.as_ref(),
+        }
+    }
+
+    /// Iterate over the keys in the builder, in sorted order.
+    ///
+    /// See [`Builder::iter`] for which keys are included.
+    pub fn keys(&self) -> Keys<'_, T> {
+        Keys(self.iter())
+    }
+
+    /// Iterate over the values in the builder, in the sorted order of their keys.
+    ///
+    /// See [`Builder::iter`] for which keys are included. A value with aliases is
+    /// visited once for each of its keys.
+    pub fn values(&self) -> Values<'_, T> {
+        Values(self.iter())
+    }
+
+    /// Move every key and value out of the builder, in sorted order.
+    ///
+    /// Unlike [`Builder::iter`], each value comes out once, under its canonical key if
+    /// it has aliases, so the pairs can be added to another builder to get the same
+    /// values back. Wildcards, denied keys and the other aliases are dropped. The
+    /// builder is left empty, but keeps its settings.
+    pub fn drain(&mut self) -> IntoIter<T> {
+        let nodes = mem::replace(&mut self.nodes, Vec::new());
+        let mut values = mem::replace(&mut self.values, Vec::new());
+        self.len = 0;
+
+        let mut pairs = Vec::new();
+        let mut walk = Walk::new(&nodes);
+        while let Some(index) = walk.next() {
+            if let Some(entry) = values[index].take() {
+                let canonical = entry.aliases.into_iter().next();
+                let key = canonical.unwrap_or_else(|| walk.key.clone());
+                pairs.push((key_string(key), entry.value));
+            }
+        }
+
+        pairs.sort_by(|(a, _), (b, _)| a.cmp(b));
+        IntoIter(pairs.into_iter())
+    }
+}
+
+impl<T, Type> IntoIterator for Builder<T, Type> {
+    type Item = (String, T);
+    type IntoIter = IntoIter<T>;
+
+    /// Move every key and value out of the builder, as [`Builder::drain`] does.
+    fn into_iter(mut self) -> Self::IntoIter {
+        self.drain()
+    }
+}
+
+/// Build several graphs into one shared set of nodes.
+///
+/// Identical subtrees are shared between all of the graphs, so graphs with common
+/// structure, such as a graph of MIME types and a graph of file extensions that map to
+/// the same values, take less space than if they were built separately. The graphs are
+/// returned in the same order as the builders, and all of them refer to `node_buffer`.
+///
+/// Like [`Builder::build`], the values are moved into the graphs.
+pub fn build_shared<'a, 'nodes, T: Ord, Type: GraphType<'a>>(
+    builders: &'a mut [Builder<T, Type>],
+    node_buffer: &'nodes mut Vec<super::Node<'a, Type::InputKey, Option<T>>>,
+) -> Vec<super::Graph<'a, 'nodes, Type::InputKey, Option<T>>> {
+    // Move the keys and values out of the builders.
+    let mut values = Vec::new();
+    let mut roots = Vec::with_capacity(builders.len());
+    for builder in builders.iter_mut() {
+        let layout = (builder.chunking(), builder.priorities());
+        let nodes = mem::replace(&mut builder.nodes, Vec::new());
+        roots.push((values.len(), layout, nodes));
+        values.extend(mem::replace(&mut builder.values, Vec::new()));
+        builder.len = 0;
+    }
+
+    let mut subtrees = Some(Subtrees {
+        classes: value_classes(&values, T::cmp),
+        offset: 0,
+        nodes: BTreeMap::new(),
+    });
+    let mut output = move_values(values);
+
+    node_buffer.clear();
+    node_buffer.reserve(builders.iter().map(|builder| builder.node_hint).sum());
+    push_trap(node_buffer, &mut output);
+
+    // Build each graph, offsetting its values into the shared list.
+    let mut starts = Vec::with_capacity(builders.len());
+    for (builder, (offset, (chunking, priorities), nodes)) in builders.iter_mut().zip(roots) {
+        builder.built = nodes;
+        if let Some(subtrees) = &mut subtrees {
+            subtrees.offset = offset;
+        }
+
+        starts.push(build_root(
+            &mut builder.built,
+            node_buffer,
+            chunking,
+            &priorities,
+            None,
+            builder.cases.as_ref(),
+            &mut subtrees,
+            &mut Type::key_bytes,
+            &mut |index: Option<usize>| output(index.map(|index| offset + index)),
+        ));
+    }
+
+    log!(
+        debug,
+        "built {} graphs with {} shared nodes",
+        starts.len(),
+        node_buffer.len()
+    );
+
+    let node_buffer = &*node_buffer;
+    starts
+        .into_iter()
+        .map(|(start, min_key_len, max_key_len)| {
+            super::Graph::with_key_lengths(node_buffer, start, min_key_len, max_key_len)
+        })
+        .collect()
+}
+
+/// Get the canonical index of each value, where equal values share an index.
+fn value_classes<T>(
+    values: &[Option<Entry<T>>],
+    compare: fn(&T, &T) -> cmp::Ordering,
+) -> Vec<usize> {
+    let value = |index: usize| &values[index].as_ref().unwrap().value;
+
+    // Sort the values so that equal values are next to each other.
+    let mut order = (0..values.len())
+        .filter(|&index| values[index].is_some())
+        .collect::<Vec<_>>();
+    order.sort_by(|&a, &b| compare(value(a), value(b)));
+
+    let mut classes = (0..values.len()).collect::<Vec<_>>();
+    for pair in order.windows(2) {
+        if compare(value(pair[0]), value(pair[1])) == cmp::Ordering::Equal {
+            classes[pair[1]] = classes[pair[0]];
+        }
+    }
+
+    classes
+}
+
+/// A graph that owns its keys and values, created by [`Builder::build_owned`].
+#[derive(Debug)]
+pub struct OwnedGraph<T, Type> {
+    /// The nodes in the graph, with owned keys.
+    nodes: Vec<super::Node<'static, Vec<u8>, Option<T>>>,
+
+    /// The index of the start node.
+    start: usize,
+
+    /// The length of the shortest key in the graph.
+    min_key_len: usize,
+
+    /// The length of the longest key in the graph.
+    max_key_len: usize,
+
+    /// The type of the graph.
+    ty: PhantomData<Type>,
+}
+
+impl<T, Type> OwnedGraph<T, Type> {
+    /// Process the input and return the output.
+    ///
+    /// This behaves the same as [`Graph::process`](super::Graph::process).
+    pub fn process<'a>(&'a self, mut input: <Type as GraphType<'a>>::InputKey) -> &'a Option<T>
+    where
+        Type: GraphType<'a>,
+    {
+        let mut node = &self.nodes[self.start];
+
+        // If the input can't possibly be a key, bail out early.
+        let len = input.len();
+        if len < self.min_key_len || len > self.max_key_len {
+            return &self.nodes[node.default].output;
+        }
+
+        loop {
+            // Get the next input chunk.
+            let ended = input.is_empty();
+            let (chunk, rest) = match input.split(node.amount) {
+                Some(result) => result,
+                None if ended || node.inputs.is_empty() => return &node.output,
+                None => return &self.nodes[node.default].output,
+            };
+
+            // Get the next node.
+            let inputs: &[_] = &node.inputs;
+            let next = match inputs.binary_search_by(|(key, _)| Type::key_bytes(key).cmp(&chunk)) {
+                Ok(i) => inputs[i].1,
+                Err(_) => node.default,
+            };
+
+            node = &self.nodes[next];
+            input = rest;
+        }
+    }
+}
+
+/// An iterator over the keys and values of a [`Builder`], created by [`Builder::iter`].
+#[derive(Debug)]
+pub struct Iter<'b, T> {
+    /// Visits the nodes of the trie.
+    walk: Walk<'b>,
+
+    /// The values that the nodes refer to.
+    values: &'b [Option<Entry<T>>],
+}
+
+impl<'b, T> Iterator for Iter<'b, T> {
+    type Item = (String, &'b T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(index) = self.walk.next() {
+            if let Some(entry) = &self.values[index] {
+                return Some((key_string(self.walk.key.clone()), &entry.value));
+            }
+        }
+
+        None
+    }
+}
+
+/// Visits the nodes of a trie with outputs, in the sorted order of their keys.
+#[derive(Debug)]
+struct Walk<'b> {
+    /// The nodes left to visit, along with the length of the key up to each one.
+    stack: Vec<(&'b Node, usize)>,
+
+    /// The key of the node that was visited last.
+    key: Vec<u8>,
+}
+
+impl<'b> Walk<'b> {
+    /// Start visiting a set of root nodes.
+    fn new(nodes: &'b [Node]) -> Self {
+        let mut walk = Walk {
+            stack: Vec::new(),
+            key: Vec::new(),
+        };
+        walk.push_children(nodes);
+        walk
+    }
+
+    /// Add a set of siblings to visit next, so that they come out in sorted order.
+    fn push_children(&mut self, children: &'b [Node]) {
+        let start = self.stack.len();
+        let len = self.key.len();
+        self.stack.extend(children.iter().map(|child| (child, len)));
+
+        // The stack is popped from the end, so the smallest key goes last.
+        self.stack[start..].sort_by(|(a, _), (b, _)| b.value.cmp(&a.value));
+    }
+
+    /// Visit the next node that has an output and isn't denied, returning the index of
+    /// its value. Its key is left in `key`.
+    fn next(&mut self) -> Option<usize> {
+        while let Some((node, len)) = self.stack.pop() {
+            self.key.truncate(len);
+            self.key.extend_from_slice(&node.value);
+            self.push_children(&node.children);
+
+            if let (false, Some(index)) = (node.deny, node.output) {
+                return Some(index);
+            }
+        }
+
+        None
+    }
+}
+
+/// An iterator over the keys and values of a [`Builder`], with the casing that the keys
+/// were added with, created by [`Builder::iter_cased`].
+#[derive(Debug)]
+pub struct IterCased<'b, T> {
+    /// Visits the canonical keys.
+    iter: Iter<'b, T>,
+
+    /// The casing of the keys, if it is kept.
+    cases: Option<&'b BTreeMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl<'b, T> Iterator for IterCased<'b, T> {
+    type Item = (String, &'b T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.iter.next()?;
+        match self.cases.and_then(|cases| cases.get(key.as_bytes())) {
+            Some(cased) => Some((key_string(cased.clone()), value)),
+            None => Some((key, value)),
+        }
+    }
+}
+
+/// An iterator over the keys of a [`Builder`], created by [`Builder::keys`].
+#[derive(Debug)]
+pub struct Keys<'b, T>(Iter<'b, T>);
+
+impl<'b, T> Iterator for Keys<'b, T> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(key, _)| key)
+    }
+}
+
+/// An iterator over the values of a [`Builder`], created by [`Builder::values`].
+#[derive(Debug)]
+pub struct Values<'b, T>(Iter<'b, T>);
+
+impl<'b, T> Iterator for Values<'b, T> {
+    type Item = &'b T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(_, value)| value)
+    }
+}
+
+/// An iterator that moves the keys and values out of a [`Builder`], created by
+/// [`Builder::drain`] or [`Builder::into_iter`].
+#[derive(Debug)]
+pub struct IntoIter<T>(vec::IntoIter<(String, T)>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = (String, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+/// The shape of a graph whose nodes were passed out by [`Builder::build_streaming`].
+///
+/// Together with the nodes, this is enough to recreate the graph with
+/// [`Graph::with_key_lengths`](super::Graph::with_key_lengths).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamedGraph {
+    /// The number of nodes that were emitted.
+    len: usize,
+
+    /// The index of the start node.
+    start: usize,
+
+    /// The length of the shortest key in the graph.
+    min_key_len: usize,
+
+    /// The length of the longest key in the graph.
+    max_key_len: usize,
+}
+
+impl StreamedGraph {
+    /// The number of nodes that were emitted.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether no nodes were emitted.
+    ///
+    /// This is never the case, since every graph has a trap node and a start node.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The index of the start node.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// The length of the shortest key in the graph.
+    pub fn min_key_len(&self) -> usize {
+        self.min_key_len
+    }
+
+    /// The length of the longest key in the graph.
+    pub fn max_key_len(&self) -> usize {
+        self.max_key_len
+    }
+}
+
+/// Statistics about a graph before it is built, returned by [`Builder::stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of keys, including aliases and wildcards.
+    keys: usize,
+
+    /// The number of nodes in the graph.
+    nodes: usize,
+
+    /// The largest number of transitions needed to match a key.
+    max_depth: usize,
+
+    /// The estimated size of the graph's static data, in bytes.
+    static_size: usize,
+}
+
+impl Stats {
+    /// The number of keys, including aliases and wildcards.
+    pub fn keys(&self) -> usize {
+        self.keys
+    }
+
+    /// The number of nodes in the built graph, including the trap and start nodes.
+    pub fn nodes(&self) -> usize {
+        self.nodes
+    }
+
+    /// The largest number of transitions needed to match a key.
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// The estimated size of the graph's static data, in bytes.
+    ///
+    /// This counts the nodes, their transitions and the bytes of their keys, as they
+    /// would be laid out in generated code. It doesn't count any data that the values
+    /// point to.
+    pub fn static_size(&self) -> usize {
+        self.static_size
+    }
+}
+
+/// Move the values out of the builder, for use as the `output` of [`build_graph`].
+///
+/// Values that can be output by several nodes, such as values with aliases, are
+/// cloned instead.
+fn move_values<T>(mut values: Vec<Option<Entry<T>>>) -> impl FnMut(Option<usize>) -> Option<T> {
+    move |output| {
+        output.map(|index| {
+            let entry = values[index].as_ref().unwrap();
+            match entry.clone {
+                Some(clone) => clone(&entry.value),
+                None => values[index].take().unwrap().value,
+            }
+        })
+    }
+}
+
+/// Build the graph, using `key` to convert each node's key and `output` to convert
+/// each node's value index.
+#[allow(clippy::too_many_arguments)]
+fn build_graph<'k, 'inst, 'nodes, K, O>(
+    nodes: &'k mut [Node],
+    node_buffer: &'nodes mut Vec<super::Node<'inst, K, O>>,
+    chunking: Chunking,
+    classes: Option<Vec<usize>>,
+    priorities: &[i32],
+    layouts: Option<&'k mut BTreeMap<Vec<u8>, SubtreeLayout>>,
+    cases: Option<&BTreeMap<Vec<u8>, Vec<u8>>>,
+    reserve: usize,
+    mut key: impl FnMut(&'k [u8]) -> K,
+    mut output: impl FnMut(Option<usize>) -> O,
+) -> super::Graph<'inst, 'nodes, K, O> {
+    // Clear the node buffer.
+    node_buffer.clear();
+    node_buffer.reserve(reserve);
+
+    let (start, min_key_len, max_key_len) = build_into(
+        nodes,
+        node_buffer,
+        chunking,
+        classes,
+        priorities,
+        layouts,
+        cases,
+        &mut key,
+        &mut output,
+    );
+    super::Graph::with_key_lengths(&*node_buffer, start, min_key_len, max_key_len)
+}
+
+/// Build the graph into a node sink.
+///
+/// Returns the index of the start node, along with the lengths of the shortest and
+/// longest keys.
+#[allow(clippy::too_many_arguments)]
+fn build_into<'k, 'inst, K: 'inst, O>(
+    nodes: &'k mut [Node],
+    node_buffer: &mut impl NodeSink<'inst, K, O>,
+    chunking: Chunking,
+    classes: Option<Vec<usize>>,
+    priorities: &[i32],
+    layouts: Option<&'k mut BTreeMap<Vec<u8>, SubtreeLayout>>,
+    cases: Option<&BTreeMap<Vec<u8>, Vec<u8>>>,
+    key: &mut impl FnMut(&'k [u8]) -> K,
+    output: &mut impl FnMut(Option<usize>) -> O,
+) -> (usize, usize, usize) {
+    // Add a "default" node at position zero.
+    push_trap(node_buffer, output);
+
+    let mut subtrees = classes.map(|classes| Subtrees {
+        classes,
+        offset: 0,
+        nodes: BTreeMap::new(),
+    });
+    let lengths = build_root(
+        nodes,
+        node_buffer,
+        chunking,
+        priorities,
+        layouts,
+        cases,
+        &mut subtrees,
+        key,
+        output,
+    );
+
+    #[cfg(feature = "log")]
+    {
+        if let Some(subtrees) = &subtrees {
+            log::debug!(
+                "shared subtrees between keys, leaving {} unique nodes",
+                subtrees.nodes.len()
+            );
+        }
+    }
+
+    lengths
+}
+
+/// Add the "default" trap node, which every failed transition leads to.
+fn push_trap<'inst, K: 'inst, O>(
+    node_buffer: &mut impl NodeSink<'inst, K, O>,
+    output: &mut impl FnMut(Option<usize>) -> O,
+) {
+    node_buffer.push(super::Node {
+        inputs: crate::MaybeSlice::Slice(&[]),
+        output: output(None),
+        default: 0,
+        amount: core::usize::MAX,
+    });
+}
+
+/// Add a set of root nodes and their children to the node buffer, followed by a start
+/// node that leads to them.
+///
+/// If `layouts` is set, the subtrees that are in it are copied over rather than laid
+/// out again. Returns the index of the start node, along with the lengths of the
+/// shortest and longest keys.
+#[allow(clippy::too_many_arguments)]
+fn build_root<'k, 'inst, K: 'inst, O>(
+    nodes: &'k mut [Node],
+    node_buffer: &mut impl NodeSink<'inst, K, O>,
+    chunking: Chunking,
+    priorities: &[i32],
+    layouts: Option<&'k mut BTreeMap<Vec<u8>, SubtreeLayout>>,
+    cases: Option<&BTreeMap<Vec<u8>, Vec<u8>>>,
+    subtrees: &mut Option<Subtrees<'k>>,
+    key: &mut impl FnMut(&'k [u8]) -> K,
+    output: &mut impl FnMut(Option<usize>) -> O,
+) -> (usize, usize, usize) {
+    log!(debug, "building graph from {} root nodes", nodes.len());
+
+    // Sort our children.
+    coalesce_children(nodes, chunking);
+    shorten_children(nodes, chunking);
+    nodes.sort_unstable_by(|a, b| a.value.cmp(&b.value));
+
+    // Subtrees that are shared can't be cached, since they depend on each other.
+    let layouts = match subtrees {
+        Some(_) => None,
+        None => layouts,
+    };
+    let cached = |node: &Node| {
+        layouts
+            .as_ref()
+            .map_or(false, |layouts| layouts.contains_key(&node.value))
+    };
+
+    // Recursively sort node children, unless the subtree hasn't changed.
+    #[cfg(not(feature = "rayon"))]
+    for node in nodes.iter_mut().filter(|node| !cached(node)) {
+        node.normalize(chunking);
+    }
+
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+        nodes
+            .par_iter_mut()
+            .filter(|node| !cached(node))
+            .for_each(|node| node.normalize(chunking));
+    }
+
+    // Give the transitions the casing of their keys, now that they are split up.
+    if let Some(cases) = cases {
+        for node in nodes.iter_mut().filter(|node| !cached(node)) {
+            node.recase(cases);
+        }
+    }
+
+    let nodes: &'k [Node] = nodes;
+
+    // Build the graph.
+    let initial_indices = match layouts {
+        Some(layouts) => build_roots_cached(
+            nodes,
+            node_buffer,
+            chunking,
+            priorities,
+            layouts,
+            key,
+            output,
+        ),
+        #[cfg(feature = "rayon")]
+        None if subtrees.is_none() => {
+            build_roots_parallel(nodes, node_buffer, chunking, priorities, key, output)
+        }
+        None => build_roots(
+            nodes,
+            node_buffer,
+            chunking,
+            priorities,
+            subtrees,
+            key,
+            output,
+        ),
+    };
+
+    let amount = shortest_value(nodes, chunking);
+
+    // Create a root node.
+    let root = super::Node {
+        inputs: crate::MaybeSlice::Vec(initial_indices),
+        output: output(None),
+        default: 0,
+        amount,
+    };
+    node_buffer.push(root);
+
+    // The last node will be our starting node.
+    let start = node_buffer.len() - 1;
+
+    // Find the lengths of the shortest and longest keys.
+    let mut min_key_len = core::usize::MAX;
+    let mut max_key_len = 0;
+    for node in nodes.iter() {
+        node.key_lengths(0, chunking, &mut min_key_len, &mut max_key_len);
+    }
+
+    if min_key_len > max_key_len {
+        // There are no keys, so nothing can match.
+        min_key_len = 0;
+    }
+
+    log!(
+        debug,
+        "built graph with {} nodes, key lengths {}..={}",
+        node_buffer.len(),
+        min_key_len,
+        max_key_len
+    );
+
+    (start, min_key_len, max_key_len)
+}
+
+/// Add each root node and its children to the node buffer.
+///
+/// Returns the transitions from the start node to the root nodes.
+fn build_roots<'k, 'inst, K: 'inst, O>(
+    nodes: &'k [Node],
+    node_buffer: &mut impl NodeSink<'inst, K, O>,
+    chunking: Chunking,
+    priorities: &[i32],
+    subtrees: &mut Option<Subtrees<'k>>,
+    key: &mut impl FnMut(&'k [u8]) -> K,
+    output: &mut impl FnMut(Option<usize>) -> O,
+) -> Vec<(K, usize)> {
+    nodes
+        .iter()
+        .map(|node| {
+            let index = node.build(
+                node_buffer,
+                chunking,
+                priorities,
+                subtrees,
+                None,
+                key,
+                output,
+            );
+            (key(node.key()), index)
+        })
+        .collect()
+}
+
+/// Add each root node and its children to the node buffer, laying out each subtree on
+/// its own thread.
+///
+/// The subtrees are laid out with raw keys and value indices, then converted in order,
+/// so the result is the same as [`build_roots`]. Subtrees can't be shared this way, since
+/// that needs to look at every subtree that came before.
+#[cfg(feature = "rayon")]
+fn build_roots_parallel<'k, 'inst, K: 'inst, O>(
+    nodes: &'k [Node],
+    node_buffer: &mut impl NodeSink<'inst, K, O>,
+    chunking: Chunking,
+    priorities: &[i32],
+    key: &mut impl FnMut(&'k [u8]) -> K,
+    output: &mut impl FnMut(Option<usize>) -> O,
+) -> Vec<(K, usize)> {
+    use rayon::prelude::*;
+
+    let layouts = nodes
+        .par_iter()
+        .map(|node| {
+            // Index zero is a stand-in for the real trap node.
+            let mut raw = Vec::new();
+            push_trap(&mut raw, &mut |output| output);
+            let index = node.build(
+                &mut raw,
+                chunking,
+                priorities,
+                &mut None,
+                None,
+                &mut |key| key,
+                &mut |output| output,
+            );
+            (raw, index)
+        })
+        .collect::<Vec<_>>();
+
+    nodes
+        .iter()
+        .zip(layouts)
+        .map(|(node, (raw, index))| {
+            // Move the subtree's indices past the nodes that are already in the buffer.
+            let offset = node_buffer.len() - 1;
+            let relocate = |index: usize| if index == 0 { 0 } else { index + offset };
+
+            for raw_node in raw.into_iter().skip(1) {
+                let inputs = raw_node
+                    .inputs
+                    .iter()
+                    .map(|&(input, next)| (key(input), relocate(next)))
+                    .collect();
+
+                node_buffer.push(super::Node {
+                    inputs: crate::MaybeSlice::Vec(inputs),
+                    output: output(raw_node.output),
+                    default: relocate(raw_node.default),
+                    amount: raw_node.amount,
+                });
+            }
+
+            (key(node.key()), relocate(index))
+        })
+        .collect()
+}
+
+/// Add each root node and its children to the node buffer, copying over the subtrees
+/// that haven't changed since the last build.
+///
+/// The other subtrees are laid out with raw keys and value indices, like in
+/// [`build_roots_parallel`], and kept in `layouts` for the next build. Layouts of root
+/// nodes that no longer exist are dropped.
+fn build_roots_cached<'k, 'inst, K: 'inst, O>(
+    nodes: &'k [Node],
+    node_buffer: &mut impl NodeSink<'inst, K, O>,
+    chunking: Chunking,
+    priorities: &[i32],
+    layouts: &'k mut BTreeMap<Vec<u8>, SubtreeLayout>,
+    key: &mut impl FnMut(&'k [u8]) -> K,
+    output: &mut impl FnMut(Option<usize>) -> O,
+) -> Vec<(K, usize)> {
+    let mut old = mem::replace(layouts, BTreeMap::new());
+    #[cfg(feature = "log")]
+    let cached = old.len();
+    for node in nodes {
+        let layout = old
+            .remove(&node.value)
+            .unwrap_or_else(|| SubtreeLayout::new(node, chunking, priorities));
+        layouts.insert(node.value.clone(), layout);
+    }
+
+    log!(
+        debug,
+        "reused {} of {} subtrees",
+        cached - old.len(),
+        nodes.len()
+    );
+
+    // The layouts are sorted by key, the same as the nodes.
+    let layouts: &'k BTreeMap<Vec<u8>, SubtreeLayout> = layouts;
+    nodes
+        .iter()
+        .zip(layouts.values())
+        .map(|(node, layout)| {
+            // Move the subtree's indices past the nodes that are already in the buffer.
+            let offset = node_buffer.len() - 1;
+            let relocate = |index: usize| if index == 0 { 0 } else { index + offset };
+
+            for raw_node in layout.nodes.iter().skip(1) {
+                let inputs = raw_node
+                    .inputs
+                    .iter()
+                    .map(|(input, next)| (key(input), relocate(*next)))
+                    .collect();
+
+                node_buffer.push(super::Node {
+                    inputs: crate::MaybeSlice::Vec(inputs),
+                    output: output(raw_node.output),
+                    default: relocate(raw_node.default),
+                    amount: raw_node.amount,
+                });
+            }
+
+            (key(node.key()), relocate(layout.root))
+        })
+        .collect()
+}
+
+/// The layout of a root node's subtree, kept between builds by
+/// [`Builder::cache_subtrees`].
+#[derive(Debug)]
+struct SubtreeLayout {
+    /// The nodes of the subtree, after a stand-in for the trap node at index zero.
+    nodes: Vec<super::Node<'static, Vec<u8>, Option<usize>>>,
+
+    /// The index of the root node in `nodes`.
+    root: usize,
+}
+
+impl SubtreeLayout {
+    /// Lay out a root node's subtree, with raw keys and value indices.
+    fn new(node: &Node, chunking: Chunking, priorities: &[i32]) -> Self {
+        let mut nodes = Vec::new();
+        push_trap(&mut nodes, &mut |output| output);
+        let root = node.build(
+            &mut nodes,
+            chunking,
+            priorities,
+            &mut None,
+            None,
+            &mut |key| key.to_vec(),
+            &mut |output| output,
+        );
+
+        SubtreeLayout { nodes, root }
+    }
+}
+
+/// Somewhere to put the nodes of a graph as they are built.
+trait NodeSink<'inst, K, O> {
+    /// Add a node, which takes the next index.
+    fn push(&mut self, node: super::Node<'inst, K, O>);
+
+    /// Get the number of nodes that have been added.
+    fn len(&self) -> usize;
+}
+
+impl<'inst, K, O> NodeSink<'inst, K, O> for Vec<super::Node<'inst, K, O>> {
+    fn push(&mut self, node: super::Node<'inst, K, O>) {
+        Vec::push(self, node);
+    }
+
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+}
+
+/// A node sink that passes each node to a function instead of storing it.
+struct Emit<F> {
+    /// The function to pass nodes to.
+    emit: F,
+
+    /// The number of nodes that have been emitted.
+    len: usize,
+}
+
+impl<'inst, K: 'inst, O, F: FnMut(super::Node<'inst, K, O>)> NodeSink<'inst, K, O> for Emit<F> {
+    fn push(&mut self, node: super::Node<'inst, K, O>) {
+        (self.emit)(node);
+        self.len += 1;
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// A node in the graph.
+#[derive(Debug)]
+struct Node {
+    /// The current value associated with this node.
+    value: Vec<u8>,
+
+    /// The index of the value associated with this node, if any.
+    output: Option<usize>,
+
+    /// The index of the value for any input that starts with this node's key, if any.
+    wildcard: Option<usize>,
+
+    /// Whether this node's key is denied, so it never matches.
+    deny: bool,
+
+    /// The value with the casing of the keys it is part of, if case is preserved and
+    /// the casing differs. This is set for each build.
+    cased: Option<Vec<u8>>,
+
+    /// The next node to use for each possible input.
+    children: Vec<Node>,
+}
+
+impl Clone for Node {
+    fn clone(&self) -> Self {
+        // Clone the children before their parents, without recursing.
+        let mut cloned = Vec::new();
+        let mut stack = vec![(self, None)];
+
+        while let Some((node, start)) = stack.pop() {
+            match start {
+                None => {
+                    stack.push((node, Some(cloned.len())));
+                    stack.extend(node.children.iter().rev().map(|child| (child, None)));
+                }
+                Some(start) => {
+                    let children = cloned.split_off(start);
+                    cloned.push(Node {
+                        value: node.value.clone(),
+                        output: node.output,
+                        wildcard: node.wildcard,
+                        deny: node.deny,
+                        cased: node.cased.clone(),
+                        children,
+                    });
+                }
+            }
+        }
+
+        cloned.pop().expect("cloned the root node")
+    }
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        // Dropping the children one level at a time keeps deep tries from overflowing
+        // the stack.
+        let mut stack = mem::replace(&mut self.children, Vec::new());
+        while let Some(mut node) = stack.pop() {
+            stack.append(&mut node.children);
+        }
+    }
+}
+
+/// The subtrees that have been added to the graph, so identical ones can be shared.
+#[derive(Debug)]
+struct Subtrees<'a> {
+    /// The canonical index of each value, where equal values share an index.
+    classes: Vec<usize>,
+
+    /// The offset of the current builder's values in `classes`.
+    offset: usize,
+
+    /// The index of each node, keyed by its signature.
+    nodes: BTreeMap<Signature<'a>, usize>,
+}
+
+/// The output class, default node and transitions of a node, which identify its subtree.
+type Signature<'a> = (Option<usize>, usize, Vec<(&'a [u8], usize)>);
+
+/// How keys are split into chunks, as determined by the graph type.
+#[derive(Debug, Clone, Copy)]
+struct Chunking {
+    /// Get the length of a key, in the units that the input key is split by.
+    len: fn(&[u8]) -> usize,
+
+    /// Get the byte offset that is the given number of units into a key.
+    offset: fn(&[u8], usize) -> usize,
+
+    /// The longest chunk that a single transition can match.
+    max_len: usize,
+
+    /// Whether keys are UTF-8, so shared prefixes can't end inside of a character.
+    utf8: bool,
+}
+
+impl Chunking {
+    /// Get the chunking for a graph type.
+    fn of<'a, Type: GraphType<'a>>() -> Self {
+        Chunking {
+            len: Type::key_len,
+            offset: Type::key_offset,
+            max_len: Type::MAX_CHUNK_LEN,
+            utf8: Type::UTF8,
+        }
+    }
+}
+
+impl Node {
+    /// Create a node with no outputs or children.
+    fn new(value: Vec<u8>) -> Self {
+        Node {
+            value,
+            output: None,
+            wildcard: None,
+            deny: false,
+            cased: None,
+            children: Vec::new(),
+        }
+    }
+
+    /// Get the value that the node's transition is built with.
+    fn key(&self) -> &[u8] {
+        self.cased.as_ref().unwrap_or(&self.value)
+    }
+
+    /// Set the casing of this node and its children from the keys that they are part
+    /// of, where this node is a root node.
+    fn recase(&mut self, cases: &BTreeMap<Vec<u8>, Vec<u8>>) {
+        let mut path = Vec::new();
+        let mut stack = vec![(self, 0)];
+
+        while let Some((node, start)) = stack.pop() {
+            path.truncate(start);
+            path.extend_from_slice(&node.value);
+
+            // Take the casing of the first key under this node that has one.
+            node.cased = cases
+                .range::<[u8], _>((Bound::Included(&path[..]), Bound::Unbounded))
+                .next()
+                .filter(|(key, _)| key.starts_with(&path))
+                .map(|(_, cased)| cased[start..path.len()].to_vec())
+                .filter(|cased| *cased != node.value);
+
+            let end = path.len();
+            stack.extend(node.children.iter_mut().map(|child| (child, end)));
+        }
+    }
+
+    /// Whether this node has anything besides its children.
+    fn is_empty(&self) -> bool {
+        self.output.is_none() && self.wildcard.is_none() && !self.deny
+    }
+
+    /// Sort this node's children and ensure all of its strings are the same length.
+    ///
+    /// This walks the subtree with a stack rather than recursing, since long keys
+    /// make for deep tries.
+    fn normalize(&mut self, chunking: Chunking) {
+        let mut stack = vec![self];
+
+        while let Some(node) = stack.pop() {
+            coalesce_children(&mut node.children, chunking);
+            shorten_children(&mut node.children, chunking);
+
+            // Sort the children.
+            node.children.sort_by(|a, b| a.value.cmp(&b.value));
+
+            // Do the same for all children.
+            stack.extend(node.children.iter_mut());
+        }
+    }
+
+    /// Try to shortern this node to be less than the given length.
+    ///
+    /// The split may be moved forward to keep a character whole, in which case the node
+    /// ends up longer than `len`.
+    #[allow(clippy::mem_replace_with_default)]
+    fn shorten(&mut self, len: usize, chunking: Chunking) {
+        let offset = (chunking.offset)(&self.value, len);
+        if offset < self.value.len() {
+            // Get the chunk that we need to split off.
+            let new_value = self.value.split_off(offset);
+
+            // Create a new node with our output and children.
+            // We use mem::replace here to support a lower MSRV.
+            let new_node = Node {
+                value: new_value,
+                output: self.output.take(),
+                wildcard: self.wildcard.take(),
+                deny: mem::replace(&mut self.deny, false),
+                cased: None,
+                children: mem::replace(&mut self.children, vec![]),
+            };
+
+            // Add the new node as a child.
+            self.children.push(new_node);
+        }
+    }
+
+    /// Update the minimum and maximum length of the keys that end in this subtree.
+    fn key_lengths(&self, prefix_len: usize, chunking: Chunking, min: &mut usize, max: &mut usize) {
+        let mut stack = vec![(self, prefix_len)];
+
+        while let Some((node, prefix_len)) = stack.pop() {
+            let len = prefix_len + (chunking.len)(&node.value);
+
+            if node.output.is_some() {
+                *min = cmp::min(*min, len);
+                *max = cmp::max(*max, len);
+            }
+
+            if node.wildcard.is_some() {
+                // Any input that starts with this key matches.
+                *min = cmp::min(*min, len);
+                *max = core::usize::MAX;
+            }
+
+            stack.extend(node.children.iter().map(|child| (child, len)));
+        }
+    }
+
+    /// Count the keys in this subtree and the depth of its deepest node.
+    fn stats(&self, depth: usize, stats: &mut Stats) {
+        let mut stack = vec![(self, depth)];
+
+        while let Some((node, depth)) = stack.pop() {
+            stats.keys += node.output.is_some() as usize + node.wildcard.is_some() as usize;
+            stats.max_depth = cmp::max(stats.max_depth, depth);
+
+            stack.extend(node.children.iter().map(|child| (child, depth + 1)));
+        }
+    }
+
+    /// Add this node and its children to the graph.
+    ///
+    /// `wildcard` is the value index of the wildcard above this node that takes
+    /// precedence, along with the index of the node that outputs it. Returns the index
+    /// of the node in the graph.
+    ///
+    /// Children are added before their parent, in order. This is done with a work list
+    /// rather than recursion, so that deep tries don't overflow the stack.
+    #[allow(clippy::too_many_arguments)]
+    fn build<'a, 'inst, K: 'inst, O>(
+        &'a self,
+        nodes: &mut impl NodeSink<'inst, K, O>,
+        chunking: Chunking,
+        priorities: &[i32],
+        subtrees: &mut Option<Subtrees<'a>>,
+        wildcard: Option<(usize, usize)>,
+        key: &mut impl FnMut(&'a [u8]) -> K,
+        output: &mut impl FnMut(Option<usize>) -> O,
+    ) -> usize {
+        /// A step in building a subtree.
+        enum Step<'a> {
+            /// Add the wildcard node, if any, and then visit the children.
+            Enter(&'a Node, Option<(usize, usize)>),
+
+            /// Add the node itself, once its children have been added starting at the
+            /// given offset into the built indices.
+            Exit(&'a Node, Option<(usize, usize)>, usize),
+        }
+
+        let priority = |index: usize| priorities.get(index).cloned().unwrap_or(0);
+
+        // A wildcard with a lower priority than the one above it is never used.
+        let shadowed = |index: usize, wildcard: Option<(usize, usize)>| {
+            wildcard.map_or(false, |(above, _)| priority(above) > priority(index))
+        };
+
+        // The indices of the nodes that have been built, but not yet added to a parent.
+        let mut built = Vec::new();
+        let mut steps = vec![Step::Enter(self, wildcard)];
+
+        while let Some(step) = steps.pop() {
+            let (node, wildcard, start) = match step {
+                Step::Enter(node, mut wildcard) => {
+                    if let Some(index) = node.wildcard.filter(|&index| !shadowed(index, wildcard)) {
+                        // Add a node that matches the rest of any input.
+                        nodes.push(super::Node {
+                            inputs: crate::MaybeSlice::Vec(Vec::new()),
+                            output: output(Some(index)),
+                            default: 0,
+                            amount: core::usize::MAX,
+                        });
+                        wildcard = Some((index, nodes.len() - 1));
+                    }
+
+                    // Build each child, then come back to this node.
+                    steps.push(Step::Exit(node, wildcard, built.len()));
+                    steps.extend(
+                        node.children
+                            .iter()
+                            .rev()
+                            .map(|child| Step::Enter(child, wildcard)),
+                    );
+                    continue;
+                }
+                Step::Exit(node, wildcard, start) => (node, wildcard, start),
+            };
+
+            let child_indices = built.split_off(start);
+
+            // Inputs that end here or don't match a child fall back to the wildcard.
+            let value = match node.output {
+                _ if node.deny => None,
+                Some(index) if !shadowed(index, wildcard) => Some(index),
+                _ => wildcard.map(|(index, _)| index),
+            };
+            let default = wildcard.map_or(0, |(_, node)| node);
+
+            // If an identical subtree has already been built, use that instead.
+            let signature = subtrees.as_ref().map(|subtrees| {
+                let class = value.map(|index| subtrees.classes[subtrees.offset + index]);
+                let transitions = node
+                    .children
+                    .iter()
+                    .zip(&child_indices)
+                    .map(|(child, &index)| (&*child.value, index))
+                    .collect::<Vec<_>>();
+                (class, default, transitions)
+            });
+
+            if let (Some(subtrees), Some(signature)) = (subtrees.as_ref(), signature.as_ref()) {
+                if let Some(&index) = subtrees.nodes.get(signature) {
+                    built.push(index);
+                    continue;
+                }
+            }
+
+            let child_indices = node
+                .children
+                .iter()
+                .zip(child_indices)
+                .map(|(child, index)| (key(child.key()), index))
+                .collect::<Vec<_>>();
+
+            let amount = shortest_value(&node.children, chunking);
+
+            // Now, add our node.
+            let node_index = nodes.len();
+            nodes.push(super::Node {
+                inputs: crate::MaybeSlice::Vec(child_indices),
+                output: output(value),
+                default,
+                amount,
+            });
+
+            if let (Some(subtrees), Some(signature)) = (subtrees.as_mut(), signature) {
+                subtrees.nodes.insert(signature, node_index);
+            }
+
+            built.push(node_index);
+        }
+
+        built.pop().expect("built the root node")
+    }
+}
+
+/// Set an empty value index slot, or return the index that is already in it.
+fn fill(slot: &mut Option<usize>, index: usize) -> Result<(), usize> {
+    match *slot {
+        Some(existing) => Err(existing),
+        None => {
+            *slot = Some(index);
+            Ok(())
+        }
+    }
+}
+
+/// Insert a node with no children into a set of siblings, merging it with an existing
+/// node for the same key.
+///
+/// Returns the index of the existing value if both nodes have an output, or both have
+/// a wildcard.
+fn insert_node(mut siblings: &mut Vec<Node>, mut node: Node, utf8: bool) -> Result<(), usize> {
+    loop {
+        // Iterate through the potential siblings to find a shared prefix.
+        let closest_node = siblings.iter_mut().enumerate().find_map(|(i, sibling)| {
+            // See if we have a shared prefix.
+            let prefix = prefix(&node.value, &sibling.value, utf8);
+
+            // If we share a prefix, match on this node.
+            if !prefix.is_empty() {
+                Some((i, prefix))
+            } else {
+                None
+            }
+        });
+
+        let (index, prefix) = match closest_node {
+            Some(result) => result,
+            None => {
+                // No shared prefix, so we can just add the node as a direct sibling.
+                siblings.push(node);
+                return Ok(());
+            }
+        };
+
+        // If the prefix is entirely equal to the node's value, we move on to the
+        // node's children.
+        if prefix == siblings[index].value || prefix == node.value {
+            let prefix_len = prefix.len();
+
+            // If both the keys are equal, we have a duplicate.
+            if node.value == siblings[index].value {
+                // We may be able to just insert the value.
+                let sibling = &mut siblings[index];
+                sibling.deny = sibling.deny || node.deny;
+                return match (node.output, node.wildcard) {
+                    (Some(output), _) => fill(&mut sibling.output, output),
+                    (None, Some(wildcard)) => fill(&mut sibling.wildcard, wildcard),
+                    (None, None) => Ok(()),
+                };
+            }
+
+            // Swap the node and the sibling if necessary.
+            if prefix == node.value {
+                mem::swap(&mut node, &mut siblings[index]);
+            }
+
+            siblings = &mut siblings[index].children;
+            node.value = node.value[prefix_len..].to_vec();
+
+            continue;
+        }
+
+        // Remove the new sibling node from the sibling set.
+        let mut sibling = siblings.swap_remove(index);
+        log!(
+            trace,
+            "splitting {:?} at shared prefix {:?}",
+            String::from_utf8_lossy(&sibling.value),
+            String::from_utf8_lossy(prefix)
+        );
+
+        // In our node and the sibling, remove the prefix.
+        let prefix = prefix.to_vec();
+        node.value = node.value[prefix.len()..].to_vec();
+        sibling.value = sibling.value[prefix.len()..].to_vec();
+
+        // Create a new node with no result that contains the shared prefix.
+        let mut prefix_node = Node::new(prefix);
+        prefix_node.children = vec![sibling, node];
+
+        // Push the new node into the sibling set.
+        siblings.push(prefix_node);
+
+        return Ok(());
+    }
+}
+
+/// Find the node for a key in a set of siblings.
+fn find_node<'n>(mut siblings: &'n [Node], mut key: &[u8]) -> Option<&'n Node> {
+    loop {
+        let node = siblings
+            .iter()
+            .find(|sibling| key.starts_with(&sibling.value))?;
+
+        key = &key[node.value.len()..];
+        if key.is_empty() {
+            return Some(node);
+        }
+
+        siblings = &node.children;
+    }
+}
+
+/// Remove a canonicalized key from a set of siblings, returning its value index.
+///
+/// Nodes that no longer have an output or children are removed, and nodes that
+/// are left with only a single child are merged with it.
+fn remove_key(siblings: &mut Vec<Node>, mut key: &[u8]) -> Option<usize> {
+    // Move each set of siblings along the key's path out of its parent, so that the
+    // path can be cleaned up from the bottom without recursing.
+    let mut path = Vec::new();
+    let mut current = mem::replace(siblings, Vec::new());
+
+    let output = loop {
+        let index = match current
+            .iter()
+            .position(|sibling| key.starts_with(&sibling.value))
+        {
+            Some(index) => index,
+            None => break None,
+        };
+
+        key = &key[current[index].value.len()..];
+        let children = mem::replace(&mut current[index].children, Vec::new());
+        let output = if key.is_empty() {
+            current[index].output.take()
+        } else {
+            None
+        };
+
+        path.push((current, index));
+        current = children;
+
+        if key.is_empty() {
+            break output;
+        }
+    };
+
+    // Put the siblings back, cleaning up the nodes along the path if the key was removed.
+    while let Some((mut parent, index)) = path.pop() {
+        parent[index].children = current;
+
+        let node = &mut parent[index];
+        if output.is_some() && node.is_empty() {
+            match node.children.len() {
+                0 => {
+                    parent.swap_remove(index);
+                }
+                1 => {
+                    let mut child = node.children.pop().unwrap();
+                    node.value.extend_from_slice(&child.value);
+                    child.value = mem::replace(&mut node.value, Vec::new());
+                    *node = child;
+                }
+                _ => {}
+            }
+        }
+
+        current = parent;
+    }
+
+    *siblings = current;
+    output
+}
+
+/// Merge chains of nodes that each have exactly one child into a single node.
+///
+/// This only applies to a node without siblings, since otherwise it has to stay the
+/// same length as its siblings. Each merged node is limited to the longest chunk that
+/// the graph type allows.
+fn coalesce_children(children: &mut [Node], chunking: Chunking) {
+    let node = match children {
+        [node] => node,
+        _ => return,
+    };
+
+    while node.is_empty() && node.children.len() == 1 {
+        let len = (chunking.len)(&node.value) + (chunking.len)(&node.children[0].value);
+        if len > chunking.max_len {
+            break;
+        }
+
+        let mut child = node.children.pop().unwrap();
+        node.value.extend_from_slice(&child.value);
+        node.output = child.output;
+        node.wildcard = child.wildcard;
+        node.deny = child.deny;
+        node.children = mem::replace(&mut child.children, Vec::new());
+    }
+}
+
+fn shorten_children(children: &mut [Node], chunking: Chunking) {
+    // Determine what the length of the shortest value is.
+    let shortest = shortest_value(children, chunking);
+
+    // Shorten each value to the shortest length.
+    let shortest = cmp::min(shortest, chunking.max_len);
+    for child in children {
+        child.shorten(shortest, chunking);
+    }
+}
+
+/// The length of the shortest value among a set of siblings, which is the amount of
+/// input that their parent matches on.
+///
+/// Siblings are usually all the same length, but a sibling that was kept longer to
+/// avoid splitting a character still starts with the same amount of input.
+fn shortest_value(siblings: &[Node], chunking: Chunking) -> usize {
+    siblings
+        .iter()
+        .map(|sibling| (chunking.len)(&sibling.value))
+        .min()
+        .unwrap_or(1)
+}
+
+/// The type that a graph can have.
+pub trait GraphType<'a> {
+    /// The type of the input key.
+    type InputKey: super::Segmentable + 'a;
+
+    /// Validate the input.
+    fn validate(input: &mut str) -> bool;
+
+    /// Validate the input, possibly replacing it with a canonical form.
+    ///
+    /// By default, this calls [`GraphType::validate`].
+    fn canonicalize(input: &mut String) -> bool {
+        Self::validate(input)
+    }
+
+    /// Convert the input into a key.
+    fn key(input: &'a str) -> Self::InputKey;
+
+    /// Validate a binary input, possibly replacing it with a canonical form.
+    ///
+    /// By default, this only accepts valid UTF-8, which is passed to
+    /// [`GraphType::canonicalize`].
+    fn canonicalize_bytes(input: &mut Vec<u8>) -> bool {
+        let mut string = match String::from_utf8(mem::replace(input, Vec::new())) {
+            Ok(string) => string,
+            Err(err) => {
+                *input = err.into_bytes();
+                return false;
+            }
+        };
+
+        let valid = Self::canonicalize(&mut string);
+        *input = string.into_bytes();
+        valid
+    }
+
+    /// Convert a binary input into a key.
+    ///
+    /// By default, this calls [`GraphType::key`], and panics if the input is not
+    /// valid UTF-8.
+    fn key_bytes(input: &'a [u8]) -> Self::InputKey {
+        Self::key(core::str::from_utf8(input).expect("key is not valid UTF-8"))
+    }
+
+    /// The longest chunk of a key that a single transition can match, as measured
+    /// by [`GraphType::key_len`].
+    const MAX_CHUNK_LEN: usize = core::usize::MAX;
+
+    /// Get the length of a key, in the units that [`GraphType::InputKey`] is split by.
+    ///
+    /// By default, this is the number of bytes.
+    fn key_len(input: &[u8]) -> usize {
+        input.len()
+    }
+
+    /// Get the byte offset that is `len` units into a key.
+    ///
+    /// This must split keys the same way that [`GraphType::InputKey`] splits its
+    /// input. For instance, a `&str` is never split inside of a character, so an offset
+    /// that lands inside of one is moved forward to the end of it. By default, this is
+    /// `len`.
+    fn key_offset(_input: &[u8], len: usize) -> usize {
+        len
+    }
+
+    /// Whether keys are UTF-8, in which case nodes never end inside of a character.
+    ///
+    /// By default, this is `true`. Binary keys aren't made of characters, so graphs
+    /// with binary keys set this to `false`.
+    const UTF8: bool = true;
+}
+
+/// A graph that supports UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Utf8Graph;
+
+impl<'a> GraphType<'a> for Utf8Graph {
+    type InputKey = &'a str;
+
+    fn validate(_: &mut str) -> bool {
+        true
+    }
+
+    fn key(input: &'a str) -> Self::InputKey {
+        input
+    }
+
+    fn key_offset(input: &[u8], len: usize) -> usize {
+        // Strings are never split inside of a character, so round up to the next one.
+        len + input
+            .iter()
+            .skip(len)
+            .take_while(|&&b| b & 0xC0 == 0x80)
+            .count()
+    }
+}
+
+/// A graph that only supports ASCII.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct AsciiGraph;
+
+impl<'a> GraphType<'a> for AsciiGraph {
+    type InputKey = &'a [u8];
+
+    fn validate(input: &mut str) -> bool {
+        input.is_ascii()
+    }
+
+    fn key(input: &'a str) -> Self::InputKey {
+        input.as_bytes()
+    }
+
+    fn key_bytes(input: &'a [u8]) -> Self::InputKey {
+        input
+    }
+}
+
+/// A graph with binary keys, which don't need to be valid UTF-8.
+///
+/// Keys are added with [`Builder::add_bytes`], and the graph is queried with `&[u8]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct ByteGraph;
+
+impl<'a> GraphType<'a> for ByteGraph {
+    type InputKey = &'a [u8];
+
+    fn validate(_: &mut str) -> bool {
+        true
+    }
+
+    fn canonicalize_bytes(_: &mut Vec<u8>) -> bool {
+        true
+    }
+
+    fn key(input: &'a str) -> Self::InputKey {
+        input.as_bytes()
+    }
+
+    fn key_bytes(input: &'a [u8]) -> Self::InputKey {
+        input
+    }
+
+    const UTF8: bool = false;
+}
+
+/// A graph whose transitions each match a single character.
+///
+/// Keys are never split inside of a multi-byte character, and the graph is queried
+/// with [`Chars`](super::Chars). This makes more nodes than [`Utf8Graph`], but the
+/// generated code and visualizations are easier to read for non-English keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct CharGraph;
+
+impl<'a> GraphType<'a> for CharGraph {
+    type InputKey = super::Chars<'a>;
+
+    fn validate(_: &mut str) -> bool {
+        true
+    }
+
+    fn key(input: &'a str) -> Self::InputKey {
+        super::Chars(input)
+    }
+
+    const MAX_CHUNK_LEN: usize = 1;
+
+    fn key_len(input: &[u8]) -> usize {
+        // Count every byte that starts a character.
+        input.iter().filter(|&&b| b & 0xC0 != 0x80).count()
+    }
+
+    fn key_offset(input: &[u8], len: usize) -> usize {
+        input
+            .iter()
+            .enumerate()
+            .filter(|&(_, &b)| b & 0xC0 != 0x80)
+            .map(|(i, _)| i)
+            .nth(len)
+            .unwrap_or(input.len())
+    }
+}
+
+/// A graph that ignores case for another graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct IgnoreCase<Graph>(core::marker::PhantomData<Graph>);
+
+impl<'a, G: GraphType<'a>> GraphType<'a> for IgnoreCase<G>
+where
+    G::InputKey: AsRef<[u8]>,
+{
+    type InputKey = super::CaseInsensitive<G::InputKey>;
+
+    fn validate(input: &mut str) -> bool {
+        input.make_ascii_lowercase();
+        G::validate(input)
+    }
+
+    fn canonicalize(input: &mut String) -> bool {
+        input.make_ascii_lowercase();
+        G::canonicalize(input)
+    }
+
+    fn canonicalize_bytes(input: &mut Vec<u8>) -> bool {
+        input.make_ascii_lowercase();
+        G::canonicalize_bytes(input)
+    }
+
+    fn key(input: &'a str) -> Self::InputKey {
+        super::CaseInsensitive(G::key(input))
+    }
+
+    fn key_bytes(input: &'a [u8]) -> Self::InputKey {
+        super::CaseInsensitive(G::key_bytes(input))
+    }
+
+    const MAX_CHUNK_LEN: usize = G::MAX_CHUNK_LEN;
+
+    const UTF8: bool = G::UTF8;
+
+    fn key_len(input: &[u8]) -> usize {
+        G::key_len(input)
+    }
+
+    fn key_offset(input: &[u8], len: usize) -> usize {
+        G::key_offset(input, len)
+    }
+}
+
+/// A graph where every byte of the key is folded by `F` before it is compared.
+///
+/// This is queried with [`Folded`](super::Folded). Keys whose folded form is not
+/// valid UTF-8 are rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct FoldBytes<Graph, F>(core::marker::PhantomData<(Graph, F)>);
+
+impl<'a, G: GraphType<'a>, F: super::ByteFold + 'a> GraphType<'a> for FoldBytes<G, F>
+where
+    G::InputKey: AsRef<[u8]>,
+{
+    type InputKey = super::Folded<G::InputKey, F>;
+
+    fn validate(input: &mut str) -> bool {
+        // Bytes can't be replaced in place, so only accept keys that are already folded.
+        input.bytes().all(|b| F::fold(b) == b) && G::validate(input)
+    }
+
+    fn canonicalize(input: &mut String) -> bool {
+        let folded = input.bytes().map(F::fold).collect::<Vec<_>>();
+
+        *input = match String::from_utf8(folded) {
+            Ok(folded) => folded,
+            Err(_) => return false,
+        };
+
+        G::canonicalize(input)
+    }
+
+    fn canonicalize_bytes(input: &mut Vec<u8>) -> bool {
+        for b in input.iter_mut() {
+            *b = F::fold(*b);
+        }
+
+        G::canonicalize_bytes(input)
+    }
+
+    fn key(input: &'a str) -> Self::InputKey {
+        super::Folded::new(G::key(input))
+    }
+
+    fn key_bytes(input: &'a [u8]) -> Self::InputKey {
+        super::Folded::new(G::key_bytes(input))
+    }
+
+    const MAX_CHUNK_LEN: usize = G::MAX_CHUNK_LEN;
+
+    const UTF8: bool = G::UTF8;
+
+    fn key_len(input: &[u8]) -> usize {
+        G::key_len(input)
+    }
+
+    fn key_offset(input: &[u8], len: usize) -> usize {
+        G::key_offset(input, len)
+    }
+}
+
+/// A graph that ignores case and treats all of the separators in `S` as equal.
+///
+/// This is queried with [`SeparatorInsensitive`](super::SeparatorInsensitive).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct IgnoreSeparators<Graph, S = super::DefaultSeparators>(
+    core::marker::PhantomData<(Graph, S)>,
+);
+
+impl<'a, G: GraphType<'a>, S: super::Separators + 'a> GraphType<'a> for IgnoreSeparators<G, S>
+where
+    G::InputKey: AsRef<[u8]>,
+{
+    type InputKey = super::SeparatorInsensitive<G::InputKey, S>;
+
+    fn validate(input: &mut str) -> bool {
+        input.make_ascii_lowercase();
+
+        // Separators can't be replaced in place, so only accept the canonical one.
+        let others = S::SEPARATORS.get(1..).unwrap_or(&[]);
+        !input.bytes().any(|b| others.contains(&b)) && G::validate(input)
+    }
+
+    fn canonicalize(input: &mut String) -> bool {
+        // Replace every separator with the canonical one.
+        *input = input
+            .chars()
+            .map(|c| {
+                if c.is_ascii() && S::SEPARATORS.contains(&(c as u8)) {
+                    S::SEPARATORS[0] as char
+                } else {
+                    c.to_ascii_lowercase()
+                }
+            })
+            .collect();
+
+        G::canonicalize(input)
+    }
+
+    fn canonicalize_bytes(input: &mut Vec<u8>) -> bool {
+        for b in input.iter_mut() {
+            if b.is_ascii() && S::SEPARATORS.contains(b) {
+                *b = S::SEPARATORS[0];
+            } else {
+                b.make_ascii_lowercase();
+            }
+        }
+
+        G::canonicalize_bytes(input)
+    }
+
+    fn key(input: &'a str) -> Self::InputKey {
+        super::SeparatorInsensitive::new(G::key(input))
+    }
+
+    fn key_bytes(input: &'a [u8]) -> Self::InputKey {
+        super::SeparatorInsensitive::new(G::key_bytes(input))
+    }
+
+    const MAX_CHUNK_LEN: usize = G::MAX_CHUNK_LEN;
+
+    const UTF8: bool = G::UTF8;
+
+    fn key_len(input: &[u8]) -> usize {
+        G::key_len(input)
+    }
+
+    fn key_offset(input: &[u8], len: usize) -> usize {
+        G::key_offset(input, len)
+    }
+}
+
+/// A graph whose keys are normalized to Unicode NFC.
+///
+/// This makes keys that look the same, such as a precomposed `é` and an `e` followed by
+/// a combining accent, refer to the same entry. Queries should be normalized with
+/// [`nfc`](super::nfc) before they are processed.
+#[cfg(feature = "unicode-normalization")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Nfc<Graph>(core::marker::PhantomData<Graph>);
+
+#[cfg(feature = "unicode-normalization")]
+impl<'a, G: GraphType<'a>> GraphType<'a> for Nfc<G> {
+    type InputKey = G::InputKey;
+
+    fn validate(input: &mut str) -> bool {
+        // Keys can't be normalized in place, so only accept normalized keys.
+        unicode_normalization::is_nfc(input) && G::validate(input)
+    }
+
+    fn canonicalize(input: &mut String) -> bool {
+        use unicode_normalization::UnicodeNormalization;
+
+        *input = input.nfc().collect();
+        G::canonicalize(input)
+    }
+
+    fn key(input: &'a str) -> Self::InputKey {
+        G::key(input)
+    }
+
+    fn key_bytes(input: &'a [u8]) -> Self::InputKey {
+        G::key_bytes(input)
+    }
+
+    const MAX_CHUNK_LEN: usize = G::MAX_CHUNK_LEN;
+
+    const UTF8: bool = G::UTF8;
+
+    fn key_len(input: &[u8]) -> usize {
+        G::key_len(input)
+    }
+
+    fn key_offset(input: &[u8], len: usize) -> usize {
+        G::key_offset(input, len)
+    }
+}
+
+/// A graph whose keys are normalized to Unicode NFKC.
+///
+/// Like [`Nfc`], but compatibility characters such as `ﬁ` are also replaced by their
+/// plain equivalents. Queries should be normalized with [`nfkc`](super::nfkc) before
+/// they are processed.
+#[cfg(feature = "unicode-normalization")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Nfkc<Graph>(core::marker::PhantomData<Graph>);
+
+#[cfg(feature = "unicode-normalization")]
+impl<'a, G: GraphType<'a>> GraphType<'a> for Nfkc<G> {
+    type InputKey = G::InputKey;
+
+    fn validate(input: &mut str) -> bool {
+        // Keys can't be normalized in place, so only accept normalized keys.
+        unicode_normalization::is_nfkc(input) && G::validate(input)
+    }
+
+    fn canonicalize(input: &mut String) -> bool {
+        use unicode_normalization::UnicodeNormalization;
+
+        *input = input.nfkc().collect();
+        G::canonicalize(input)
+    }
+
+    fn key(input: &'a str) -> Self::InputKey {
+        G::key(input)
+    }
+
+    fn key_bytes(input: &'a [u8]) -> Self::InputKey {
+        G::key_bytes(input)
+    }
+
+    const MAX_CHUNK_LEN: usize = G::MAX_CHUNK_LEN;
+
+    const UTF8: bool = G::UTF8;
+
+    fn key_len(input: &[u8]) -> usize {
+        G::key_len(input)
+    }
+
+    fn key_offset(input: &[u8], len: usize) -> usize {
+        G::key_offset(input, len)
+    }
+}
+
+/// How a [`Builder`] splits keys into the chunks that each transition matches.
+///
+/// This trades the number of steps it takes to match a key against the number of
+/// transitions each node has. For keys with long shared prefixes, such as MIME types or
+/// paths, longer chunks skip over the prefixes in a single step. For short keys that
+/// differ early, such as words in a dictionary, matching one unit at a time keeps every
+/// node's transitions unique, with fewer nodes overall.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Splitting {
+    /// Match the longest chunk that a node's children can all be split to, and merge
+    /// chains of nodes with a single child.
+    ///
+    /// The length is still limited by [`Builder::set_max_chunk_len`].
+    LongestPrefix,
+
+    /// Match a single unit of the key, such as a byte or a character, in each step.
+    Units,
+}
+
+impl Default for Splitting {
+    fn default() -> Self {
+        Splitting::LongestPrefix
+    }
+}
 
-        if !Type::validate(&mut key) {
-            return Err(AddError::Invalid(key, value));
-        }
+/// What to do when a key is added to a [`Builder`] that already contains it.
+#[derive(Debug)]
+pub enum DuplicatePolicy<T> {
+    /// Return [`AddError::Conflict`].
+    Error,
 
-        // The node we are inserting.
-        let mut node = Node {
-            value: key,
-            output: Some(value),
-            children: Vec::new(),
-        };
+    /// Replace the old value with the new one.
+    Replace,
 
-        // The current set of siblings we're trying to insert a node into.
-        let mut siblings = &mut self.nodes;
+    /// Keep the old value and discard the new one.
+    KeepFirst,
 
-        loop {
-            // Iterate through the potential siblings to find a shared prefix.
-            let closest_node = siblings.iter_mut().enumerate().find_map(|(i, sibling)| {
-                // See if we have a shared prefix.
-                let prefix = prefix(&node.value, &sibling.value);
-
-                // If we share a prefix, match on this node.
-                if !prefix.is_empty() {
-                    Some((i, prefix))
-                } else {
-                    None
-                }
-            });
+    /// Merge the new value into the old one.
+    Merge(fn(&mut T, T)),
+}
 
-            let (index, prefix) = match closest_node {
-                Some(result) => result,
-                None => {
-                    // No shared prefix, so we can just add the node as a direct sibling.
-                    siblings.push(node);
-                    return Ok(());
-                }
-            };
+impl<T> Clone for DuplicatePolicy<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
 
-            // If the prefix is entirely equal to the node's value, we move on to the
-            // node's children.
-            if prefix == siblings[index].value || prefix == node.value {
-                let prefix_len = prefix.len();
-
-                // If both the keys are equal, we have a duplicate.
-                if node.value == siblings[index].value {
-                    // We may be able to just insert the value.
-                    if siblings[index].output.is_none() {
-                        siblings[index].output = node.output;
-                        return Ok(());
-                    }
+impl<T> Copy for DuplicatePolicy<T> {}
 
-                    // Otherwise, we have a duplicate.
-                    return Err(AddError::Duplicate(node.value, node.output.unwrap()));
-                }
+impl<T> Default for DuplicatePolicy<T> {
+    fn default() -> Self {
+        DuplicatePolicy::Error
+    }
+}
 
-                // Swap the node and the sibling if necessary.
-                if prefix == node.value {
-                    mem::swap(&mut node, &mut siblings[index]);
-                }
+/// The entry for a key in a [`Builder`], returned by [`Builder::entry`].
+#[derive(Debug)]
+pub enum KeyEntry<'b, T, Type> {
+    /// The key is already in the builder.
+    Occupied(OccupiedEntry<'b, T, Type>),
 
-                siblings = &mut siblings[index].children;
-                node.value = node.value[prefix_len..].to_string();
+    /// The key is not in the builder.
+    Vacant(VacantEntry<'b, T, Type>),
+}
 
-                continue;
-            }
+/// The entry for a key that is already in a [`Builder`].
+#[derive(Debug)]
+pub struct OccupiedEntry<'b, T, Type> {
+    /// The builder that the key is in.
+    builder: &'b mut Builder<T, Type>,
 
-            // Remove the new sibling node from the sibling set.
-            let mut sibling = siblings.swap_remove(index);
+    /// The index of the key's value.
+    index: usize,
+}
 
-            // In our node and the sibling, remove the prefix.
-            let prefix = prefix.to_string();
-            node.value = node.value[prefix.len()..].to_string();
-            sibling.value = sibling.value[prefix.len()..].to_string();
+/// The entry for a key that is not in a [`Builder`].
+#[derive(Debug)]
+pub struct VacantEntry<'b, T, Type> {
+    /// The builder to add the key to.
+    builder: &'b mut Builder<T, Type>,
 
-            // Create a new node with no result that contains the shared prefix.
-            let prefix_node = Node {
-                value: prefix,
-                output: None,
-                children: vec![sibling, node],
-            };
+    /// The canonicalized key, or the original key if it is empty or invalid.
+    key: Result<Vec<u8>, String>,
+}
 
-            // Push the new node into the sibling set.
-            siblings.push(prefix_node);
+impl<'a, 'b, T, Type: GraphType<'a>> KeyEntry<'b, T, Type> {
+    /// Insert `value` if the key is vacant, and return the key's value.
+    pub fn or_insert(self, value: T) -> Result<&'b mut T, AddError<T>> {
+        match self {
+            KeyEntry::Occupied(entry) => Ok(entry.into_mut()),
+            KeyEntry::Vacant(entry) => entry.insert(value),
+        }
+    }
 
-            return Ok(());
+    /// Insert the result of `value` if the key is vacant, and return the key's value.
+    pub fn or_insert_with(self, value: impl FnOnce() -> T) -> Result<&'b mut T, AddError<T>> {
+        match self {
+            KeyEntry::Occupied(entry) => Ok(entry.into_mut()),
+            KeyEntry::Vacant(entry) => entry.insert(value()),
         }
     }
 
-    /// Build the graph.
-    pub fn build<'nodes>(
-        &'a mut self,
-        node_buffer: &'nodes mut Vec<super::Node<'a, Type::InputKey, Option<T>>>,
-    ) -> super::Graph<'a, 'nodes, Type::InputKey, Option<T>>
-    where
-        T: Clone,
-    {
-        // Clear the node buffer.
-        node_buffer.clear();
+    /// Modify the key's value if the key is occupied.
+    pub fn and_modify(mut self, modify: impl FnOnce(&mut T)) -> Self {
+        if let KeyEntry::Occupied(entry) = &mut self {
+            modify(entry.get_mut());
+        }
 
-        // Sort our children.
-        shorten_children(&mut self.nodes);
-        self.nodes.sort_unstable_by(|a, b| a.value.cmp(&b.value));
+        self
+    }
+}
 
-        // Recursively sort node children.
-        for node in &mut self.nodes {
-            node.normalize();
-        }
+impl<'b, T, Type> OccupiedEntry<'b, T, Type> {
+    /// Get the key's value.
+    pub fn get(&self) -> &T {
+        &self.builder.value_entry(self.index).value
+    }
 
-        // Add a "default" node at position zero.
-        node_buffer.push(super::Node {
-            inputs: crate::MaybeSlice::Slice(&[]),
-            output: None,
-            default: 0,
-            amount: core::usize::MAX,
-        });
+    /// Get a mutable reference to the key's value.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.builder.value_entry_mut(self.index).value
+    }
 
-        // Build the graph.
-        let initial_indices = self
-            .nodes
-            .iter()
-            .map(|node| {
-                let index = node.build::<Type>(node_buffer);
-                let value = Type::key(&node.value);
-                (value, index)
-            })
-            .collect::<Vec<_>>();
+    /// Get a mutable reference to the key's value that lives as long as the builder.
+    pub fn into_mut(self) -> &'b mut T {
+        &mut self.builder.value_entry_mut(self.index).value
+    }
 
-        let amount = initial_indices.first().map_or(1, |(key, _)| key.len());
+    /// Replace the key's value, returning the old one.
+    pub fn insert(&mut self, value: T) -> T {
+        mem::replace(self.get_mut(), value)
+    }
+}
 
-        // Create a root node.
-        let root = super::Node {
-            inputs: crate::MaybeSlice::Vec(initial_indices),
-            output: None,
-            default: 0,
-            amount,
+impl<'a, 'b, T, Type: GraphType<'a>> VacantEntry<'b, T, Type> {
+    /// Add the key with the given value, and return a mutable reference to it.
+    ///
+    /// Fails if the key is empty or not valid for the graph type.
+    pub fn insert(self, value: T) -> Result<&'b mut T, AddError<T>> {
+        let key = match self.key {
+            Ok(key) => key,
+            Err(key) if key.is_empty() => return Err(AddError::Empty(value)),
+            Err(key) => return Err(AddError::Invalid(key, value)),
         };
-        node_buffer.push(root);
 
-        // The last node will be our starting node.
-        let end = node_buffer.len() - 1;
+        let builder = self.builder;
+        builder.insert_new(key, value, None);
 
-        super::Graph::new(&*node_buffer, end)
+        let index = builder.values.len() - 1;
+        Ok(&mut builder.value_entry_mut(index).value)
     }
 }
 
-/// A node in the graph.
-#[derive(Debug)]
-struct Node<T> {
-    /// The current value associated with this node.
-    value: String,
+/// How [`Builder`] cleans up keys, set with [`Builder::set_key_options`].
+///
+/// Surrounding whitespace is trimmed first, then the prefix and suffix are stripped,
+/// and then any whitespace they leave at the ends is trimmed as well. Finally, runs of
+/// whitespace inside the key are collapsed. By default, keys are left alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyOptions {
+    /// Whether whitespace around keys is removed.
+    trim: bool,
 
-    /// The output associated with this node, if any.
-    output: Option<T>,
+    /// Whether runs of whitespace inside keys are replaced with a single space.
+    collapse_whitespace: bool,
 
-    /// The next node to use for each possible input.
-    children: Vec<Node<T>>,
-}
+    /// A prefix that is removed from keys that start with it.
+    prefix: Option<&'static str>,
 
-impl<T: Clone> Node<T> {
-    /// Sort this node's children and ensure all of its strings are the same length.
-    fn normalize(&mut self) {
-        shorten_children(&mut self.children);
+    /// A suffix that is removed from keys that end with it.
+    suffix: Option<&'static str>,
+}
 
-        // Sort the children.
-        self.children.sort_by(|a, b| a.value.cmp(&b.value));
+impl Default for KeyOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        // Do the same for all children.
-        for child in &mut self.children {
-            child.normalize();
+impl KeyOptions {
+    /// Create the default options, which leave keys alone.
+    pub fn new() -> Self {
+        KeyOptions {
+            trim: false,
+            collapse_whitespace: false,
+            prefix: None,
+            suffix: None,
         }
     }
 
-    /// Try to shortern this node to be less than the given length.
-    #[allow(clippy::mem_replace_with_default)]
-    fn shorten(&mut self, len: usize) {
-        if self.value.len() > len {
-            // Get the chunk that we need to split off.
-            let new_value = self.value.split_off(len);
+    /// Set whether whitespace around keys is removed.
+    pub fn trim(mut self, trim: bool) -> Self {
+        self.trim = trim;
+        self
+    }
 
-            // Create a new node with our output and children.
-            // We use mem::replace here to support a lower MSRV.
-            let new_node = Node {
-                value: new_value,
-                output: self.output.take(),
-                children: mem::replace(&mut self.children, vec![]),
-            };
+    /// Set whether runs of whitespace inside keys, such as `"text /  plain"`, are
+    /// replaced with a single space.
+    pub fn collapse_whitespace(mut self, collapse: bool) -> Self {
+        self.collapse_whitespace = collapse;
+        self
+    }
 
-            // Add the new node as a child.
-            self.children.push(new_node);
-        }
+    /// Remove `prefix` from keys that start with it, such as `"mime:"`.
+    pub fn strip_prefix(mut self, prefix: &'static str) -> Self {
+        self.prefix = Some(prefix);
+        self
     }
 
-    /// Add this node and its children to the graph.
+    /// Remove `suffix` from keys that end with it.
+    pub fn strip_suffix(mut self, suffix: &'static str) -> Self {
+        self.suffix = Some(suffix);
+        self
+    }
+
+    /// Clean up a key or query.
     ///
-    /// Returns the index of the node in the graph.
-    fn build<'a, 'nodes, Type: GraphType<'a>>(
-        &'a self,
-        nodes: &'nodes mut Vec<super::Node<'a, Type::InputKey, Option<T>>>,
-    ) -> usize {
-        // Build each child.
-        let child_indices = self
-            .children
-            .iter()
-            .map(|child| {
-                let index = child.build::<Type>(nodes);
-                let value = Type::key(&child.value);
-                (value, index)
-            })
-            .collect::<Vec<_>>();
+    /// Use this on queries against graphs built with these options, so they match the
+    /// keys. The input is only copied if whitespace inside of it is collapsed.
+    pub fn apply<'k>(&self, key: &'k str) -> Cow<'k, str> {
+        let mut key = key;
+        if self.trim {
+            key = key.trim();
+        }
 
-        let amount = child_indices.first().map_or(1, |(key, _)| key.len());
+        if let Some(prefix) = self.prefix {
+            if key.starts_with(prefix) {
+                key = &key[prefix.len()..];
+            }
+        }
 
-        // Now, add our node.
-        let node_index = nodes.len();
-        nodes.push(super::Node {
-            inputs: crate::MaybeSlice::Vec(child_indices),
-            output: self.output.clone(),
-            default: 0,
-            amount,
-        });
+        if let Some(suffix) = self.suffix {
+            if key.ends_with(suffix) {
+                key = &key[..key.len() - suffix.len()];
+            }
+        }
 
-        node_index
-    }
-}
+        if self.trim {
+            key = key.trim();
+        }
 
-fn shorten_children<T: Clone>(children: &mut [Node<T>]) {
-    // Determine what the length of the shortest value is.
-    let shortest = children
-        .iter()
-        .map(|child| child.value.len())
-        .min()
-        .unwrap_or(0);
+        if !self.collapse_whitespace || !has_whitespace_run(key) {
+            return Cow::Borrowed(key);
+        }
 
-    // Shorten each value to the shortest length.
-    for child in children {
-        child.shorten(shortest);
+        let mut collapsed = String::with_capacity(key.len());
+        let mut in_run = false;
+        for c in key.chars() {
+            if !c.is_whitespace() {
+                collapsed.push(c);
+            } else if !in_run {
+                collapsed.push(' ');
+            }
+
+            in_run = c.is_whitespace();
+        }
+
+        Cow::Owned(collapsed)
     }
 }
 
-/// The type that a graph can have.
-pub trait GraphType<'a> {
-    /// The type of the input key.
-    type InputKey: super::Segmentable + 'a;
+/// Tell whether a key has whitespace that isn't a single space.
+fn has_whitespace_run(key: &str) -> bool {
+    let mut in_run = false;
+    key.chars().any(|c| {
+        let run = c.is_whitespace() && (in_run || c != ' ');
+        in_run = c.is_whitespace();
+        run
+    })
+}
 
-    /// Validate the input.
-    fn validate(input: &mut str) -> bool;
+/// Which lines [`Builder::add_lines`] skips.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct LineOptions {
+    /// Lines that start with this prefix are comments.
+    comment: Option<&'static str>,
 
-    /// Convert the input into a key.
-    fn key(input: &'a str) -> Self::InputKey;
+    /// Whether lines that are empty or only whitespace are skipped.
+    skip_blank: bool,
+
+    /// Whether lines that aren't ASCII are skipped.
+    ascii_only: bool,
 }
 
-/// A graph that supports UTF-8.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
-pub struct Utf8Graph;
+#[cfg(feature = "std")]
+impl Default for LineOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-impl<'a> GraphType<'a> for Utf8Graph {
-    type InputKey = &'a str;
+#[cfg(feature = "std")]
+impl LineOptions {
+    /// Create the default options, which only skip blank lines.
+    pub fn new() -> Self {
+        LineOptions {
+            comment: None,
+            skip_blank: true,
+            ascii_only: false,
+        }
+    }
 
-    fn validate(_: &mut str) -> bool {
-        true
+    /// Skip lines that start with `prefix`, such as `#`.
+    pub fn comment(mut self, prefix: &'static str) -> Self {
+        self.comment = Some(prefix);
+        self
     }
 
-    fn key(input: &'a str) -> Self::InputKey {
-        input
+    /// Set whether lines that are empty or only whitespace are skipped.
+    ///
+    /// If they aren't, empty lines are rejected with [`AddError::Empty`].
+    pub fn skip_blank(mut self, skip: bool) -> Self {
+        self.skip_blank = skip;
+        self
+    }
+
+    /// Set whether lines that aren't ASCII are skipped.
+    pub fn ascii_only(mut self, ascii_only: bool) -> Self {
+        self.ascii_only = ascii_only;
+        self
+    }
+
+    /// Whether to skip a line.
+    fn skip(&self, line: &str) -> bool {
+        (self.skip_blank && line.trim().is_empty())
+            || (self.ascii_only && !line.is_ascii())
+            || self
+                .comment
+                .map_or(false, |prefix| line.starts_with(prefix))
     }
 }
 
-/// A graph that only supports ASCII.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
-pub struct AsciiGraph;
+/// An error that occurs when adding lines with [`Builder::add_lines`].
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum LinesError<T> {
+    /// The lines couldn't be read.
+    Io(std::io::Error),
 
-impl<'a> GraphType<'a> for AsciiGraph {
-    type InputKey = &'a [u8];
+    /// A line couldn't be added.
+    Add {
+        /// The line number, starting from one.
+        line: usize,
 
-    fn validate(input: &mut str) -> bool {
-        input.is_ascii()
+        /// The reason it couldn't be added.
+        error: AddError<T>,
+    },
+}
+
+#[cfg(feature = "std")]
+impl<T: fmt::Display> fmt::Display for LinesError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinesError::Io(err) => write!(f, "Cannot read lines: {}", err),
+            LinesError::Add { line, error } => write!(f, "Line {}: {}", line, error),
+        }
     }
+}
 
-    fn key(input: &'a str) -> Self::InputKey {
-        input.as_bytes()
+#[cfg(feature = "std")]
+impl<T: fmt::Debug + fmt::Display> std::error::Error for LinesError<T> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LinesError::Io(err) => Some(err),
+            LinesError::Add { .. } => None,
+        }
     }
 }
 
-/// A graph that ignores case for another graph.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
-pub struct IgnoreCase<Graph>(core::marker::PhantomData<Graph>);
+/// A type with a fixed set of values that each have a name, such as a fieldless enum.
+///
+/// With the `strum` feature enabled, this is implemented for every enum that derives
+/// `strum::EnumIter` and `strum::IntoStaticStr`, so they can be passed straight to
+/// [`Builder::from_key_set`].
+pub trait KeySet: Sized {
+    /// Get every value, along with its name.
+    fn keys() -> Vec<(&'static str, Self)>;
+}
 
-impl<'a, G: GraphType<'a>> GraphType<'a> for IgnoreCase<G>
+#[cfg(feature = "strum")]
+impl<T> KeySet for T
 where
-    G::InputKey: AsRef<[u8]>,
+    T: strum::IntoEnumIterator + Clone + Into<&'static str>,
 {
-    type InputKey = super::CaseInsensitive<G::InputKey>;
-
-    fn validate(input: &mut str) -> bool {
-        input.make_ascii_lowercase();
-        G::validate(input)
-    }
-
-    fn key(input: &'a str) -> Self::InputKey {
-        super::CaseInsensitive(G::key(input))
+    fn keys() -> Vec<(&'static str, Self)> {
+        T::iter()
+            .map(|value| (value.clone().into(), value))
+            .collect()
     }
 }
 
@@ -348,8 +4021,80 @@ pub enum AddError<T> {
     /// The key is not valid.
     Invalid(String, T),
 
-    /// The key is already in the graph.
+    /// The key appears more than once in the keys being added.
     Duplicate(String, T),
+
+    /// The key is already in the graph, as part of an earlier entry.
+    Conflict {
+        /// The key that was being added.
+        key: String,
+
+        /// The value that was being added.
+        value: T,
+
+        /// The key that the earlier entry was added with.
+        ///
+        /// If the key is an alias, this is the key that its aliases were registered
+        /// with. Otherwise, it is the same as `key` after canonicalization.
+        existing: String,
+
+        /// The index of the earlier entry's value, counting every value added since the
+        /// builder was created, built or drained, from zero.
+        ///
+        /// Wildcards, fallbacks and values that have since been removed take up an index
+        /// too, and aliases share their value's index, so this is only the entry's
+        /// position among the keys that were added if each was added once with
+        /// [`Builder::add`].
+        index: usize,
+    },
+}
+
+impl<T> AddError<T> {
+    /// Get the key that couldn't be added, if it isn't empty.
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            AddError::Empty(_) => None,
+            AddError::Invalid(key, _) | AddError::Duplicate(key, _) => Some(key),
+            AddError::Conflict { key, .. } => Some(key),
+        }
+    }
+
+    /// Get the value that couldn't be added.
+    pub fn value(&self) -> &T {
+        match self {
+            AddError::Empty(value)
+            | AddError::Invalid(_, value)
+            | AddError::Duplicate(_, value)
+            | AddError::Conflict { value, .. } => value,
+        }
+    }
+
+    /// Get the key of the earlier entry that the key conflicts with, along with the
+    /// index of its value, if any.
+    pub fn existing(&self) -> Option<(&str, usize)> {
+        match self {
+            AddError::Conflict {
+                existing, index, ..
+            } => Some((existing, *index)),
+            _ => None,
+        }
+    }
+
+    /// Take back the value that couldn't be added.
+    pub fn into_value(self) -> T {
+        self.into_key_value().1
+    }
+
+    /// Take back the key and value that couldn't be added.
+    ///
+    /// The key is `None` if it was empty.
+    pub fn into_key_value(self) -> (Option<String>, T) {
+        match self {
+            AddError::Empty(value) => (None, value),
+            AddError::Invalid(key, value) | AddError::Duplicate(key, value) => (Some(key), value),
+            AddError::Conflict { key, value, .. } => (Some(key), value),
+        }
+    }
 }
 
 impl<T: fmt::Display> fmt::Display for AddError<T> {
@@ -366,6 +4111,16 @@ impl<T: fmt::Display> fmt::Display for AddError<T> {
                 "Cannot add a duplicate key to the graph: {} ({})",
                 key, value
             ),
+            AddError::Conflict {
+                key,
+                value,
+                existing,
+                ..
+            } => write!(
+                f,
+                "Cannot add a key that is already in the graph: {} ({}), added as {}",
+                key, value, existing
+            ),
         }
     }
 }
@@ -373,17 +4128,147 @@ impl<T: fmt::Display> fmt::Display for AddError<T> {
 #[cfg(feature = "std")]
 impl<T: fmt::Debug + fmt::Display> std::error::Error for AddError<T> {}
 
-/// Get the shared prefix for two strings.
-fn prefix<'a>(a: &'a str, b: &str) -> &'a str {
-    let mut i = 0;
-
-    for (a, b) in a.chars().zip(b.chars()) {
-        if a != b {
-            break;
+#[cfg(feature = "defmt")]
+impl<T: defmt::Format> defmt::Format for AddError<T> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        match self {
+            AddError::Empty(value) => {
+                defmt::write!(f, "Cannot add an empty key to the graph: {}", value)
+            }
+            AddError::Invalid(key, value) => defmt::write!(
+                f,
+                "Cannot add an invalid key to the graph: {=str} ({})",
+                key.as_str(),
+                value
+            ),
+            AddError::Duplicate(key, value) => defmt::write!(
+                f,
+                "Cannot add a duplicate key to the graph: {=str} ({})",
+                key.as_str(),
+                value
+            ),
+            AddError::Conflict {
+                key,
+                value,
+                existing,
+                ..
+            } => defmt::write!(
+                f,
+                "Cannot add a key that is already in the graph: {=str} ({}), added as {=str}",
+                key.as_str(),
+                value,
+                existing.as_str()
+            ),
         }
+    }
+}
 
-        i += a.len_utf8();
+/// Get the shared prefix for two keys.
+///
+/// If the keys are UTF-8, then unless the prefix covers all of one of the keys, it
+/// never ends in the middle of a character.
+fn prefix<'a>(a: &'a [u8], b: &[u8], utf8: bool) -> &'a [u8] {
+    let mut i = a.iter().zip(b).take_while(|(a, b)| a == b).count();
+
+    if utf8 && i < a.len() && i < b.len() {
+        // Back up to the start of the character.
+        while i > 0 && a[i] & 0xC0 == 0x80 {
+            i -= 1;
+        }
     }
 
     &a[..i]
 }
+
+/// The most keys that a pattern for [`Builder::add_pattern`] can expand into.
+const MAX_PATTERN_KEYS: usize = 1024;
+
+/// Expand a pattern for [`Builder::add_pattern`] into every key that it matches.
+///
+/// Returns `None` if the pattern is malformed, or if it expands into more than
+/// [`MAX_PATTERN_KEYS`] keys.
+fn expand_pattern(pattern: &str) -> Option<Vec<String>> {
+    let mut chars = pattern.chars().peekable();
+    let keys = expand_alternatives(&mut chars)?;
+
+    // Any leftover characters are unmatched parentheses.
+    if chars.next().is_some() {
+        return None;
+    }
+
+    Some(keys.into_iter().flatten().collect())
+}
+
+/// Expand alternatives separated by `|`, up to the end of the group.
+///
+/// Returns the keys for each alternative.
+fn expand_alternatives(chars: &mut Peekable<core::str::Chars<'_>>) -> Option<Vec<Vec<String>>> {
+    let mut alternatives = vec![expand_sequence(chars)?];
+    let mut len = alternatives[0].len();
+    while chars.peek() == Some(&'|') {
+        chars.next();
+        let alternative = expand_sequence(chars)?;
+        len += alternative.len();
+        if len > MAX_PATTERN_KEYS {
+            return None;
+        }
+
+        alternatives.push(alternative);
+    }
+
+    Some(alternatives)
+}
+
+/// Expand a sequence of characters and groups, up to the next `|` or `)`.
+fn expand_sequence(chars: &mut Peekable<core::str::Chars<'_>>) -> Option<Vec<String>> {
+    let mut keys = vec![String::new()];
+
+    loop {
+        let suffixes = match chars.peek() {
+            None | Some('|') | Some(')') => return Some(keys),
+            Some('(') => {
+                chars.next();
+                let mut alternatives = expand_alternatives(chars)?;
+                if chars.next() != Some(')') {
+                    return None;
+                }
+
+                // A group with one alternative is optional.
+                if alternatives.len() == 1 {
+                    alternatives.push(vec![String::new()]);
+                }
+
+                alternatives.into_iter().flatten().collect::<Vec<_>>()
+            }
+            Some('\\') => {
+                chars.next();
+                vec![chars.next()?.to_string()]
+            }
+            Some(&c) => {
+                chars.next();
+                vec![c.to_string()]
+            }
+        };
+
+        if keys.len().saturating_mul(suffixes.len()) > MAX_PATTERN_KEYS {
+            return None;
+        }
+
+        keys = keys
+            .iter()
+            .flat_map(|key| {
+                suffixes
+                    .iter()
+                    .map(move |suffix| format!("{}{}", key, suffix))
+            })
+            .collect();
+    }
+}
+
+/// Convert a key back into a string for an error message.
+fn key_string(key: Vec<u8>) -> String {
+    match String::from_utf8(key) {
+        Ok(key) => key,
+        Err(err) => String::from_utf8_lossy(err.as_bytes()).into_owned(),
+    }
+}
@@ -0,0 +1,178 @@
+//! Building graphs from keys that are stored in a bump arena.
+//!
+//! Every key is copied into the arena as it is added, so adding a key doesn't allocate
+//! anything of its own. Once every key is known, they are sorted and the graph is laid
+//! out from the sorted keys, one level of the trie at a time. The transitions of the
+//! graph borrow their keys straight from the arena.
+
+use super::{prefix, push_trap, AddError, Chunking, GraphType};
+use crate::{Graph, MaybeSlice, Node};
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use bumpalo::Bump;
+
+use core::cmp;
+use core::marker::PhantomData;
+
+/// A builder that stores its keys in a bump arena.
+///
+/// [`Builder`](super::Builder) keeps a trie with a separate allocation for the key and
+/// children of every node, which dominates the time it takes to add keys to a large
+/// dictionary. This builder only keeps a list of keys in the arena, and doesn't build
+/// the trie until [`ArenaBuilder::build`] is called.
+///
+/// Wildcards, aliases and the other features of [`Builder`](super::Builder) aren't
+/// supported, and adding a key twice is an error.
+#[derive(Debug)]
+pub struct ArenaBuilder<'bump, T, Type> {
+    /// The arena that keys are copied into.
+    arena: &'bump Bump,
+
+    /// The canonicalized keys, along with their values.
+    pairs: Vec<(&'bump [u8], T)>,
+
+    /// A buffer to canonicalize keys in before they are copied into the arena.
+    scratch: String,
+
+    /// The type of the graph.
+    ty: PhantomData<Type>,
+}
+
+impl<'bump, T, Type: GraphType<'bump>> ArenaBuilder<'bump, T, Type> {
+    /// Create a builder that copies its keys into `arena`.
+    pub fn new(arena: &'bump Bump) -> Self {
+        ArenaBuilder {
+            arena,
+            pairs: Vec::new(),
+            scratch: String::new(),
+            ty: PhantomData,
+        }
+    }
+
+    /// Add a key and its value.
+    ///
+    /// Keys are canonicalized by the graph type. Keys that were already added are only
+    /// found once the graph is built, so [`ArenaBuilder::build`] reports them.
+    pub fn add(&mut self, key: &str, value: T) -> Result<(), AddError<T>> {
+        if key.is_empty() {
+            return Err(AddError::Empty(value));
+        }
+
+        self.scratch.clear();
+        self.scratch.push_str(key);
+        if !Type::canonicalize(&mut self.scratch) {
+            return Err(AddError::Invalid(self.scratch.clone(), value));
+        }
+
+        let key = self.arena.alloc_slice_copy(self.scratch.as_bytes());
+        self.pairs.push((key, value));
+        Ok(())
+    }
+
+    /// Build the graph.
+    ///
+    /// Returns an error with one of the keys that was added more than once.
+    pub fn build<'nodes>(
+        mut self,
+        node_buffer: &'nodes mut Vec<Node<'bump, Type::InputKey, Option<T>>>,
+    ) -> Result<Graph<'bump, 'nodes, Type::InputKey, Option<T>>, AddError<T>> {
+        let chunking = Chunking::of::<Type>();
+        self.pairs.sort_by_key(|pair| pair.0);
+
+        if let Some(i) = (1..self.pairs.len()).find(|&i| self.pairs[i].0 == self.pairs[i - 1].0) {
+            let (key, value) = self.pairs.swap_remove(i);
+            return Err(AddError::Duplicate(super::key_string(key.to_vec()), value));
+        }
+
+        let (keys, values): (Vec<_>, Vec<_>) = self.pairs.into_iter().unzip();
+        let mut values = values.into_iter().map(Some).collect::<Vec<_>>();
+
+        // The trap node comes first, followed by the root node. Each node after that
+        // covers a range of the keys, which share the bytes up to an offset.
+        node_buffer.clear();
+        push_trap(node_buffer, &mut |_| None);
+        let placeholder = || Node {
+            inputs: MaybeSlice::Slice(&[]),
+            output: None,
+            default: 0,
+            amount: 0,
+        };
+        node_buffer.push(placeholder());
+        let mut ranges = vec![(0, keys.len(), 0)];
+        let mut runs = Vec::new();
+
+        let mut current = 0;
+        while current < ranges.len() {
+            let (mut start, end, offset) = ranges[current];
+
+            // The shortest key sorts first, and may end at this node.
+            let mut output = None;
+            if start < end && keys[start].len() == offset {
+                output = values[start].take();
+                start += 1;
+            }
+
+            // Each run of keys that start with the same unit gets its own child, which
+            // covers the prefix that all of them share.
+            runs.clear();
+            while start < end {
+                let first = &keys[start][offset..];
+                let mut run_end = start + 1;
+                while run_end < end
+                    && !prefix(first, &keys[run_end][offset..], chunking.utf8).is_empty()
+                {
+                    run_end += 1;
+                }
+
+                let shared = prefix(first, &keys[run_end - 1][offset..], chunking.utf8).len();
+                runs.push((start, run_end, shared));
+                start = run_end;
+            }
+
+            // Every child matches a chunk as long as the shortest one.
+            let shortest = runs
+                .iter()
+                .map(|&(start, _, shared)| (chunking.len)(&keys[start][offset..offset + shared]))
+                .min()
+                .map_or(1, |shortest| cmp::min(shortest, chunking.max_len));
+
+            let mut inputs = Vec::with_capacity(runs.len());
+            let mut amount = core::usize::MAX;
+            for &(start, run_end, shared) in &runs {
+                let key: &'bump [u8] = keys[start];
+                let len = (chunking.offset)(&key[offset..offset + shared], shortest);
+                let chunk = &key[offset..offset + len];
+                amount = cmp::min(amount, (chunking.len)(chunk));
+
+                inputs.push((Type::key_bytes(chunk), node_buffer.len()));
+                node_buffer.push(placeholder());
+                ranges.push((start, run_end, offset + len));
+            }
+
+            node_buffer[current + 1] = Node {
+                inputs: MaybeSlice::Vec(inputs),
+                output,
+                default: 0,
+                amount: if runs.is_empty() { 1 } else { amount },
+            };
+            current += 1;
+        }
+
+        let lengths = keys.iter().map(|key| (chunking.len)(key));
+        let min_key_len = lengths.clone().min().unwrap_or(0);
+        let max_key_len = lengths.max().unwrap_or(0);
+
+        log!(debug, "built graph with {} nodes", node_buffer.len());
+
+        let node_buffer: &'nodes Vec<_> = node_buffer;
+        Ok(Graph::with_key_lengths(
+            node_buffer,
+            1,
+            min_key_len,
+            max_key_len,
+        ))
+    }
+}
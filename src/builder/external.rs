@@ -0,0 +1,549 @@
+//! Building graphs that are too large to hold in memory.
+//!
+//! Keys are collected into sorted runs, which are spilled to temporary files once
+//! they grow past a memory limit. The runs are then merged, and since the merged keys
+//! come out in order, each subtree of the graph is finished as soon as the keys move
+//! past it. Finished nodes are written straight to the
+//! [binary serialization format](crate::serialize), so only the path to the current
+//! key is kept in memory.
+
+use super::{prefix, AddError, Chunking, GraphType};
+use crate::serialize;
+
+use alloc::boxed::Box;
+use alloc::collections::BinaryHeap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use core::cmp::{self, Reverse};
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+/// Tells apart the run files of builders in the same process.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// The memory used by a key/value pair, besides the bytes of the key and value.
+const PAIR_OVERHEAD: usize = 2 * mem::size_of::<Vec<u8>>();
+
+/// A key and its serialized output.
+type Pair = (Vec<u8>, Vec<u8>);
+
+/// A builder for graphs with too many keys to hold in memory.
+///
+/// Unlike [`Builder`](super::Builder), the values are bytes that are written into the
+/// graph as its serialized outputs, and the graph is written to a file rather than
+/// built in memory. It can be loaded with [`serialize::read`]. Wildcards, aliases and
+/// the other features of [`Builder`](super::Builder) aren't supported, and adding a key
+/// twice is an error.
+#[derive(Debug)]
+pub struct ExternalBuilder<Type> {
+    /// The directory to write runs to.
+    dir: PathBuf,
+
+    /// The number of bytes the current run can take up before it is spilled.
+    memory_limit: usize,
+
+    /// Tells apart this builder's run files from other builders'.
+    id: usize,
+
+    /// The keys and values that haven't been spilled yet.
+    pairs: Vec<Pair>,
+
+    /// The number of bytes that `pairs` takes up.
+    size: usize,
+
+    /// The files that runs have been spilled to.
+    runs: Vec<PathBuf>,
+
+    /// The output of nodes that no key ends at.
+    miss: Vec<u8>,
+
+    /// The type of the graph.
+    ty: PhantomData<Type>,
+}
+
+impl<'a, Type: GraphType<'a>> ExternalBuilder<Type> {
+    /// Create a builder that spills runs to files in `dir` once they take up more than
+    /// `memory_limit` bytes.
+    ///
+    /// `miss` is the serialized output of inputs that aren't in the graph.
+    pub fn new(dir: impl Into<PathBuf>, memory_limit: usize, miss: impl Into<Vec<u8>>) -> Self {
+        ExternalBuilder {
+            dir: dir.into(),
+            memory_limit,
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            pairs: Vec::new(),
+            size: 0,
+            runs: Vec::new(),
+            miss: miss.into(),
+            ty: PhantomData,
+        }
+    }
+
+    /// Add a key, along with the serialized output for it.
+    ///
+    /// Keys are canonicalized by the graph type. Keys that were already added are only
+    /// found once the graph is written, so [`ExternalBuilder::finish`] reports them.
+    pub fn add(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<Vec<u8>>,
+    ) -> Result<(), ExternalError> {
+        let mut key = key.into();
+        let value = value.into();
+        if key.is_empty() {
+            return Err(ExternalError::Add(AddError::Empty(value)));
+        }
+
+        if !Type::canonicalize(&mut key) {
+            return Err(ExternalError::Add(AddError::Invalid(key, value)));
+        }
+
+        self.size += key.len() + value.len() + PAIR_OVERHEAD;
+        self.pairs.push((key.into_bytes(), value));
+        if self.size > self.memory_limit {
+            self.spill()?;
+        }
+
+        Ok(())
+    }
+
+    /// Sort the current run and write it to a file.
+    fn spill(&mut self) -> io::Result<()> {
+        // Keep the order of equal keys, so duplicates are reported in order.
+        self.pairs.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let path = self.dir.join(format!(
+            "intern-str-{}-{}-{}.run",
+            std::process::id(),
+            self.id,
+            self.runs.len()
+        ));
+        log!(
+            debug,
+            "spilling {} keys to {}",
+            self.pairs.len(),
+            path.display()
+        );
+
+        let mut file = BufWriter::new(File::create(&path)?);
+        self.runs.push(path);
+        for (key, value) in self.pairs.drain(..) {
+            write_bytes(&mut file, &key)?;
+            write_bytes(&mut file, &value)?;
+        }
+
+        self.size = 0;
+        file.flush()
+    }
+
+    /// Merge the keys and write the graph to `out`, starting at its current position.
+    ///
+    /// Each node is written with a separate call to `out`, so it should be buffered.
+    /// Returns the number of nodes in the graph.
+    pub fn finish<W: Write + Seek>(mut self, out: &mut W) -> Result<usize, ExternalError> {
+        let pairs: Box<dyn Iterator<Item = io::Result<Pair>>> = if self.runs.is_empty() {
+            // Everything fits in memory, so there's nothing to merge.
+            self.pairs.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Box::new(
+                mem::replace(&mut self.pairs, Vec::new())
+                    .into_iter()
+                    .map(Ok),
+            )
+        } else {
+            if !self.pairs.is_empty() {
+                self.spill()?;
+            }
+
+            Box::new(Merge::new(&self.runs)?)
+        };
+
+        // Leave room for the header, which is written once the graph's shape is known.
+        let header = out.seek(SeekFrom::Current(0))?;
+        let mut writer = Writer {
+            out: &mut *out,
+            buffer: Vec::new(),
+            len: 0,
+        };
+        writer.write_header(0, 0, 0, 0)?;
+        writer.node(&self.miss, &[], core::usize::MAX)?;
+
+        let mut trie = Trie {
+            chunking: Chunking::of::<Type>(),
+            stack: vec![Open::default()],
+            last: Vec::new(),
+            miss: &self.miss,
+            min_key_len: core::usize::MAX,
+            max_key_len: 0,
+        };
+
+        for pair in pairs {
+            let (key, value) = pair?;
+            if !trie.last.is_empty() && key == trie.last {
+                return Err(ExternalError::Add(AddError::Duplicate(
+                    super::key_string(key),
+                    value,
+                )));
+            }
+
+            trie.push(&mut writer, key, value)?;
+        }
+
+        let start = trie.finish(&mut writer)?;
+        let (min_key_len, max_key_len) = if trie.min_key_len > trie.max_key_len {
+            // There are no keys, so nothing can match.
+            (0, 0)
+        } else {
+            (trie.min_key_len, trie.max_key_len)
+        };
+        let len = writer.len;
+
+        // Go back and fill in the header.
+        let end = out.seek(SeekFrom::Current(0))?;
+        out.seek(SeekFrom::Start(header))?;
+        let mut buffer = Vec::new();
+        serialize::write_header(&mut buffer, len, start, min_key_len, max_key_len);
+        out.write_all(&buffer)?;
+        out.seek(SeekFrom::Start(end))?;
+
+        log!(debug, "wrote graph with {} nodes", len);
+        Ok(len)
+    }
+}
+
+impl<Type> Drop for ExternalBuilder<Type> {
+    fn drop(&mut self) {
+        for run in &self.runs {
+            // The run may not have been created if spilling it failed.
+            let _ = fs::remove_file(run);
+        }
+    }
+}
+
+/// Write a length-prefixed byte string to a run.
+fn write_bytes(out: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    let len = bytes.len() as u64;
+    let mut prefix = [0; 8];
+    for (shift, byte) in prefix.iter_mut().enumerate() {
+        *byte = (len >> (shift * 8)) as u8;
+    }
+
+    out.write_all(&prefix)?;
+    out.write_all(bytes)
+}
+
+/// Read a length-prefixed byte string from a run, or `None` at the end of the run.
+fn read_bytes(input: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0; 8];
+    match input.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+
+    let len = len
+        .iter()
+        .rev()
+        .fold(0u64, |len, &byte| len << 8 | u64::from(byte));
+    let mut bytes = vec![0; len as usize];
+    input.read_exact(&mut bytes)?;
+    Ok(Some(bytes))
+}
+
+/// The next key of a run, along with the index of the run and the key's output.
+type Head = (Vec<u8>, usize, Vec<u8>);
+
+/// Merges sorted runs into a single sorted list of keys and values.
+struct Merge {
+    /// The runs being merged.
+    runs: Vec<BufReader<File>>,
+
+    /// The next key and value of each run that has any left, along with the index of
+    /// the run, so equal keys come out in the order they were added.
+    heads: BinaryHeap<Reverse<Head>>,
+}
+
+impl Merge {
+    /// Open each run and read its first key.
+    fn new(paths: &[PathBuf]) -> io::Result<Self> {
+        let mut merge = Merge {
+            runs: Vec::with_capacity(paths.len()),
+            heads: BinaryHeap::with_capacity(paths.len()),
+        };
+
+        for path in paths {
+            merge.runs.push(BufReader::new(File::open(path)?));
+            merge.advance(merge.runs.len() - 1)?;
+        }
+
+        Ok(merge)
+    }
+
+    /// Read the next key and value from a run.
+    fn advance(&mut self, run: usize) -> io::Result<()> {
+        let input = &mut self.runs[run];
+        if let Some(key) = read_bytes(input)? {
+            let value = read_bytes(input)?
+                .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "truncated run"))?;
+            self.heads.push(Reverse((key, run, value)));
+        }
+
+        Ok(())
+    }
+}
+
+impl Iterator for Merge {
+    type Item = io::Result<Pair>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((key, run, value)) = self.heads.pop()?;
+        Some(self.advance(run).map(|()| (key, value)))
+    }
+}
+
+/// Writes serialized nodes, keeping track of their indices.
+struct Writer<W: Write> {
+    /// Where the nodes are written.
+    out: W,
+
+    /// A buffer for each node's bytes.
+    buffer: Vec<u8>,
+
+    /// The number of nodes that have been written.
+    len: usize,
+}
+
+impl<W: Write> Writer<W> {
+    /// Write the header, which is always the same length.
+    fn write_header(
+        &mut self,
+        len: usize,
+        start: usize,
+        min_key_len: usize,
+        max_key_len: usize,
+    ) -> io::Result<()> {
+        self.buffer.clear();
+        serialize::write_header(&mut self.buffer, len, start, min_key_len, max_key_len);
+        self.out.write_all(&self.buffer)
+    }
+
+    /// Write a node with no default, returning its index.
+    fn node(
+        &mut self,
+        output: &[u8],
+        inputs: &[(&[u8], usize)],
+        amount: usize,
+    ) -> io::Result<usize> {
+        self.buffer.clear();
+        serialize::write_node(&mut self.buffer, output, 0, amount, inputs.iter().cloned());
+        self.out.write_all(&self.buffer)?;
+        self.len += 1;
+        Ok(self.len - 1)
+    }
+}
+
+/// A node of the trie that may still get more children.
+#[derive(Default)]
+struct Open {
+    /// The key of the transition that leads to this node.
+    label: Vec<u8>,
+
+    /// The output of any input that ends at this node.
+    output: Option<Vec<u8>>,
+
+    /// The finished children of this node, with the key of the transition to each.
+    children: Vec<(Vec<u8>, usize)>,
+}
+
+/// A trie that is built from sorted keys, and written out as its subtrees are finished.
+struct Trie<'m> {
+    /// How keys are split into chunks.
+    chunking: Chunking,
+
+    /// The path from the root to the last key that was added.
+    stack: Vec<Open>,
+
+    /// The last key that was added.
+    last: Vec<u8>,
+
+    /// The output of nodes that no key ends at.
+    miss: &'m [u8],
+
+    /// The length of the shortest key.
+    min_key_len: usize,
+
+    /// The length of the longest key.
+    max_key_len: usize,
+}
+
+impl Trie<'_> {
+    /// Add a key that comes after every key before it.
+    fn push<W: Write>(
+        &mut self,
+        writer: &mut Writer<W>,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    ) -> io::Result<()> {
+        let len = (self.chunking.len)(&key);
+        self.min_key_len = cmp::min(self.min_key_len, len);
+        self.max_key_len = cmp::max(self.max_key_len, len);
+
+        // Finish the nodes that are past the prefix this key shares with the last one.
+        let shared = prefix(&key, &self.last, self.chunking.utf8).len();
+        let mut depth = self
+            .stack
+            .iter()
+            .map(|node| node.label.len())
+            .sum::<usize>();
+        while depth > shared {
+            let node = self.stack.pop().unwrap();
+            let parent_depth = depth - node.label.len();
+
+            if parent_depth >= shared {
+                self.close(writer, node)?;
+            } else {
+                // The shared prefix ends partway through this node's key, so split it.
+                let split = shared - parent_depth;
+                let mut node = node;
+                let head = node.label[..split].to_vec();
+                node.label = node.label[split..].to_vec();
+
+                self.stack.push(Open {
+                    label: head,
+                    output: None,
+                    children: Vec::new(),
+                });
+                self.close(writer, node)?;
+            }
+
+            depth = parent_depth;
+        }
+
+        self.stack.push(Open {
+            label: key[shared..].to_vec(),
+            output: Some(value),
+            children: Vec::new(),
+        });
+        self.last = key;
+        Ok(())
+    }
+
+    /// Finish every node, returning the index of the start node.
+    fn finish<W: Write>(&mut self, writer: &mut Writer<W>) -> io::Result<usize> {
+        while self.stack.len() > 1 {
+            let node = self.stack.pop().unwrap();
+            self.close(writer, node)?;
+        }
+
+        let root = self.stack.pop().unwrap();
+        self.write(writer, root)
+    }
+
+    /// Write a finished node and add it to its parent's children.
+    fn close<W: Write>(&mut self, writer: &mut Writer<W>, mut node: Open) -> io::Result<()> {
+        let label = mem::replace(&mut node.label, Vec::new());
+        let index = self.write(writer, node)?;
+        self.stack
+            .last_mut()
+            .expect("closed the root node")
+            .children
+            .push((label, index));
+        Ok(())
+    }
+
+    /// Write a node whose children have all been written, returning its index.
+    ///
+    /// Like [`Builder::build`](super::Builder::build), every transition out of the node
+    /// matches a chunk of the same length, so longer keys are split up with nodes in
+    /// between.
+    fn write<W: Write>(&self, writer: &mut Writer<W>, node: Open) -> io::Result<usize> {
+        let chunking = self.chunking;
+        let shortest = node
+            .children
+            .iter()
+            .map(|(label, _)| (chunking.len)(label))
+            .min()
+            .map_or(1, |shortest| cmp::min(shortest, chunking.max_len));
+
+        let mut inputs = Vec::with_capacity(node.children.len());
+        for (label, index) in &node.children {
+            let split = (chunking.offset)(label, shortest);
+            let (head, tail) = label.split_at(split);
+            let index = self.write_chain(writer, tail, *index)?;
+            inputs.push((head, index));
+        }
+
+        let output = node.output.as_ref().map_or(self.miss, |output| output);
+        writer.node(output, &inputs, shortest)
+    }
+
+    /// Write the nodes that lead through the rest of a key to the node at `index`,
+    /// returning the index of the first one.
+    fn write_chain<W: Write>(
+        &self,
+        writer: &mut Writer<W>,
+        rest: &[u8],
+        index: usize,
+    ) -> io::Result<usize> {
+        let chunking = self.chunking;
+        let mut chunks = Vec::new();
+        let mut rest = rest;
+        while !rest.is_empty() {
+            let amount = cmp::min((chunking.len)(rest), chunking.max_len);
+            let (head, tail) = rest.split_at((chunking.offset)(rest, amount));
+            chunks.push((head, amount));
+            rest = tail;
+        }
+
+        // Each node needs the index of the one after it, so write them from the end.
+        let mut next = index;
+        for (head, amount) in chunks.into_iter().rev() {
+            next = writer.node(self.miss, &[(head, next)], amount)?;
+        }
+        Ok(next)
+    }
+}
+
+/// An error that occurs when building a graph with [`ExternalBuilder`].
+#[derive(Debug)]
+pub enum ExternalError {
+    /// A run or the graph couldn't be read or written.
+    Io(io::Error),
+
+    /// A key couldn't be added to the graph.
+    Add(AddError<Vec<u8>>),
+}
+
+impl From<io::Error> for ExternalError {
+    fn from(err: io::Error) -> Self {
+        ExternalError::Io(err)
+    }
+}
+
+impl fmt::Display for ExternalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExternalError::Io(err) => write!(f, "Cannot read or write the graph: {}", err),
+            ExternalError::Add(AddError::Empty(_)) => {
+                f.write_str("Cannot add an empty key to the graph")
+            }
+            ExternalError::Add(AddError::Invalid(key, _)) => {
+                write!(f, "Cannot add an invalid key to the graph: {}", key)
+            }
+            ExternalError::Add(AddError::Duplicate(key, _)) => {
+                write!(f, "Cannot add a duplicate key to the graph: {}", key)
+            }
+            ExternalError::Add(_) => f.write_str("Cannot add a key to the graph"),
+        }
+    }
+}
+
+impl std::error::Error for ExternalError {}
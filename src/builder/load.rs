@@ -0,0 +1,249 @@
+//! Loading keys and values from CSV and JSON files.
+//!
+//! CSV files are loaded with the `load-csv` feature, and JSON files are loaded with the
+//! `load-json` feature. In both cases, a conversion function turns the value in the file
+//! into the builder's value type.
+
+use super::{AddError, Builder, GraphType};
+
+use alloc::string::{String, ToString};
+use core::fmt;
+use std::io;
+
+/// A column of a CSV file.
+#[cfg(feature = "load-csv")]
+#[derive(Debug, Clone, Copy)]
+pub enum Column<'a> {
+    /// The column at this index, starting from zero.
+    Index(usize),
+
+    /// The column with this name in the header row.
+    Name(&'a str),
+}
+
+#[cfg(feature = "load-csv")]
+impl Column<'_> {
+    /// Find the index of this column.
+    fn index(self, headers: &csv::StringRecord) -> Option<usize> {
+        match self {
+            Column::Index(index) => Some(index),
+            Column::Name(name) => headers.iter().position(|header| header == name),
+        }
+    }
+
+    /// Get the name of this column for an error message.
+    fn name(self) -> String {
+        match self {
+            Column::Index(index) => index.to_string(),
+            Column::Name(name) => name.to_string(),
+        }
+    }
+}
+
+impl<'a, T, Type: GraphType<'a>> Builder<T, Type> {
+    /// Add the rows of a CSV file, taking each key and value from the given columns.
+    ///
+    /// The first row of the file is a header row, which is used to find columns by name.
+    /// `convert` turns the text in the value column into a value. If a key already
+    /// exists, the builder's [`DuplicatePolicy`](super::DuplicatePolicy) decides what
+    /// happens to the value.
+    ///
+    /// Returns the number of rows that were added, or stops at the first error.
+    #[cfg(feature = "load-csv")]
+    pub fn add_csv<R: io::Read, E>(
+        &mut self,
+        reader: R,
+        key: Column<'_>,
+        value: Column<'_>,
+        mut convert: impl FnMut(&str) -> Result<T, E>,
+    ) -> Result<usize, LoadError<T, E>> {
+        let mut reader = csv::Reader::from_reader(reader);
+        let headers = reader.headers().map_err(LoadError::Csv)?.clone();
+        let missing = |column: Column<'_>| LoadError::MissingField {
+            record: 0,
+            field: column.name(),
+        };
+        let key_index = key.index(&headers).ok_or_else(|| missing(key))?;
+        let value_index = value.index(&headers).ok_or_else(|| missing(value))?;
+
+        let mut added = 0;
+        for (i, row) in reader.records().enumerate() {
+            // Count the header row, so record numbers match the lines of the file.
+            let record = i + 2;
+            let row = row.map_err(LoadError::Csv)?;
+
+            let field = |index: usize, column: Column<'_>| {
+                row.get(index).ok_or_else(|| LoadError::MissingField {
+                    record,
+                    field: column.name(),
+                })
+            };
+            let key = field(key_index, key)?;
+            let value = field(value_index, value)?;
+
+            let value = convert(value).map_err(|error| LoadError::Convert { record, error })?;
+            self.add(key, value)
+                .map_err(|error| LoadError::Add { record, error })?;
+            added += 1;
+        }
+
+        Ok(added)
+    }
+
+    /// Add the entries of a JSON object, such as `{"png": "image/png"}`.
+    ///
+    /// `convert` turns each value in the object into a value. If a key already exists,
+    /// the builder's [`DuplicatePolicy`](super::DuplicatePolicy) decides what happens to
+    /// the value.
+    ///
+    /// Returns the number of entries that were added, or stops at the first error. The
+    /// entries are numbered in the order of their keys, rather than the order they
+    /// appear in the file.
+    #[cfg(feature = "load-json")]
+    pub fn add_json_object<R: io::Read, E>(
+        &mut self,
+        reader: R,
+        mut convert: impl FnMut(&serde_json::Value) -> Result<T, E>,
+    ) -> Result<usize, LoadError<T, E>> {
+        let object = match serde_json::from_reader(reader).map_err(LoadError::Json)? {
+            serde_json::Value::Object(object) => object,
+            _ => return Err(LoadError::UnexpectedType { record: 0 }),
+        };
+
+        let mut added = 0;
+        for (i, (key, value)) in object.into_iter().enumerate() {
+            let record = i + 1;
+            let value = convert(&value).map_err(|error| LoadError::Convert { record, error })?;
+            self.add(key, value)
+                .map_err(|error| LoadError::Add { record, error })?;
+            added += 1;
+        }
+
+        Ok(added)
+    }
+
+    /// Add the records of a JSON array of objects, taking each key and value from the
+    /// given fields.
+    ///
+    /// For instance, `[{"ext": "png", "mime": "image/png"}]` can be loaded with the
+    /// `"ext"` field as the key and the `"mime"` field as the value. The key field must
+    /// be a string. `convert` turns the value field into a value.
+    ///
+    /// Returns the number of records that were added, or stops at the first error.
+    #[cfg(feature = "load-json")]
+    pub fn add_json_records<R: io::Read, E>(
+        &mut self,
+        reader: R,
+        key: &str,
+        value: &str,
+        mut convert: impl FnMut(&serde_json::Value) -> Result<T, E>,
+    ) -> Result<usize, LoadError<T, E>> {
+        let records = match serde_json::from_reader(reader).map_err(LoadError::Json)? {
+            serde_json::Value::Array(records) => records,
+            _ => return Err(LoadError::UnexpectedType { record: 0 }),
+        };
+
+        let mut added = 0;
+        for (i, row) in records.iter().enumerate() {
+            let record = i + 1;
+            let row = row
+                .as_object()
+                .ok_or(LoadError::UnexpectedType { record })?;
+            let missing = |field: &str| LoadError::MissingField {
+                record,
+                field: field.to_string(),
+            };
+
+            let key = row
+                .get(key)
+                .and_then(|key| key.as_str())
+                .ok_or_else(|| missing(key))?;
+            let value = row.get(value).ok_or_else(|| missing(value))?;
+
+            let value = convert(value).map_err(|error| LoadError::Convert { record, error })?;
+            self.add(key, value)
+                .map_err(|error| LoadError::Add { record, error })?;
+            added += 1;
+        }
+
+        Ok(added)
+    }
+}
+
+/// An error that occurs when loading keys and values from a file.
+///
+/// Records are numbered from one. For CSV files, the header row is record one, so the
+/// numbers match the lines of the file (unless a field spans several lines). Errors
+/// about the file as a whole use record zero.
+#[derive(Debug)]
+pub enum LoadError<T, E> {
+    /// The CSV file couldn't be read.
+    #[cfg(feature = "load-csv")]
+    Csv(csv::Error),
+
+    /// The JSON file couldn't be read.
+    #[cfg(feature = "load-json")]
+    Json(serde_json::Error),
+
+    /// A JSON record, or the whole JSON file, is not the expected type.
+    UnexpectedType {
+        /// The record that is not an object, or zero for the whole file.
+        record: usize,
+    },
+
+    /// A record is missing the key or value field, or the column isn't in the header.
+    MissingField {
+        /// The record that is missing the field.
+        record: usize,
+
+        /// The name of the field, or the index of the column.
+        field: String,
+    },
+
+    /// A value couldn't be converted.
+    Convert {
+        /// The record that holds the value.
+        record: usize,
+
+        /// The error from the conversion function.
+        error: E,
+    },
+
+    /// A key couldn't be added to the builder.
+    Add {
+        /// The record that holds the key.
+        record: usize,
+
+        /// The reason it couldn't be added.
+        error: AddError<T>,
+    },
+}
+
+impl<T: fmt::Display, E: fmt::Display> fmt::Display for LoadError<T, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "load-csv")]
+            LoadError::Csv(err) => write!(f, "Cannot read CSV: {}", err),
+            #[cfg(feature = "load-json")]
+            LoadError::Json(err) => write!(f, "Cannot read JSON: {}", err),
+            LoadError::UnexpectedType { record: 0 } => {
+                write!(f, "JSON file does not have the expected shape")
+            }
+            LoadError::UnexpectedType { record } => {
+                write!(f, "Record {}: JSON record is not an object", record)
+            }
+            LoadError::MissingField { record, field } => {
+                write!(f, "Record {}: missing field `{}`", record, field)
+            }
+            LoadError::Convert { record, error } => {
+                write!(f, "Record {}: cannot convert value: {}", record, error)
+            }
+            LoadError::Add { record, error } => write!(f, "Record {}: {}", record, error),
+        }
+    }
+}
+
+impl<T: fmt::Debug + fmt::Display, E: fmt::Debug + fmt::Display> std::error::Error
+    for LoadError<T, E>
+{
+}
@@ -0,0 +1,214 @@
+//! A radix trie that can be frozen into a graph.
+
+use super::{find_node, insert_node, key_string, remove_key, AddError, Builder, GraphType};
+use super::{IntoIter, Node, Walk};
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use core::mem;
+
+/// A map from strings to values, stored as a radix trie.
+///
+/// This is the same trie that [`Builder`] keeps its keys in, without any of the
+/// builder's options. Keys are stored as they are, without being canonicalized, and
+/// inserting a key that already exists replaces its value. This makes it useful for
+/// preparing a set of keys and values before freezing them into a graph with
+/// [`Trie::into_builder`].
+#[derive(Debug, Clone)]
+pub struct Trie<T> {
+    /// The nodes in the trie.
+    nodes: Vec<Node>,
+
+    /// The values that the nodes refer to.
+    ///
+    /// Values that have been removed are left as `None`, so the indices of the
+    /// other values don't change.
+    values: Vec<Option<T>>,
+
+    /// The value of the empty key, which doesn't have a node.
+    empty: Option<T>,
+
+    /// The number of keys in the trie.
+    len: usize,
+}
+
+impl<T> Default for Trie<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Trie<T> {
+    /// Create an empty trie.
+    pub fn new() -> Self {
+        Trie {
+            nodes: Vec::new(),
+            values: Vec::new(),
+            empty: None,
+            len: 0,
+        }
+    }
+
+    /// The number of keys in the trie.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Tell whether the trie has no keys.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Insert a key and its value, returning the old value if the key already existed.
+    pub fn insert(&mut self, key: impl Into<String>, value: T) -> Option<T> {
+        let key = key.into().into_bytes();
+        if key.is_empty() {
+            let old = self.empty.replace(value);
+            if old.is_none() {
+                self.len += 1;
+            }
+            return old;
+        }
+
+        let mut node = Node::new(key);
+        node.output = Some(self.values.len());
+        // The keys are strings, so they're always UTF-8.
+        match insert_node(&mut self.nodes, node, true) {
+            Ok(()) => {
+                self.values.push(Some(value));
+                self.len += 1;
+                None
+            }
+            Err(index) => self.values[index]
+                .as_mut()
+                .map(|old| mem::replace(old, value)),
+        }
+    }
+
+    /// Tell whether the trie has a value for a key.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Get the value for a key.
+    pub fn get(&self, key: &str) -> Option<&T> {
+        if key.is_empty() {
+            return self.empty.as_ref();
+        }
+
+        let index = find_node(&self.nodes, key.as_bytes())?.output?;
+        self.values[index].as_ref()
+    }
+
+    /// Get the value for a key mutably.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut T> {
+        if key.is_empty() {
+            return self.empty.as_mut();
+        }
+
+        let index = find_node(&self.nodes, key.as_bytes())?.output?;
+        self.values[index].as_mut()
+    }
+
+    /// Remove a key, returning its value if it was in the trie.
+    pub fn remove(&mut self, key: &str) -> Option<T> {
+        let value = if key.is_empty() {
+            self.empty.take()
+        } else {
+            let index = remove_key(&mut self.nodes, key.as_bytes())?;
+            self.values[index].take()
+        };
+
+        if value.is_some() {
+            self.len -= 1;
+        }
+        value
+    }
+
+    /// Iterate over the keys in the trie and their values, in sorted order.
+    pub fn iter(&self) -> TrieIter<'_, T> {
+        TrieIter {
+            empty: self.empty.as_ref(),
+            walk: Walk::new(&self.nodes),
+            values: &self.values,
+        }
+    }
+
+    /// Move the keys and values into a [`Builder`], which can build them into a graph.
+    ///
+    /// The keys are canonicalized by the graph type. Returns an error if the trie has
+    /// an empty key, or if two keys are the same once they are canonicalized.
+    pub fn into_builder<'a, Type: GraphType<'a>>(self) -> Result<Builder<T, Type>, AddError<T>> {
+        Builder::from_sorted_iter(self)
+    }
+}
+
+impl<T> IntoIterator for Trie<T> {
+    type Item = (String, T);
+    type IntoIter = IntoIter<T>;
+
+    /// Move the keys and values out of the trie, in sorted order.
+    fn into_iter(mut self) -> Self::IntoIter {
+        let mut pairs = Vec::with_capacity(self.len);
+        if let Some(value) = self.empty.take() {
+            pairs.push((String::new(), value));
+        }
+
+        let mut walk = Walk::new(&self.nodes);
+        while let Some(index) = walk.next() {
+            if let Some(value) = self.values[index].take() {
+                pairs.push((key_string(walk.key.clone()), value));
+            }
+        }
+
+        IntoIter(pairs.into_iter())
+    }
+}
+
+impl<K: Into<String>, T> core::iter::FromIterator<(K, T)> for Trie<T> {
+    fn from_iter<I: IntoIterator<Item = (K, T)>>(iter: I) -> Self {
+        let mut trie = Trie::new();
+        trie.extend(iter);
+        trie
+    }
+}
+
+impl<K: Into<String>, T> Extend<(K, T)> for Trie<T> {
+    fn extend<I: IntoIterator<Item = (K, T)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+/// An iterator over the keys and values of a [`Trie`], created by [`Trie::iter`].
+#[derive(Debug)]
+pub struct TrieIter<'t, T> {
+    /// The value of the empty key, if it hasn't been visited yet.
+    empty: Option<&'t T>,
+
+    /// Visits the nodes of the trie.
+    walk: Walk<'t>,
+
+    /// The values that the nodes refer to.
+    values: &'t [Option<T>],
+}
+
+impl<'t, T> Iterator for TrieIter<'t, T> {
+    type Item = (String, &'t T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(value) = self.empty.take() {
+            return Some((String::new(), value));
+        }
+
+        while let Some(index) = self.walk.next() {
+            if let Some(value) = &self.values[index] {
+                return Some((key_string(self.walk.key.clone()), value));
+            }
+        }
+
+        None
+    }
+}
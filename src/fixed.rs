@@ -0,0 +1,181 @@
+//! Building small graphs at runtime without an allocator.
+
+use super::{Graph, MaybeSlice, Node, Segmentable};
+
+use core::{fmt, mem};
+
+/// A builder for small graphs that uses buffers provided by the caller.
+///
+/// This is meant for targets without an allocator, where a handful of keys is only
+/// known at runtime. Unlike the builder in the `builder` module, every
+/// transition matches a single unit of input (a byte of a `&[u8]`, or a character of
+/// a `&str`), and nodes are never shared, so the graph is larger. The buffers need
+/// about one node and one transition per unit of every key.
+///
+/// The contents of the buffers are overwritten, so they can be filled with any
+/// nodes to start with, such as `Node::new(&[], None, 0, 0)`.
+#[derive(Debug)]
+pub struct FixedBuilder<'nodes, 'inst, Input, Output> {
+    /// The buffer that nodes are written to.
+    nodes: &'nodes mut [Node<'inst, Input, Output>],
+
+    /// The buffer that the transitions of each node are written to.
+    transitions: &'inst mut [(Input, usize)],
+}
+
+impl<'nodes, 'inst, Input, Output> FixedBuilder<'nodes, 'inst, Input, Output> {
+    /// Create a new builder that writes to the given buffers.
+    pub fn new(
+        nodes: &'nodes mut [Node<'inst, Input, Output>],
+        transitions: &'inst mut [(Input, usize)],
+    ) -> Self {
+        Self { nodes, transitions }
+    }
+}
+
+impl<'nodes, 'inst, Input: Segmentable + Copy, Output: Clone>
+    FixedBuilder<'nodes, 'inst, Input, Output>
+{
+    /// Build a graph from a set of keys and their outputs.
+    ///
+    /// `entries` is sorted by key in place. Inputs that aren't keys produce `miss`.
+    pub fn build(
+        self,
+        entries: &mut [(Input, Output)],
+        miss: Output,
+    ) -> Result<Graph<'inst, 'nodes, Input, Output>, FixedError> {
+        let FixedBuilder {
+            nodes,
+            mut transitions,
+        } = self;
+        if nodes.len() < 2 {
+            return Err(FixedError::TooManyNodes);
+        }
+
+        entries.sort_unstable_by_key(|entry| entry.0);
+
+        // Add the trap node, and a root node that leads to every key. Until a node is
+        // laid out, its default and amount hold the range of entries under it.
+        let placeholder = |start: usize, end: usize| Node {
+            inputs: MaybeSlice::Slice(&[]),
+            output: miss.clone(),
+            default: start,
+            amount: end,
+        };
+        nodes[0] = placeholder(0, core::usize::MAX);
+        nodes[1] = placeholder(0, entries.len());
+        let mut len = 2;
+
+        // Nodes are laid out level by level, so the depth changes once every node that
+        // was added for the previous level has been laid out.
+        let mut depth = 0;
+        let mut level_end = len;
+        let mut current = 1;
+
+        while current < len {
+            if current == level_end {
+                depth += 1;
+                level_end = len;
+            }
+
+            let (mut start, end) = (nodes[current].default, nodes[current].amount);
+            let mut output = miss.clone();
+
+            // The shortest key sorts first, and may end at this node.
+            if start < end && chunk(entries[start].0, depth).is_none() {
+                if start + 1 < end && chunk(entries[start + 1].0, depth).is_none() {
+                    return Err(FixedError::DuplicateKey);
+                }
+
+                output = entries[start].1.clone();
+                start += 1;
+            }
+
+            // Each run of keys with the same next unit gets its own child.
+            let count = (start..end)
+                .filter(|&i| {
+                    i == start || chunk(entries[i].0, depth) != chunk(entries[i - 1].0, depth)
+                })
+                .count();
+            if count > transitions.len() {
+                return Err(FixedError::TooManyTransitions);
+            }
+            let (inputs, rest) = mem::replace(&mut transitions, &mut []).split_at_mut(count);
+            transitions = rest;
+
+            for input in inputs.iter_mut() {
+                let next = chunk(entries[start].0, depth);
+                let mut run_end = start + 1;
+                while run_end < end && chunk(entries[run_end].0, depth) == next {
+                    run_end += 1;
+                }
+
+                if len == nodes.len() {
+                    return Err(FixedError::TooManyNodes);
+                }
+                nodes[len] = placeholder(start, run_end);
+                *input = (next.unwrap(), len);
+
+                len += 1;
+                start = run_end;
+            }
+
+            nodes[current] = Node {
+                inputs: MaybeSlice::Slice(inputs),
+                output,
+                default: 0,
+                amount: 1,
+            };
+            current += 1;
+        }
+
+        let lengths = entries.iter().map(|(key, _)| key.len());
+        let min_key_len = lengths.clone().min().unwrap_or(0);
+        let max_key_len = lengths.max().unwrap_or(0);
+
+        let nodes: &'nodes [_] = nodes;
+        Ok(Graph::with_key_lengths(
+            &nodes[..len],
+            1,
+            min_key_len,
+            max_key_len,
+        ))
+    }
+}
+
+/// Get the unit of a key after the first `depth` units, if the key is long enough.
+fn chunk<Input: Segmentable + Copy>(mut key: Input, depth: usize) -> Option<Input> {
+    for _ in 0..depth {
+        key = key.split(1)?.1;
+    }
+
+    key.split(1).map(|(chunk, _)| chunk)
+}
+
+/// An error that occurs when building a graph with a [`FixedBuilder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixedError {
+    /// Two entries have the same key.
+    DuplicateKey,
+
+    /// The graph needs more nodes than the buffer holds.
+    TooManyNodes,
+
+    /// The graph needs more transitions than the buffer holds.
+    TooManyTransitions,
+}
+
+impl fmt::Display for FixedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FixedError::DuplicateKey => f.write_str("Two entries have the same key"),
+            FixedError::TooManyNodes => f.write_str("Graph does not fit in the node buffer"),
+            FixedError::TooManyTransitions => {
+                f.write_str("Graph does not fit in the transition buffer")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FixedError {}
@@ -0,0 +1,246 @@
+//! A graph stored in flat tables of integers and bytes.
+
+use super::ByteKey;
+
+#[cfg(feature = "alloc")]
+use super::Graph;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use core::marker::PhantomData;
+
+/// The number of entries at the start of the node table.
+const HEADER_LEN: usize = 3;
+
+/// The number of entries for each node.
+const NODE_LEN: usize = 5;
+
+/// The number of entries for each transition.
+const EDGE_LEN: usize = 3;
+
+/// The value used in place of `usize::MAX`.
+const NONE: u32 = core::u32::MAX;
+
+/// A graph stored in flat tables of integers and bytes, with outputs stored in a
+/// separate table.
+///
+/// A [`Graph`](super::Graph) is a table of [`Node`](super::Node)s, each with a slice of transitions
+/// that each hold a key. When it is written as code, every node and every key is a
+/// reference that needs its own relocation, which adds up to a lot of code and data
+/// for large graphs, especially on `wasm` targets. A `Flat` graph is four slices
+/// instead, so it costs the same no matter how many nodes there are.
+///
+/// ## Layout
+///
+/// All integers are `u32`s, where `u32::MAX` stands in for `usize::MAX`.
+///
+/// - The node table starts with the index of the start node and the minimum and
+///   maximum key lengths. Each node is then the index of its first transition, its
+///   number of transitions, the index of its output, the index of its default node
+///   and its amount.
+/// - Each transition is the offset and length of its key in the key table, followed
+///   by the index of the next node.
+/// - The key table holds the bytes of every key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Flat<'a, Input, Output> {
+    /// The table of nodes.
+    nodes: &'a [u32],
+
+    /// The table of transitions.
+    edges: &'a [u32],
+
+    /// The bytes of the keys.
+    keys: &'a [u8],
+
+    /// The table of outputs.
+    outputs: &'a [Output],
+
+    /// The type of the input.
+    input: PhantomData<Input>,
+}
+
+impl<'a, Input, Output> Flat<'a, Input, Output> {
+    /// Create a new flat graph from its tables.
+    pub const fn new(
+        nodes: &'a [u32],
+        edges: &'a [u32],
+        keys: &'a [u8],
+        outputs: &'a [Output],
+    ) -> Self {
+        Self {
+            nodes,
+            edges,
+            keys,
+            outputs,
+            input: PhantomData,
+        }
+    }
+
+    /// Get the table of nodes.
+    pub const fn nodes(&self) -> &'a [u32] {
+        self.nodes
+    }
+
+    /// Get the table of transitions.
+    pub const fn edges(&self) -> &'a [u32] {
+        self.edges
+    }
+
+    /// Get the bytes of the keys.
+    pub const fn keys(&self) -> &'a [u8] {
+        self.keys
+    }
+
+    /// Get the table of outputs.
+    pub const fn outputs(&self) -> &'a [Output] {
+        self.outputs
+    }
+
+    /// Get the length of the shortest key in the graph.
+    pub fn min_key_len(&self) -> usize {
+        from_u32(self.nodes[1])
+    }
+
+    /// Get the length of the longest key in the graph.
+    pub fn max_key_len(&self) -> usize {
+        from_u32(self.nodes[2])
+    }
+
+    /// Get the entry of the node at `index` that is `field` entries into it.
+    fn node(&self, index: usize, field: usize) -> usize {
+        from_u32(self.nodes[HEADER_LEN + index * NODE_LEN + field])
+    }
+
+    /// Get the output of the node at `index`.
+    fn output(&self, index: usize) -> &'a Output {
+        &self.outputs[self.node(index, 2)]
+    }
+}
+
+impl<'a, Input: ByteKey<'a>, Output> Flat<'a, Input, Output> {
+    /// Process the input and return the output.
+    ///
+    /// This gives the same output as [`Graph::process`](super::Graph::process) on the graph that the tables
+    /// were made from.
+    pub fn process(&self, mut input: Input) -> &'a Output {
+        let mut index = from_u32(self.nodes[0]);
+
+        // If the input can't possibly be a key, bail out early.
+        let len = input.len();
+        if len < self.min_key_len() || len > self.max_key_len() {
+            return self.output(self.node(index, 3));
+        }
+
+        loop {
+            // Get the next input chunk.
+            let ended = input.is_empty();
+            let (chunk, rest) = match input.split(self.node(index, 4)) {
+                Some(result) => result,
+                None if ended || self.node(index, 1) == 0 => return self.output(index),
+                None => {
+                    // The input ends partway through every transition.
+                    return self.output(self.node(index, 3));
+                }
+            };
+
+            index = self.next(index, &chunk);
+            input = rest;
+        }
+    }
+
+    /// Determine the index of the next node to go to based on the input.
+    fn next(&self, index: usize, input: &Input) -> usize {
+        let first = self.node(index, 0);
+        let mut low = 0;
+        let mut high = self.node(index, 1);
+
+        // Binary search over the transitions, since they are sorted.
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let edge = &self.edges[(first + mid) * EDGE_LEN..(first + mid + 1) * EDGE_LEN];
+
+            let key_offset = edge[0] as usize;
+            let key_len = edge[1] as usize;
+            let key = match Input::from_bytes(&self.keys[key_offset..key_offset + key_len]) {
+                Some(key) => key,
+                None => break,
+            };
+
+            match key.cmp(input) {
+                core::cmp::Ordering::Less => low = mid + 1,
+                core::cmp::Ordering::Greater => high = mid,
+                core::cmp::Ordering::Equal => return edge[2] as usize,
+            }
+        }
+
+        self.node(index, 3)
+    }
+
+    /// Write the tables for a graph into `nodes`, `edges` and `keys`.
+    ///
+    /// `output` gives the index of each node's output in the table of outputs, so
+    /// equal outputs can share a single entry.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the tables would have more than `u32::MAX` entries.
+    #[cfg(feature = "alloc")]
+    pub fn flatten(
+        graph: &Graph<'_, '_, Input, Output>,
+        nodes: &mut Vec<u32>,
+        edges: &mut Vec<u32>,
+        keys: &mut Vec<u8>,
+        mut output: impl FnMut(&Output) -> usize,
+    ) {
+        nodes.clear();
+        edges.clear();
+        keys.clear();
+
+        nodes.push(to_u32(graph.start()));
+        nodes.push(to_u32(graph.min_key_len()));
+        nodes.push(to_u32(graph.max_key_len()));
+
+        let mut first = 0;
+        for node in graph.nodes() {
+            nodes.push(to_u32(first));
+            nodes.push(to_u32(node.inputs().len()));
+            nodes.push(to_u32(output(node.output())));
+            nodes.push(to_u32(node.default()));
+            nodes.push(to_u32(node.amount()));
+
+            for (input, next) in node.inputs() {
+                let key = input.to_bytes();
+                edges.push(to_u32(keys.len()));
+                edges.push(to_u32(key.len()));
+                edges.push(to_u32(*next));
+                keys.extend_from_slice(key);
+            }
+
+            first += node.inputs().len();
+        }
+
+        assert!(
+            nodes.len() < NONE as usize && keys.len() < NONE as usize,
+            "flat graph would have more than u32::MAX entries"
+        );
+    }
+}
+
+/// Convert a `u32` from a table into a `usize`.
+fn from_u32(value: u32) -> usize {
+    if value == NONE {
+        core::usize::MAX
+    } else {
+        value as usize
+    }
+}
+
+/// Convert a `usize` into a `u32` for a table.
+#[cfg(feature = "alloc")]
+fn to_u32(value: usize) -> u32 {
+    if value == core::usize::MAX {
+        NONE
+    } else {
+        value as u32
+    }
+}
@@ -12,15 +12,43 @@
 //!
 //! [MIME Types]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Basics_of_HTTP/MIME_types
 //!
-//! `intern-str` is `no_std`, no `extern crate alloc`,
-//! `forbid(unsafe_code)`, and has no dependencies aside from the Rust
-//! `core` library.
+//! `intern-str` is `no_std` and doesn't use `unsafe` code outside of the `mmap`
+//! feature. With its default features, it doesn't use `alloc` and has no dependencies
+//! aside from the Rust `core` library.
+//!
+//! ## Features
+//!
+//! - `alloc` uses `extern crate alloc` for owned graphs and serialization.
+//! - `builder` enables the [`builder`] module. Implies `alloc`.
+//! - `std` uses the standard library for I/O, like reading keys from lines, loading
+//!   serialized graphs from files and the external builder.
+//! - `arena` adds a builder that allocates its nodes in an arena. Implies `builder`
+//!   and pulls in `bumpalo`.
+//! - `load-csv` and `load-json` load keys from CSV and JSON files. They imply
+//!   `builder` and `std`, and pull in `csv` and `serde_json`.
+//! - `mmap` loads serialized graphs by memory-mapping their files. Implies `alloc`
+//!   and `std`, and pulls in `memmap2`.
+//! - `rayon` sorts the nodes of large graphs in parallel while building them.
+//! - `unicode-normalization` adds graph types and helpers that normalize keys to NFC
+//!   or NFKC.
+//! - `strum` builds graphs from enums that implement `strum::IntoEnumIterator`.
+//! - `log` logs the path taken by [`Graph::process`] at the `trace` level.
+//! - `defmt` implements `defmt::Format` for the types in this crate.
 //!
 //! To generate `intern-str` graphs as code, see the [`builder`] module
 //! and the [`intern-str-codegen`] crate.
 //!
 //! [`intern-str-codegen`]: https://crates.io/crates/intern-str-codegen
 //!
+//! To declare a small graph inline, without a build script, see the `intern_map!` macro
+//! in the [`intern-str-macros`] crate.
+//!
+//! [`intern-str-macros`]: https://crates.io/crates/intern-str-macros
+//!
+//! To build a small graph at runtime without an allocator, see [`FixedBuilder`]. The
+//! [`builder`] module itself only needs `alloc`, so larger graphs can be built on
+//! `no_std` targets that have an allocator.
+//!
 //! ## Implementation
 //!
 //! `intern-str` generates a DFA consisting of all possible options for
@@ -43,8 +71,8 @@
 //! [`phf`]: https://crates.io/crates/phf
 
 #![no_std]
+#![deny(unsafe_code)]
 #![forbid(
-    unsafe_code,
     missing_docs,
     missing_debug_implementations,
     missing_copy_implementations,
@@ -52,21 +80,48 @@
     rust_2018_idioms
 )]
 
+/// Emit a `log` event, if the `log` feature is enabled.
+macro_rules! log {
+    ($level:ident, $($arg:tt)*) => {
+        #[cfg(feature = "log")]
+        log::$level!($($arg)*);
+    };
+}
+
 #[cfg(feature = "builder")]
 pub mod builder;
 
-#[cfg(all(feature = "builder", not(intern_str_no_alloc)))]
+#[cfg(feature = "alloc")]
+pub mod serialize;
+
+mod fixed;
+pub use fixed::{FixedBuilder, FixedError};
+
+mod map;
+pub use map::Map;
+
+mod packed;
+pub use packed::Packed;
+
+mod flat;
+pub use flat::Flat;
+
+#[cfg(all(feature = "alloc", feature = "unicode-normalization"))]
+mod normalize;
+#[cfg(all(feature = "alloc", feature = "unicode-normalization"))]
+pub use normalize::{nfc, nfkc};
+
+#[cfg(feature = "alloc")]
 extern crate alloc;
-#[cfg(all(feature = "builder", intern_str_no_alloc))]
-extern crate std as alloc;
 
 #[cfg(feature = "std")]
 extern crate std;
 
-#[cfg(feature = "builder")]
+#[cfg(feature = "alloc")]
 use alloc::vec::Vec;
 
-use core::{cmp, hash, ops};
+use core::marker::PhantomData;
+use core::{cmp, fmt, hash, ops};
 
 /// A node in a DFA.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -96,6 +151,12 @@ pub struct Graph<'inst, 'nodes, Input, Output> {
 
     /// The index of the start node.
     start: usize,
+
+    /// The length of the shortest key in the graph.
+    min_key_len: usize,
+
+    /// The length of the longest key in the graph.
+    max_key_len: usize,
 }
 
 impl<'inst, Input, Output> Node<'inst, Input, Output> {
@@ -113,6 +174,30 @@ impl<'inst, Input, Output> Node<'inst, Input, Output> {
             amount,
         }
     }
+
+    /// Create a new node from its parts, using an owned list of inputs.
+    ///
+    /// This allows graphs to be assembled at runtime. Unlike [`Node::new`], the
+    /// inputs do not need to be sorted ahead of time.
+    #[cfg(feature = "alloc")]
+    pub fn from_vec(
+        mut inputs: Vec<(Input, usize)>,
+        output: Output,
+        default: usize,
+        amount: usize,
+    ) -> Self
+    where
+        Input: Ord,
+    {
+        inputs.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Self {
+            inputs: MaybeSlice::Vec(inputs),
+            output,
+            default,
+            amount,
+        }
+    }
 }
 
 impl<'inst, Input: Segmentable, Output> Node<'inst, Input, Output> {
@@ -129,7 +214,7 @@ impl<'inst, Input: Segmentable, Output> Node<'inst, Input, Output> {
     pub fn inputs(&self) -> &[(Input, usize)] {
         match &self.inputs {
             MaybeSlice::Slice(s) => s,
-            #[cfg(feature = "builder")]
+            #[cfg(feature = "alloc")]
             MaybeSlice::Vec(v) => v,
         }
     }
@@ -150,10 +235,44 @@ impl<'inst, Input: Segmentable, Output> Node<'inst, Input, Output> {
     }
 }
 
+#[cfg(feature = "defmt")]
+impl<'inst, Input: defmt::Format, Output: defmt::Format> defmt::Format
+    for Node<'inst, Input, Output>
+{
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(
+            f,
+            "Node {{ inputs: {}, output: {}, default: {=usize}, amount: {=usize} }}",
+            &*self.inputs,
+            self.output,
+            self.default,
+            self.amount
+        )
+    }
+}
+
 impl<'nodes, 'inst, Input, Output> Graph<'inst, 'nodes, Input, Output> {
     /// Create a new graph from a set of nodes.
     pub const fn new(nodes: &'nodes [Node<'inst, Input, Output>], start: usize) -> Self {
-        Self { nodes, start }
+        Self::with_key_lengths(nodes, start, 0, core::usize::MAX)
+    }
+
+    /// Create a new graph from a set of nodes, with known bounds on the key lengths.
+    ///
+    /// Inputs that are shorter than `min_key_len` or longer than `max_key_len` are
+    /// rejected without walking the graph.
+    pub const fn with_key_lengths(
+        nodes: &'nodes [Node<'inst, Input, Output>],
+        start: usize,
+        min_key_len: usize,
+        max_key_len: usize,
+    ) -> Self {
+        Self {
+            nodes,
+            start,
+            min_key_len,
+            max_key_len,
+        }
     }
 }
 
@@ -168,31 +287,423 @@ impl<'nodes, 'inst, Input: Segmentable, Output> Graph<'inst, 'nodes, Input, Outp
         self.start
     }
 
+    /// Get the length of the shortest key in the graph.
+    pub fn min_key_len(&self) -> usize {
+        self.min_key_len
+    }
+
+    /// Get the length of the longest key in the graph.
+    pub fn max_key_len(&self) -> usize {
+        self.max_key_len
+    }
+
     /// Process the input and return the output.
     pub fn process(&self, mut input: Input) -> &Output {
-        let mut node = &self.nodes[self.start];
+        let mut index = self.start;
+        let mut node = &self.nodes[index];
+
+        // If the input can't possibly be a key, bail out early.
+        let len = input.len();
+        if len < self.min_key_len || len > self.max_key_len {
+            log!(
+                trace,
+                "input length {} is outside of the key lengths {}..={}",
+                len,
+                self.min_key_len,
+                self.max_key_len
+            );
+            return &self.nodes[node.default].output;
+        }
+
+        // Track the offset for logging without measuring the rest of the input.
+        #[cfg(feature = "log")]
+        let mut offset = 0;
 
         // Process the input in chunks.
         loop {
             // Get the next input chunk.
+            let ended = input.is_empty();
             let (chunk, rest) = match input.split(node.amount) {
                 Some(result) => result,
-                None => {
+                None if ended || node.inputs.is_empty() => {
                     // Return the value of the current node.
+                    log!(trace, "halted on node {}", index);
                     return &node.output;
                 }
+                None => {
+                    // The input ends partway through every transition.
+                    log!(trace, "input ends early on node {}", index);
+                    return &self.nodes[node.default].output;
+                }
             };
 
             // Get the next node.
-            node = &self.nodes[node.next(&chunk)];
+            let next = node.next(&chunk);
+
+            #[cfg(feature = "log")]
+            {
+                if next == node.default {
+                    log::trace!(
+                        "no transition from node {} at offset {}, falling back to node {}",
+                        index,
+                        offset,
+                        next
+                    );
+                } else {
+                    log::trace!("node {} -> node {} at offset {}", index, next, offset);
+                }
+                offset += chunk.len();
+            }
+
+            index = next;
+            node = &self.nodes[index];
             input = rest;
         }
     }
+
+    /// Iterate over the outputs of the accepting nodes in this graph.
+    ///
+    /// A node is considered accepting if its output differs from the output of its
+    /// default node. Outputs are yielded in node order, and may repeat if several
+    /// keys map to the same output.
+    pub fn outputs(&self) -> Outputs<'inst, 'nodes, Input, Output>
+    where
+        Output: PartialEq,
+    {
+        Outputs {
+            nodes: self.nodes,
+            iter: self.nodes.iter(),
+        }
+    }
+
+    /// Get the distinct outputs of the accepting nodes in this graph, in sorted order.
+    #[cfg(feature = "alloc")]
+    pub fn distinct_outputs(&self) -> Vec<&'nodes Output>
+    where
+        Output: Ord,
+    {
+        let mut outputs = self.outputs().collect::<Vec<_>>();
+        outputs.sort();
+        outputs.dedup();
+        outputs
+    }
+
+    /// Compute a stable fingerprint of this graph's keys and outputs.
+    ///
+    /// The fingerprint is a 64-bit FNV-1a hash over each node's output, the output
+    /// of its default node and its transitions, walked from the start node in input
+    /// order. It does not depend on how the nodes are numbered, and integers are
+    /// always hashed in little-endian order, so it is the same across platforms as
+    /// long as the `Hash` implementations of `Input` and `Output` are.
+    ///
+    /// Each node is only hashed once, so this takes time linear in the size of the
+    /// graph even when subtrees are shared between keys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the transitions of the graph form a cycle, which is never the case
+    /// for graphs produced by the builder.
+    #[cfg(feature = "alloc")]
+    pub fn fingerprint(&self) -> u64
+    where
+        Input: hash::Hash,
+        Output: hash::Hash,
+    {
+        use core::hash::Hasher;
+
+        /// The state of a node in the walk.
+        #[derive(Clone, Copy, PartialEq)]
+        enum State {
+            Unvisited,
+            Visiting,
+            Done,
+        }
+
+        let mut states = alloc::vec![State::Unvisited; self.nodes.len()];
+        let mut hashes = alloc::vec![0u64; self.nodes.len()];
+
+        // Walk the graph in post-order, so every node is hashed after the nodes that
+        // its transitions lead to.
+        let mut stack = alloc::vec![(self.start, false)];
+        while let Some((index, expanded)) = stack.pop() {
+            let node = &self.nodes[index];
+
+            if !expanded {
+                match states[index] {
+                    State::Done => continue,
+                    State::Visiting => panic!("the transitions of the graph form a cycle"),
+                    State::Unvisited => {}
+                }
+
+                states[index] = State::Visiting;
+                stack.push((index, true));
+                stack.extend(
+                    node.inputs()
+                        .iter()
+                        .filter(|&&(_, next)| states[next] != State::Done)
+                        .map(|&(_, next)| (next, false)),
+                );
+                continue;
+            }
+
+            let mut hasher = Fnv::default();
+            node.output.hash(&mut hasher);
+            self.nodes[node.default].output.hash(&mut hasher);
+
+            let inputs = node.inputs();
+            hasher.write_usize(inputs.len());
+            for (input, next) in inputs {
+                input.hash(&mut hasher);
+                hasher.write_u64(hashes[*next]);
+            }
+
+            hashes[index] = hasher.finish();
+            states[index] = State::Done;
+        }
+
+        hashes[self.start]
+    }
+
+    /// Create a new graph that only contains the outputs that satisfy the predicate.
+    ///
+    /// Nodes whose output is rejected take on the output of their default node, and
+    /// nodes that can no longer reach an accepted output are dropped from the graph.
+    /// The nodes of the new graph are written into `node_buffer`.
+    #[cfg(feature = "alloc")]
+    pub fn retain<'out>(
+        &self,
+        node_buffer: &'out mut Vec<Node<'inst, Input, Output>>,
+        mut predicate: impl FnMut(&Output) -> bool,
+    ) -> Graph<'inst, 'out, Input, Output>
+    where
+        Input: Clone,
+        Output: Clone,
+    {
+        node_buffer.clear();
+
+        // Determine which nodes keep their output.
+        let accepted = self
+            .nodes
+            .iter()
+            .map(|node| predicate(&node.output))
+            .collect::<Vec<_>>();
+
+        // A node is live if it is accepted or it leads to a live node.
+        let mut live = accepted.clone();
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for (i, node) in self.nodes.iter().enumerate() {
+                if !live[i] && node.inputs.iter().any(|&(_, next)| live[next]) {
+                    live[i] = true;
+                    changed = true;
+                }
+            }
+        }
+
+        // Find every node that is still reachable from the start node.
+        let mut reachable = alloc::vec![false; self.nodes.len()];
+        let mut stack = alloc::vec![self.start];
+        while let Some(index) = stack.pop() {
+            if reachable[index] {
+                continue;
+            }
+
+            reachable[index] = true;
+            let node = &self.nodes[index];
+            stack.push(node.default);
+            stack.extend(
+                node.inputs
+                    .iter()
+                    .filter(|&&(_, next)| live[next])
+                    .map(|&(_, next)| next),
+            );
+        }
+
+        // Renumber the reachable nodes, preserving their relative order.
+        let mut new_indices = alloc::vec![0; self.nodes.len()];
+        let mut count = 0;
+        for (i, &reachable) in reachable.iter().enumerate() {
+            if reachable {
+                new_indices[i] = count;
+                count += 1;
+            }
+        }
+
+        // Copy over the reachable nodes.
+        for (i, node) in self.nodes.iter().enumerate() {
+            if !reachable[i] {
+                continue;
+            }
+
+            let inputs = node
+                .inputs
+                .iter()
+                .filter(|&&(_, next)| live[next])
+                .map(|(input, next)| (input.clone(), new_indices[*next]))
+                .collect();
+
+            let output = if accepted[i] {
+                node.output.clone()
+            } else {
+                self.nodes[node.default].output.clone()
+            };
+
+            node_buffer.push(Node {
+                inputs: MaybeSlice::Vec(inputs),
+                output,
+                default: new_indices[node.default],
+                amount: node.amount,
+            });
+        }
+
+        Graph::with_key_lengths(
+            &*node_buffer,
+            new_indices[self.start],
+            self.min_key_len,
+            self.max_key_len,
+        )
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<'inst, 'nodes, Input, Output> defmt::Format for Graph<'inst, 'nodes, Input, Output> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        // Only write out the metadata, since the nodes can be very large.
+        defmt::write!(
+            f,
+            "Graph {{ nodes: {=usize}, start: {=usize}, min_key_len: {=usize}, max_key_len: {=usize} }}",
+            self.nodes.len(),
+            self.start,
+            self.min_key_len,
+            self.max_key_len
+        )
+    }
+}
+
+/// An iterator over the outputs of the accepting nodes in a [`Graph`].
+///
+/// This is returned by [`Graph::outputs`].
+#[derive(Debug, Clone)]
+pub struct Outputs<'inst, 'nodes, Input, Output> {
+    /// All of the nodes in the graph.
+    nodes: &'nodes [Node<'inst, Input, Output>],
+
+    /// The nodes that we have yet to visit.
+    iter: core::slice::Iter<'nodes, Node<'inst, Input, Output>>,
+}
+
+impl<'inst, 'nodes, Input, Output: PartialEq> Iterator for Outputs<'inst, 'nodes, Input, Output> {
+    type Item = &'nodes Output;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = self.iter.next()?;
+
+            // Skip over nodes that behave like their default node.
+            if node.output != self.nodes[node.default].output {
+                return Some(&node.output);
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+impl<'nodes, 'inst, 'output, Input: Segmentable, T>
+    Graph<'inst, 'nodes, Input, Option<&'output T>>
+{
+    /// Process the input and return the referenced output, if any.
+    ///
+    /// This is a convenience for graphs whose outputs are references, such as
+    /// `&'static` payloads that are shared between many keys instead of being
+    /// copied into each node.
+    pub fn get(&self, input: Input) -> Option<&'output T> {
+        *self.process(input)
+    }
+}
+
+/// A 64-bit FNV-1a hasher that writes integers in little-endian order.
+///
+/// This is public so that `intern-str-codegen` hashes its inputs the same way, and
+/// isn't part of the stable API.
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl hash::Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write_u64(u64::from(i));
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write_u64(u64::from(i));
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        for shift in 0..8 {
+            self.write(&[(i >> (shift * 8)) as u8]);
+        }
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write_u64(i as u64);
+        self.write_u64((i >> 64) as u64);
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_i64(i64::from(i));
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_i64(i64::from(i));
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_i64(i as i64);
+    }
 }
 
 /// An item that can be segmented into parts.
 pub trait Segmentable: Ord + Sized {
     /// Split the item into two parts.
+    ///
+    /// Returns `None` if `at` is past the end of the item. Implementations may move the
+    /// split forward so that it doesn't land inside of a unit that can't be divided,
+    /// such as a character of a `&str`, in which case the first part is longer than
+    /// `at`.
     fn split(self, at: usize) -> Option<(Self, Self)>;
 
     /// Get the length of the item.
@@ -204,12 +715,18 @@ pub trait Segmentable: Ord + Sized {
     }
 }
 
-impl<'a> Segmentable for &'a str {
-    fn split(self, at: usize) -> Option<(Self, Self)> {
+/// Strings are split by bytes, but never inside of a character. A split that would
+/// land inside of one is moved forward to the end of that character.
+impl Segmentable for &str {
+    fn split(self, mut at: usize) -> Option<(Self, Self)> {
         if at > self.len() {
             return None;
         }
 
+        while !self.is_char_boundary(at) {
+            at += 1;
+        }
+
         let (left, right) = self.split_at(at);
         Some((left, right))
     }
@@ -219,7 +736,7 @@ impl<'a> Segmentable for &'a str {
     }
 }
 
-impl<'a, T: Ord> Segmentable for &'a [T] {
+impl<T: Ord> Segmentable for &[T] {
     fn split(self, at: usize) -> Option<(Self, Self)> {
         if at > self.len() {
             return None;
@@ -234,6 +751,65 @@ impl<'a, T: Ord> Segmentable for &'a [T] {
     }
 }
 
+/// A key that can be converted to and from bytes.
+pub trait ByteKey<'a>: Segmentable {
+    /// Get the bytes of this key.
+    fn to_bytes(&self) -> &[u8];
+
+    /// Create a key from its bytes.
+    fn from_bytes(bytes: &'a [u8]) -> Option<Self>;
+}
+
+impl<'a> ByteKey<'a> for &'a str {
+    fn to_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+
+    fn from_bytes(bytes: &'a [u8]) -> Option<Self> {
+        core::str::from_utf8(bytes).ok()
+    }
+}
+
+impl<'a> ByteKey<'a> for &'a [u8] {
+    fn to_bytes(&self) -> &[u8] {
+        self
+    }
+
+    fn from_bytes(bytes: &'a [u8]) -> Option<Self> {
+        Some(bytes)
+    }
+}
+
+impl<'a, T: ByteKey<'a> + AsRef<[u8]>> ByteKey<'a> for CaseInsensitive<T> {
+    fn to_bytes(&self) -> &[u8] {
+        self.0.to_bytes()
+    }
+
+    fn from_bytes(bytes: &'a [u8]) -> Option<Self> {
+        T::from_bytes(bytes).map(CaseInsensitive)
+    }
+}
+
+impl<'a, T: ByteKey<'a> + AsRef<[u8]>, S: Separators> ByteKey<'a> for SeparatorInsensitive<T, S> {
+    fn to_bytes(&self) -> &[u8] {
+        self.0.to_bytes()
+    }
+
+    fn from_bytes(bytes: &'a [u8]) -> Option<Self> {
+        T::from_bytes(bytes).map(SeparatorInsensitive::new)
+    }
+}
+
+impl<'a, T: ByteKey<'a> + AsRef<[u8]>, F: ByteFold> ByteKey<'a> for Folded<T, F> {
+    fn to_bytes(&self) -> &[u8] {
+        self.0.to_bytes()
+    }
+
+    fn from_bytes(bytes: &'a [u8]) -> Option<Self> {
+        T::from_bytes(bytes).map(Folded::new)
+    }
+}
+
 /// The wrapper type for a string that is compared case-insensitively.
 ///
 /// The inner string is implied to be ASCII.
@@ -307,6 +883,20 @@ impl<T: AsRef<[u8]>> hash::Hash for CaseInsensitive<T> {
     }
 }
 
+/// Displays the wrapped value, with its case unchanged.
+impl<T: fmt::Display> fmt::Display for CaseInsensitive<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<T: defmt::Format> defmt::Format for CaseInsensitive<T> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "CaseInsensitive({})", self.0)
+    }
+}
+
 impl<T: Segmentable + AsRef<[u8]>> Segmentable for CaseInsensitive<T> {
     fn split(self, at: usize) -> Option<(Self, Self)> {
         T::split(self.0, at).map(|(left, right)| (left.into(), right.into()))
@@ -317,10 +907,295 @@ impl<T: Segmentable + AsRef<[u8]>> Segmentable for CaseInsensitive<T> {
     }
 }
 
+/// The wrapper type for a string that is split by characters rather than by bytes.
+///
+/// Lengths are measured in characters, so a graph over `Chars` never splits its input
+/// inside of a multi-byte character.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Chars<'a>(pub &'a str);
+
+impl<'a> ops::Deref for Chars<'a> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.0
+    }
+}
+
+impl<'a> From<&'a str> for Chars<'a> {
+    fn from(value: &'a str) -> Self {
+        Chars(value)
+    }
+}
+
+impl<'a> AsRef<[u8]> for Chars<'a> {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+impl<'a> AsRef<str> for Chars<'a> {
+    fn as_ref(&self) -> &str {
+        self.0
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<'a> defmt::Format for Chars<'a> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "Chars({=str})", self.0)
+    }
+}
+
+impl<'a> Segmentable for Chars<'a> {
+    fn split(self, at: usize) -> Option<(Self, Self)> {
+        // Only walk as far as the split, so that splitting off each chunk of a long
+        // input doesn't count the characters of the rest of it.
+        let mut count = 0;
+        for (offset, _) in self.0.char_indices() {
+            if count == at {
+                let (left, right) = self.0.split_at(offset);
+                return Some((Chars(left), Chars(right)));
+            }
+            count += 1;
+        }
+
+        if count == at {
+            Some((self, Chars("")))
+        } else {
+            None
+        }
+    }
+
+    /// Count the characters in the string.
+    ///
+    /// This takes time linear in the length of the string, so [`Graph::process`] only
+    /// calls it once per lookup.
+    fn len(&self) -> usize {
+        self.0.chars().count()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<'a> ByteKey<'a> for Chars<'a> {
+    fn to_bytes(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+
+    fn from_bytes(bytes: &'a [u8]) -> Option<Self> {
+        core::str::from_utf8(bytes).ok().map(Chars)
+    }
+}
+
+/// A mapping from bytes to a canonical form, defining a set of byte equivalence classes.
+pub trait ByteFold {
+    /// Map a byte to its canonical form.
+    ///
+    /// This should be idempotent, so folding a canonical byte should return it
+    /// unchanged.
+    fn fold(byte: u8) -> u8;
+}
+
+/// The wrapper type for a string where bytes are compared after being folded by `F`.
+///
+/// This generalizes [`CaseInsensitive`] to any user-defined equivalence between
+/// bytes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Folded<T, F>(pub T, pub PhantomData<F>);
+
+impl<T, F> Folded<T, F> {
+    /// Wrap a string.
+    pub const fn new(value: T) -> Self {
+        Folded(value, PhantomData)
+    }
+}
+
+impl<T, F> ops::Deref for Folded<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T, F> ops::DerefMut for Folded<T, F> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T, F> From<T> for Folded<T, F> {
+    fn from(value: T) -> Self {
+        Folded::new(value)
+    }
+}
+
+impl<T: AsRef<[u8]>, F: ByteFold> PartialEq for Folded<T, F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == cmp::Ordering::Equal
+    }
+}
+
+impl<T: AsRef<[u8]>, F: ByteFold> Eq for Folded<T, F> {}
+
+impl<T: AsRef<[u8]>, F: ByteFold> PartialOrd for Folded<T, F> {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: AsRef<[u8]>, F: ByteFold> Ord for Folded<T, F> {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        let this = self.0.as_ref().iter().map(|&b| F::fold(b));
+        let other = other.0.as_ref().iter().map(|&b| F::fold(b));
+
+        this.cmp(other)
+    }
+}
+
+impl<T: AsRef<[u8]>, F: ByteFold> hash::Hash for Folded<T, F> {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        for &byte in self.0.as_ref() {
+            state.write_u8(F::fold(byte));
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<T: defmt::Format, F> defmt::Format for Folded<T, F> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "Folded({})", self.0)
+    }
+}
+
+impl<T: Segmentable + AsRef<[u8]>, F: ByteFold> Segmentable for Folded<T, F> {
+    fn split(self, at: usize) -> Option<(Self, Self)> {
+        T::split(self.0, at).map(|(left, right)| (left.into(), right.into()))
+    }
+
+    fn len(&self) -> usize {
+        T::len(&self.0)
+    }
+}
+
+/// A set of separator bytes that are considered equal to one another.
+pub trait Separators {
+    /// The separator bytes.
+    ///
+    /// These should all be ASCII. The first byte is the canonical separator that the
+    /// others are replaced with when building a graph.
+    const SEPARATORS: &'static [u8];
+}
+
+/// The default set of separators: `-`, `_` and ` `.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct DefaultSeparators;
+
+impl Separators for DefaultSeparators {
+    const SEPARATORS: &'static [u8] = b"-_ ";
+}
+
+/// The wrapper type for a string that is compared case-insensitively, and where all
+/// of the separators in `S` are considered equal.
+///
+/// With the default separators, `content-type`, `content_type` and `CONTENT TYPE`
+/// all compare equal. The inner string is implied to be ASCII.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SeparatorInsensitive<T, S = DefaultSeparators>(pub T, pub PhantomData<S>);
+
+impl<T, S> SeparatorInsensitive<T, S> {
+    /// Wrap a string.
+    pub const fn new(value: T) -> Self {
+        SeparatorInsensitive(value, PhantomData)
+    }
+}
+
+impl<T, S: Separators> SeparatorInsensitive<T, S> {
+    /// Fold a byte into the form that it is compared in.
+    fn fold(byte: u8) -> u8 {
+        if S::SEPARATORS.contains(&byte) {
+            S::SEPARATORS[0]
+        } else {
+            byte.to_ascii_lowercase()
+        }
+    }
+}
+
+impl<T, S> ops::Deref for SeparatorInsensitive<T, S> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T, S> ops::DerefMut for SeparatorInsensitive<T, S> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T, S> From<T> for SeparatorInsensitive<T, S> {
+    fn from(value: T) -> Self {
+        SeparatorInsensitive::new(value)
+    }
+}
+
+impl<T: AsRef<[u8]>, S: Separators> PartialEq for SeparatorInsensitive<T, S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == cmp::Ordering::Equal
+    }
+}
+
+impl<T: AsRef<[u8]>, S: Separators> Eq for SeparatorInsensitive<T, S> {}
+
+impl<T: AsRef<[u8]>, S: Separators> PartialOrd for SeparatorInsensitive<T, S> {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: AsRef<[u8]>, S: Separators> Ord for SeparatorInsensitive<T, S> {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        let this = self.0.as_ref().iter().map(|&b| Self::fold(b));
+        let other = other.0.as_ref().iter().map(|&b| Self::fold(b));
+
+        this.cmp(other)
+    }
+}
+
+impl<T: AsRef<[u8]>, S: Separators> hash::Hash for SeparatorInsensitive<T, S> {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        for &byte in self.0.as_ref() {
+            state.write_u8(Self::fold(byte));
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<T: defmt::Format, S> defmt::Format for SeparatorInsensitive<T, S> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "SeparatorInsensitive({})", self.0)
+    }
+}
+
+impl<T: Segmentable + AsRef<[u8]>, S: Separators> Segmentable for SeparatorInsensitive<T, S> {
+    fn split(self, at: usize) -> Option<(Self, Self)> {
+        T::split(self.0, at).map(|(left, right)| (left.into(), right.into()))
+    }
+
+    fn len(&self) -> usize {
+        T::len(&self.0)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 enum MaybeSlice<'a, T> {
     Slice(&'a [T]),
-    #[cfg(feature = "builder")]
+    #[cfg(feature = "alloc")]
     Vec(Vec<T>),
 }
 
@@ -330,7 +1205,7 @@ impl<'a, T> core::ops::Deref for MaybeSlice<'a, T> {
     fn deref(&self) -> &Self::Target {
         match self {
             MaybeSlice::Slice(slice) => slice,
-            #[cfg(feature = "builder")]
+            #[cfg(feature = "alloc")]
             MaybeSlice::Vec(vec) => vec,
         }
     }
@@ -0,0 +1,52 @@
+//! A graph whose outputs are stored in a separate table.
+
+use super::{Graph, Segmentable};
+
+/// A graph where each node stores the index of its output in a table of outputs.
+///
+/// Nodes that do not accept any input store an index that is out of bounds for the
+/// table, conventionally `core::usize::MAX`. This means that outputs do not need to
+/// be wrapped in an `Option`, and nodes that do not accept any input do not need to
+/// store an output at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Map<'inst, 'nodes, 'outputs, Input, Output> {
+    /// The graph mapping inputs to output indices.
+    graph: Graph<'inst, 'nodes, Input, usize>,
+
+    /// The table of outputs.
+    outputs: &'outputs [Output],
+}
+
+impl<'inst, 'nodes, 'outputs, Input, Output> Map<'inst, 'nodes, 'outputs, Input, Output> {
+    /// Create a new map from a graph of output indices and a table of outputs.
+    pub const fn new(
+        graph: Graph<'inst, 'nodes, Input, usize>,
+        outputs: &'outputs [Output],
+    ) -> Self {
+        Self { graph, outputs }
+    }
+
+    /// Get the graph mapping inputs to output indices.
+    pub fn graph(&self) -> Graph<'inst, 'nodes, Input, usize> {
+        Graph {
+            nodes: self.graph.nodes,
+            start: self.graph.start,
+            min_key_len: self.graph.min_key_len,
+            max_key_len: self.graph.max_key_len,
+        }
+    }
+
+    /// Get the table of outputs.
+    pub fn outputs(&self) -> &'outputs [Output] {
+        self.outputs
+    }
+}
+
+impl<'inst, 'nodes, 'outputs, Input: Segmentable, Output>
+    Map<'inst, 'nodes, 'outputs, Input, Output>
+{
+    /// Process the input and return the output, or `None` if the input is not a key.
+    pub fn get(&self, input: Input) -> Option<&'outputs Output> {
+        self.outputs.get(*self.graph.process(input))
+    }
+}
@@ -0,0 +1,28 @@
+//! Unicode normalization for queries.
+
+use alloc::borrow::Cow;
+use unicode_normalization::{is_nfc_quick, is_nfkc_quick, IsNormalized, UnicodeNormalization};
+
+/// Normalize a query to NFC.
+///
+/// Use this on queries against graphs built with [`builder::Nfc`](crate::builder::Nfc),
+/// so they match keys that were normalized at build time. The input is only copied
+/// if it isn't already normalized.
+pub fn nfc(input: &str) -> Cow<'_, str> {
+    match is_nfc_quick(input.chars()) {
+        IsNormalized::Yes => Cow::Borrowed(input),
+        _ => Cow::Owned(input.nfc().collect()),
+    }
+}
+
+/// Normalize a query to NFKC.
+///
+/// Use this on queries against graphs built with [`builder::Nfkc`](crate::builder::Nfkc),
+/// so they match keys that were normalized at build time. The input is only copied
+/// if it isn't already normalized.
+pub fn nfkc(input: &str) -> Cow<'_, str> {
+    match is_nfkc_quick(input.chars()) {
+        IsNormalized::Yes => Cow::Borrowed(input),
+        _ => Cow::Owned(input.nfkc().collect()),
+    }
+}
@@ -0,0 +1,240 @@
+//! A position-independent representation of a graph, packed into a single byte slice.
+
+use super::ByteKey;
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use core::marker::PhantomData;
+
+/// The size of the header at the start of the blob.
+#[cfg(feature = "alloc")]
+const HEADER_LEN: usize = 12;
+
+/// The size of a node's fixed fields.
+const NODE_LEN: usize = 16;
+
+/// The size of a single transition.
+const TRANSITION_LEN: usize = 12;
+
+/// The value used in place of `usize::MAX` and missing outputs.
+const NONE: u32 = core::u32::MAX;
+
+/// A graph packed into a single byte slice, with outputs stored in a separate table.
+///
+/// Every reference between nodes is stored as an offset into the blob, so the blob
+/// can be stored in a single `static` (or loaded with `include_bytes!`) without the
+/// per-node relocations that a table of [`Node`](super::Node)s needs.
+///
+/// ## Layout
+///
+/// All integers are little-endian `u32`s, where `u32::MAX` stands in for
+/// `usize::MAX` and for nodes without an output. The blob starts with the offset of
+/// the start node and the minimum and maximum key lengths. Each node consists of its
+/// output index, the offset of its default node, its amount and its number of
+/// transitions, followed by the transitions. Each transition is the offset and
+/// length of its key followed by the offset of the next node, and the keys are
+/// stored after the transitions of each node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Packed<'a, Input, Output> {
+    /// The packed nodes.
+    blob: &'a [u8],
+
+    /// The table of outputs.
+    outputs: &'a [Output],
+
+    /// The type of the input.
+    input: PhantomData<fn(Input)>,
+}
+
+impl<'a, Input, Output> Packed<'a, Input, Output> {
+    /// Create a new packed graph from a blob and a table of outputs.
+    pub const fn new(blob: &'a [u8], outputs: &'a [Output]) -> Self {
+        Self {
+            blob,
+            outputs,
+            input: PhantomData,
+        }
+    }
+
+    /// Get the packed nodes.
+    pub fn blob(&self) -> &'a [u8] {
+        self.blob
+    }
+
+    /// Get the table of outputs.
+    pub fn outputs(&self) -> &'a [Output] {
+        self.outputs
+    }
+
+    /// Get the length of the shortest key in the graph.
+    pub fn min_key_len(&self) -> usize {
+        from_u32(self.read(4))
+    }
+
+    /// Get the length of the longest key in the graph.
+    pub fn max_key_len(&self) -> usize {
+        from_u32(self.read(8))
+    }
+
+    /// Read the `u32` at the given offset.
+    fn read(&self, offset: usize) -> u32 {
+        let bytes = &self.blob[offset..offset + 4];
+        u32::from(bytes[0])
+            | u32::from(bytes[1]) << 8
+            | u32::from(bytes[2]) << 16
+            | u32::from(bytes[3]) << 24
+    }
+}
+
+impl<'a, Input: ByteKey<'a>, Output> Packed<'a, Input, Output> {
+    /// Process the input and return the output, or `None` if the input is not a key.
+    pub fn get(&self, mut input: Input) -> Option<&'a Output> {
+        let mut node = self.read(0) as usize;
+
+        // If the input can't possibly be a key, bail out early.
+        let len = input.len();
+        if len < self.min_key_len() || len > self.max_key_len() {
+            return None;
+        }
+
+        loop {
+            // Get the next input chunk.
+            let ended = input.is_empty();
+            let (chunk, rest) = match input.split(from_u32(self.read(node + 8))) {
+                Some(result) => result,
+                None if ended || self.read(node + 12) == 0 => {
+                    return self.outputs.get(self.read(node) as usize)
+                }
+                None => {
+                    // The input ends partway through every transition.
+                    let default = self.read(node + 4) as usize;
+                    return self.outputs.get(self.read(default) as usize);
+                }
+            };
+
+            node = self.next(node, &chunk);
+            input = rest;
+        }
+    }
+
+    /// Determine the offset of the next node to go to based on the input.
+    fn next(&self, node: usize, input: &Input) -> usize {
+        let transitions = node + NODE_LEN;
+        let mut low = 0;
+        let mut high = self.read(node + 12) as usize;
+
+        // Binary search over the transitions, since they are sorted.
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let transition = transitions + mid * TRANSITION_LEN;
+
+            let key_offset = self.read(transition) as usize;
+            let key_len = self.read(transition + 4) as usize;
+            let key = match Input::from_bytes(&self.blob[key_offset..key_offset + key_len]) {
+                Some(key) => key,
+                None => break,
+            };
+
+            match key.cmp(input) {
+                core::cmp::Ordering::Less => low = mid + 1,
+                core::cmp::Ordering::Greater => high = mid,
+                core::cmp::Ordering::Equal => return self.read(transition + 8) as usize,
+            }
+        }
+
+        self.read(node + 4) as usize
+    }
+
+    /// Pack the nodes of a map into `blob`.
+    ///
+    /// The resulting blob can be combined with the map's output table to create a
+    /// [`Packed`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the blob would be larger than `u32::MAX` bytes.
+    #[cfg(feature = "alloc")]
+    pub fn pack(map: &super::Map<'_, '_, '_, Input, Output>, blob: &mut Vec<u8>) {
+        let graph = map.graph();
+        let nodes = graph.nodes();
+        blob.clear();
+
+        // Figure out where each node will be placed.
+        let mut offsets = Vec::with_capacity(nodes.len());
+        let mut offset = HEADER_LEN;
+        for node in nodes {
+            offsets.push(offset);
+            offset += NODE_LEN + node.inputs().len() * TRANSITION_LEN;
+            offset += node
+                .inputs()
+                .iter()
+                .map(|(input, _)| input.to_bytes().len())
+                .sum::<usize>();
+        }
+
+        assert!(
+            offset <= NONE as usize,
+            "packed graph would be larger than u32::MAX bytes"
+        );
+
+        write_u32(blob, offsets[graph.start()]);
+        write_u32(blob, graph.min_key_len());
+        write_u32(blob, graph.max_key_len());
+
+        for node in nodes {
+            let output = *node.output();
+            write_u32(
+                blob,
+                if output < map.outputs().len() {
+                    output
+                } else {
+                    core::usize::MAX
+                },
+            );
+            write_u32(blob, offsets[node.default()]);
+            write_u32(blob, node.amount());
+            write_u32(blob, node.inputs().len());
+
+            // Write the transitions, with the keys following them.
+            let mut key_offset = blob.len() + node.inputs().len() * TRANSITION_LEN;
+            for (input, next) in node.inputs() {
+                let key_len = input.to_bytes().len();
+                write_u32(blob, key_offset);
+                write_u32(blob, key_len);
+                write_u32(blob, offsets[*next]);
+                key_offset += key_len;
+            }
+
+            for (input, _) in node.inputs() {
+                blob.extend_from_slice(input.to_bytes());
+            }
+        }
+    }
+}
+
+/// Convert a `u32` from the blob into a `usize`.
+fn from_u32(value: u32) -> usize {
+    if value == NONE {
+        core::usize::MAX
+    } else {
+        value as usize
+    }
+}
+
+/// Append a `usize` to the blob as a little-endian `u32`.
+#[cfg(feature = "alloc")]
+fn write_u32(blob: &mut Vec<u8>, value: usize) {
+    let value = if value == core::usize::MAX {
+        NONE
+    } else {
+        value as u32
+    };
+
+    blob.extend_from_slice(&[
+        value as u8,
+        (value >> 8) as u8,
+        (value >> 16) as u8,
+        (value >> 24) as u8,
+    ]);
+}
@@ -0,0 +1,378 @@
+//! A binary serialization format for graphs.
+//!
+//! This allows graphs to be written to a file and loaded at runtime, instead of
+//! being embedded into the binary with codegen. Keys are borrowed directly from the
+//! serialized bytes, so only the node table needs to be allocated when loading.
+//!
+//! To load a graph from a file, use [`GraphFile`] with the `std` feature, which reads
+//! the whole file into memory, or [`MappedGraphFile`] with the `mmap` feature, which
+//! memory-maps it with [`memmap2`]. [`read`] accepts any byte slice, so the bytes can
+//! come from anywhere else as well.
+//!
+//! [`memmap2`]: https://crates.io/crates/memmap2
+//!
+//! ## Format
+//!
+//! All integers are little-endian `u64`s, where `u64::MAX` stands in for
+//! `usize::MAX`. A serialized graph consists of:
+//!
+//! - The magic bytes `ISTR`, followed by the format version as a little-endian `u32`.
+//! - The number of nodes, the start index, and the minimum and maximum key lengths.
+//! - For each node, the length of its output followed by the output bytes, the
+//!   default index, the amount, and the number of inputs.
+//! - For each input of that node, the length of the key, the key bytes, and the index
+//!   of the next node.
+
+pub use super::ByteKey;
+
+use super::{Graph, MaybeSlice, Node};
+
+use alloc::vec::Vec;
+use core::fmt;
+
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "mmap")]
+pub use mmap::MappedGraphFile;
+
+/// The magic bytes at the start of a serialized graph.
+const MAGIC: &[u8; 4] = b"ISTR";
+
+/// The current version of the format.
+const VERSION: u32 = 1;
+
+/// Serialize a graph, appending it to `out`.
+///
+/// `write_output` is called to append the bytes for each node's output.
+pub fn write<'a, Input: ByteKey<'a>, Output>(
+    graph: &Graph<'_, '_, Input, Output>,
+    out: &mut Vec<u8>,
+    mut write_output: impl FnMut(&Output, &mut Vec<u8>),
+) {
+    write_header(
+        out,
+        graph.nodes().len(),
+        graph.start(),
+        graph.min_key_len(),
+        graph.max_key_len(),
+    );
+
+    let mut output = Vec::new();
+    for node in graph.nodes() {
+        output.clear();
+        write_output(node.output(), &mut output);
+        write_node(
+            out,
+            &output,
+            node.default(),
+            node.amount(),
+            node.inputs()
+                .iter()
+                .map(|(input, next)| (input.to_bytes(), *next)),
+        );
+    }
+}
+
+/// Append the magic bytes, the version, and the shape of the graph.
+///
+/// This is always the same length, so it can be written again once the shape of the
+/// graph is known.
+pub(crate) fn write_header(
+    out: &mut Vec<u8>,
+    len: usize,
+    start: usize,
+    min_key_len: usize,
+    max_key_len: usize,
+) {
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&[
+        VERSION as u8,
+        (VERSION >> 8) as u8,
+        (VERSION >> 16) as u8,
+        (VERSION >> 24) as u8,
+    ]);
+
+    write_usize(out, len);
+    write_usize(out, start);
+    write_usize(out, min_key_len);
+    write_usize(out, max_key_len);
+}
+
+/// Append a node, given the bytes of its output and its inputs.
+pub(crate) fn write_node<'k>(
+    out: &mut Vec<u8>,
+    output: &[u8],
+    default: usize,
+    amount: usize,
+    inputs: impl ExactSizeIterator<Item = (&'k [u8], usize)>,
+) {
+    write_usize(out, output.len());
+    out.extend_from_slice(output);
+
+    write_usize(out, default);
+    write_usize(out, amount);
+
+    write_usize(out, inputs.len());
+    for (bytes, next) in inputs {
+        write_usize(out, bytes.len());
+        out.extend_from_slice(bytes);
+        write_usize(out, next);
+    }
+}
+
+/// Deserialize a graph from `bytes`, writing its nodes into `node_buffer`.
+///
+/// `read_output` is called with the bytes written for each node's output. The keys
+/// of the graph borrow from `bytes`.
+pub fn read<'a, 'nodes, Input: ByteKey<'a>, Output>(
+    bytes: &'a [u8],
+    node_buffer: &'nodes mut Vec<Node<'a, Input, Output>>,
+    mut read_output: impl FnMut(&'a [u8]) -> Option<Output>,
+) -> Result<Graph<'a, 'nodes, Input, Output>, ReadError> {
+    node_buffer.clear();
+    let mut reader = Reader(bytes);
+    reader.header()?;
+
+    let len = reader.usize()?;
+    let start = reader.usize()?;
+    let min_key_len = reader.usize()?;
+    let max_key_len = reader.usize()?;
+
+    let index = |index: usize| {
+        if index < len {
+            Ok(index)
+        } else {
+            Err(ReadError::InvalidIndex)
+        }
+    };
+    let start = index(start)?;
+
+    for _ in 0..len {
+        let output_len = reader.usize()?;
+        let output = read_output(reader.take(output_len)?).ok_or(ReadError::InvalidOutput)?;
+
+        let default = index(reader.usize()?)?;
+        let amount = reader.usize()?;
+
+        let input_count = reader.usize()?;
+        let mut inputs: Vec<(Input, usize)> = Vec::new();
+        for _ in 0..input_count {
+            let key_len = reader.usize()?;
+            let key = Input::from_bytes(reader.take(key_len)?).ok_or(ReadError::InvalidKey)?;
+            let next = index(reader.usize()?)?;
+
+            // The inputs need to be sorted for the binary search to work.
+            if let Some((last, _)) = inputs.last() {
+                if *last >= key {
+                    return Err(ReadError::InvalidKey);
+                }
+            }
+
+            inputs.push((key, next));
+        }
+
+        node_buffer.push(Node {
+            inputs: MaybeSlice::Vec(inputs),
+            output,
+            default,
+            amount,
+        });
+    }
+
+    Ok(Graph::with_key_lengths(
+        &*node_buffer,
+        start,
+        min_key_len,
+        max_key_len,
+    ))
+}
+
+/// A serialized graph that has been loaded from a file.
+///
+/// The bytes of the file are owned by this type, and graphs read from it borrow their
+/// keys from it.
+///
+/// ```
+/// use intern_str::builder::{Builder, Utf8Graph};
+/// use intern_str::serialize::{self, GraphFile};
+///
+/// let mut builder = Builder::<u8, Utf8Graph>::new();
+/// builder.add("get", 1).unwrap();
+/// builder.add("set", 2).unwrap();
+///
+/// let mut buffer = Vec::new();
+/// let graph = builder.build(&mut buffer);
+///
+/// let mut bytes = Vec::new();
+/// serialize::write(&graph, &mut bytes, |output, out| out.extend(*output));
+///
+/// let path = std::env::temp_dir().join(format!("graph-{}.bin", std::process::id()));
+/// std::fs::write(&path, &bytes).unwrap();
+///
+/// let file = GraphFile::open(&path).unwrap();
+/// let mut nodes = Vec::new();
+/// let loaded = file
+///     .graph::<&str, Option<u8>>(&mut nodes, |bytes| match bytes.len() {
+///         0 => Some(None),
+///         1 => Some(Some(bytes[0])),
+///         _ => None,
+///     })
+///     .unwrap();
+///
+/// assert_eq!(*loaded.process("set"), Some(2));
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphFile {
+    /// The contents of the file.
+    bytes: Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+impl GraphFile {
+    /// Read the file at `path`.
+    ///
+    /// This fails with [`std::io::ErrorKind::InvalidData`] if the file doesn't start
+    /// with the header of a serialized graph. The rest of the file is checked when
+    /// the graph is read.
+    pub fn open(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        Self::from_bytes(std::fs::read(path)?)
+    }
+
+    /// Wrap bytes that have already been read.
+    pub fn from_bytes(bytes: Vec<u8>) -> std::io::Result<Self> {
+        check_header(&bytes)?;
+        Ok(GraphFile { bytes })
+    }
+
+    /// Get the bytes of the file.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Read the graph in the file, writing its nodes into `node_buffer`.
+    ///
+    /// This is the same as calling [`read`] on the bytes of the file.
+    pub fn graph<'a, 'nodes, Input: ByteKey<'a>, Output>(
+        &'a self,
+        node_buffer: &'nodes mut Vec<Node<'a, Input, Output>>,
+        read_output: impl FnMut(&'a [u8]) -> Option<Output>,
+    ) -> Result<Graph<'a, 'nodes, Input, Output>, ReadError> {
+        read(&self.bytes, node_buffer, read_output)
+    }
+}
+
+/// Check that the contents of a file start with the header of a serialized graph.
+#[cfg(feature = "std")]
+fn check_header(bytes: &[u8]) -> std::io::Result<()> {
+    Reader(bytes)
+        .header()
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+}
+
+/// An error that occurs while reading a serialized graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadError {
+    /// The data does not start with the magic bytes.
+    BadMagic,
+
+    /// The data was written with an unsupported version of the format.
+    UnsupportedVersion(u32),
+
+    /// The data ended unexpectedly.
+    UnexpectedEnd,
+
+    /// A key could not be read, or the keys of a node are not sorted.
+    InvalidKey,
+
+    /// An output could not be read.
+    InvalidOutput,
+
+    /// A node index is out of bounds.
+    InvalidIndex,
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadError::BadMagic => f.write_str("Data is not a serialized graph"),
+            ReadError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported serialized graph version: {}", version)
+            }
+            ReadError::UnexpectedEnd => f.write_str("Serialized graph ended unexpectedly"),
+            ReadError::InvalidKey => f.write_str("Serialized graph contains an invalid key"),
+            ReadError::InvalidOutput => f.write_str("Serialized graph contains an invalid output"),
+            ReadError::InvalidIndex => {
+                f.write_str("Serialized graph contains an out of bounds node index")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ReadError {}
+
+/// Append a `usize` to the buffer as a little-endian `u64`.
+fn write_usize(out: &mut Vec<u8>, value: usize) {
+    let value = if value == core::usize::MAX {
+        core::u64::MAX
+    } else {
+        value as u64
+    };
+
+    for shift in 0..8 {
+        out.push((value >> (shift * 8)) as u8);
+    }
+}
+
+/// A cursor over the serialized bytes.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    /// Take the next `len` bytes.
+    /// Read the magic bytes and the version.
+    fn header(&mut self) -> Result<(), ReadError> {
+        if self.take(MAGIC.len())? != MAGIC {
+            return Err(ReadError::BadMagic);
+        }
+
+        let version = self.take(4)?;
+        let version = u32::from(version[0])
+            | u32::from(version[1]) << 8
+            | u32::from(version[2]) << 16
+            | u32::from(version[3]) << 24;
+        if version != VERSION {
+            return Err(ReadError::UnsupportedVersion(version));
+        }
+
+        Ok(())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ReadError> {
+        if len > self.0.len() {
+            return Err(ReadError::UnexpectedEnd);
+        }
+
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    /// Read a little-endian `u64` as a `usize`.
+    fn usize(&mut self) -> Result<usize, ReadError> {
+        let bytes = self.take(8)?;
+        let value = bytes
+            .iter()
+            .rev()
+            .fold(0u64, |value, &byte| value << 8 | u64::from(byte));
+
+        if value == core::u64::MAX {
+            Ok(core::usize::MAX)
+        } else if value > core::usize::MAX as u64 {
+            Err(ReadError::InvalidIndex)
+        } else {
+            Ok(value as usize)
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Loading serialized graphs by memory-mapping their files.
+//!
+//! This is the only module in the crate that uses `unsafe` code, since mapping a file
+//! is only sound if nothing modifies the file while it is mapped.
+
+use super::{read, ByteKey, Graph, Node, ReadError};
+
+use alloc::vec::Vec;
+use memmap2::Mmap;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// A serialized graph in a file that has been memory-mapped.
+///
+/// Unlike [`GraphFile`](super::GraphFile), the file isn't read into memory up front.
+/// The operating system pages it in as the graph is read and walked, and can share the
+/// pages between processes that map the same file. Graphs read from it borrow their
+/// keys from the map.
+///
+/// ```
+/// use intern_str::builder::{Builder, Utf8Graph};
+/// use intern_str::serialize::{self, MappedGraphFile};
+///
+/// let mut builder = Builder::<u8, Utf8Graph>::new();
+/// builder.add("get", 1).unwrap();
+/// builder.add("set", 2).unwrap();
+///
+/// let mut buffer = Vec::new();
+/// let graph = builder.build(&mut buffer);
+///
+/// let mut bytes = Vec::new();
+/// serialize::write(&graph, &mut bytes, |output, out| out.extend(*output));
+///
+/// let path = std::env::temp_dir().join(format!("mapped-graph-{}.bin", std::process::id()));
+/// std::fs::write(&path, &bytes).unwrap();
+///
+/// // Nothing else writes to the file while it is mapped.
+/// let file = unsafe { MappedGraphFile::map(&path) }.unwrap();
+/// let mut nodes = Vec::new();
+/// let loaded = file
+///     .graph::<&str, Option<u8>>(&mut nodes, |bytes| match bytes.len() {
+///         0 => Some(None),
+///         1 => Some(Some(bytes[0])),
+///         _ => None,
+///     })
+///     .unwrap();
+///
+/// assert_eq!(*loaded.process("set"), Some(2));
+/// # drop(loaded);
+/// # drop(file);
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct MappedGraphFile {
+    /// The mapped contents of the file.
+    map: Mmap,
+}
+
+impl MappedGraphFile {
+    /// Memory-map the file at `path`.
+    ///
+    /// This fails with [`std::io::ErrorKind::InvalidData`] if the file doesn't start
+    /// with the header of a serialized graph. The rest of the file is checked when
+    /// the graph is read.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while it is mapped, by this process
+    /// or any other. Otherwise, the keys of a graph read from it can change under it,
+    /// which is undefined behavior. To update a graph that is in use, write the new
+    /// graph to another file and rename it over the old one. Maps of the old file keep
+    /// seeing the old contents.
+    #[allow(unsafe_code)]
+    pub unsafe fn map(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let map = Mmap::map(&file)?;
+        super::check_header(&map)?;
+        Ok(MappedGraphFile { map })
+    }
+
+    /// Get the bytes of the file.
+    pub fn bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    /// Read the graph in the file, writing its nodes into `node_buffer`.
+    ///
+    /// This is the same as calling [`read`] on the bytes of the file.
+    pub fn graph<'a, 'nodes, Input: ByteKey<'a>, Output>(
+        &'a self,
+        node_buffer: &'nodes mut Vec<Node<'a, Input, Output>>,
+        read_output: impl FnMut(&'a [u8]) -> Option<Output>,
+    ) -> Result<Graph<'a, 'nodes, Input, Output>, ReadError> {
+        read(&self.map, node_buffer, read_output)
+    }
+}
@@ -0,0 +1,265 @@
+#![cfg(feature = "builder")]
+
+use intern_str::builder::{Builder, IgnoreCase, Utf8Graph};
+use intern_str::serialize::{self, ReadError};
+use intern_str::CaseInsensitive;
+
+#[test]
+fn round_trip() {
+    let mut builder = Builder::<u32, IgnoreCase<Utf8Graph>>::new();
+    builder.add("text/plain".to_string(), 1).unwrap();
+    builder.add("text/html".to_string(), 2).unwrap();
+    builder.add("image/png".to_string(), 3).unwrap();
+
+    let mut buffer = vec![];
+    let graph = builder.build(&mut buffer);
+
+    let mut bytes = vec![];
+    serialize::write(&graph, &mut bytes, |output, out| {
+        if let Some(output) = output {
+            out.extend_from_slice(&output.to_le_bytes());
+        }
+    });
+
+    let mut nodes = vec![];
+    let loaded =
+        serialize::read::<CaseInsensitive<&str>, Option<u32>>(&bytes, &mut nodes, |bytes| {
+            match bytes.len() {
+                0 => Some(None),
+                4 => Some(Some(u32::from_le_bytes([
+                    bytes[0], bytes[1], bytes[2], bytes[3],
+                ]))),
+                _ => None,
+            }
+        })
+        .unwrap();
+
+    assert_eq!(loaded.nodes().len(), graph.nodes().len());
+    assert_eq!(loaded.fingerprint(), graph.fingerprint());
+    assert_eq!(*loaded.process(CaseInsensitive("Text/Plain")), Some(1));
+    assert_eq!(*loaded.process(CaseInsensitive("text/html")), Some(2));
+    assert_eq!(*loaded.process(CaseInsensitive("IMAGE/PNG")), Some(3));
+    assert_eq!(*loaded.process(CaseInsensitive("image/gif")), None);
+
+    // Truncated or corrupted data is rejected.
+    let read = |bytes: &[u8]| {
+        let mut nodes = vec![];
+        serialize::read::<&str, ()>(bytes, &mut nodes, |_| Some(())).map(|_| ())
+    };
+    assert_eq!(
+        read(&bytes[..bytes.len() - 1]),
+        Err(ReadError::UnexpectedEnd)
+    );
+    assert_eq!(read(b"PNG\0"), Err(ReadError::BadMagic));
+    assert_eq!(
+        read(b"ISTR\x02\0\0\0"),
+        Err(ReadError::UnsupportedVersion(2))
+    );
+}
+
+#[test]
+fn packed() {
+    use intern_str::Packed;
+
+    let mut builder = Builder::<u32, IgnoreCase<Utf8Graph>>::new();
+    builder.add("text/plain".to_string(), 1).unwrap();
+    builder.add("text/html".to_string(), 2).unwrap();
+    builder.add("image/png".to_string(), 3).unwrap();
+    builder.add("image/jpeg".to_string(), 4).unwrap();
+
+    let mut nodes = vec![];
+    let mut outputs = vec![];
+    let map = builder.build_map(&mut nodes, &mut outputs);
+
+    let mut blob = vec![];
+    Packed::pack(&map, &mut blob);
+    let packed = Packed::<CaseInsensitive<&str>, u32>::new(&blob, map.outputs());
+
+    assert_eq!(packed.min_key_len(), 9);
+    assert_eq!(packed.max_key_len(), 10);
+    assert_eq!(packed.get(CaseInsensitive("Text/Plain")), Some(&1));
+    assert_eq!(packed.get(CaseInsensitive("text/html")), Some(&2));
+    assert_eq!(packed.get(CaseInsensitive("IMAGE/PNG")), Some(&3));
+    assert_eq!(packed.get(CaseInsensitive("image/jpeg")), Some(&4));
+    assert_eq!(packed.get(CaseInsensitive("image/gif")), None);
+    assert_eq!(packed.get(CaseInsensitive("text")), None);
+}
+
+#[test]
+fn flat() {
+    use intern_str::Flat;
+
+    let mut builder = Builder::<u32, IgnoreCase<Utf8Graph>>::new();
+    builder.add("text/plain".to_string(), 1).unwrap();
+    builder.add("text/html".to_string(), 2).unwrap();
+    builder.add("image/png".to_string(), 3).unwrap();
+    builder.add("image/jpeg".to_string(), 4).unwrap();
+
+    let mut buffer = vec![];
+    let graph = builder.build(&mut buffer);
+
+    let (mut nodes, mut edges, mut keys) = (vec![], vec![], vec![]);
+    let mut outputs = vec![];
+    Flat::flatten(&graph, &mut nodes, &mut edges, &mut keys, |output| {
+        outputs.push(*output);
+        outputs.len() - 1
+    });
+    let flat = Flat::<CaseInsensitive<&str>, Option<u32>>::new(&nodes, &edges, &keys, &outputs);
+
+    assert_eq!(flat.min_key_len(), 9);
+    assert_eq!(flat.max_key_len(), 10);
+    assert_eq!(outputs.len(), graph.nodes().len());
+    assert_eq!(
+        flat.keys().len(),
+        graph
+            .nodes()
+            .iter()
+            .flat_map(|node| node.inputs())
+            .map(|(key, _)| key.len())
+            .sum::<usize>()
+    );
+    for key in &[
+        "Text/Plain",
+        "text/html",
+        "IMAGE/PNG",
+        "image/jpeg",
+        "image/gif",
+        "text",
+        "",
+        "image/jpegs",
+    ] {
+        assert_eq!(
+            flat.process(CaseInsensitive(key)),
+            graph.process(CaseInsensitive(key)),
+            "{}",
+            key
+        );
+    }
+    assert_eq!(flat.process(CaseInsensitive("IMAGE/PNG")), &Some(3));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn graph_file() {
+    use intern_str::serialize::GraphFile;
+    use std::fs;
+    use std::io::ErrorKind;
+
+    let mut builder = Builder::<u32, Utf8Graph>::new();
+    builder.add("text/plain".to_string(), 1).unwrap();
+    builder.add("text/html".to_string(), 2).unwrap();
+
+    let mut buffer = vec![];
+    let graph = builder.build(&mut buffer);
+
+    let mut bytes = vec![];
+    serialize::write(&graph, &mut bytes, |output, out| {
+        if let Some(output) = output {
+            out.push(*output as u8);
+        }
+    });
+
+    let dir = std::env::temp_dir().join(format!("intern-str-file-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("graph.bin");
+    fs::write(&path, &bytes).unwrap();
+
+    let file = GraphFile::open(&path).unwrap();
+    assert_eq!(file.bytes(), &bytes[..]);
+
+    let mut nodes = vec![];
+    let loaded = file
+        .graph::<&str, Option<u32>>(&mut nodes, |bytes| match bytes.len() {
+            0 => Some(None),
+            1 => Some(Some(u32::from(bytes[0]))),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(loaded.fingerprint(), graph.fingerprint());
+    assert_eq!(*loaded.process("text/html"), Some(2));
+    assert_eq!(*loaded.process("text/css"), None);
+
+    // Files that aren't serialized graphs are rejected when they're opened.
+    fs::write(&path, b"PNG\0").unwrap();
+    assert_eq!(
+        GraphFile::open(&path).unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+    assert_eq!(
+        GraphFile::open(dir.join("missing.bin")).unwrap_err().kind(),
+        ErrorKind::NotFound
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn mapped_graph_file() {
+    use intern_str::serialize::MappedGraphFile;
+    use std::fs;
+    use std::io::ErrorKind;
+
+    let mut builder = Builder::<u32, IgnoreCase<Utf8Graph>>::new();
+    builder.add("text/plain".to_string(), 1).unwrap();
+    builder.add("text/html".to_string(), 2).unwrap();
+
+    let mut buffer = vec![];
+    let graph = builder.build(&mut buffer);
+
+    let mut bytes = vec![];
+    serialize::write(&graph, &mut bytes, |output, out| {
+        if let Some(output) = output {
+            out.push(*output as u8);
+        }
+    });
+
+    let dir = std::env::temp_dir().join(format!("intern-str-mmap-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("graph.bin");
+    fs::write(&path, &bytes).unwrap();
+
+    // Nothing writes to the files while they are mapped.
+    let file = unsafe { MappedGraphFile::map(&path) }.unwrap();
+    assert_eq!(file.bytes(), &bytes[..]);
+
+    {
+        let mut nodes = vec![];
+        let loaded = file
+            .graph::<CaseInsensitive<&str>, Option<u32>>(&mut nodes, |bytes| match bytes.len() {
+                0 => Some(None),
+                1 => Some(Some(u32::from(bytes[0]))),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(loaded.fingerprint(), graph.fingerprint());
+        assert_eq!(*loaded.process(CaseInsensitive("TEXT/HTML")), Some(2));
+        assert_eq!(*loaded.process(CaseInsensitive("text/css")), None);
+    }
+
+    // Replacing the file doesn't change the map of the old one.
+    let new_path = dir.join("graph.bin.new");
+    fs::write(&new_path, b"ISTR").unwrap();
+    fs::rename(&new_path, &path).unwrap();
+    assert_eq!(file.bytes(), &bytes[..]);
+    drop(file);
+
+    // Files that aren't serialized graphs are rejected when they're mapped.
+    assert_eq!(
+        unsafe { MappedGraphFile::map(&path) }.unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+    fs::write(&path, b"").unwrap();
+    assert_eq!(
+        unsafe { MappedGraphFile::map(&path) }.unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+    assert_eq!(
+        unsafe { MappedGraphFile::map(dir.join("missing.bin")) }
+            .unwrap_err()
+            .kind(),
+        ErrorKind::NotFound
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
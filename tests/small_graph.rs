@@ -3,7 +3,7 @@
 use intern_str::builder::{Builder, Utf8Graph};
 use intern_str::{Graph, Node};
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 enum Color {
     Red,
     Gray,
@@ -83,6 +83,8 @@ fn builder() {
     let graph = builder.build(&mut buffer);
 
     // Test the graph.
+    assert_eq!(graph.min_key_len(), 3);
+    assert_eq!(graph.max_key_len(), 5);
     assert_eq!(*graph.process("Red"), Some(Color::Red));
     assert_eq!(*graph.process("Gray"), Some(Color::Gray));
     assert_eq!(*graph.process("Green"), Some(Color::Green));
@@ -93,4 +95,2412 @@ fn builder() {
     assert_eq!(*graph.process("Re"), None);
     assert_eq!(*graph.process(""), None);
     assert_eq!(*graph.process("Indigo"), None);
+    assert_eq!(*graph.process("Reddish"), None);
+}
+
+#[test]
+fn retain() {
+    extern crate alloc;
+
+    let mut builder = Builder::<Color, Utf8Graph>::new();
+    builder.add("Red".to_string(), Color::Red).unwrap();
+    builder.add("Gray".to_string(), Color::Gray).unwrap();
+    builder.add("Green".to_string(), Color::Green).unwrap();
+    builder.add("Black".to_string(), Color::Black).unwrap();
+    builder.add("Blue".to_string(), Color::Blue).unwrap();
+    builder.add("Beige".to_string(), Color::Beige).unwrap();
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+
+    // Only keep the colors that start with "B".
+    let mut retained = alloc::vec![];
+    let filtered = graph.retain(&mut retained, |color| {
+        matches!(color, Some(Color::Black | Color::Blue | Color::Beige))
+    });
+
+    assert!(filtered.nodes().len() < graph.nodes().len());
+    assert_eq!(*filtered.process("Black"), Some(Color::Black));
+    assert_eq!(*filtered.process("Blue"), Some(Color::Blue));
+    assert_eq!(*filtered.process("Beige"), Some(Color::Beige));
+    assert_eq!(*filtered.process("Red"), None);
+    assert_eq!(*filtered.process("Gray"), None);
+    assert_eq!(*filtered.process("Green"), None);
+    assert_eq!(*filtered.process("Bl"), None);
+    assert_eq!(*filtered.process(""), None);
+
+    // The manually-constructed graph works too.
+    let filtered = GRAPH.retain(&mut retained, |color| *color == Some(Color::Red));
+    assert_eq!(*filtered.process("Red"), Some(Color::Red));
+    assert_eq!(*filtered.process("Gray"), None);
+    assert_eq!(*filtered.process("Beige"), None);
+}
+
+#[test]
+fn outputs() {
+    extern crate alloc;
+
+    let outputs = GRAPH.outputs().copied().collect::<alloc::vec::Vec<_>>();
+    assert_eq!(outputs.len(), 6);
+    for color in &[
+        Color::Red,
+        Color::Gray,
+        Color::Green,
+        Color::Black,
+        Color::Blue,
+        Color::Beige,
+    ] {
+        assert!(outputs.contains(&Some(*color)));
+    }
+
+    // Map several keys to the same output.
+    let mut builder = Builder::<u8, Utf8Graph>::new();
+    builder.add("one".to_string(), 1).unwrap();
+    builder.add("uno".to_string(), 1).unwrap();
+    builder.add("two".to_string(), 2).unwrap();
+    builder.add("dos".to_string(), 2).unwrap();
+    builder.add("three".to_string(), 3).unwrap();
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+
+    assert_eq!(graph.outputs().count(), 5);
+    assert_eq!(
+        graph.distinct_outputs(),
+        alloc::vec![&Some(1), &Some(2), &Some(3)]
+    );
+}
+
+#[test]
+fn runtime_nodes() {
+    extern crate alloc;
+    use alloc::vec;
+
+    // Recreate part of the manually-constructed graph at runtime.
+    let nodes = vec![
+        Node::from_vec(vec![], None, 0, core::usize::MAX),
+        Node::from_vec(vec![("R", 3), ("G", 2)], None, 0, 1),
+        Node::from_vec(vec![("ray", 4)], None, 0, 3),
+        Node::from_vec(vec![("ed", 5)], None, 0, 2),
+        Node::from_vec(vec![], Some(Color::Gray), 0, 1),
+        Node::from_vec(vec![], Some(Color::Red), 0, 1),
+    ];
+    let graph = Graph::new(&nodes, 1);
+
+    assert_eq!(nodes[1].inputs(), &[("G", 2), ("R", 3)]);
+    assert_eq!(*graph.process("Red"), Some(Color::Red));
+    assert_eq!(*graph.process("Gray"), Some(Color::Gray));
+    assert_eq!(*graph.process("Green"), None);
+    assert_eq!(*graph.process("Gr"), None);
+}
+
+#[test]
+fn fingerprint() {
+    extern crate alloc;
+
+    let colors = [
+        ("Red", Color::Red),
+        ("Gray", Color::Gray),
+        ("Green", Color::Green),
+        ("Black", Color::Black),
+        ("Blue", Color::Blue),
+        ("Beige", Color::Beige),
+    ];
+
+    // Insert the keys forwards and backwards.
+    let mut forwards = Builder::<Color, Utf8Graph>::new();
+    for (key, color) in colors.iter() {
+        forwards.add(key.to_string(), *color).unwrap();
+    }
+
+    let mut backwards = Builder::<Color, Utf8Graph>::new();
+    for (key, color) in colors.iter().rev() {
+        backwards.add(key.to_string(), *color).unwrap();
+    }
+
+    let mut forwards_buffer = alloc::vec![];
+    let forwards = forwards.build(&mut forwards_buffer);
+    let mut backwards_buffer = alloc::vec![];
+    let backwards = backwards.build(&mut backwards_buffer);
+
+    assert_eq!(forwards.fingerprint(), backwards.fingerprint());
+    assert_eq!(forwards.fingerprint(), GRAPH.fingerprint());
+
+    // Changing the outputs changes the fingerprint.
+    let mut retained = alloc::vec![];
+    let filtered = forwards.retain(&mut retained, |color| *color != Some(Color::Red));
+    assert_ne!(forwards.fingerprint(), filtered.fingerprint());
+
+    // Wide integers are hashed as little-endian bytes, like the narrower ones.
+    let wide = [Node::<&str, _>::new(&[], 1u128 << 64 | 2, 0, usize::MAX)];
+    let halves = [Node::<&str, _>::new(&[], (2u64, 1u64), 0, usize::MAX)];
+    assert_eq!(
+        Graph::new(&wide, 0).fingerprint(),
+        Graph::new(&halves, 0).fingerprint()
+    );
+}
+
+#[test]
+fn fingerprint_shared() {
+    extern crate alloc;
+
+    // Every key over `{a, b}` of the same length shares the same subtrees.
+    let mut keys = alloc::vec![String::new()];
+    for _ in 0..10 {
+        keys = keys
+            .iter()
+            .flat_map(|key| alloc::vec![format!("{}a", key), format!("{}b", key)])
+            .collect();
+    }
+
+    let mut plain = Builder::<u32, Utf8Graph>::new();
+    let mut shared = Builder::<u32, Utf8Graph>::new();
+    shared.share_subtrees();
+    for key in &keys {
+        plain.add(key.clone(), 1).unwrap();
+        shared.add(key.clone(), 1).unwrap();
+    }
+
+    let mut plain_buffer = alloc::vec![];
+    let plain = plain.build(&mut plain_buffer);
+    let mut shared_buffer = alloc::vec![];
+    let shared = shared.build(&mut shared_buffer);
+
+    assert!(shared.nodes().len() < plain.nodes().len());
+    assert_eq!(shared.fingerprint(), plain.fingerprint());
+
+    // A deep chain where both transitions of every node lead to the same node has
+    // 2^n paths, and is too deep to walk recursively.
+    let depth = 100_000;
+    let inputs = (0..depth)
+        .map(|i| [("a", i + 1), ("b", i + 1)])
+        .collect::<alloc::vec::Vec<_>>();
+    let mut nodes = inputs
+        .iter()
+        .map(|inputs| Node::new(&inputs[..], None, depth + 1, 1))
+        .collect::<alloc::vec::Vec<_>>();
+    nodes.push(Node::new(&[], Some(1), depth + 1, usize::MAX));
+    nodes.push(Node::new(&[], None, depth + 1, usize::MAX));
+
+    let chain = Graph::new(&nodes, 0);
+    assert_eq!(*chain.process(&"ab".repeat(depth / 2)), Some(1));
+    assert_ne!(chain.fingerprint(), Graph::new(&nodes, 1).fingerprint());
+}
+
+#[test]
+fn separator_insensitive() {
+    extern crate alloc;
+    use intern_str::builder::IgnoreSeparators;
+    use intern_str::SeparatorInsensitive;
+
+    let mut builder = Builder::<u8, IgnoreSeparators<Utf8Graph>>::new();
+    builder.add("Content-Type".to_string(), 1).unwrap();
+    builder.add("content_length".to_string(), 2).unwrap();
+    builder.add("Accept".to_string(), 3).unwrap();
+    assert!(builder.add("CONTENT TYPE".to_string(), 4).is_err());
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+
+    for key in &[
+        "content-type",
+        "content_type",
+        "CONTENT TYPE",
+        "Content-type",
+    ] {
+        assert_eq!(*graph.process(SeparatorInsensitive::new(key)), Some(1));
+    }
+    for key in &["content-length", "Content_Length", "CONTENT LENGTH"] {
+        assert_eq!(*graph.process(SeparatorInsensitive::new(key)), Some(2));
+    }
+    assert_eq!(*graph.process(SeparatorInsensitive::new("accept")), Some(3));
+    assert_eq!(
+        *graph.process(SeparatorInsensitive::new("contenttype")),
+        None
+    );
+    assert_eq!(
+        *graph.process(SeparatorInsensitive::new("content.type")),
+        None
+    );
+}
+
+#[test]
+fn byte_fold() {
+    extern crate alloc;
+    use intern_str::builder::FoldBytes;
+    use intern_str::{ByteFold, Folded};
+
+    /// Treat forward and back slashes as equal, and ignore case.
+    #[derive(Debug)]
+    struct Paths;
+
+    impl ByteFold for Paths {
+        fn fold(byte: u8) -> u8 {
+            match byte {
+                b'\\' => b'/',
+                byte => byte.to_ascii_lowercase(),
+            }
+        }
+    }
+
+    let mut builder = Builder::<u8, FoldBytes<Utf8Graph, Paths>>::new();
+    builder.add("usr/Bin".to_string(), 1).unwrap();
+    builder.add("usr\\lib".to_string(), 2).unwrap();
+    assert!(builder.add("USR/LIB".to_string(), 3).is_err());
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+
+    assert_eq!(*graph.process(Folded::<_, Paths>::new("usr/bin")), Some(1));
+    assert_eq!(*graph.process(Folded::<_, Paths>::new("USR\\BIN")), Some(1));
+    assert_eq!(*graph.process(Folded::<_, Paths>::new("usr/lib")), Some(2));
+    assert_eq!(*graph.process(Folded::<_, Paths>::new("usr-lib")), None);
+}
+
+#[test]
+fn map() {
+    extern crate alloc;
+
+    let mut builder = Builder::<Color, Utf8Graph>::new();
+    builder.add("Red".to_string(), Color::Red).unwrap();
+    builder.add("Gray".to_string(), Color::Gray).unwrap();
+    builder.add("Green".to_string(), Color::Green).unwrap();
+
+    let mut nodes = alloc::vec![];
+    let mut outputs = alloc::vec![];
+    let map = builder.build_map(&mut nodes, &mut outputs);
+
+    assert_eq!(map.outputs().len(), 3);
+    assert_eq!(map.get("Red"), Some(&Color::Red));
+    assert_eq!(map.get("Gray"), Some(&Color::Gray));
+    assert_eq!(map.get("Green"), Some(&Color::Green));
+    assert_eq!(map.get("Gr"), None);
+    assert_eq!(map.get("Blue"), None);
+    assert_eq!(map.get(""), None);
+}
+
+#[test]
+fn aliases() {
+    extern crate alloc;
+    use intern_str::builder::IgnoreCase;
+    use intern_str::CaseInsensitive;
+
+    let mut builder = Builder::<&'static str, IgnoreCase<Utf8Graph>>::new();
+    builder
+        .add_with_aliases(
+            "UTF-8".to_string(),
+            alloc::vec!["utf8".to_string(), "unicode-1-1-utf-8".to_string()],
+            "UTF-8 encoding",
+        )
+        .unwrap();
+    builder.add("ascii".to_string(), "ASCII encoding").unwrap();
+
+    // Adding a duplicate alias doesn't add any of the keys.
+    assert!(builder
+        .add_with_aliases(
+            "latin1".to_string(),
+            alloc::vec!["UTF8".to_string()],
+            "Latin-1 encoding",
+        )
+        .is_err());
+    assert_eq!(builder.canonical("latin1"), None);
+
+    assert_eq!(builder.canonical("Utf8").as_deref(), Some("utf-8"));
+    assert_eq!(builder.canonical("utf-8").as_deref(), Some("utf-8"));
+    assert_eq!(builder.canonical("ASCII").as_deref(), Some("ascii"));
+    assert_eq!(builder.canonical("ebcdic"), None);
+
+    let mut nodes = alloc::vec![];
+    let mut outputs = alloc::vec![];
+    let map = builder.build_map(&mut nodes, &mut outputs);
+
+    // The value is only stored once.
+    assert_eq!(map.outputs().len(), 2);
+    for key in &["utf-8", "UTF8", "Unicode-1-1-UTF-8"] {
+        assert_eq!(map.get(CaseInsensitive(key)), Some(&"UTF-8 encoding"));
+    }
+    assert_eq!(map.get(CaseInsensitive("ASCII")), Some(&"ASCII encoding"));
+    assert_eq!(map.get(CaseInsensitive("latin1")), None);
+}
+
+#[test]
+fn static_outputs() {
+    extern crate alloc;
+
+    /// A large payload that we don't want to copy into every node.
+    #[derive(Debug, PartialEq)]
+    struct Payload {
+        name: &'static str,
+        table: [u32; 64],
+    }
+
+    static RED: Payload = Payload {
+        name: "red",
+        table: [1; 64],
+    };
+    static GRAY: Payload = Payload {
+        name: "gray",
+        table: [2; 64],
+    };
+
+    const NODES: &[Node<'static, &'static str, Option<&'static Payload>>] = &[
+        Node::new(&[], None, 0, core::usize::MAX),
+        Node::new(&[("G", 3), ("R", 2)], None, 0, 1),
+        Node::new(&[("ed", 4)], None, 0, 2),
+        Node::new(&[("ray", 5)], None, 0, 3),
+        Node::new(&[], Some(&RED), 0, 1),
+        Node::new(&[], Some(&GRAY), 0, 1),
+    ];
+    const GRAPH: Graph<'static, 'static, &'static str, Option<&'static Payload>> =
+        Graph::new(NODES, 1);
+
+    assert_eq!(GRAPH.get("Red").map(|p| p.name), Some("red"));
+    assert_eq!(GRAPH.get("Gray").map(|p| p.table[0]), Some(2));
+    assert_eq!(GRAPH.get("Green"), None);
+
+    // The builder can reference its values instead of cloning them.
+    let mut builder = Builder::<Payload, Utf8Graph>::new();
+    builder
+        .add(
+            "Red".to_string(),
+            Payload {
+                name: "red",
+                table: [1; 64],
+            },
+        )
+        .unwrap();
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build_ref(&mut buffer);
+    assert_eq!(graph.get("Red"), Some(&RED));
+    assert_eq!(graph.get("Gray"), None);
+}
+
+#[test]
+fn remove() {
+    extern crate alloc;
+
+    let mut builder = Builder::<Color, Utf8Graph>::new();
+    builder.add("Red".to_string(), Color::Red).unwrap();
+    builder.add("Gray".to_string(), Color::Gray).unwrap();
+    builder.add("Green".to_string(), Color::Green).unwrap();
+    builder.add("Black".to_string(), Color::Black).unwrap();
+    builder.add("Blue".to_string(), Color::Blue).unwrap();
+    builder.add("Beige".to_string(), Color::Beige).unwrap();
+
+    assert_eq!(builder.remove("Green"), Some(Color::Green));
+    assert_eq!(builder.remove("Green"), None);
+    assert_eq!(builder.remove("Gr"), None);
+    assert_eq!(builder.remove("Blue"), Some(Color::Blue));
+    assert_eq!(builder.remove("Beige"), Some(Color::Beige));
+
+    // Keys can be added back after they are removed.
+    builder.add("Blue".to_string(), Color::Blue).unwrap();
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+
+    assert_eq!(*graph.process("Red"), Some(Color::Red));
+    assert_eq!(*graph.process("Gray"), Some(Color::Gray));
+    assert_eq!(*graph.process("Black"), Some(Color::Black));
+    assert_eq!(*graph.process("Blue"), Some(Color::Blue));
+    assert_eq!(*graph.process("Green"), None);
+    assert_eq!(*graph.process("Beige"), None);
+    assert_eq!(graph.outputs().count(), 4);
+
+    // Removing a key with aliases removes all of them.
+    let mut builder = Builder::<u8, Utf8Graph>::new();
+    builder
+        .add_with_aliases("utf-8".to_string(), alloc::vec!["utf8".to_string()], 8)
+        .unwrap();
+    builder.add("ascii".to_string(), 7).unwrap();
+    assert_eq!(builder.remove("utf8"), Some(8));
+    assert_eq!(builder.canonical("utf-8"), None);
+
+    let mut nodes = alloc::vec![];
+    let mut outputs = alloc::vec![];
+    let map = builder.build_map(&mut nodes, &mut outputs);
+    assert_eq!(map.outputs(), &[7]);
+    assert_eq!(map.get("ascii"), Some(&7));
+    assert_eq!(map.get("utf-8"), None);
+}
+
+#[test]
+fn duplicate_policy() {
+    extern crate alloc;
+    use alloc::vec::Vec;
+    use intern_str::builder::{AddError, DuplicatePolicy};
+
+    let mut builder = Builder::<u32, Utf8Graph>::new();
+    builder.add("one".to_string(), 1).unwrap();
+    assert!(matches!(
+        builder.add("one".to_string(), 2),
+        Err(AddError::Conflict { value: 2, .. })
+    ));
+
+    assert_eq!(
+        builder.add_if_absent("one".to_string(), 3).unwrap(),
+        Some(3)
+    );
+    assert_eq!(builder.add_if_absent("two".to_string(), 2).unwrap(), None);
+    assert_eq!(
+        builder.add_or_replace("one".to_string(), 4).unwrap(),
+        Some(1)
+    );
+    assert_eq!(
+        builder.add_or_replace("three".to_string(), 3).unwrap(),
+        None
+    );
+
+    builder.set_duplicate_policy(intern_str::builder::DuplicatePolicy::Replace);
+    builder.add("two".to_string(), 5).unwrap();
+    builder.set_duplicate_policy(DuplicatePolicy::KeepFirst);
+    builder.add("three".to_string(), 6).unwrap();
+
+    let mut buffer = Vec::new();
+    let graph = builder.build(&mut buffer);
+    assert_eq!(*graph.process("one"), Some(4));
+    assert_eq!(*graph.process("two"), Some(5));
+    assert_eq!(*graph.process("three"), Some(3));
+
+    // Merge the values for duplicate keys into a list.
+    let mut builder = Builder::<Vec<u32>, Utf8Graph>::new();
+    builder.set_duplicate_policy(DuplicatePolicy::Merge(|old, new| old.extend(new)));
+    builder.add("even".to_string(), alloc::vec![2]).unwrap();
+    builder.add("odd".to_string(), alloc::vec![1]).unwrap();
+    builder.add("even".to_string(), alloc::vec![4, 6]).unwrap();
+
+    let mut buffer = Vec::new();
+    let graph = builder.build(&mut buffer);
+    assert_eq!(*graph.process("even"), Some(alloc::vec![2, 4, 6]));
+    assert_eq!(*graph.process("odd"), Some(alloc::vec![1]));
+}
+
+#[test]
+fn borrowed_keys() {
+    extern crate alloc;
+    use alloc::borrow::Cow;
+
+    const WORDS: &[&str] = &["apple", "banana", "cherry"];
+
+    let mut builder = Builder::<usize, Utf8Graph>::new();
+    for (i, word) in WORDS.iter().enumerate() {
+        builder.add(*word, i).unwrap();
+    }
+    builder.add(Cow::Borrowed("date"), 3).unwrap();
+    builder
+        .add_with_aliases("elderberry", ["elder"].iter().copied(), 4)
+        .unwrap();
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(*graph.process("banana"), Some(1));
+    assert_eq!(*graph.process("date"), Some(3));
+    assert_eq!(*graph.process("elder"), Some(4));
+}
+
+#[test]
+fn byte_keys() {
+    extern crate alloc;
+    use intern_str::builder::{AddError, ByteGraph, IgnoreCase};
+
+    let mut builder = Builder::<&'static str, ByteGraph>::new();
+    builder.add_bytes(b"\x89PNG".to_vec(), "png").unwrap();
+    builder.add_bytes(b"\xff\xd8\xff".to_vec(), "jpeg").unwrap();
+    builder.add_bytes(b"\xff\xfe".to_vec(), "utf-16le").unwrap();
+    builder.add_bytes(b"\xfe\xff".to_vec(), "utf-16be").unwrap();
+    builder.add("GIF8", "gif").unwrap();
+    assert!(matches!(
+        builder.add_bytes(b"\xff\xfe".to_vec(), "bom"),
+        Err(AddError::Conflict { value: "bom", .. })
+    ));
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(*graph.process(&b"\x89PNG"[..]), Some("png"));
+    assert_eq!(*graph.process(&b"\xff\xd8\xff"[..]), Some("jpeg"));
+    assert_eq!(*graph.process(&b"\xff\xfe"[..]), Some("utf-16le"));
+    assert_eq!(*graph.process(&b"\xfe\xff"[..]), Some("utf-16be"));
+    assert_eq!(*graph.process(&b"GIF8"[..]), Some("gif"));
+    assert_eq!(*graph.process(&b"\xff"[..]), None);
+
+    // Graph types for strings reject keys that aren't UTF-8.
+    let mut builder = Builder::<u8, Utf8Graph>::new();
+    assert!(matches!(
+        builder.add_bytes(b"\xff".to_vec(), 1),
+        Err(AddError::Invalid(_, 1))
+    ));
+    builder
+        .add_bytes("caf\u{e9}".as_bytes().to_vec(), 2)
+        .unwrap();
+    builder.add("caf\u{e8}", 3).unwrap();
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(*graph.process("caf\u{e9}"), Some(2));
+    assert_eq!(*graph.process("caf\u{e8}"), Some(3));
+
+    // Wrappers canonicalize binary keys as well.
+    let mut builder = Builder::<u8, IgnoreCase<ByteGraph>>::new();
+    builder.add_bytes(b"ABC\xff".to_vec(), 1).unwrap();
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(
+        *graph.process(intern_str::CaseInsensitive(&b"aBc\xff"[..])),
+        Some(1)
+    );
+}
+
+#[test]
+fn byte_keys_prefixes() {
+    extern crate alloc;
+    use intern_str::builder::ByteGraph;
+
+    // Bytes that look like UTF-8 continuation bytes still split binary keys.
+    let mut builder = Builder::<u8, ByteGraph>::new();
+    builder.add_bytes(alloc::vec![0x41, 0x80], 1).unwrap();
+    builder.add_bytes(alloc::vec![0x41, 0x81], 2).unwrap();
+    builder.add_bytes(alloc::vec![0x41], 3).unwrap();
+    builder.add_bytes(alloc::vec![0xc3, 0xa9, 0x80], 4).unwrap();
+    builder.add_bytes(alloc::vec![0xc3, 0xa9], 5).unwrap();
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(*graph.process(&[0x41, 0x80][..]), Some(1));
+    assert_eq!(*graph.process(&[0x41, 0x81][..]), Some(2));
+    assert_eq!(*graph.process(&[0x41][..]), Some(3));
+    assert_eq!(*graph.process(&[0xc3, 0xa9, 0x80][..]), Some(4));
+    assert_eq!(*graph.process(&[0xc3, 0xa9][..]), Some(5));
+    assert_eq!(*graph.process(&[0x41, 0x82][..]), None);
+    assert_eq!(*graph.process(&[0xc3][..]), None);
+}
+
+#[test]
+fn build_without_clone() {
+    extern crate alloc;
+    use alloc::boxed::Box;
+
+    // Boxed closures can't be cloned.
+    let mut builder = Builder::<Box<dyn Fn(u32) -> u32>, Utf8Graph>::new();
+    assert!(builder.add("double", Box::new(|x: u32| x * 2)).is_ok());
+    assert!(builder.add("square", Box::new(|x: u32| x * x)).is_ok());
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(graph.process("double").as_ref().unwrap()(3), 6);
+    assert_eq!(graph.process("square").as_ref().unwrap()(3), 9);
+    assert!(graph.process("cube").is_none());
+
+    // The builder is empty afterwards, but can be filled again.
+    assert!(builder.is_empty());
+    assert!(!builder.contains_key("double"));
+    assert!(builder.add("cube", Box::new(|x: u32| x * x * x)).is_ok());
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(graph.process("cube").as_ref().unwrap()(3), 27);
+    assert!(graph.process("double").is_none());
+}
+
+#[test]
+fn build_cloned() {
+    extern crate alloc;
+
+    let mut builder = Builder::<u32, Utf8Graph>::new();
+    builder.add("one", 1).unwrap();
+    builder.add("two", 2).unwrap();
+
+    for _ in 0..2 {
+        let mut buffer = alloc::vec![];
+        let graph = builder.build_cloned(&mut buffer);
+        assert_eq!(*graph.process("one"), Some(1));
+        assert_eq!(*graph.process("two"), Some(2));
+        assert_eq!(*graph.process("three"), None);
+    }
+
+    assert_eq!(builder.len(), 2);
+    assert_eq!(builder.get("two"), Some(&2));
+}
+
+#[test]
+fn build_owned() {
+    use intern_str::builder::{IgnoreCase, OwnedGraph};
+
+    struct Colors {
+        graph: OwnedGraph<Color, IgnoreCase<Utf8Graph>>,
+    }
+
+    fn colors() -> Colors {
+        let mut builder = Builder::new();
+        builder.add("Red", Color::Red).unwrap();
+        builder.add("Green", Color::Green).unwrap();
+        builder.add("Gray", Color::Gray).unwrap();
+        builder.add("Blue", Color::Blue).unwrap();
+
+        Colors {
+            graph: builder.build_owned(),
+        }
+    }
+
+    let colors = colors();
+    let graph = &colors.graph;
+    assert_eq!(
+        *graph.process(intern_str::CaseInsensitive("RED")),
+        Some(Color::Red)
+    );
+    assert_eq!(
+        *graph.process(intern_str::CaseInsensitive("gray")),
+        Some(Color::Gray)
+    );
+    assert_eq!(
+        *graph.process(intern_str::CaseInsensitive("Green")),
+        Some(Color::Green)
+    );
+    assert_eq!(
+        *graph.process(intern_str::CaseInsensitive("blue")),
+        Some(Color::Blue)
+    );
+    assert_eq!(*graph.process(intern_str::CaseInsensitive("Gre")), None);
+    assert_eq!(*graph.process(intern_str::CaseInsensitive("Black")), None);
+}
+
+#[cfg(feature = "unicode-normalization")]
+#[test]
+fn unicode_normalization() {
+    extern crate alloc;
+    use intern_str::builder::{AddError, Nfc, Nfkc};
+
+    let mut builder = Builder::<u8, Nfc<Utf8Graph>>::new();
+    builder.add("caf\u{e9}", 1).unwrap();
+    assert!(matches!(
+        builder.add("cafe\u{301}", 2),
+        Err(AddError::Conflict { value: 2, .. })
+    ));
+    builder.add("na\u{ef}ve", 3).unwrap();
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(*graph.process(&intern_str::nfc("caf\u{e9}")), Some(1));
+    assert_eq!(*graph.process(&intern_str::nfc("cafe\u{301}")), Some(1));
+    assert_eq!(*graph.process(&intern_str::nfc("nai\u{308}ve")), Some(3));
+
+    let mut builder = Builder::<u8, Nfkc<Utf8Graph>>::new();
+    builder.add("\u{fb01}le", 1).unwrap();
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(*graph.process(&intern_str::nfkc("file")), Some(1));
+    assert_eq!(*graph.process(&intern_str::nfkc("\u{fb01}le")), Some(1));
+}
+
+#[test]
+fn char_graph() {
+    extern crate alloc;
+    use intern_str::builder::CharGraph;
+    use intern_str::{Chars, Segmentable};
+
+    let mut builder = Builder::<u8, CharGraph>::new();
+    builder.add("日本", 1).unwrap();
+    builder.add("日曜日", 2).unwrap();
+    builder.add("über", 3).unwrap();
+    builder.add("uber", 4).unwrap();
+    builder.add("ü", 5).unwrap();
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+
+    // Every transition matches exactly one character.
+    for node in graph.nodes() {
+        for (key, _) in node.inputs() {
+            assert_eq!(key.chars().count(), 1);
+        }
+    }
+
+    assert_eq!(*graph.process(Chars("日本")), Some(1));
+    assert_eq!(*graph.process(Chars("日曜日")), Some(2));
+    assert_eq!(*graph.process(Chars("über")), Some(3));
+    assert_eq!(*graph.process(Chars("uber")), Some(4));
+    assert_eq!(*graph.process(Chars("ü")), Some(5));
+    assert_eq!(*graph.process(Chars("日")), None);
+    assert_eq!(*graph.process(Chars("日曜")), None);
+    assert_eq!(*graph.process(Chars("übe")), None);
+
+    // Splits are counted in characters.
+    assert_eq!(Chars("日曜日").split(1), Some((Chars("日"), Chars("曜日"))));
+    assert_eq!(Chars("日曜日").split(3), Some((Chars("日曜日"), Chars(""))));
+    assert_eq!(Chars("日曜日").split(4), None);
+    assert_eq!(Chars("").split(0), Some((Chars(""), Chars(""))));
+    assert!(Chars("").is_empty() && !Chars("ü").is_empty());
+}
+
+#[test]
+fn non_ascii_keys() {
+    extern crate alloc;
+    use intern_str::builder::Splitting;
+
+    // Siblings start with characters of different widths, so they can't all be split
+    // at the same byte.
+    let keys = [
+        "日本",
+        "日曜日",
+        "über",
+        "uber",
+        "ü",
+        "a",
+        "é",
+        "è",
+        "e",
+        "😀!",
+        "ab",
+    ];
+    let misses = ["日", "日曜", "übe", "u", "😀", "\u{f0}", "", "éa", "b"];
+
+    for &(splitting, max_chunk_len) in &[
+        (Splitting::LongestPrefix, usize::MAX),
+        (Splitting::LongestPrefix, 2),
+        (Splitting::Units, usize::MAX),
+    ] {
+        let mut builder = Builder::<usize, Utf8Graph>::new();
+        builder.set_splitting(splitting);
+        builder.set_max_chunk_len(max_chunk_len);
+        for (i, key) in keys.iter().enumerate() {
+            builder.add(*key, i).unwrap();
+        }
+
+        let mut buffer = alloc::vec![];
+        let graph = builder.build(&mut buffer);
+
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(*graph.process(key), Some(i));
+        }
+        for key in &misses {
+            assert_eq!(*graph.process(key), None);
+        }
+    }
+}
+
+#[test]
+fn str_split_boundary() {
+    use intern_str::Segmentable;
+
+    // Splits inside of a character move forward to the end of it.
+    assert_eq!(Segmentable::split("日本", 0), Some(("", "日本")));
+    assert_eq!(Segmentable::split("日本", 1), Some(("日", "本")));
+    assert_eq!(Segmentable::split("日本", 2), Some(("日", "本")));
+    assert_eq!(Segmentable::split("日本", 3), Some(("日", "本")));
+    assert_eq!(Segmentable::split("日本", 4), Some(("日本", "")));
+    assert_eq!(Segmentable::split("日本", 6), Some(("日本", "")));
+    assert_eq!(Segmentable::split("日本", 7), None);
+    assert_eq!(Segmentable::split("a😀b", 2), Some(("a😀", "b")));
+    assert_eq!(Segmentable::split("éa", 1), Some(("é", "a")));
+}
+
+#[test]
+fn share_subtrees() {
+    extern crate alloc;
+
+    const WORDS: &[&str] = &[
+        "nation", "station", "relation", "kindness", "darkness", "sadness",
+    ];
+
+    let build = |share: bool| {
+        let mut builder = Builder::<bool, Utf8Graph>::new();
+        if share {
+            builder.share_subtrees();
+        }
+
+        for word in WORDS {
+            builder.add(*word, true).unwrap();
+        }
+        builder.add("ration", false).unwrap();
+
+        let mut buffer = alloc::vec![];
+        let graph = builder.build(&mut buffer);
+
+        for word in WORDS {
+            assert_eq!(*graph.process(word), Some(true));
+        }
+        assert_eq!(*graph.process("ration"), Some(false));
+        assert_eq!(*graph.process("ness"), None);
+        assert_eq!(*graph.process("nations"), None);
+
+        graph.nodes().len()
+    };
+
+    assert!(build(true) < build(false));
+}
+
+#[test]
+fn coalesce_chains() {
+    extern crate alloc;
+
+    let mut builder = Builder::<u8, Utf8Graph>::new();
+    builder.add("supercalifragilistic", 1).unwrap();
+    builder.add("superlative", 2).unwrap();
+    builder.add("superb", 3).unwrap();
+    builder.add("expialidocious", 4).unwrap();
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+
+    // A node with a single transition never leads to another node with a single
+    // transition and no output; the two would be merged into a longer chunk.
+    let nodes = graph.nodes();
+    for node in nodes {
+        if let [(_, next)] = node.inputs() {
+            let next = &nodes[*next];
+            assert!(next.output().is_some() || next.inputs().len() != 1);
+        }
+    }
+
+    assert_eq!(*graph.process("supercalifragilistic"), Some(1));
+    assert_eq!(*graph.process("superlative"), Some(2));
+    assert_eq!(*graph.process("superb"), Some(3));
+    assert_eq!(*graph.process("expialidocious"), Some(4));
+    assert_eq!(*graph.process("super"), None);
+    assert_eq!(*graph.process("supercali"), None);
+}
+
+#[test]
+fn dedup_outputs() {
+    extern crate alloc;
+
+    let mut builder = Builder::<Color, Utf8Graph>::new();
+    builder.dedup_outputs();
+    builder.add("red", Color::Red).unwrap();
+    builder.add("crimson", Color::Red).unwrap();
+    builder.add("scarlet", Color::Red).unwrap();
+    builder.add("green", Color::Green).unwrap();
+    builder.add("lime", Color::Green).unwrap();
+    builder.add("blue", Color::Blue).unwrap();
+
+    let mut nodes = alloc::vec![];
+    let mut outputs = alloc::vec![];
+    let map = builder.build_map(&mut nodes, &mut outputs);
+
+    // Outputs are ordered by the keys that first refer to them.
+    assert_eq!(map.outputs(), &[Color::Blue, Color::Red, Color::Green]);
+    assert_eq!(map.get("crimson"), Some(&Color::Red));
+    assert_eq!(map.get("scarlet"), Some(&Color::Red));
+    assert_eq!(map.get("lime"), Some(&Color::Green));
+    assert_eq!(map.get("blue"), Some(&Color::Blue));
+    assert_eq!(map.get("yellow"), None);
+}
+
+#[test]
+fn wildcards() {
+    extern crate alloc;
+    use intern_str::builder::{AddError, IgnoreCase};
+    use intern_str::CaseInsensitive;
+
+    let mut builder = Builder::<&'static str, IgnoreCase<Utf8Graph>>::new();
+    builder.add_wildcard("image/*", "image").unwrap();
+    builder.add("image/png", "png").unwrap();
+    builder.add("image/svg+xml", "svg").unwrap();
+    builder.add_wildcard("image/svg*", "svg-ish").unwrap();
+    builder.add_wildcard("text/*", "text").unwrap();
+    builder.add("text/plain", "plain").unwrap();
+    builder.add("application/json", "json").unwrap();
+
+    assert!(matches!(
+        builder.add_wildcard("text/*", "other"),
+        Err(AddError::Conflict { value: "other", .. })
+    ));
+    assert!(matches!(
+        builder.add_wildcard("text/", "other"),
+        Err(AddError::Invalid(_, "other"))
+    ));
+
+    // Rejected keys are returned as they were given.
+    let err = builder.add_wildcard("text/", "other").unwrap_err();
+    assert_eq!(err.key(), Some("text/"));
+    let mut ascii = Builder::<u8, intern_str::builder::AsciiGraph>::new();
+    let err = ascii.add_wildcard("caf\u{e9}/*", 1).unwrap_err();
+    assert!(matches!(err, AddError::Invalid(_, 1)));
+    assert_eq!(err.key(), Some("caf\u{e9}/*"));
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    let get = |input| *graph.process(CaseInsensitive(input));
+
+    assert_eq!(get("image/png"), Some("png"));
+    assert_eq!(get("IMAGE/PNG"), Some("png"));
+    assert_eq!(get("image/pn"), Some("image"));
+    assert_eq!(get("image/pngx"), Some("image"));
+    assert_eq!(get("image/webp"), Some("image"));
+    assert_eq!(get("image/"), Some("image"));
+    assert_eq!(get("image/svg+xml"), Some("svg"));
+    assert_eq!(get("image/svgz"), Some("svg-ish"));
+    assert_eq!(get("image/svg+xml+more"), Some("svg-ish"));
+    assert_eq!(get("text/plain"), Some("plain"));
+    assert_eq!(get("text/html; charset=utf-8"), Some("text"));
+    assert_eq!(get("application/json"), Some("json"));
+    assert_eq!(get("application/xml"), None);
+    assert_eq!(get("image"), None);
+    assert_eq!(get("video/mp4"), None);
+}
+
+#[test]
+fn build_shared() {
+    extern crate alloc;
+    use intern_str::builder::build_shared;
+
+    let mut builders = [
+        Builder::<Color, Utf8Graph>::new(),
+        Builder::<Color, Utf8Graph>::new(),
+    ];
+
+    // Both graphs end in the same suffixes with the same values.
+    builders[0].add("Red", Color::Red).unwrap();
+    builders[0].add("Green", Color::Green).unwrap();
+    builders[0].add("Blue", Color::Blue).unwrap();
+    builders[1].add("Dark Red", Color::Red).unwrap();
+    builders[1].add("Dark Green", Color::Green).unwrap();
+    builders[1].add("Dark Blue", Color::Blue).unwrap();
+    builders[1].add("Black", Color::Black).unwrap();
+
+    let mut buffer = alloc::vec![];
+    let graphs = build_shared(&mut builders, &mut buffer);
+    let (plain, dark) = (graphs[0], graphs[1]);
+
+    assert!(core::ptr::eq(plain.nodes(), dark.nodes()));
+    assert_ne!(plain.start(), dark.start());
+
+    assert_eq!(*plain.process("Red"), Some(Color::Red));
+    assert_eq!(*plain.process("Green"), Some(Color::Green));
+    assert_eq!(*plain.process("Blue"), Some(Color::Blue));
+    assert_eq!(*plain.process("Black"), None);
+    assert_eq!(*plain.process("Dark Red"), None);
+
+    assert_eq!(*dark.process("Dark Red"), Some(Color::Red));
+    assert_eq!(*dark.process("Dark Green"), Some(Color::Green));
+    assert_eq!(*dark.process("Dark Blue"), Some(Color::Blue));
+    assert_eq!(*dark.process("Black"), Some(Color::Black));
+    assert_eq!(*dark.process("Red"), None);
+}
+
+#[test]
+fn stats() {
+    extern crate alloc;
+
+    let mut builder = Builder::<_, Utf8Graph>::new();
+    builder.add("Red", Color::Red).unwrap();
+    builder.add("Green", Color::Green).unwrap();
+    builder.add("Greenish", Color::Green).unwrap();
+    builder.add("Blue", Color::Blue).unwrap();
+
+    let stats = builder.stats();
+    assert_eq!(stats.keys(), 4);
+    assert!(stats.max_depth() >= 2);
+    assert!(stats.static_size() > 0);
+
+    // The stats match the graph that is actually built.
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(stats.nodes(), graph.nodes().len());
+    assert_eq!(*graph.process("Greenish"), Some(Color::Green));
+}
+
+#[test]
+fn deterministic_numbering() {
+    extern crate alloc;
+    use alloc::format;
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    let keys = [
+        "a", "ab", "abc", "abd", "b", "bcd", "bce", "héllo", "hélium", "hello", "x*", "xy",
+    ];
+
+    let dump = |order: &[usize]| -> (String, String) {
+        let mut builder = Builder::<_, Utf8Graph>::new();
+        for &i in order {
+            if keys[i].ends_with('*') {
+                builder.add_wildcard(keys[i], i as u32 % 3).unwrap();
+            } else {
+                builder.add(keys[i], i as u32 % 3).unwrap();
+            }
+        }
+
+        let mut nodes = alloc::vec![];
+        let mut outputs = alloc::vec![];
+        let map = builder.build_map(&mut nodes, &mut outputs);
+        let map = format!("{:?} {:?}", map.graph().nodes(), map.outputs());
+
+        let mut builder = Builder::<_, Utf8Graph>::new();
+        for &i in order {
+            if !keys[i].ends_with('*') {
+                builder.add(keys[i], i as u32 % 3).unwrap();
+            }
+        }
+        let mut buffer = alloc::vec![];
+        let graph = builder.build(&mut buffer);
+        (format!("{:?}", graph), map)
+    };
+
+    // Try a handful of different insertion orders.
+    let forward = (0..keys.len()).collect::<Vec<_>>();
+    let expected = dump(&forward);
+    let mut order = forward.clone();
+    for step in 1..keys.len() {
+        order.rotate_left(step);
+        order.swap(0, step);
+        assert_eq!(dump(&order), expected);
+        let reversed = order.iter().rev().copied().collect::<Vec<_>>();
+        assert_eq!(dump(&reversed), expected);
+    }
+}
+
+#[test]
+fn many_keys() {
+    extern crate alloc;
+    use alloc::format;
+    use alloc::vec::Vec;
+
+    // Enough keys for each root subtree to be laid out separately with `rayon`.
+    let keys = (0..2000u32)
+        .map(|i| format!("{}-{:x}", (b'a' + (i % 26) as u8) as char, i * 7919))
+        .collect::<Vec<_>>();
+
+    let mut builder = Builder::<_, Utf8Graph>::new();
+    for (i, key) in keys.iter().enumerate() {
+        builder.add(key.clone(), i).unwrap();
+    }
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    for (i, key) in keys.iter().enumerate() {
+        assert_eq!(*graph.process(key), Some(i));
+    }
+    assert_eq!(*graph.process("a-"), None);
+    assert_eq!(*graph.process("z"), None);
+}
+
+#[test]
+fn with_capacity() {
+    extern crate alloc;
+
+    let mut builder = Builder::<_, Utf8Graph>::with_capacity(2, 8);
+    builder.add("Red", Color::Red).unwrap();
+    builder.add("Blue", Color::Blue).unwrap();
+    builder.reserve(1, 5);
+    builder.add("Green", Color::Green).unwrap();
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(*graph.process("Red"), Some(Color::Red));
+    assert_eq!(*graph.process("Green"), Some(Color::Green));
+    assert_eq!(*graph.process("Blue"), Some(Color::Blue));
+    assert!(buffer.capacity() >= 6);
+}
+
+#[test]
+fn from_sorted_iter() {
+    extern crate alloc;
+    use alloc::format;
+    use intern_str::builder::IgnoreCase;
+    use intern_str::CaseInsensitive;
+
+    let words = [
+        "a", "ab", "abc", "abd", "b", "bcd", "bce", "hello", "hélium", "héllo", "x", "xy",
+    ];
+
+    // The graph is the same as one built by adding the keys one by one.
+    let mut builder = Builder::<_, Utf8Graph>::new();
+    for (i, word) in words.iter().enumerate() {
+        builder.add(*word, i).unwrap();
+    }
+    let mut buffer = alloc::vec![];
+    let expected = format!("{:?}", builder.build(&mut buffer));
+
+    let mut builder =
+        Builder::<_, Utf8Graph>::from_sorted_iter(words.iter().copied().zip(0..)).unwrap();
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(format!("{:?}", graph), expected);
+
+    // Keys that are out of order after canonicalization are still added.
+    let mut builder = Builder::<_, IgnoreCase<Utf8Graph>>::from_sorted_iter(alloc::vec![
+        ("Zebra", 1),
+        ("apple", 2),
+        ("Apricot", 3),
+    ])
+    .unwrap();
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(*graph.process(CaseInsensitive("zebra")), Some(1));
+    assert_eq!(*graph.process(CaseInsensitive("apple")), Some(2));
+    assert_eq!(*graph.process(CaseInsensitive("apricot")), Some(3));
+
+    // Duplicates are still caught.
+    match Builder::<_, Utf8Graph>::from_sorted_iter(alloc::vec![("a", 1), ("a", 2)]) {
+        Err(error) => assert_eq!(error.existing(), Some(("a", 0))),
+        _ => panic!("expected a duplicate key error"),
+    }
+}
+
+#[test]
+fn entry() {
+    extern crate alloc;
+    use intern_str::builder::{AddError, KeyEntry};
+
+    // Count the words.
+    let mut builder = Builder::<u32, Utf8Graph>::new();
+    for word in "the cat and the dog and the bird".split(' ') {
+        *builder.entry(word).or_insert(0).unwrap() += 1;
+    }
+
+    match builder.entry("the") {
+        KeyEntry::Occupied(mut entry) => {
+            assert_eq!(*entry.get(), 3);
+            assert_eq!(entry.insert(4), 3);
+        }
+        KeyEntry::Vacant(_) => panic!("expected an occupied entry"),
+    }
+
+    builder.entry("cat").and_modify(|count| *count *= 10);
+    builder.entry("fish").and_modify(|count| *count *= 10);
+    assert!(matches!(builder.entry("fish"), KeyEntry::Vacant(_)));
+    assert!(matches!(
+        builder.entry("").or_insert(1),
+        Err(AddError::Empty(1))
+    ));
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(*graph.process("the"), Some(4));
+    assert_eq!(*graph.process("cat"), Some(10));
+    assert_eq!(*graph.process("and"), Some(2));
+    assert_eq!(*graph.process("bird"), Some(1));
+    assert_eq!(*graph.process("fish"), None);
+}
+
+#[test]
+fn try_add_all() {
+    extern crate alloc;
+    use intern_str::builder::{AddError, AsciiGraph};
+
+    let mut builder = Builder::<u32, AsciiGraph>::new();
+    builder.add("one", 1).unwrap();
+
+    // Every problem is reported, and nothing is added.
+    let errors = builder
+        .try_add_all(alloc::vec![
+            ("two", 2),
+            ("one", 11),
+            ("", 0),
+            ("thrée", 3),
+            ("four", 4),
+            ("two", 22),
+        ])
+        .unwrap_err();
+    assert_eq!(errors.len(), 4);
+    assert_eq!(errors[0].existing(), Some(("one", 0)));
+    assert_eq!(*errors[0].value(), 11);
+    assert!(matches!(errors[1], AddError::Empty(0)));
+    assert!(matches!(errors[2], AddError::Invalid(_, 3)));
+    assert!(matches!(&errors[3], AddError::Duplicate(key, 22) if key == "two"));
+
+    // The builder is left as it was, so a good batch can still be added.
+    builder
+        .try_add_all(alloc::vec![("two", 2), ("three", 3)])
+        .unwrap();
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(*graph.process(b"one"), Some(1));
+    assert_eq!(*graph.process(b"two"), Some(2));
+    assert_eq!(*graph.process(b"three"), Some(3));
+    assert_eq!(*graph.process(b"four"), None);
+}
+
+#[test]
+fn add_error() {
+    extern crate alloc;
+    use alloc::string::ToString;
+    use intern_str::builder::AddError;
+
+    let mut builder = Builder::<u32, Utf8Graph>::new();
+    builder.add("red", 1).unwrap();
+    builder
+        .add_with_aliases("green", alloc::vec!["verde", "vert"], 2)
+        .unwrap();
+
+    // The error says which earlier key the new one collided with.
+    let error = builder.add("vert", 3).unwrap_err();
+    assert_eq!(error.key(), Some("vert"));
+    assert_eq!(*error.value(), 3);
+    assert_eq!(error.existing(), Some(("green", 1)));
+    assert_eq!(
+        error.to_string(),
+        "Cannot add a key that is already in the graph: vert (3), added as green"
+    );
+    assert_eq!(error.into_key_value(), (Some("vert".to_string()), 3));
+
+    let error = builder
+        .add_with_aliases("blue", alloc::vec!["azul", "azul"], 4)
+        .unwrap_err();
+    assert!(matches!(error, AddError::Duplicate(_, 4)));
+    assert_eq!(error.existing(), None);
+    assert_eq!(error.into_value(), 4);
+
+    let error = builder.add("", 5).unwrap_err();
+    assert_eq!(error.key(), None);
+    assert_eq!(error.into_key_value(), (None, 5));
+}
+
+#[test]
+fn normalizer() {
+    extern crate alloc;
+    use alloc::string::{String, ToString};
+    use intern_str::builder::AddError;
+
+    // Trim whitespace, reject control characters and collapse internal spaces.
+    fn normalize(key: &mut String) -> bool {
+        if key.chars().any(char::is_control) {
+            return false;
+        }
+
+        *key = key
+            .split_whitespace()
+            .collect::<alloc::vec::Vec<_>>()
+            .join(" ");
+        true
+    }
+
+    let mut builder = Builder::<u32, Utf8Graph>::new();
+    builder.set_normalizer(normalize);
+    builder.add("  New   York ", 1).unwrap();
+    builder.add("Los Angeles", 2).unwrap();
+
+    assert!(matches!(
+        builder.add("New York", 3),
+        Err(AddError::Conflict { value: 3, .. })
+    ));
+    assert!(matches!(
+        builder.add("Bell\u{7}", 4),
+        Err(AddError::Invalid(_, 4))
+    ));
+    assert!(matches!(
+        builder.add("   ", 5),
+        Err(AddError::Invalid(_, 5))
+    ));
+    assert_eq!(
+        builder.canonical(" Los  Angeles"),
+        Some("Los Angeles".to_string())
+    );
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(*graph.process("New York"), Some(1));
+    assert_eq!(*graph.process("Los Angeles"), Some(2));
+    assert_eq!(*graph.process("  New   York "), None);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn add_lines() {
+    extern crate alloc;
+    use intern_str::builder::{LineOptions, LinesError};
+
+    let words = "# Colors\nred\r\ngreen\n\n  \nbleu\u{301}\nblue\n";
+
+    let mut builder = Builder::<usize, Utf8Graph>::new();
+    let options = LineOptions::new().comment("#").ascii_only(true);
+    let added = builder
+        .add_lines(words.as_bytes(), options, |line, _| line)
+        .unwrap();
+    assert_eq!(added, 3);
+
+    // Errors say which line they came from.
+    match builder.add_lines("purple\nred\n".as_bytes(), options, |line, _| line) {
+        Err(LinesError::Add { line: 2, error }) => assert_eq!(error.key(), Some("red")),
+        _ => panic!("expected an error on the second line"),
+    }
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(*graph.process("red"), Some(2));
+    assert_eq!(*graph.process("green"), Some(3));
+    assert_eq!(*graph.process("blue"), Some(7));
+    assert_eq!(*graph.process("purple"), Some(1));
+    assert_eq!(*graph.process("# Colors"), None);
+}
+
+#[cfg(feature = "load-csv")]
+#[test]
+fn add_csv() {
+    extern crate alloc;
+    use intern_str::builder::{Column, LoadError};
+
+    let csv = "ext,mime,binary\npng,image/png,1\ntxt,text/plain,0\nhtml,text/html,0\n";
+
+    let mut builder = Builder::<bool, Utf8Graph>::new();
+    let added = builder
+        .add_csv(
+            csv.as_bytes(),
+            Column::Name("ext"),
+            Column::Index(2),
+            |value| match value {
+                "0" => Ok(false),
+                "1" => Ok(true),
+                _ => Err("not a boolean"),
+            },
+        )
+        .unwrap();
+    assert_eq!(added, 3);
+
+    // Errors say which record they came from.
+    match builder.add_csv(
+        "ext,binary\ngif,1\njpg,yes\n".as_bytes(),
+        Column::Name("ext"),
+        Column::Name("binary"),
+        |value| value.parse::<u8>().map(|value| value != 0),
+    ) {
+        Err(LoadError::Convert { record: 3, .. }) => {}
+        _ => panic!("expected a conversion error on the third record"),
+    }
+    assert!(matches!(
+        builder.add_csv(
+            "a,b\n".as_bytes(),
+            Column::Name("c"),
+            Column::Index(0),
+            |_| { Ok::<_, ()>(true) }
+        ),
+        Err(LoadError::MissingField { record: 0, .. })
+    ));
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(*graph.process("png"), Some(true));
+    assert_eq!(*graph.process("html"), Some(false));
+    assert_eq!(*graph.process("gif"), Some(true));
+    assert_eq!(*graph.process("jpg"), None);
+}
+
+#[cfg(feature = "load-json")]
+#[test]
+fn add_json() {
+    extern crate alloc;
+    use alloc::string::{String, ToString};
+    use intern_str::builder::LoadError;
+
+    let as_string = |value: &serde_json::Value| {
+        value
+            .as_str()
+            .map(ToString::to_string)
+            .ok_or("not a string")
+    };
+
+    let mut builder = Builder::<String, Utf8Graph>::new();
+    let object = r#"{"png": "image/png", "txt": "text/plain"}"#;
+    assert_eq!(
+        builder
+            .add_json_object(object.as_bytes(), as_string)
+            .unwrap(),
+        2
+    );
+
+    let records = r#"[{"ext": "gif", "mime": "image/gif"}, {"ext": "css", "mime": "text/css"}]"#;
+    assert_eq!(
+        builder
+            .add_json_records(records.as_bytes(), "ext", "mime", as_string)
+            .unwrap(),
+        2
+    );
+
+    assert!(matches!(
+        builder.add_json_records(r#"[{"ext": "js"}]"#.as_bytes(), "ext", "mime", as_string),
+        Err(LoadError::MissingField { record: 1, .. })
+    ));
+    assert!(matches!(
+        builder.add_json_object("[]".as_bytes(), as_string),
+        Err(LoadError::UnexpectedType { record: 0 })
+    ));
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(graph.process("png").as_deref(), Some("image/png"));
+    assert_eq!(graph.process("css").as_deref(), Some("text/css"));
+    assert_eq!(*graph.process("js"), None);
+}
+
+#[test]
+fn from_key_set() {
+    extern crate alloc;
+    use alloc::vec::Vec;
+    use intern_str::builder::KeySet;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Method {
+        Get,
+        Post,
+        Delete,
+    }
+
+    impl KeySet for Method {
+        fn keys() -> Vec<(&'static str, Self)> {
+            alloc::vec![
+                ("GET", Method::Get),
+                ("POST", Method::Post),
+                ("DELETE", Method::Delete),
+            ]
+        }
+    }
+
+    let graph = Builder::<Method, Utf8Graph>::from_key_set()
+        .unwrap()
+        .build_owned();
+    assert_eq!(*graph.process("GET"), Some(Method::Get));
+    assert_eq!(*graph.process("POST"), Some(Method::Post));
+    assert_eq!(*graph.process("DELETE"), Some(Method::Delete));
+    assert_eq!(*graph.process("PUT"), None);
+}
+
+#[cfg(feature = "strum")]
+#[test]
+fn from_strum_enum() {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, strum::EnumIter, strum::IntoStaticStr)]
+    #[strum(serialize_all = "lowercase")]
+    enum Level {
+        Debug,
+        Info,
+        Warn,
+    }
+
+    let graph = Builder::<Level, Utf8Graph>::from_key_set()
+        .unwrap()
+        .build_owned();
+    assert_eq!(*graph.process("debug"), Some(Level::Debug));
+    assert_eq!(*graph.process("info"), Some(Level::Info));
+    assert_eq!(*graph.process("warn"), Some(Level::Warn));
+    assert_eq!(*graph.process("Info"), None);
+}
+
+#[test]
+fn add_pattern() {
+    extern crate alloc;
+    use alloc::string::ToString;
+    use intern_str::builder::AddError;
+
+    let mut builder = Builder::<&str, Utf8Graph>::new();
+    builder.share_subtrees();
+    builder.add_pattern("colo(u)r", "color").unwrap();
+    builder.add_pattern("gr(a|e)y", "gray").unwrap();
+    builder
+        .add_pattern("((dark|light) )blue|navy", "blue")
+        .unwrap();
+    builder.add_pattern(r"\(paren\)", "paren").unwrap();
+
+    assert_eq!(builder.canonical("color"), Some("colour".to_string()));
+    assert_eq!(
+        builder.canonical("light blue"),
+        Some("dark blue".to_string())
+    );
+
+    // Malformed patterns and patterns that overlap other keys are rejected.
+    assert!(matches!(
+        builder.add_pattern("(unclosed", "bad"),
+        Err(AddError::Invalid(_, "bad"))
+    ));
+    assert!(matches!(
+        builder.add_pattern("unopened)", "bad"),
+        Err(AddError::Invalid(_, "bad"))
+    ));
+    assert!(matches!(
+        builder.add_pattern("(a)", "bad"),
+        Err(AddError::Empty("bad"))
+    ));
+    assert!(matches!(
+        builder.add_pattern("grey(ish)", "grey"),
+        Err(AddError::Conflict { value: "grey", .. })
+    ));
+
+    // Patterns that expand into too many keys are rejected before they're expanded.
+    let wide = "(0|1|2|3)".repeat(5);
+    assert!(builder.add_pattern(&wide, "wide").is_ok());
+    assert!(matches!(
+        builder.add_pattern(&alloc::format!("(4|5){}", wide), "wide"),
+        Err(AddError::Invalid(_, "wide"))
+    ));
+    assert!(matches!(
+        builder.add_pattern(&"(x)".repeat(64), "wide"),
+        Err(AddError::Invalid(_, "wide"))
+    ));
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    for key in &["colour", "color"] {
+        assert_eq!(*graph.process(key), Some("color"));
+    }
+    for key in &["gray", "grey"] {
+        assert_eq!(*graph.process(key), Some("gray"));
+    }
+    for key in &["blue", "dark blue", "light blue", "navy"] {
+        assert_eq!(*graph.process(key), Some("blue"));
+    }
+    assert_eq!(*graph.process("(paren)"), Some("paren"));
+    assert_eq!(*graph.process("colouur"), None);
+    assert_eq!(*graph.process("greyish"), None);
+}
+
+#[test]
+fn priorities() {
+    extern crate alloc;
+    use alloc::string::ToString;
+    use intern_str::builder::AddError;
+
+    let mut builder = Builder::<&str, Utf8Graph>::new();
+    builder.add("image/png", "png").unwrap();
+    builder.add("image/svg", "svg").unwrap();
+    builder.add_wildcard("text/*", "text").unwrap();
+    builder.add_wildcard("text/html/*", "html").unwrap();
+
+    // A pattern with a higher priority takes over the keys it overlaps.
+    builder.set_priority(1);
+    builder.add_pattern("image/(png|apng)", "animated").unwrap();
+    builder.add_wildcard("image/*", "image").unwrap();
+    builder.add_wildcard("text/*", "plain").unwrap();
+
+    // Keys with a lower priority are shadowed, and equal ones are ambiguous.
+    builder.set_priority(-1);
+    assert!(matches!(
+        builder.add_or_replace("image/apng", "low"),
+        Ok(Some("low"))
+    ));
+    builder.set_priority(1);
+    assert!(matches!(
+        builder.add("image/png", "same"),
+        Err(AddError::Conflict { value: "same", .. })
+    ));
+    assert_eq!(
+        builder.canonical("image/apng"),
+        Some("image/png".to_string())
+    );
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(*graph.process("image/png"), Some("animated"));
+    assert_eq!(*graph.process("image/apng"), Some("animated"));
+    assert_eq!(*graph.process("image/svg"), Some("image"));
+    assert_eq!(*graph.process("image/gif"), Some("image"));
+    assert_eq!(*graph.process("text/css"), Some("plain"));
+    assert_eq!(*graph.process("text/html/5"), Some("plain"));
+    assert_eq!(*graph.process("video/mp4"), None);
+}
+
+#[test]
+fn multi_map() {
+    extern crate alloc;
+    use alloc::vec;
+    use intern_str::builder::IgnoreCase;
+    use intern_str::CaseInsensitive;
+
+    let mut builder = Builder::<alloc::vec::Vec<&str>, IgnoreCase<Utf8Graph>>::multi_map();
+    builder.push("rust", "systems").unwrap();
+    builder.push("Rust", "language").unwrap();
+    builder
+        .add("python", vec!["scripting", "language"])
+        .unwrap();
+    builder.add("PYTHON", vec!["snake"]).unwrap();
+    builder
+        .add_with_aliases("js", ["javascript"], vec!["web"])
+        .unwrap();
+    builder.push("JavaScript", "language").unwrap();
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(
+        *graph.process(CaseInsensitive("RUST")),
+        Some(vec!["systems", "language"])
+    );
+    assert_eq!(
+        *graph.process(CaseInsensitive("python")),
+        Some(vec!["scripting", "language", "snake"])
+    );
+    assert_eq!(
+        *graph.process(CaseInsensitive("js")),
+        Some(vec!["web", "language"])
+    );
+    assert_eq!(*graph.process(CaseInsensitive("go")), None);
+}
+
+#[test]
+fn splitting() {
+    extern crate alloc;
+    use intern_str::builder::Splitting;
+
+    let keys = ["application/json", "application/xml", "text/plain"];
+    let build = |splitting: Splitting, max_chunk_len: usize| {
+        let mut builder = Builder::<usize, Utf8Graph>::new();
+        builder.set_splitting(splitting);
+        builder.set_max_chunk_len(max_chunk_len);
+        for (i, key) in keys.iter().enumerate() {
+            builder.add(*key, i).unwrap();
+        }
+        builder
+    };
+
+    let mut longest = build(Splitting::LongestPrefix, usize::MAX);
+    let mut limited = build(Splitting::LongestPrefix, 4);
+    let mut units = build(Splitting::Units, usize::MAX);
+    let (mut a, mut b, mut c) = (alloc::vec![], alloc::vec![], alloc::vec![]);
+    let graphs = [
+        longest.build(&mut a),
+        limited.build(&mut b),
+        units.build(&mut c),
+    ];
+
+    // Every graph matches the same keys, with more nodes for shorter chunks.
+    for graph in &graphs {
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(*graph.process(key), Some(i));
+        }
+        assert_eq!(*graph.process("application/"), None);
+    }
+    assert!(graphs[0].nodes().len() < graphs[1].nodes().len());
+    assert!(graphs[1].nodes().len() < graphs[2].nodes().len());
+
+    let longest_chunk = |graph: &Graph<'_, '_, &str, Option<usize>>, limit: usize| {
+        graph
+            .nodes()
+            .iter()
+            .flat_map(|node| node.inputs())
+            .all(|(input, _)| input.len() <= limit)
+    };
+    assert!(!longest_chunk(&graphs[0], 4));
+    assert!(longest_chunk(&graphs[1], 4));
+    assert!(longest_chunk(&graphs[2], 1));
+}
+
+#[test]
+fn deny() {
+    extern crate alloc;
+    use intern_str::builder::IgnoreCase;
+    use intern_str::CaseInsensitive;
+
+    let mut builder = Builder::<&str, IgnoreCase<Utf8Graph>>::new();
+    builder.add_wildcard("image/*", "image").unwrap();
+    builder.add("image/bmp", "bitmap").unwrap();
+    assert!(builder.deny("image/svg+xml"));
+    assert!(builder.deny("IMAGE/BMP"));
+    assert!(builder.deny("text/plain"));
+    assert!(!builder.deny(""));
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(*graph.process(CaseInsensitive("image/png")), Some("image"));
+    assert_eq!(*graph.process(CaseInsensitive("image/svg+xml")), None);
+    assert_eq!(*graph.process(CaseInsensitive("Image/SVG+XML")), None);
+    assert_eq!(*graph.process(CaseInsensitive("image/bmp")), None);
+    assert_eq!(*graph.process(CaseInsensitive("image/svg")), Some("image"));
+    assert_eq!(
+        *graph.process(CaseInsensitive("image/svg+xml+zip")),
+        Some("image")
+    );
+    assert_eq!(*graph.process(CaseInsensitive("text/plain")), None);
+}
+
+#[test]
+fn cache_subtrees() {
+    extern crate alloc;
+
+    let keys = ["apple", "apricot", "banana", "blueberry", "cherry"];
+    let mut builders = [Builder::<usize, Utf8Graph>::new(), Builder::new()];
+    builders[0].cache_subtrees();
+
+    for builder in &mut builders {
+        for (i, key) in keys.iter().enumerate() {
+            builder.add(*key, i).unwrap();
+        }
+
+        {
+            let mut buffer = alloc::vec![];
+            let graph = builder.build_ref(&mut buffer);
+            assert_eq!(*graph.process("banana"), Some(&2));
+        }
+
+        // Only the subtrees under "b" and "c" change.
+        builder.add("blackberry", 5).unwrap();
+        builder.remove("cherry").unwrap();
+        builder.add_or_replace("apple", 6).unwrap();
+    }
+
+    // Copying over the subtree under "a" gives the same graph as laying it out again.
+    let [cached, uncached] = &mut builders;
+    let (mut a, mut b) = (alloc::vec![], alloc::vec![]);
+    let graph = cached.build_ref(&mut a);
+    let expected = uncached.build_ref(&mut b);
+    assert_eq!(graph.nodes(), expected.nodes());
+    assert_eq!(graph.start(), expected.start());
+    assert_eq!(*graph.process("apple"), Some(&6));
+    assert_eq!(*graph.process("blackberry"), Some(&5));
+    assert_eq!(*graph.process("cherry"), None);
+}
+
+#[test]
+fn preserve_case() {
+    extern crate alloc;
+    use alloc::string::String;
+    use intern_str::builder::IgnoreCase;
+    use intern_str::{CaseInsensitive, Graph};
+
+    // Follow the transitions for a key, and put the keys of the transitions together.
+    fn spell(graph: &Graph<'_, '_, CaseInsensitive<&str>, Option<&usize>>, key: &str) -> String {
+        let mut node = &graph.nodes()[graph.start()];
+        let mut input = key;
+        let mut spelled = String::new();
+        while !input.is_empty() {
+            let (chunk, rest) = input.split_at(node.amount());
+            let (key, next) = node
+                .inputs()
+                .iter()
+                .find(|(key, _)| *key == CaseInsensitive(chunk))
+                .unwrap();
+            spelled.push_str(key.0);
+            node = &graph.nodes()[*next];
+            input = rest;
+        }
+        spelled
+    }
+
+    let keys = [
+        "Content-Type",
+        "content-length",
+        "Accept",
+        "X-Request-ID",
+        "ACCEPT-Language",
+    ];
+    let mut builders = [
+        Builder::<usize, IgnoreCase<Utf8Graph>>::new(),
+        Builder::new(),
+    ];
+    builders[0].preserve_case();
+    builders[0].cache_subtrees();
+    for builder in &mut builders {
+        for (i, key) in keys.iter().enumerate() {
+            builder.add(*key, i).unwrap();
+        }
+    }
+
+    // The builder spells the keys the way they were added.
+    assert_eq!(
+        builders[0].cased("x-request-id").as_deref(),
+        Some("X-Request-ID")
+    );
+    assert_eq!(
+        builders[1].cased("x-request-id").as_deref(),
+        Some("x-request-id")
+    );
+    assert_eq!(builders[0].cased("x-request"), None);
+    let cased = builders[0]
+        .iter_cased()
+        .map(|(key, value)| (key, *value))
+        .collect::<alloc::vec::Vec<_>>();
+    assert_eq!(cased[0], (String::from("Accept"), 2));
+    assert_eq!(cased[4], (String::from("X-Request-ID"), 3));
+    assert_eq!(builders[0].keys().nth(4).unwrap(), "x-request-id");
+
+    {
+        let [preserved, folded] = &mut builders;
+        let (mut a, mut b) = (alloc::vec![], alloc::vec![]);
+        let graph = preserved.build_ref(&mut a);
+        let expected = folded.build_ref(&mut b);
+        assert_eq!(spell(&graph, "x-request-id"), "X-Request-ID");
+        assert_eq!(spell(&expected, "x-request-id"), "x-request-id");
+
+        // Shared prefixes take the casing of the first key under them.
+        assert_eq!(spell(&graph, "ACCEPT"), "Accept");
+        assert_eq!(spell(&graph, "accept-language"), "Accept-Language");
+        assert_eq!(spell(&graph, "content-type"), "content-Type");
+
+        // The same inputs match.
+        assert_eq!(graph.nodes().len(), expected.nodes().len());
+        for key in &[
+            "content-type",
+            "ACCEPT",
+            "Accept-Language",
+            "x-request",
+            "nope",
+        ] {
+            assert_eq!(
+                graph.process(CaseInsensitive(key)),
+                expected.process(CaseInsensitive(key))
+            );
+        }
+    }
+
+    // Cached subtrees pick up the casing of new keys, and forget removed ones.
+    let builder = &mut builders[0];
+    builder.add("CONTENT-ENCODING", 5).unwrap();
+    builder.add("x-request-id", 6).unwrap_err();
+    {
+        let mut buffer = alloc::vec![];
+        let graph = builder.build_ref(&mut buffer);
+        assert_eq!(spell(&graph, "content-type"), "CONTENT-Type");
+        assert_eq!(spell(&graph, "x-request-id"), "X-Request-ID");
+    }
+
+    builder.remove("content-encoding").unwrap();
+    {
+        let mut buffer = alloc::vec![];
+        let graph = builder.build_ref(&mut buffer);
+        assert_eq!(spell(&graph, "content-type"), "content-Type");
+    }
+
+    // Batches that are rejected don't leave their casing behind.
+    assert!(builder
+        .try_add_all(alloc::vec![("Cache-Control", 7), ("", 8)])
+        .is_err());
+    builder.add("CACHE-CONTROL", 9).unwrap();
+    let mut buffer = alloc::vec![];
+    let graph = builder.build_ref(&mut buffer);
+    assert_eq!(spell(&graph, "cache-control"), "CACHE-CONTROL");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn external_builder() {
+    extern crate alloc;
+    use alloc::string::ToString;
+    use intern_str::builder::{AddError, ExternalBuilder, ExternalError};
+    use std::io::Cursor;
+
+    let keys = [
+        "image/png",
+        "application/json",
+        "image/jpeg",
+        "text/plain",
+        "application/javascript",
+        "image/jp2",
+        "text/plains",
+        "font/woff2",
+    ];
+
+    // A tiny memory limit spills every few keys to its own run.
+    let mut builder = ExternalBuilder::<Utf8Graph>::new(std::env::temp_dir(), 64, []);
+    for (i, key) in keys.iter().enumerate() {
+        builder.add(*key, [i as u8]).unwrap();
+    }
+
+    let mut out = Cursor::new(alloc::vec![0xFF]);
+    out.set_position(1);
+    let len = builder.finish(&mut out).unwrap();
+
+    let bytes = out.into_inner();
+    let mut nodes = alloc::vec![];
+    let graph = intern_str::serialize::read::<&str, Option<u8>>(&bytes[1..], &mut nodes, |b| {
+        Some(b.first().cloned())
+    })
+    .unwrap();
+    assert_eq!(graph.nodes().len(), len);
+    for (i, key) in keys.iter().enumerate() {
+        assert_eq!(*graph.process(key), Some(i as u8));
+    }
+    for key in &[
+        "image/",
+        "image/jp",
+        "text/plai",
+        "font/woff",
+        "video/mp4",
+        "",
+    ] {
+        assert_eq!(*graph.process(key), None);
+    }
+    assert_eq!(graph.min_key_len(), "image/jp2".len());
+    assert_eq!(graph.max_key_len(), "application/javascript".len());
+
+    // Keys with multi-byte characters are never split inside of a character.
+    let keys = ["日本", "über", "uber", "é", "e", "😀!"];
+    let mut builder = ExternalBuilder::<Utf8Graph>::new(std::env::temp_dir(), 16, []);
+    for (i, key) in keys.iter().enumerate() {
+        builder.add(*key, [i as u8]).unwrap();
+    }
+    let mut out = Cursor::new(alloc::vec![]);
+    builder.finish(&mut out).unwrap();
+
+    let bytes = out.into_inner();
+    let mut nodes = alloc::vec![];
+    let graph = intern_str::serialize::read::<&str, Option<u8>>(&bytes, &mut nodes, |b| {
+        Some(b.first().cloned())
+    })
+    .unwrap();
+    for (i, key) in keys.iter().enumerate() {
+        assert_eq!(*graph.process(key), Some(i as u8));
+    }
+    for key in &["日", "übe", "😀"] {
+        assert_eq!(*graph.process(key), None);
+    }
+
+    // Duplicates are found when the runs are merged.
+    let mut builder = ExternalBuilder::<Utf8Graph>::new(std::env::temp_dir(), 0, []);
+    builder.add("a", [1]).unwrap();
+    builder.add("b", [2]).unwrap();
+    builder.add("a", [3]).unwrap();
+    match builder.finish(&mut Cursor::new(alloc::vec![])) {
+        Err(ExternalError::Add(AddError::Duplicate(key, value))) => {
+            assert_eq!(key, "a".to_string());
+            assert_eq!(value, alloc::vec![3]);
+        }
+        result => panic!("expected a duplicate, got {:?}", result),
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn deep_keys() {
+    extern crate alloc;
+    use alloc::string::String;
+    use intern_str::builder::Splitting;
+
+    let deep = || {
+        // Splitting a long key into single characters makes a trie as deep as the key.
+        let long = "a/".repeat(20_000);
+        let mut builder = Builder::<usize, Utf8Graph>::new();
+        builder.set_splitting(Splitting::Units);
+        builder.add(long.clone(), 0).unwrap();
+        builder.add(String::from("a/b"), 1).unwrap();
+        builder.add(long.clone() + "c", 2).unwrap();
+        assert_eq!(builder.stats().max_depth(), long.len() + 1);
+
+        let mut nodes = alloc::vec![];
+        let graph = builder.build(&mut nodes);
+        assert_eq!(*graph.process(&long), Some(0));
+        assert_eq!(*graph.process("a/b"), Some(1));
+        assert_eq!(*graph.process(&(long.clone() + "c")), Some(2));
+        assert_eq!(*graph.process(&long[1..]), None);
+        assert_eq!(graph.max_key_len(), long.len() + 1);
+
+        // Every prefix of a key is a key, so removing one walks down a deep path.
+        let keys = (1..=5_000).map(|len| &long[..len]).collect::<Vec<_>>();
+        let mut builder = Builder::<usize, Utf8Graph>::new();
+        for (i, key) in keys.iter().enumerate() {
+            builder.add(String::from(*key), i).unwrap();
+        }
+        assert_eq!(builder.remove(keys[4_999]), Some(4_999));
+        assert_eq!(builder.remove(keys[4_999]), None);
+        assert_eq!(builder.remove(&long[..6_000]), None);
+
+        let mut nodes = alloc::vec![];
+        let graph = builder.build(&mut nodes);
+        for (i, key) in keys[..4_999].iter().enumerate() {
+            assert_eq!(*graph.process(key), Some(i));
+        }
+        assert_eq!(*graph.process(keys[4_999]), None);
+    };
+
+    // Nothing recurses through the trie, so this fits on a small stack.
+    std::thread::Builder::new()
+        .stack_size(256 * 1024)
+        .spawn(deep)
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn fixed_builder() {
+    extern crate alloc;
+    use intern_str::{FixedBuilder, FixedError};
+
+    let mut entries = [
+        ("image/png", 1),
+        ("image/jpeg", 2),
+        ("text/plain", 3),
+        ("text/plains", 4),
+        ("日本", 5),
+        ("é", 6),
+        ("e", 7),
+    ];
+    const EMPTY: Node<'static, &str, u8> = Node::new(&[], 0, 0, 0);
+    let mut nodes = [EMPTY; 64];
+    let mut transitions = [("", 0); 64];
+
+    let graph = FixedBuilder::new(&mut nodes, &mut transitions)
+        .build(&mut entries, 0)
+        .unwrap();
+    for &(key, value) in &entries {
+        assert_eq!(*graph.process(key), value);
+    }
+    for key in &["image/", "text/plai", "text/plainss", "日", "", "x"] {
+        assert_eq!(*graph.process(key), 0);
+    }
+    assert_eq!(graph.min_key_len(), "e".len());
+    assert_eq!(graph.max_key_len(), "text/plains".len());
+
+    // Errors are reported rather than writing past the buffers.
+    let build = |entries: &mut [(&'static str, u8)], nodes: usize, transitions: usize| {
+        let mut node_buffer = alloc::vec![Node::new(&[], 0, 0, 0); nodes];
+        let mut transition_buffer = alloc::vec![("", 0); transitions];
+        FixedBuilder::new(&mut node_buffer, &mut transition_buffer)
+            .build(entries, 0)
+            .map(|graph| graph.nodes().len())
+    };
+    assert_eq!(build(&mut [("ab", 1), ("ac", 2)], 5, 3), Ok(5));
+    assert_eq!(
+        build(&mut [("ab", 1), ("ac", 2)], 4, 3),
+        Err(FixedError::TooManyNodes)
+    );
+    assert_eq!(
+        build(&mut [("ab", 1), ("ac", 2)], 5, 2),
+        Err(FixedError::TooManyTransitions)
+    );
+    assert_eq!(
+        build(&mut [("ab", 1), ("ab", 2)], 5, 3),
+        Err(FixedError::DuplicateKey)
+    );
+}
+
+#[cfg(feature = "arena")]
+#[test]
+fn arena_builder() {
+    extern crate alloc;
+    use bumpalo::Bump;
+    use intern_str::builder::{AddError, ArenaBuilder, IgnoreCase};
+    use intern_str::CaseInsensitive;
+
+    let keys = [
+        "image/png",
+        "application/json",
+        "image/jpeg",
+        "text/plain",
+        "application/javascript",
+        "image/jp2",
+        "text/plains",
+        "日本",
+        "über",
+        "uber",
+    ];
+
+    let arena = Bump::new();
+    let mut builder = ArenaBuilder::<usize, IgnoreCase<Utf8Graph>>::new(&arena);
+    for (i, key) in keys.iter().enumerate() {
+        builder.add(key, i).unwrap();
+    }
+    assert!(matches!(builder.add("", 0), Err(AddError::Empty(0))));
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer).unwrap();
+    for (i, key) in keys.iter().enumerate() {
+        assert_eq!(*graph.process(CaseInsensitive(key)), Some(i));
+    }
+    assert_eq!(*graph.process(CaseInsensitive("IMAGE/PNG")), Some(0));
+    for key in &[
+        "image/",
+        "image/jp",
+        "text/plai",
+        "日",
+        "übe",
+        "video/mp4",
+        "",
+    ] {
+        assert_eq!(*graph.process(CaseInsensitive(key)), None);
+    }
+    assert_eq!(graph.min_key_len(), "uber".len());
+    assert_eq!(graph.max_key_len(), "application/javascript".len());
+
+    // Duplicates are found when the graph is built.
+    let mut builder = ArenaBuilder::<u8, Utf8Graph>::new(&arena);
+    builder.add("a", 1).unwrap();
+    builder.add("b", 2).unwrap();
+    builder.add("a", 3).unwrap();
+    match builder.build(&mut alloc::vec![]) {
+        Err(AddError::Duplicate(key, _)) => assert_eq!(key, "a"),
+        _ => panic!("expected a duplicate key"),
+    }
+}
+
+#[test]
+fn iter() {
+    extern crate alloc;
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use intern_str::builder::IgnoreCase;
+
+    let mut builder = Builder::<&str, IgnoreCase<Utf8Graph>>::new();
+    builder.add("text/plain", "text").unwrap();
+    builder.add("Image/PNG", "png").unwrap();
+    builder.add("image/png-x", "png-x").unwrap();
+    builder
+        .add_with_aliases("image/jpeg", ["image/jpg"], "jpeg")
+        .unwrap();
+    builder.add("text/html", "html").unwrap();
+    builder.add_wildcard("video/*", "video").unwrap();
+    builder.add("audio/ogg", "ogg").unwrap();
+    assert!(builder.deny("audio/ogg"));
+    assert_eq!(builder.remove("text/html"), Some("html"));
+
+    // Keys come out canonicalized and sorted, with one key for each alias.
+    let entries = builder.iter().collect::<Vec<_>>();
+    assert_eq!(
+        entries,
+        [
+            (String::from("image/jpeg"), &"jpeg"),
+            (String::from("image/jpg"), &"jpeg"),
+            (String::from("image/png"), &"png"),
+            (String::from("image/png-x"), &"png-x"),
+            (String::from("text/plain"), &"text"),
+        ]
+    );
+    assert!(builder
+        .keys()
+        .eq(entries.iter().map(|(key, _)| key.clone())));
+    assert!(builder.values().eq(entries.iter().map(|(_, value)| *value)));
+
+    // Building the graph leaves the builder empty.
+    let mut buffer = alloc::vec![];
+    builder.build(&mut buffer);
+    drop(buffer);
+    assert_eq!(builder.iter().next(), None);
+}
+
+#[test]
+fn contains_key() {
+    use intern_str::builder::IgnoreCase;
+
+    let mut builder = Builder::<u32, IgnoreCase<Utf8Graph>>::new();
+    builder.add("text/plain", 1).unwrap();
+    builder.add("text/plainer", 2).unwrap();
+    builder
+        .add_with_aliases("image/jpeg", ["image/jpg"], 3)
+        .unwrap();
+    builder.add_wildcard("video/*", 4).unwrap();
+    builder.add("audio/ogg", 5).unwrap();
+    builder.deny("audio/ogg");
+
+    assert!(builder.contains_key("text/plain"));
+    assert!(builder.contains_key("TEXT/Plain"));
+    assert_eq!(builder.get("Text/Plainer"), Some(&2));
+    assert_eq!(builder.get("image/jpg"), Some(&3));
+    assert_eq!(builder.get("text/plai"), None);
+    assert_eq!(builder.get("text/"), None);
+    assert_eq!(builder.get("video/mp4"), None);
+    assert_eq!(builder.get("audio/ogg"), None);
+    assert!(!builder.contains_key(""));
+
+    builder.remove("text/plain");
+    assert!(!builder.contains_key("text/plain"));
+    assert!(builder.contains_key("text/plainer"));
+}
+
+#[test]
+fn len() {
+    extern crate alloc;
+
+    let mut builder = Builder::<u32, Utf8Graph>::new();
+    assert!(builder.is_empty());
+
+    builder.add("text/plain", 1).unwrap();
+    builder.add("text/html", 2).unwrap();
+    assert_eq!(builder.len(), 2);
+
+    assert!(builder.add("text/plain", 3).is_err());
+    builder.add_or_replace("text/plain", 4).unwrap();
+    assert_eq!(builder.len(), 2);
+
+    builder
+        .add_with_aliases("image/jpeg", ["image/jpg"], 5)
+        .unwrap();
+    builder.add_wildcard("video/*", 6).unwrap();
+    assert_eq!(builder.len(), 4);
+    assert!(!builder.is_empty());
+
+    builder.remove("text/html");
+    builder.remove("text/html");
+    assert_eq!(builder.len(), 3);
+
+    let mut buffer = alloc::vec![];
+    builder.build(&mut buffer);
+    assert!(builder.is_empty());
+}
+
+#[test]
+fn into_iter() {
+    extern crate alloc;
+    use alloc::string::{String, ToString};
+
+    let mut builder = Builder::<u32, Utf8Graph>::new();
+    builder.add("text/plain", 1).unwrap();
+    builder.add("text/html", 2).unwrap();
+    builder
+        .add_with_aliases("image/jpeg", ["image/jpg"], 3)
+        .unwrap();
+    builder.add_wildcard("video/*", 4).unwrap();
+    builder.set_duplicate_policy(intern_str::builder::DuplicatePolicy::Replace);
+
+    let drained = builder.drain().collect::<alloc::vec::Vec<_>>();
+    assert_eq!(
+        drained,
+        [
+            ("image/jpeg".to_string(), 3),
+            ("text/html".to_string(), 2),
+            ("text/plain".to_string(), 1),
+        ]
+    );
+    assert!(builder.is_empty());
+    assert_eq!(builder.iter().next(), None);
+
+    // Re-key everything through a late normalization pass.
+    for (key, value) in drained {
+        builder.add(key.replace('/', "-"), value).unwrap();
+    }
+    builder.add("text-plain", 5).unwrap();
+
+    let pairs = builder
+        .into_iter()
+        .collect::<alloc::vec::Vec<(String, u32)>>();
+    assert_eq!(
+        pairs,
+        [
+            ("image-jpeg".to_string(), 3),
+            ("text-html".to_string(), 2),
+            ("text-plain".to_string(), 5),
+        ]
+    );
+}
+
+#[test]
+fn trie() {
+    extern crate alloc;
+    use alloc::string::ToString;
+    use intern_str::builder::{IgnoreCase, Trie};
+    use intern_str::CaseInsensitive;
+
+    let mut trie = Trie::new();
+    assert!(trie.is_empty());
+    assert_eq!(trie.insert("Text/Plain", 1), None);
+    assert_eq!(trie.insert("text/html", 2), None);
+    assert_eq!(trie.insert("text", 3), None);
+    assert_eq!(trie.insert("text/html", 4), Some(2));
+    assert_eq!(trie.len(), 3);
+
+    assert_eq!(trie.get("text/html"), Some(&4));
+    assert_eq!(trie.get("text/plain"), None);
+    assert_eq!(trie.get("text/"), None);
+    *trie.get_mut("text").unwrap() += 10;
+    assert!(trie.contains_key("text"));
+
+    assert_eq!(trie.remove("text"), Some(13));
+    assert_eq!(trie.remove("text"), None);
+    assert_eq!(trie.len(), 2);
+
+    assert_eq!(trie.insert("", 5), None);
+    assert_eq!(
+        trie.iter().collect::<alloc::vec::Vec<_>>(),
+        [
+            ("".to_string(), &5),
+            ("Text/Plain".to_string(), &1),
+            ("text/html".to_string(), &4)
+        ]
+    );
+    assert!(trie
+        .clone()
+        .into_builder::<IgnoreCase<Utf8Graph>>()
+        .is_err());
+    trie.remove("");
+
+    let mut builder = trie.into_builder::<IgnoreCase<Utf8Graph>>().unwrap();
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(*graph.process(CaseInsensitive("text/plain")), Some(1));
+    assert_eq!(*graph.process(CaseInsensitive("TEXT/HTML")), Some(4));
+    assert_eq!(*graph.process(CaseInsensitive("text")), None);
+}
+
+#[test]
+fn key_options() {
+    extern crate alloc;
+    use intern_str::builder::{AddError, KeyOptions};
+
+    let options = KeyOptions::new()
+        .trim(true)
+        .collapse_whitespace(true)
+        .strip_prefix("mime:")
+        .strip_suffix(";");
+    assert_eq!(options.apply("  mime: text/plain ;"), "text/plain");
+    assert_eq!(options.apply("font\t\tcollection"), "font collection");
+    assert_eq!(options.apply("a b"), "a b");
+    assert_eq!(KeyOptions::new().apply(" mime:x "), " mime:x ");
+
+    let mut builder = Builder::<u32, Utf8Graph>::new();
+    builder.set_key_options(options);
+    builder.add(" text/plain\n", 1).unwrap();
+    builder.add("mime:text/html", 2).unwrap();
+    builder.add("font \t collection;", 3).unwrap();
+    assert!(matches!(
+        builder.add("mime:text/plain", 4),
+        Err(AddError::Conflict { value: 4, .. })
+    ));
+    assert!(matches!(
+        builder.add("  mime: ", 5),
+        Err(AddError::Invalid(..))
+    ));
+    assert!(builder.contains_key("  text/html;"));
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    let lookup = |key: &str| *graph.process(&options.apply(key));
+    assert_eq!(lookup("text/plain "), Some(1));
+    assert_eq!(lookup("mime:text/html"), Some(2));
+    assert_eq!(lookup("font  collection"), Some(3));
+    assert_eq!(lookup("text/css"), None);
+}
+
+#[test]
+fn fallback() {
+    extern crate alloc;
+    use intern_str::builder::AddError;
+
+    let mut builder = Builder::<&str, Utf8Graph>::new();
+    builder.add("application/json", "json").unwrap();
+    builder.add("application/xml", "xml").unwrap();
+    builder.add("text/plain", "text").unwrap();
+    builder.add_fallback("application/", "binary").unwrap();
+    builder.add_fallback("application/vnd.", "vendor").unwrap();
+    assert!(matches!(
+        builder.add_fallback("application/", "other"),
+        Err(AddError::Conflict { ref key, .. }) if key == "application/"
+    ));
+    assert!(matches!(
+        builder.add_wildcard("application/*", "other"),
+        Err(AddError::Conflict { ref key, .. }) if key == "application/*"
+    ));
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build(&mut buffer);
+    assert_eq!(*graph.process("application/json"), Some("json"));
+    assert_eq!(*graph.process("application/jso"), Some("binary"));
+    assert_eq!(*graph.process("application/pdf"), Some("binary"));
+    assert_eq!(*graph.process("application/"), Some("binary"));
+    assert_eq!(*graph.process("application/vnd.ms-excel"), Some("vendor"));
+    assert_eq!(*graph.process("application"), None);
+    assert_eq!(*graph.process("text/html"), None);
+}
+
+#[test]
+fn build_with_miss() {
+    extern crate alloc;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Mime {
+        Text,
+        Html,
+        Json,
+        OctetStream,
+    }
+
+    let mut builder = Builder::<Mime, Utf8Graph>::new();
+    builder.add("text/plain", Mime::Text).unwrap();
+    builder.add("text/html", Mime::Html).unwrap();
+    builder.add_fallback("application/", Mime::Json).unwrap();
+
+    let mut buffer = alloc::vec![];
+    let graph = builder.build_with_miss(&mut buffer, Mime::OctetStream);
+    assert_eq!(*graph.process("text/plain"), Mime::Text);
+    assert_eq!(*graph.process("text/html"), Mime::Html);
+    assert_eq!(*graph.process("application/x"), Mime::Json);
+    assert_eq!(*graph.process("text/"), Mime::OctetStream);
+    assert_eq!(*graph.process("text/htm"), Mime::OctetStream);
+    assert_eq!(*graph.process("image/png"), Mime::OctetStream);
+    assert_eq!(*graph.process(""), Mime::OctetStream);
 }
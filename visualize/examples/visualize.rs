@@ -0,0 +1,62 @@
+//! Render a graph of some keys into an image.
+//!
+//! Run with `cargo run -p intern-str-visualize --features render --example visualize`,
+//! optionally followed by the path of the image and the keys to put in the graph. The
+//! format is picked from the extension of the path, which is `graph.svg` by default.
+//! This needs Graphviz to be installed, or `GRAPHVIZ_DOT` to point to its `dot` binary.
+
+use intern_str::builder::{Builder, Utf8Graph};
+use intern_str_visualize::{as_graphviz_with_options, render, Format, VisualizeOptions};
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let path = PathBuf::from(args.next().unwrap_or_else(|| "graph.svg".to_string()));
+    let mut keys = args.collect::<Vec<_>>();
+    if keys.is_empty() {
+        keys = ["get", "head", "post", "put", "delete", "options", "patch"]
+            .iter()
+            .map(|key| key.to_string())
+            .collect();
+    }
+
+    let format = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("png") => Format::Png,
+        Some("svg") | None => Format::Svg,
+        Some(ext) => {
+            eprintln!("unsupported image format: {}", ext);
+            process::exit(1);
+        }
+    };
+
+    // Build the graph, where each key maps to its index.
+    let mut builder = Builder::<_, Utf8Graph>::new();
+    for (i, key) in keys.iter().enumerate() {
+        if let Err(err) = builder.add(key.clone(), i) {
+            eprintln!("failed to add {:?}: {}", key, err);
+            process::exit(1);
+        }
+    }
+    let mut buffer = Vec::new();
+    let graph = builder.build(&mut buffer);
+
+    let options = VisualizeOptions::new()
+        .default_edges(false)
+        .accepting_color("palegreen")
+        .collapse_chains(true);
+    let mut dot = Vec::new();
+    as_graphviz_with_options(&graph, &mut dot, "G", &options).unwrap();
+
+    let mut image = Vec::new();
+    let result = render(&dot, format, &mut image).and_then(|()| fs::write(&path, &image));
+    if let Err(err) = result {
+        eprintln!("failed to render {}: {}", path.display(), err);
+        process::exit(1);
+    }
+
+    println!("wrote {}", path.display());
+}
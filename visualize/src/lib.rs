@@ -1,30 +1,691 @@
 //! Basic utility for converting an `intern-str` DFA into an easy-to-comprehend graph.
 
 use intern_str::{Graph, Segmentable};
-use std::fmt::{Debug, Display};
+use std::collections::VecDeque;
+use std::fmt::{self, Debug, Display};
 use std::io;
 
+mod options;
+#[cfg(feature = "render")]
+mod render;
+mod report;
+mod trace;
+
+pub use options::{RankDir, VisualizeOptions};
+#[cfg(feature = "render")]
+pub use render::{find_dot, render, render_with, Format};
+pub use report::report;
+use trace::Trace;
+
 /// Convert a DFA into a graphviz dot file.
 pub fn as_graphviz<Input: Segmentable + Display, Output: Debug>(
     graph: &Graph<'_, '_, Input, Output>,
     out: &mut impl io::Write,
     name: &str,
 ) -> io::Result<()> {
+    write_graphviz(
+        graph,
+        out,
+        name,
+        &VisualizeOptions::new(),
+        None,
+        |_| false,
+        |f, input| write!(f, "{}", input),
+        |f, output| write!(f, "{:?}", output),
+    )
+}
+
+/// Convert a DFA into a graphviz dot file, with options for how it looks.
+///
+/// ```
+/// use intern_str::builder::{Builder, Utf8Graph};
+/// use intern_str_visualize::{as_graphviz_with_options, VisualizeOptions};
+///
+/// let mut builder = Builder::<_, Utf8Graph>::new();
+/// builder.add("get", 1).unwrap();
+/// builder.add("set", 2).unwrap();
+///
+/// let mut buffer = Vec::new();
+/// let graph = builder.build(&mut buffer);
+///
+/// let options = VisualizeOptions::new()
+///     .default_edges(false)
+///     .accepting_color("palegreen");
+/// let mut dot = Vec::new();
+/// as_graphviz_with_options(&graph, &mut dot, "G", &options).unwrap();
+///
+/// let dot = String::from_utf8(dot).unwrap();
+/// assert!(dot.contains("fillcolor=\"palegreen\""));
+/// ```
+pub fn as_graphviz_with_options<Input: Segmentable + Display, Output: Debug + PartialEq>(
+    graph: &Graph<'_, '_, Input, Output>,
+    out: &mut impl io::Write,
+    name: &str,
+    options: &VisualizeOptions,
+) -> io::Result<()> {
+    as_graphviz_with_labels(
+        graph,
+        out,
+        name,
+        options,
+        None,
+        |f, input| write!(f, "{}", input),
+        |f, output| write!(f, "{:?}", output),
+    )
+}
+
+/// Convert a DFA into a graphviz dot file, highlighting the path that `input` takes
+/// through it.
+///
+/// The nodes and edges taken while processing `input` are drawn in the trace color
+/// of the options. If the input falls back to a default node, the node where that
+/// first happens is drawn in the miss color and labeled with the reason. Nodes on
+/// the path are always written, even if they are past the maximum number of nodes.
+///
+/// ```
+/// use intern_str::builder::{Builder, Utf8Graph};
+/// use intern_str_visualize::{as_graphviz_with_trace, VisualizeOptions};
+///
+/// let mut builder = Builder::<_, Utf8Graph>::new();
+/// builder.add("get", 1).unwrap();
+/// builder.add("set", 2).unwrap();
+///
+/// let mut buffer = Vec::new();
+/// let graph = builder.build(&mut buffer);
+///
+/// let mut dot = Vec::new();
+/// as_graphviz_with_trace(&graph, "gex", &mut dot, "G", &VisualizeOptions::new()).unwrap();
+///
+/// let dot = String::from_utf8(dot).unwrap();
+/// assert!(dot.contains("xlabel=\"no transition for gex at offset 0\""));
+/// ```
+pub fn as_graphviz_with_trace<Input: Segmentable + Display, Output: Debug + PartialEq>(
+    graph: &Graph<'_, '_, Input, Output>,
+    input: Input,
+    out: &mut impl io::Write,
+    name: &str,
+    options: &VisualizeOptions,
+) -> io::Result<()> {
+    as_graphviz_with_labels(
+        graph,
+        out,
+        name,
+        options,
+        Some(input),
+        |f, input| write!(f, "{}", input),
+        |f, output| write!(f, "{:?}", output),
+    )
+}
+
+/// Convert a DFA into a graphviz dot file, with closures that format the labels.
+///
+/// `format_input` writes the label of each transition and `format_output` writes the
+/// label of each node, so the input and output types don't need to implement
+/// `Display` or `Debug`. If `trace` is given, the path that it takes through the
+/// graph is highlighted like in [`as_graphviz_with_trace`].
+///
+/// ```
+/// use intern_str::builder::{Builder, ByteGraph};
+/// use intern_str_visualize::{as_graphviz_with_labels, VisualizeOptions};
+///
+/// let mut builder = Builder::<_, ByteGraph>::new();
+/// builder.add_bytes(b"\x01\x02".to_vec(), 1).unwrap();
+/// builder.add_bytes(b"\x03\x04".to_vec(), 2).unwrap();
+///
+/// let mut buffer = Vec::new();
+/// let graph = builder.build(&mut buffer);
+///
+/// let mut dot = Vec::new();
+/// as_graphviz_with_labels(
+///     &graph,
+///     &mut dot,
+///     "G",
+///     &VisualizeOptions::new(),
+///     None,
+///     |f, input| write!(f, "{:02x?}", input),
+///     |f, output| match output {
+///         Some(output) => write!(f, "#{}", output),
+///         None => write!(f, "miss"),
+///     },
+/// )
+/// .unwrap();
+///
+/// let dot = String::from_utf8(dot).unwrap();
+/// assert!(dot.contains("[label=\"[01, 02]\"]"));
+/// assert!(dot.contains("[label=\"#2\"]"));
+/// ```
+pub fn as_graphviz_with_labels<Input: Segmentable, Output: PartialEq>(
+    graph: &Graph<'_, '_, Input, Output>,
+    out: &mut impl io::Write,
+    name: &str,
+    options: &VisualizeOptions,
+    trace: Option<Input>,
+    mut format_input: impl FnMut(&mut dyn fmt::Write, &Input) -> fmt::Result,
+    format_output: impl FnMut(&mut dyn fmt::Write, &Output) -> fmt::Result,
+) -> io::Result<()> {
+    let trace = match trace {
+        Some(input) => Some(Trace::new(graph, input, &mut format_input)?),
+        None => None,
+    };
+    let trap = graph.nodes()[graph.nodes()[graph.start()].default()].output();
+
+    write_graphviz(
+        graph,
+        out,
+        name,
+        options,
+        trace.as_ref(),
+        |output| output != trap,
+        format_input,
+        format_output,
+    )
+}
+
+/// Write a graphviz dot file, where `accepting` tells if an output is accepted.
+#[allow(clippy::too_many_arguments)]
+fn write_graphviz<Input: Segmentable, Output>(
+    graph: &Graph<'_, '_, Input, Output>,
+    out: &mut impl io::Write,
+    name: &str,
+    options: &VisualizeOptions,
+    trace: Option<&Trace>,
+    accepting: impl Fn(&Output) -> bool,
+    mut format_input: impl FnMut(&mut dyn fmt::Write, &Input) -> fmt::Result,
+    mut format_output: impl FnMut(&mut dyn fmt::Write, &Output) -> fmt::Result,
+) -> io::Result<()> {
+    let nodes = graph.nodes();
+    let trap = nodes[graph.start()].default();
+
+    // Figure out which nodes are written.
+    let collapsed = if options.collapse_chains {
+        collapsed_nodes(graph, trace, &accepting)
+    } else {
+        vec![false; nodes.len()]
+    };
+    let mut order = match options.max_nodes {
+        Some(max_nodes) => breadth_first(graph, max_nodes),
+        None => (0..nodes.len()).collect(),
+    };
+    order.retain(|&i| !collapsed[i]);
+    let mut shown = vec![false; nodes.len()];
+    for &i in &order {
+        shown[i] = true;
+    }
+    if let Some(trace) = trace {
+        for (i, _) in trace.nodes.iter().enumerate().filter(|(_, &taken)| taken) {
+            if !shown[i] && !collapsed[i] {
+                shown[i] = true;
+                order.push(i);
+            }
+        }
+    }
+    let mut truncated = false;
+
+    let traced_node = |i: usize| trace.map_or(false, |trace| trace.nodes[i]);
+    let traced_edge = |i: usize, edge: Option<usize>| {
+        trace.map_or(false, |trace| trace.edges.contains(&(i, edge)))
+    };
+    let missed = |i: usize| {
+        trace.and_then(|trace| match &trace.miss {
+            Some((node, reason)) if *node == i => Some(reason),
+            _ => None,
+        })
+    };
+
     writeln!(out, "digraph {} {{", name)?;
+    if let Some(rankdir) = options.rankdir {
+        writeln!(out, "rankdir={};", rankdir.as_str())?;
+    }
+    if let Some(shape) = &options.node_shape {
+        writeln!(out, "node [shape=\"{}\"];", shape)?;
+    }
 
     // Write out each node.
-    for (i, node) in graph.nodes().iter().enumerate() {
-        writeln!(out, "s{} [label=\"{:?}\"]", i, node.output())?;
+    for &i in &order {
+        let node = &nodes[i];
+        let label = format_label(&mut format_output, node.output())?;
+        let mut attributes = vec![format!("label=\"{}\"", dot_escape(&label))];
+
+        let fill = if i == trap {
+            options.trap_color.as_ref()
+        } else if accepting(node.output()) {
+            options.accepting_color.as_ref()
+        } else {
+            None
+        };
+        if let Some(fill) = fill {
+            attributes.push("style=filled".to_string());
+            attributes.push(format!("fillcolor=\"{}\"", fill));
+        }
+
+        if let Some(reason) = missed(i) {
+            attributes.push(format!("color=\"{}\"", options.miss_color));
+            attributes.push("penwidth=2".to_string());
+            attributes.push(format!("xlabel=\"{}\"", dot_escape(reason)));
+        } else if traced_node(i) {
+            attributes.push(format!("color=\"{}\"", options.trace_color));
+            attributes.push("penwidth=2".to_string());
+        }
+
+        writeln!(out, "s{} [{}]", i, attributes.join(", "))?;
 
         // Write out each connection.
+        for (j, (input, next)) in node.inputs().iter().enumerate() {
+            let mut label = format_label(&mut format_input, input)?;
+            let mut next = *next;
+            let mut traced = traced_edge(i, Some(j));
+
+            // Follow the chain through any collapsed nodes.
+            while collapsed[next] {
+                let (input, after) = &nodes[next].inputs()[0];
+                label.push_str(&format_label(&mut format_input, input)?);
+                traced &= traced_edge(next, Some(0));
+                next = *after;
+            }
+
+            let mut attributes = vec![format!("label=\"{}\"", dot_escape(&label))];
+            if traced {
+                attributes.push(format!("color=\"{}\"", options.trace_color));
+                attributes.push("penwidth=2".to_string());
+            }
+
+            let target = if shown[next] {
+                format!("s{}", next)
+            } else {
+                truncated = true;
+                "more".to_string()
+            };
+            writeln!(out, "s{} -> {} [{}];", i, target, attributes.join(", "))?;
+        }
+
+        // The default edge is always written if the input took it.
+        let taken = traced_edge(i, None);
+        if options.default_edges || taken {
+            let target = if shown[node.default()] {
+                format!("s{}", node.default())
+            } else {
+                truncated = true;
+                "more".to_string()
+            };
+
+            if taken {
+                writeln!(
+                    out,
+                    "s{} -> {} [color=\"{}\", penwidth=2];",
+                    i, target, options.miss_color
+                )?;
+            } else {
+                writeln!(out, "s{} -> {};", i, target)?;
+            }
+        }
+    }
+
+    if truncated {
+        writeln!(out, "more [label=\"...\", shape=plaintext]")?;
+    }
+
+    writeln!(out, "}}")?;
+
+    Ok(())
+}
+
+/// Find the nodes that are collapsed into the edges of a chain.
+///
+/// A node is collapsed if it has a single transition, a single transition into it,
+/// and the trap node as its default, and it isn't the start node, accepting or where
+/// the input misses.
+fn collapsed_nodes<Input: Segmentable, Output>(
+    graph: &Graph<'_, '_, Input, Output>,
+    trace: Option<&Trace>,
+    accepting: impl Fn(&Output) -> bool,
+) -> Vec<bool> {
+    let nodes = graph.nodes();
+    let trap = nodes[graph.start()].default();
+
+    let mut incoming = vec![0usize; nodes.len()];
+    for node in nodes {
+        for (_, next) in node.inputs() {
+            incoming[*next] += 1;
+        }
+    }
+
+    let missed = trace.and_then(|trace| trace.miss.as_ref().map(|(node, _)| *node));
+
+    nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| {
+            i != graph.start()
+                && i != trap
+                && node.inputs().len() == 1
+                && incoming[i] == 1
+                && node.default() == trap
+                && !accepting(node.output())
+                && missed != Some(i)
+        })
+        .collect()
+}
+
+/// Get up to `max_nodes` nodes in breadth-first order from the start node.
+fn breadth_first<Input: Segmentable, Output>(
+    graph: &Graph<'_, '_, Input, Output>,
+    max_nodes: usize,
+) -> Vec<usize> {
+    let nodes = graph.nodes();
+    let mut seen = vec![false; nodes.len()];
+    let mut order = Vec::new();
+    let mut queue = VecDeque::new();
+
+    seen[graph.start()] = true;
+    queue.push_back(graph.start());
+
+    while let Some(i) = queue.pop_front() {
+        if order.len() >= max_nodes {
+            break;
+        }
+        order.push(i);
+
+        let node = &nodes[i];
+        let nexts = node.inputs().iter().map(|(_, next)| *next);
+        for next in nexts.chain(Some(node.default())) {
+            if !seen[next] {
+                seen[next] = true;
+                queue.push_back(next);
+            }
+        }
+    }
+
+    order
+}
+
+/// Convert a DFA into a Mermaid flowchart.
+///
+/// Mermaid diagrams can be embedded into Markdown inside of a `mermaid` code block,
+/// which GitHub renders inline without needing a Graphviz toolchain.
+pub fn as_mermaid<Input: Segmentable + Display, Output: Debug>(
+    graph: &Graph<'_, '_, Input, Output>,
+    out: &mut impl io::Write,
+) -> io::Result<()> {
+    writeln!(out, "flowchart LR")?;
+
+    // Write out each node.
+    for (i, node) in graph.nodes().iter().enumerate() {
+        let label = format!("{:?}", node.output());
+        writeln!(out, "    s{}[\"{}\"]", i, mermaid_escape(&label))?;
+    }
+
+    // Write out each connection.
+    for (i, node) in graph.nodes().iter().enumerate() {
+        for (input, next) in node.inputs() {
+            let label = input.to_string();
+            writeln!(
+                out,
+                "    s{} -->|\"{}\"| s{}",
+                i,
+                mermaid_escape(&label),
+                next
+            )?;
+        }
+
+        writeln!(out, "    s{} -.-> s{}", i, node.default())?;
+    }
+
+    Ok(())
+}
+
+/// Convert a DFA into a GraphML file.
+///
+/// GraphML can be loaded into tools like Gephi or yEd, which are better at laying out
+/// and exploring very large graphs than a static dot render. Nodes carry their output
+/// and whether they are the start node, while edges carry their input or are marked
+/// as going to the default node.
+pub fn as_graphml<Input: Segmentable + Display, Output: Debug>(
+    graph: &Graph<'_, '_, Input, Output>,
+    out: &mut impl io::Write,
+    name: &str,
+) -> io::Result<()> {
+    writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(
+        out,
+        "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">"
+    )?;
+    writeln!(
+        out,
+        "  <key id=\"output\" for=\"node\" attr.name=\"output\" attr.type=\"string\"/>"
+    )?;
+    writeln!(
+        out,
+        "  <key id=\"start\" for=\"node\" attr.name=\"start\" attr.type=\"boolean\">"
+    )?;
+    writeln!(out, "    <default>false</default>")?;
+    writeln!(out, "  </key>")?;
+    writeln!(
+        out,
+        "  <key id=\"input\" for=\"edge\" attr.name=\"input\" attr.type=\"string\"/>"
+    )?;
+    writeln!(
+        out,
+        "  <key id=\"default\" for=\"edge\" attr.name=\"default\" attr.type=\"boolean\">"
+    )?;
+    writeln!(out, "    <default>false</default>")?;
+    writeln!(out, "  </key>")?;
+    writeln!(
+        out,
+        "  <graph id=\"{}\" edgedefault=\"directed\">",
+        xml_escape(name)
+    )?;
+
+    // Write out each node.
+    for (i, node) in graph.nodes().iter().enumerate() {
+        let label = format!("{:?}", node.output());
+        writeln!(out, "    <node id=\"s{}\">", i)?;
+        writeln!(
+            out,
+            "      <data key=\"output\">{}</data>",
+            xml_escape(&label)
+        )?;
+        if i == graph.start() {
+            writeln!(out, "      <data key=\"start\">true</data>")?;
+        }
+        writeln!(out, "    </node>")?;
+    }
+
+    // Write out each connection.
+    for (i, node) in graph.nodes().iter().enumerate() {
         for (input, next) in node.inputs() {
-            writeln!(out, "s{} -> s{} [label=\"{}\"];", i, next, input)?;
+            let label = input.to_string();
+            writeln!(out, "    <edge source=\"s{}\" target=\"s{}\">", i, next)?;
+            writeln!(
+                out,
+                "      <data key=\"input\">{}</data>",
+                xml_escape(&label)
+            )?;
+            writeln!(out, "    </edge>")?;
+        }
+
+        writeln!(
+            out,
+            "    <edge source=\"s{}\" target=\"s{}\">",
+            i,
+            node.default()
+        )?;
+        writeln!(out, "      <data key=\"default\">true</data>")?;
+        writeln!(out, "    </edge>")?;
+    }
+
+    writeln!(out, "  </graph>")?;
+    writeln!(out, "</graphml>")?;
+
+    Ok(())
+}
+
+/// Convert a DFA into JSON.
+///
+/// This lets external tooling, like web viewers or analysis scripts, read the
+/// structure of a graph without parsing Rust or dot. `format_output` writes the text
+/// of each output, which is stored as a string. The JSON looks like this:
+///
+/// ```json
+/// {
+///   "start": 0,
+///   "nodes": [
+///     {
+///       "output": "None",
+///       "default": 1,
+///       "amount": 3,
+///       "transitions": [
+///         { "input": "get", "next": 2 }
+///       ]
+///     }
+///   ]
+/// }
+/// ```
+///
+/// `amount` is `null` for nodes that take the rest of the input.
+pub fn to_json<Input: Segmentable + Display, Output>(
+    graph: &Graph<'_, '_, Input, Output>,
+    out: &mut impl io::Write,
+    mut format_output: impl FnMut(&mut dyn fmt::Write, &Output) -> fmt::Result,
+) -> io::Result<()> {
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"start\": {},", graph.start())?;
+    writeln!(out, "  \"nodes\": [")?;
+
+    let nodes = graph.nodes();
+    for (i, node) in nodes.iter().enumerate() {
+        let mut output = String::new();
+        format_output(&mut output, node.output())
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to format output"))?;
+
+        writeln!(out, "    {{")?;
+        writeln!(out, "      \"output\": \"{}\",", json_escape(&output))?;
+        writeln!(out, "      \"default\": {},", node.default())?;
+        if node.amount() == std::usize::MAX {
+            writeln!(out, "      \"amount\": null,")?;
+        } else {
+            writeln!(out, "      \"amount\": {},", node.amount())?;
+        }
+
+        // Write out each connection.
+        let inputs = node.inputs();
+        if inputs.is_empty() {
+            writeln!(out, "      \"transitions\": []")?;
+        } else {
+            writeln!(out, "      \"transitions\": [")?;
+            for (j, (input, next)) in inputs.iter().enumerate() {
+                let comma = if j + 1 < inputs.len() { "," } else { "" };
+                writeln!(
+                    out,
+                    "        {{ \"input\": \"{}\", \"next\": {} }}{}",
+                    json_escape(&input.to_string()),
+                    next,
+                    comma
+                )?;
+            }
+            writeln!(out, "      ]")?;
         }
 
-        writeln!(out, "s{} -> s{};", i, node.default())?;
+        let comma = if i + 1 < nodes.len() { "," } else { "" };
+        writeln!(out, "    }}{}", comma)?;
     }
 
+    writeln!(out, "  ]")?;
     writeln!(out, "}}")?;
 
     Ok(())
 }
+
+/// Format a label with one of the closures that format labels.
+pub(crate) fn format_label<T>(
+    format: &mut impl FnMut(&mut dyn fmt::Write, &T) -> fmt::Result,
+    value: &T,
+) -> io::Result<String> {
+    let mut label = String::new();
+    format(&mut label, value)
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to format label"))?;
+    Ok(label)
+}
+
+/// Escape a label so that it can be put in quotes in a dot file.
+fn dot_escape(label: &str) -> String {
+    let mut escaped = String::with_capacity(label.len());
+
+    for c in label.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            // Write other control characters as escapes, with the backslash escaped.
+            c if c.is_control() => {
+                for c in c.escape_default() {
+                    if c == '\\' {
+                        escaped.push_str("\\\\");
+                    } else {
+                        escaped.push(c);
+                    }
+                }
+            }
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Escape a label so that it can be put in quotes in a Mermaid diagram.
+fn mermaid_escape(label: &str) -> String {
+    let mut escaped = String::with_capacity(label.len());
+
+    for c in label.chars() {
+        match c {
+            '"' => escaped.push_str("#quot;"),
+            '#' => escaped.push_str("#35;"),
+            '<' => escaped.push_str("#lt;"),
+            '>' => escaped.push_str("#gt;"),
+            '\n' => escaped.push(' '),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Escape text so that it can be put in an XML attribute or element.
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Most control characters can't appear in XML at all.
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => escaped.push('\u{fffd}'),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Escape text so that it can be put in a JSON string.
+fn json_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
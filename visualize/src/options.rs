@@ -0,0 +1,174 @@
+//! Options for the graphviz output.
+
+/// Options for [`as_graphviz_with_options`](crate::as_graphviz_with_options).
+///
+/// The default options write the same graph as [`as_graphviz`](crate::as_graphviz).
+///
+/// ```
+/// use intern_str_visualize::{RankDir, VisualizeOptions};
+///
+/// let options = VisualizeOptions::new()
+///     .rankdir(RankDir::LeftToRight)
+///     .node_shape("circle")
+///     .default_edges(false)
+///     .accepting_color("palegreen");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VisualizeOptions {
+    /// The direction that the graph is laid out in.
+    pub(crate) rankdir: Option<RankDir>,
+
+    /// The shape of the nodes.
+    pub(crate) node_shape: Option<String>,
+
+    /// Whether the edges to default nodes are written.
+    pub(crate) default_edges: bool,
+
+    /// The largest number of nodes to write.
+    pub(crate) max_nodes: Option<usize>,
+
+    /// Whether chains of nodes with a single transition are collapsed.
+    pub(crate) collapse_chains: bool,
+
+    /// The fill color of accepting nodes.
+    pub(crate) accepting_color: Option<String>,
+
+    /// The fill color of the trap node.
+    pub(crate) trap_color: Option<String>,
+
+    /// The color of the path taken by a traced input.
+    pub(crate) trace_color: String,
+
+    /// The color of the point where a traced input misses.
+    pub(crate) miss_color: String,
+}
+
+impl Default for VisualizeOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VisualizeOptions {
+    /// Create the default options.
+    pub fn new() -> Self {
+        VisualizeOptions {
+            rankdir: None,
+            node_shape: None,
+            default_edges: true,
+            max_nodes: None,
+            collapse_chains: false,
+            accepting_color: None,
+            trap_color: None,
+            trace_color: "blue".to_string(),
+            miss_color: "red".to_string(),
+        }
+    }
+
+    /// Set the direction that the graph is laid out in.
+    pub fn rankdir(mut self, rankdir: RankDir) -> Self {
+        self.rankdir = Some(rankdir);
+        self
+    }
+
+    /// Set the graphviz shape of the nodes, like `circle` or `box`.
+    pub fn node_shape(mut self, shape: impl Into<String>) -> Self {
+        self.node_shape = Some(shape.into());
+        self
+    }
+
+    /// Set whether the edges to each node's default node are written.
+    ///
+    /// Most nodes fall back to the same trap node, so large graphs are much easier
+    /// to read without these edges. They are written by default.
+    pub fn default_edges(mut self, default_edges: bool) -> Self {
+        self.default_edges = default_edges;
+        self
+    }
+
+    /// Only write the first `max_nodes` nodes reachable from the start node.
+    ///
+    /// Nodes are taken in breadth-first order, so the nodes closest to the start
+    /// node are kept. Edges to any nodes that are left out point to a single node
+    /// labeled `...` instead.
+    pub fn max_nodes(mut self, max_nodes: usize) -> Self {
+        self.max_nodes = Some(max_nodes);
+        self
+    }
+
+    /// Set whether chains of nodes with a single transition are collapsed into one
+    /// edge.
+    ///
+    /// The edge is labeled with the chunks of every transition in the chain joined
+    /// together, so graphs of word lists don't need a node for every run of letters.
+    /// Nodes that are accepting, or where a traced input misses, are never collapsed.
+    pub fn collapse_chains(mut self, collapse_chains: bool) -> Self {
+        self.collapse_chains = collapse_chains;
+        self
+    }
+
+    /// Fill accepting nodes with a graphviz color.
+    ///
+    /// A node is accepting if its output is different from the output of the trap
+    /// node, which is the default node of the start node.
+    pub fn accepting_color(mut self, color: impl Into<String>) -> Self {
+        self.accepting_color = Some(color.into());
+        self
+    }
+
+    /// Fill the trap node with a graphviz color.
+    ///
+    /// The trap node is the default node of the start node, where inputs that
+    /// aren't keys end up.
+    pub fn trap_color(mut self, color: impl Into<String>) -> Self {
+        self.trap_color = Some(color.into());
+        self
+    }
+
+    /// Set the graphviz color of the path taken by the input in
+    /// [`as_graphviz_with_trace`](crate::as_graphviz_with_trace).
+    ///
+    /// The default is `blue`.
+    pub fn trace_color(mut self, color: impl Into<String>) -> Self {
+        self.trace_color = color.into();
+        self
+    }
+
+    /// Set the graphviz color of the node where the input falls back to a default
+    /// node in [`as_graphviz_with_trace`](crate::as_graphviz_with_trace), and of the
+    /// default edges it takes.
+    ///
+    /// The default is `red`.
+    pub fn miss_color(mut self, color: impl Into<String>) -> Self {
+        self.miss_color = color.into();
+        self
+    }
+}
+
+/// The direction that a graph is laid out in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RankDir {
+    /// From top to bottom, which is the graphviz default.
+    TopToBottom,
+
+    /// From left to right.
+    LeftToRight,
+
+    /// From bottom to top.
+    BottomToTop,
+
+    /// From right to left.
+    RightToLeft,
+}
+
+impl RankDir {
+    /// Get the graphviz name of the direction.
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            RankDir::TopToBottom => "TB",
+            RankDir::LeftToRight => "LR",
+            RankDir::BottomToTop => "BT",
+            RankDir::RightToLeft => "RL",
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! Rendering dot files into images with Graphviz.
+
+use std::env;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// The name of the environment variable that overrides the `dot` binary to use.
+const DOT_VAR: &str = "GRAPHVIZ_DOT";
+
+/// An image format that `dot` can render.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// Scalable Vector Graphics.
+    Svg,
+
+    /// Portable Network Graphics.
+    Png,
+}
+
+impl Format {
+    /// Get the name that `dot` uses for the format, which is also its file extension.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Format::Svg => "svg",
+            Format::Png => "png",
+        }
+    }
+}
+
+/// Find the Graphviz `dot` binary.
+///
+/// The `GRAPHVIZ_DOT` environment variable is used if it is set. Otherwise, each
+/// directory in `PATH` is searched for `dot`.
+pub fn find_dot() -> io::Result<PathBuf> {
+    if let Some(dot) = env::var_os(DOT_VAR) {
+        return Ok(PathBuf::from(dot));
+    }
+
+    let name = if cfg!(windows) { "dot.exe" } else { "dot" };
+    env::var_os("PATH")
+        .and_then(|paths| {
+            env::split_paths(&paths)
+                .map(|dir| dir.join(name))
+                .find(|path| path.is_file())
+        })
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "could not find the Graphviz `dot` binary; install Graphviz or set GRAPHVIZ_DOT",
+            )
+        })
+}
+
+/// Render a dot file into an image, using the `dot` binary from [`find_dot`].
+///
+/// ```no_run
+/// use intern_str::builder::{Builder, Utf8Graph};
+/// use intern_str_visualize::{as_graphviz, render, Format};
+///
+/// let mut builder = Builder::<_, Utf8Graph>::new();
+/// builder.add("get", 1).unwrap();
+/// builder.add("set", 2).unwrap();
+///
+/// let mut buffer = Vec::new();
+/// let graph = builder.build(&mut buffer);
+///
+/// let mut dot = Vec::new();
+/// as_graphviz(&graph, &mut dot, "G").unwrap();
+///
+/// let mut svg = std::fs::File::create("graph.svg").unwrap();
+/// render(&dot, Format::Svg, &mut svg).unwrap();
+/// ```
+pub fn render(dot: &[u8], format: Format, out: &mut impl Write) -> io::Result<()> {
+    render_with(&find_dot()?, dot, format, out)
+}
+
+/// Render a dot file into an image, using the `dot` binary at `program`.
+pub fn render_with(
+    program: &Path,
+    dot: &[u8],
+    format: Format,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let mut child = Command::new(program)
+        .arg(format!("-T{}", format.as_str()))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Write the input on another thread, so that neither side blocks on a full pipe.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = dot.to_vec();
+    let writer = thread::spawn(move || stdin.write_all(&input));
+
+    let output = child.wait_with_output()?;
+    let written = writer
+        .join()
+        .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::Other, "writer panicked")));
+
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "`dot` failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ));
+    }
+
+    written?;
+    out.write_all(&output.stdout)
+}
@@ -0,0 +1,185 @@
+//! Text reports on the shape of a graph.
+
+use intern_str::{Graph, Segmentable};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Display;
+use std::io;
+
+/// The longest bar in a histogram.
+const BAR_WIDTH: usize = 40;
+
+/// Write a text report on the shape of a DFA.
+///
+/// This complements the other outputs for graphs that are too large to render. The
+/// report has the number of nodes and transitions, a histogram of how many
+/// transitions it takes to reach each node from the start node, how many nodes
+/// have each number of transitions, and the `top` nodes with the most nodes
+/// reachable from them, along with the input that reaches them.
+///
+/// ```
+/// use intern_str::builder::{Builder, Utf8Graph};
+///
+/// let mut builder = Builder::<_, Utf8Graph>::new();
+/// builder.add("get", 1).unwrap();
+/// builder.add("set", 2).unwrap();
+/// builder.add("settings", 3).unwrap();
+///
+/// let mut buffer = Vec::new();
+/// let graph = builder.build(&mut buffer);
+///
+/// let mut report = Vec::new();
+/// intern_str_visualize::report(&graph, &mut report, 5).unwrap();
+///
+/// let report = String::from_utf8(report).unwrap();
+/// assert!(report.contains("transitions: 3\n"));
+/// ```
+pub fn report<Input: Segmentable + Display, Output>(
+    graph: &Graph<'_, '_, Input, Output>,
+    out: &mut impl io::Write,
+    top: usize,
+) -> io::Result<()> {
+    let nodes = graph.nodes();
+    let transitions: usize = nodes.iter().map(|node| node.inputs().len()).sum();
+
+    writeln!(out, "nodes: {}", nodes.len())?;
+    writeln!(out, "transitions: {}", transitions)?;
+    writeln!(out, "start: s{}", graph.start())?;
+    if graph.max_key_len() == std::usize::MAX {
+        writeln!(out, "key lengths: {}..", graph.min_key_len())?;
+    } else {
+        writeln!(
+            out,
+            "key lengths: {}..={}",
+            graph.min_key_len(),
+            graph.max_key_len()
+        )?;
+    }
+
+    // Find the depth of each node, and the transition used to reach it first.
+    let mut depths = vec![None; nodes.len()];
+    let mut parents = vec![None; nodes.len()];
+    let mut queue = VecDeque::new();
+    depths[graph.start()] = Some(0);
+    queue.push_back(graph.start());
+
+    while let Some(i) = queue.pop_front() {
+        let depth = depths[i].unwrap_or(0);
+        for (j, (_, next)) in nodes[i].inputs().iter().enumerate() {
+            if depths[*next].is_none() {
+                depths[*next] = Some(depth + 1);
+                parents[*next] = Some((i, j));
+                queue.push_back(*next);
+            }
+        }
+    }
+
+    let mut histogram = BTreeMap::new();
+    let mut unreachable = 0;
+    for depth in &depths {
+        match depth {
+            Some(depth) => *histogram.entry(*depth).or_insert(0) += 1,
+            None => unreachable += 1,
+        }
+    }
+
+    writeln!(out)?;
+    writeln!(out, "depth histogram:")?;
+    write_histogram(out, &histogram, |depth| depth.to_string())?;
+    if unreachable > 0 {
+        writeln!(out, "  only reachable by default: {}", unreachable)?;
+    }
+
+    let mut fanouts = BTreeMap::new();
+    for node in nodes {
+        *fanouts.entry(node.inputs().len()).or_insert(0) += 1;
+    }
+
+    writeln!(out)?;
+    writeln!(out, "fanout distribution:")?;
+    write_histogram(out, &fanouts, |fanout| match fanout {
+        1 => "1 transition".to_string(),
+        fanout => format!("{} transitions", fanout),
+    })?;
+
+    // Count the nodes reachable from each node by its transitions.
+    let mut sizes = Vec::with_capacity(nodes.len());
+    let mut seen = vec![std::usize::MAX; nodes.len()];
+    let mut stack = Vec::new();
+    for i in 0..nodes.len() {
+        let mut size = 0;
+        seen[i] = i;
+        stack.push(i);
+
+        while let Some(j) = stack.pop() {
+            size += 1;
+            for (_, next) in nodes[j].inputs() {
+                if seen[*next] != i {
+                    seen[*next] = i;
+                    stack.push(*next);
+                }
+            }
+        }
+
+        sizes.push((size, i));
+    }
+
+    // Sort by size, largest first.
+    sizes.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+    writeln!(out)?;
+    writeln!(out, "largest subtrees:")?;
+    for &(size, i) in sizes.iter().take(top) {
+        write!(out, "  s{}: {} nodes", i, size)?;
+
+        // Write the input that first reaches the node.
+        if depths[i].is_some() {
+            let mut path = Vec::new();
+            let mut at = i;
+            while let Some((parent, j)) = parents[at] {
+                path.push(&nodes[parent].inputs()[j].0);
+                at = parent;
+            }
+
+            write!(out, ", reached by \"")?;
+            for input in path.iter().rev() {
+                write!(out, "{}", input)?;
+            }
+            write!(out, "\"")?;
+        }
+
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+/// Write a histogram of counts, with bars scaled to the largest count.
+fn write_histogram(
+    out: &mut impl io::Write,
+    counts: &BTreeMap<usize, usize>,
+    label: impl Fn(usize) -> String,
+) -> io::Result<()> {
+    let labels = counts.keys().map(|&key| label(key)).collect::<Vec<_>>();
+    let label_width = labels.iter().map(|label| label.len()).max().unwrap_or(0);
+    let count_width = counts
+        .values()
+        .map(|count| count.to_string().len())
+        .max()
+        .unwrap_or(0);
+    let max = counts.values().cloned().max().unwrap_or(0);
+
+    for (label, &count) in labels.iter().zip(counts.values()) {
+        let bar = (count * BAR_WIDTH + max - 1) / max;
+        writeln!(
+            out,
+            "  {:>label_width$}: {:>count_width$} {}",
+            label,
+            count,
+            "#".repeat(bar),
+            label_width = label_width,
+            count_width = count_width
+        )?;
+    }
+
+    Ok(())
+}
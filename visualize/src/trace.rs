@@ -0,0 +1,97 @@
+//! Tracing the path that an input takes through a graph.
+
+use super::format_label;
+use intern_str::{Graph, Segmentable};
+use std::collections::HashSet;
+use std::{fmt, io};
+
+/// The nodes and edges that were taken while processing an input.
+pub(crate) struct Trace {
+    /// Whether each node was visited.
+    pub(crate) nodes: Vec<bool>,
+
+    /// The edges that were taken, as the node they start from and the index of their
+    /// transition, or `None` for the edge to the default node.
+    pub(crate) edges: HashSet<(usize, Option<usize>)>,
+
+    /// The first node where the input fell back to a default node, and why.
+    pub(crate) miss: Option<(usize, String)>,
+}
+
+impl Trace {
+    /// Walk through the graph the same way as `Graph::process`, using `format_input`
+    /// to write the chunk where the input misses.
+    pub(crate) fn new<Input: Segmentable, Output>(
+        graph: &Graph<'_, '_, Input, Output>,
+        mut input: Input,
+        mut format_input: impl FnMut(&mut dyn fmt::Write, &Input) -> fmt::Result,
+    ) -> io::Result<Self> {
+        let nodes = graph.nodes();
+        let mut trace = Trace {
+            nodes: vec![false; nodes.len()],
+            edges: HashSet::new(),
+            miss: None,
+        };
+
+        let mut index = graph.start();
+        trace.nodes[index] = true;
+
+        // If the input can't possibly be a key, it goes right to the default node.
+        let len = input.len();
+        if len < graph.min_key_len() || len > graph.max_key_len() {
+            let reason = if len < graph.min_key_len() {
+                format!("input is shorter than {} bytes", graph.min_key_len())
+            } else {
+                format!("input is longer than {} bytes", graph.max_key_len())
+            };
+            trace.fall_back(index, nodes[index].default(), reason);
+            return Ok(trace);
+        }
+
+        loop {
+            let node = &nodes[index];
+
+            // Get the next input chunk.
+            let ended = input.is_empty();
+            let remaining = input.len();
+            let (chunk, rest) = match input.split(node.amount()) {
+                Some(result) => result,
+                None if ended || node.inputs().is_empty() => return Ok(trace),
+                None => {
+                    let offset = len - remaining;
+                    let reason = format!("input ends early at offset {}", offset);
+                    trace.fall_back(index, node.default(), reason);
+                    return Ok(trace);
+                }
+            };
+
+            // Get the next node.
+            let offset = len - rest.len() - chunk.len();
+            index = match node.inputs().binary_search_by(|(i, _)| i.cmp(&chunk)) {
+                Ok(i) => {
+                    trace.edges.insert((index, Some(i)));
+                    node.inputs()[i].1
+                }
+                Err(_) => {
+                    let chunk = format_label(&mut format_input, &chunk)?;
+                    let reason = format!("no transition for {} at offset {}", chunk, offset);
+                    trace.fall_back(index, node.default(), reason)
+                }
+            };
+
+            trace.nodes[index] = true;
+            input = rest;
+        }
+    }
+
+    /// Take the edge from `index` to its default node, which is returned.
+    fn fall_back(&mut self, index: usize, default: usize, reason: String) -> usize {
+        self.edges.insert((index, None));
+        self.nodes[default] = true;
+        if self.miss.is_none() {
+            self.miss = Some((index, reason));
+        }
+
+        default
+    }
+}
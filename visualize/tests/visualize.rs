@@ -0,0 +1,354 @@
+use intern_str::builder::{Builder, Utf8Graph};
+
+#[test]
+fn mermaid() {
+    let mut builder = Builder::<_, Utf8Graph>::new();
+    builder.add("get", 1).unwrap();
+    builder.add("say \"hi\"", 2).unwrap();
+
+    let mut buffer = vec![];
+    let graph = builder.build(&mut buffer);
+
+    let mut out = vec![];
+    intern_str_visualize::as_mermaid(&graph, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    assert!(out.starts_with("flowchart LR\n"));
+    assert!(out.contains("[\"Some(1)\"]"));
+    assert!(out.contains("#quot;hi#quot;"));
+    assert!(!out.contains("\"hi\""));
+
+    // Every node has a default edge.
+    assert_eq!(out.matches(" -.-> ").count(), graph.nodes().len());
+}
+
+#[test]
+fn graphml() {
+    let mut builder = Builder::<_, Utf8Graph>::new();
+    builder.add("get", 1).unwrap();
+    builder.add("a<b>&c", 2).unwrap();
+
+    let mut buffer = vec![];
+    let graph = builder.build(&mut buffer);
+
+    let mut out = vec![];
+    intern_str_visualize::as_graphml(&graph, &mut out, "G").unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    assert!(out.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"));
+    assert!(out.contains("<graph id=\"G\" edgedefault=\"directed\">"));
+    assert!(out.contains("<data key=\"output\">Some(1)</data>"));
+    assert!(out.contains("&lt;") && out.contains("&gt;") && out.contains("&amp;"));
+    assert!(!out.contains("<b") && !out.contains("&c"));
+    assert!(out.trim_end().ends_with("</graphml>"));
+
+    // There is exactly one start node, and every node has a default edge.
+    assert_eq!(out.matches("<data key=\"start\">true</data>").count(), 1);
+    assert_eq!(out.matches("<node id=").count(), graph.nodes().len());
+    assert_eq!(
+        out.matches("<data key=\"default\">true</data>").count(),
+        graph.nodes().len()
+    );
+}
+
+#[test]
+fn json() {
+    let mut builder = Builder::<_, Utf8Graph>::new();
+    builder.add("get", 1).unwrap();
+    builder.add("a\\\"b", 2).unwrap();
+
+    let mut buffer = vec![];
+    let graph = builder.build(&mut buffer);
+
+    let mut out = vec![];
+    intern_str_visualize::to_json(&graph, &mut out, |f, out| match out {
+        Some(out) => write!(f, "{}", out),
+        None => write!(f, "none"),
+    })
+    .unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    assert!(out.starts_with("{\n"));
+    assert!(out.contains(&format!("\"start\": {},", graph.start())));
+    assert!(out.contains("\"output\": \"1\""));
+    assert!(out.contains("\"output\": \"none\""));
+    assert!(out.contains("\\\\") && out.contains("\\\""));
+    assert_eq!(out.matches("\"output\": ").count(), graph.nodes().len());
+    assert_eq!(
+        out.matches("\"next\": ").count(),
+        graph
+            .nodes()
+            .iter()
+            .map(|node| node.inputs().len())
+            .sum::<usize>()
+    );
+}
+
+#[test]
+fn graphviz_options() {
+    use intern_str_visualize::{as_graphviz, as_graphviz_with_options, RankDir, VisualizeOptions};
+
+    let mut builder = Builder::<_, Utf8Graph>::new();
+    builder.add("get", 1).unwrap();
+    builder.add("set", 2).unwrap();
+    builder.add("settings", 3).unwrap();
+
+    let mut buffer = vec![];
+    let graph = builder.build(&mut buffer);
+
+    // The default options write the same graph as `as_graphviz`.
+    let mut plain = vec![];
+    as_graphviz(&graph, &mut plain, "G").unwrap();
+    let mut default = vec![];
+    as_graphviz_with_options(&graph, &mut default, "G", &VisualizeOptions::new()).unwrap();
+    assert_eq!(plain, default);
+
+    let options = VisualizeOptions::new()
+        .rankdir(RankDir::LeftToRight)
+        .node_shape("circle")
+        .default_edges(false)
+        .accepting_color("palegreen")
+        .trap_color("gray");
+    let mut out = vec![];
+    as_graphviz_with_options(&graph, &mut out, "G", &options).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    assert!(out.contains("rankdir=LR;\n"));
+    assert!(out.contains("node [shape=\"circle\"];\n"));
+    assert_eq!(out.matches("fillcolor=\"palegreen\"").count(), 3);
+    assert_eq!(out.matches("fillcolor=\"gray\"").count(), 1);
+    for line in out.lines().filter(|line| line.contains(" -> ")) {
+        assert!(line.contains("[label="), "{}", line);
+    }
+
+    // Only the nodes closest to the start are kept.
+    let options = VisualizeOptions::new().max_nodes(2);
+    let mut out = vec![];
+    as_graphviz_with_options(&graph, &mut out, "G", &options).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    let nodes = out
+        .lines()
+        .filter(|line| line.starts_with('s') && !line.contains(" -> "))
+        .count();
+    assert_eq!(nodes, 2);
+    assert!(out.contains(&format!("s{} [label=", graph.start())));
+    assert!(out.contains("more [label=\"...\", shape=plaintext]"));
+}
+
+#[test]
+fn graphviz_trace() {
+    use intern_str_visualize::{as_graphviz_with_trace, VisualizeOptions};
+
+    let mut builder = Builder::<_, Utf8Graph>::new();
+    builder.add("get", 1).unwrap();
+    builder.add("set", 2).unwrap();
+    builder.add("settings", 3).unwrap();
+
+    let mut buffer = vec![];
+    let graph = builder.build(&mut buffer);
+    let options = VisualizeOptions::new().default_edges(false);
+
+    let trace = |input| {
+        let mut out = vec![];
+        as_graphviz_with_trace(&graph, input, &mut out, "G", &options).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+
+    // A hit colors the path, but nothing is missed.
+    let out = trace("settings");
+    assert!(out.contains(&format!(
+        "s{} [label=\"None\", color=\"blue\", penwidth=2]",
+        graph.start()
+    )));
+    assert!(out.contains("[label=\"Some(3)\", color=\"blue\", penwidth=2]"));
+    assert!(!out.contains("red"));
+    assert!(out.contains("[label=\"Some(2)\", color=\"blue\", penwidth=2]"));
+    assert!(out.contains("[label=\"Some(1)\"]\n"));
+    assert!(out
+        .lines()
+        .any(|line| line.contains(" -> ") && line.contains("color=\"blue\"")));
+
+    // A miss marks the divergence, and the default edge is written.
+    let out = trace("setxxxxx");
+    assert_eq!(out.matches("xlabel=").count(), 1);
+    assert!(
+        out.contains("color=\"red\", penwidth=2, xlabel=\"no transition for xxxxx at offset 3\"")
+    );
+    assert_eq!(out.matches("[color=\"red\", penwidth=2];").count(), 1);
+
+    // Inputs of the wrong length miss at the start node.
+    let out = trace("settingsandmore");
+    assert!(out.contains(&format!(
+        "s{} [label=\"None\", color=\"red\", penwidth=2, xlabel=\"input is longer than 8 bytes\"]",
+        graph.start()
+    )));
+}
+
+#[test]
+fn graphviz_collapse_chains() {
+    use intern_str_visualize::{
+        as_graphviz_with_options, as_graphviz_with_trace, VisualizeOptions,
+    };
+
+    let mut builder = Builder::<_, Utf8Graph>::new();
+    builder.add("get", 1).unwrap();
+    builder.add("gone", 2).unwrap();
+    builder.add("going", 3).unwrap();
+    builder.add("set", 4).unwrap();
+    builder.add("sets", 5).unwrap();
+    builder.add("settle", 6).unwrap();
+    builder.add("settings", 7).unwrap();
+
+    let mut buffer = vec![];
+    let graph = builder.build(&mut buffer);
+    let options = VisualizeOptions::new()
+        .default_edges(false)
+        .collapse_chains(true);
+
+    let mut out = vec![];
+    as_graphviz_with_options(&graph, &mut out, "G", &options).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    // Chunks along a chain are joined into one edge.
+    assert!(out.contains(" [label=\"ing\"];"));
+    assert!(out.contains(" [label=\"ings\"];"));
+    assert!(out.contains(" [label=\"set\"];"));
+
+    // Accepting nodes are kept.
+    for output in 1..=7 {
+        let label = format!("[label=\"Some({})\"]", output);
+        assert_eq!(out.matches(&label).count(), 1, "{}", label);
+    }
+
+    // The path of a traced input follows the collapsed edges.
+    let mut out = vec![];
+    as_graphviz_with_trace(&graph, "settings", &mut out, "G", &options).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    assert!(out.contains(" [label=\"ings\", color=\"blue\", penwidth=2];"));
+    assert!(out.contains(" [label=\"ing\"];"));
+}
+
+#[test]
+fn report() {
+    let mut builder = Builder::<_, Utf8Graph>::new();
+    builder.add("get", 1).unwrap();
+    builder.add("gone", 2).unwrap();
+    builder.add("going", 3).unwrap();
+    builder.add("set", 4).unwrap();
+    builder.add("sets", 5).unwrap();
+    builder.add("settle", 6).unwrap();
+    builder.add("settings", 7).unwrap();
+
+    let mut buffer = vec![];
+    let graph = builder.build(&mut buffer);
+
+    let mut out = vec![];
+    intern_str_visualize::report(&graph, &mut out, 3).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    assert!(out.contains(&format!("nodes: {}\n", graph.nodes().len())));
+    assert!(out.contains("key lengths: 3..=8\n"));
+    assert!(out.contains("depth histogram:\n  0:"));
+    assert!(out.contains("only reachable by default: 1\n"));
+    assert!(out.contains("fanout distribution:\n"));
+
+    // The start node reaches everything but the trap node.
+    let largest = out.split("largest subtrees:\n").nth(1).unwrap();
+    assert_eq!(largest.lines().count(), 3);
+    assert_eq!(
+        largest.lines().next().unwrap(),
+        format!(
+            "  s{}: {} nodes, reached by \"\"",
+            graph.start(),
+            graph.nodes().len() - 1
+        )
+    );
+    assert!(largest.contains(" nodes, reached by \"g\"\n"));
+    assert!(largest.contains(" nodes, reached by \"s\"\n"));
+}
+
+#[test]
+fn graphviz_escape() {
+    use intern_str_visualize::as_graphviz;
+
+    let mut builder = Builder::<_, Utf8Graph>::new();
+    builder.add("say \"hi\"", "quote").unwrap();
+    builder.add("a\\b", "back\\slash").unwrap();
+    builder.add("two\nlines", "newline").unwrap();
+
+    let mut buffer = vec![];
+    let graph = builder.build(&mut buffer);
+
+    let mut out = vec![];
+    as_graphviz(&graph, &mut out, "G").unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    assert!(out.contains("[label=\"Some(\\\"quote\\\")\"]"));
+    assert!(out.contains("[label=\"Some(\\\"back\\\\\\\\slash\\\")\"]"));
+    assert!(out.contains("\\\"hi\\\""));
+    assert!(out.contains("a\\\\b"));
+    assert!(out.contains("\\n"));
+
+    // Every label is on one line, and every quote in it is escaped.
+    for line in out.lines().filter(|line| line.contains("label=")) {
+        let label = line.split("label=\"").nth(1).unwrap();
+        let mut escaped = false;
+        let mut end = None;
+        for (i, c) in label.char_indices() {
+            match c {
+                '\\' if !escaped => escaped = true,
+                '"' if !escaped => {
+                    end = Some(i);
+                    break;
+                }
+                _ => escaped = false,
+            }
+        }
+
+        let end = end.unwrap();
+        assert!(label[end..].starts_with("\"]"), "{}", line);
+    }
+}
+
+#[cfg(all(unix, feature = "render"))]
+#[test]
+fn render() {
+    use intern_str_visualize::{as_graphviz, render_with, Format};
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut builder = Builder::<_, Utf8Graph>::new();
+    builder.add("get", 1).unwrap();
+    builder.add("set", 2).unwrap();
+
+    let mut buffer = vec![];
+    let graph = builder.build(&mut buffer);
+
+    let mut dot = vec![];
+    as_graphviz(&graph, &mut dot, "G").unwrap();
+
+    // Stand in for `dot` with a script that echoes the format and its input.
+    let dir = std::env::temp_dir().join(format!("intern-str-render-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let program = dir.join("dot");
+    fs::write(&program, "#!/bin/sh\necho \"$1\"\ncat\n").unwrap();
+    fs::set_permissions(&program, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let mut out = vec![];
+    render_with(&program, &dot, Format::Png, &mut out).unwrap();
+    assert_eq!(out, [&b"-Tpng\n"[..], &dot].concat());
+
+    // Failures carry the output of `dot`.
+    let failing = dir.join("failing");
+    fs::write(&failing, "#!/bin/sh\necho 'syntax error' >&2\nexit 1\n").unwrap();
+    fs::set_permissions(&failing, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let mut out = vec![];
+    let err = render_with(&failing, &dot, Format::Svg, &mut out).unwrap_err();
+    assert!(err.to_string().contains("syntax error"));
+    assert!(out.is_empty());
+
+    fs::remove_dir_all(&dir).unwrap();
+}